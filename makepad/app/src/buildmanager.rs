@@ -13,6 +13,10 @@ pub struct BuildManager {
     pub search_index: SearchIndex,
     pub tail_log_items: bool,
     pub artifacts: Vec<String>,
+    pub test_results: Vec<TestResult>,
+    // saves wait for this before the targets they touch rebuild
+    pub build_timer: Timer,
+    pub dirty_paths: Vec<String>,
 }
 
 impl BuildManager {
@@ -23,8 +27,11 @@ impl BuildManager {
             log_items: Vec::new(),
             tail_log_items: true,
             artifacts: Vec::new(),
+            test_results: Vec::new(),
             active_builds: Vec::new(),
             search_index: SearchIndex::new(),
+            build_timer: Timer::empty(),
+            dirty_paths: Vec::new(),
         }
     }
     
@@ -32,6 +39,27 @@ impl BuildManager {
     pub fn status_new_artifact() -> StatusId {uid!()}
     pub fn status_cargo_end() -> StatusId {uid!()}
     pub fn status_program_end() -> StatusId {uid!()}
+    pub fn status_test_update() -> StatusId {uid!()}
+    pub fn status_queue_update() -> StatusId {uid!()}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TestState {
+    Running,
+    Passed,
+    Failed,
+    Ignored
+}
+
+#[derive(Clone)]
+pub struct TestResult {
+    pub uid: HubUid,
+    pub suite: String,
+    pub name: String,
+    pub state: TestState,
+    pub stdout: String,
+    pub panic: Option<LocMessage>,
+    pub reason: Option<String>,
 }
 
 #[derive(Clone)]
@@ -40,6 +68,10 @@ pub struct ActiveBuild {
     pub build_result: Option<BuildResult>,
     pub build_uid: Option<HubUid>,
     pub run_uid: Option<HubUid>,
+    // waiting for a free slot on its builder
+    pub queued: bool,
+    // what the last build said about the code, kept when other targets rebuild
+    pub loc_items: Vec<HubLogItem>,
 }
 
 impl BuildManager {
@@ -72,7 +104,7 @@ impl BuildManager {
     
     pub fn is_any_cargo_running(&self) -> bool {
         for ab in &self.active_builds {
-            if ab.build_uid.is_some() {
+            if ab.build_uid.is_some() || ab.queued {
                 return true
             }
         }
@@ -88,6 +120,18 @@ impl BuildManager {
         return false
     }
     
+    // rustc counts lines and columns from 1, in chars
+    pub fn loc_suggestion_to_fix(text_buffer: &TextBuffer, suggestion: &LocSuggestion) -> TextBufferFix {
+        TextBufferFix {
+            label: suggestion.message.clone(),
+            edits: suggestion.edits.iter().map( | edit | TextBufferEdit {
+                start: text_buffer.text_pos_to_offset(TextPos {row: edit.line_start.max(1) - 1, col: edit.column_start.max(1) - 1}),
+                end: text_buffer.text_pos_to_offset(TextPos {row: edit.line_end.max(1) - 1, col: edit.column_end.max(1) - 1}),
+                replacement: edit.replacement.clone()
+            }).collect()
+        }
+    }
+    
     pub fn process_loc_message_for_textbuffers(
         &self,
         cx: &mut Cx,
@@ -96,6 +140,10 @@ impl BuildManager {
         makepad_storage: &mut MakepadStorage
     ) {
         let atb = makepad_storage.text_buffer_from_path(cx, &makepad_storage.remap_sync_path(&loc_message.path));
+        let fixes = loc_message.suggestions.iter()
+            .filter( | suggestion | suggestion.is_quick_fix())
+            .map( | suggestion | Self::loc_suggestion_to_fix(&atb.text_buffer, suggestion))
+            .collect();
         let markers = &mut atb.text_buffer.markers;
         markers.mutation_id = atb.text_buffer.mutation_id.max(1);
         if markers.message_cursors.len() > 100000 { // crash saftey
@@ -131,7 +179,8 @@ impl BuildManager {
             
             let msg = TextBufferMessage {
                 body: loc_message.body.clone(),
-                level: level
+                level: level,
+                fixes: fixes
             };
             if let Some(pos) = inserted {
                 atb.text_buffer.markers.message_bodies.insert(pos, msg);
@@ -170,6 +219,12 @@ impl BuildManager {
             HubMsg::ListBuildersResponse {..} => {
                 self.restart_build(cx, makepad_storage);
             },
+            HubMsg::ConnectionError(e) => {
+                // a builder dropped, the build restarts when it is back in the builder list
+                self.handle_log_item_limit(cx);
+                self.log_items.push(HubLogItem::Error(format!("Lost connection to a builder: {}", e.msg)));
+                cx.send_signal(self.signal, BuildManager::status_new_log_item());
+            },
             HubMsg::CargoBegin {uid} => if self.is_running_uid(uid) {
                 cx.send_signal(self.signal, BuildManager::status_new_log_item());
             },
@@ -187,7 +242,10 @@ impl BuildManager {
                         HubLogItem::Warning(_) => TextBufferMessageLevel::Warning,
                         HubLogItem::Message(_) => TextBufferMessageLevel::Log,
                     };
-                    self.process_loc_message_for_textbuffers(cx, loc_message, level, makepad_storage)
+                    self.process_loc_message_for_textbuffers(cx, loc_message, level, makepad_storage);
+                    if let Some(ab) = self.active_builds.iter_mut().find( | ab | ab.build_uid == Some(*uid)) {
+                        ab.loc_items.push(item.clone());
+                    }
                 }
                 cx.send_signal(self.signal, BuildManager::status_new_log_item());
             },
@@ -197,12 +255,12 @@ impl BuildManager {
                 cx.send_signal(self.signal, BuildManager::status_new_artifact());
             },
             HubMsg::BuildFailure {uid} => if self.is_running_uid(uid) {
-                // if we didnt have any errors, check if we need to run
                 for ab in &mut self.active_builds {
                     if ab.build_uid == Some(*uid) {
                         ab.build_uid = None;
                     }
                 }
+                self.pump_build_queue(cx, makepad_storage);
             },
             HubMsg::CargoEnd {uid, build_result} => if self.is_running_uid(uid) {
                 for ab in &mut self.active_builds {
@@ -211,12 +269,47 @@ impl BuildManager {
                         ab.build_result = Some(build_result.clone());
                     }
                 }
+                self.pump_build_queue(cx, makepad_storage);
                 if !self.is_any_cargo_running() && self.exec_when_done {
-                    self.run_all_artifacts(makepad_storage)
+                    self.run_artifacts(makepad_storage, false)
                 }
                 cx.send_signal(self.signal, BuildManager::status_cargo_end());
             },
-            HubMsg::ProgramEnd {uid} => if self.is_running_uid(uid) {
+            HubMsg::TestStarted {uid, suite, name} => if self.is_running_uid(uid) {
+                self.test_results.push(TestResult {
+                    uid: *uid,
+                    suite: suite.clone(),
+                    name: name.clone(),
+                    state: TestState::Running,
+                    stdout: String::new(),
+                    panic: None,
+                    reason: None
+                });
+                cx.send_signal(self.signal, BuildManager::status_test_update());
+            },
+            HubMsg::TestPassed {uid, suite, name, stdout} => if self.is_running_uid(uid) {
+                let test = self.test_result(*uid, suite, name);
+                test.state = TestState::Passed;
+                test.stdout = stdout.clone();
+                cx.send_signal(self.signal, BuildManager::status_test_update());
+            },
+            HubMsg::TestFailed {uid, suite, name, stdout, panic} => if self.is_running_uid(uid) {
+                let test = self.test_result(*uid, suite, name);
+                test.state = TestState::Failed;
+                test.stdout = stdout.clone();
+                test.panic = panic.clone();
+                if let Some(panic) = panic {
+                    self.process_loc_message_for_textbuffers(cx, panic, TextBufferMessageLevel::Error, makepad_storage);
+                }
+                cx.send_signal(self.signal, BuildManager::status_test_update());
+            },
+            HubMsg::TestIgnored {uid, suite, name, reason} => if self.is_running_uid(uid) {
+                let test = self.test_result(*uid, suite, name);
+                test.state = TestState::Ignored;
+                test.reason = reason.clone();
+                cx.send_signal(self.signal, BuildManager::status_test_update());
+            },
+            HubMsg::ProgramEnd {uid, ..} => if self.is_running_uid(uid) {
                 // if we didnt have any errors, check if we need to run
                 for ab in &mut self.active_builds {
                     if ab.run_uid == Some(*uid) {
@@ -229,9 +322,30 @@ impl BuildManager {
         }
     }
     
+    // finds the row of a test, a result without a started event still gets one
+    fn test_result(&mut self, uid: HubUid, suite: &str, name: &str) -> &mut TestResult {
+        if let Some(index) = self.test_results.iter().rposition( | test | test.uid == uid && test.suite == suite && test.name == name) {
+            return &mut self.test_results[index]
+        }
+        self.test_results.push(TestResult {
+            uid: uid,
+            suite: suite.to_string(),
+            name: name.to_string(),
+            state: TestState::Running,
+            stdout: String::new(),
+            panic: None,
+            reason: None
+        });
+        self.test_results.last_mut().unwrap()
+    }
+    
     pub fn add_log_message(&mut self, cx: &mut Cx, msg: String) {
+        self.add_log_item(cx, HubLogItem::Message(msg));
+    }
+    
+    pub fn add_log_item(&mut self, cx: &mut Cx, item: HubLogItem) {
         self.handle_log_item_limit(cx);
-        self.log_items.push(HubLogItem::Message(msg));
+        self.log_items.push(item);
         cx.send_signal(self.signal, BuildManager::status_new_log_item());
     }
     
@@ -267,7 +381,8 @@ impl BuildManager {
                 body: err.message.clone(),
                 range: Some((off, off + err.len)),
                 rendered: None,
-                explanation: None
+                explanation: None,
+                suggestions: Vec::new()
             };
             self.process_loc_message_for_textbuffers(cx, &msg, TextBufferMessageLevel::Error, makepad_storage);
             self.log_items.push(HubLogItem::LocError(msg));
@@ -276,9 +391,17 @@ impl BuildManager {
     }
     
     pub fn run_all_artifacts(&mut self, makepad_storage: &mut MakepadStorage) {
+        self.run_artifacts(makepad_storage, true)
+    }
+    
+    // after a partial rebuild only the targets that were stopped start again
+    fn run_artifacts(&mut self, makepad_storage: &mut MakepadStorage, restart_running: bool) {
         let hub_ui = makepad_storage.hub_ui.as_mut().unwrap();
         // otherwise execute all we have artifacts for
         for ab in &mut self.active_builds {
+            if ab.run_uid.is_some() && !restart_running {
+                continue
+            }
             if let Some(build_result) = &ab.build_result {
                 if let BuildResult::Executable {path} = build_result {
                     let uid = hub_ui.route_send.alloc_uid();
@@ -296,7 +419,8 @@ impl BuildManager {
                         msg: HubMsg::ProgramRun {
                             uid: ab.run_uid.unwrap(),
                             path: path.clone(),
-                            args: Vec::new()
+                            args: Vec::new(),
+                            options: ab.build_target.run_options.clone()
                         }
                     });
                 }
@@ -313,6 +437,49 @@ impl BuildManager {
         }
     }
     
+    // types into the programs that were started interactive
+    pub fn program_stdin(&mut self, makepad_storage: &mut MakepadStorage, input: &str) {
+        let hub_ui = makepad_storage.hub_ui.as_mut().unwrap();
+        for ab in &self.active_builds {
+            if let Some(run_uid) = ab.run_uid {
+                if ab.build_target.run_options.pty || ab.build_target.run_options.stdin {
+                    hub_ui.route_send.send(ToHubMsg {
+                        to: HubMsgTo::Builder(ab.build_target.builder.clone()),
+                        msg: HubMsg::ProgramStdin {
+                            uid: run_uid,
+                            input: input.to_string(),
+                            eof: false
+                        }
+                    });
+                }
+            }
+        }
+    }
+    
+    fn cancel_build(hub_ui: &mut HubUI, ab: &mut ActiveBuild) {
+        ab.build_result = None;
+        ab.queued = false;
+        ab.loc_items.truncate(0);
+        if let Some(build_uid) = ab.build_uid {
+            hub_ui.route_send.send(ToHubMsg {
+                to: HubMsgTo::Builder(ab.build_target.builder.clone()),
+                msg: HubMsg::BuildKill {
+                    uid: build_uid,
+                }
+            });
+            ab.build_uid = None
+        }
+        if let Some(run_uid) = ab.run_uid {
+            hub_ui.route_send.send(ToHubMsg {
+                to: HubMsgTo::Builder(ab.build_target.builder.clone()),
+                msg: HubMsg::ProgramKill {
+                    uid: run_uid,
+                }
+            });
+            ab.run_uid = None
+        }
+    }
+    
     pub fn restart_build(&mut self, cx: &mut Cx, makepad_storage: &mut MakepadStorage) {
         if !cx.platform_type.is_desktop() {
             return
@@ -320,53 +487,145 @@ impl BuildManager {
         
         self.artifacts.truncate(0);
         self.log_items.truncate(0);
+        self.test_results.truncate(0);
         //self.selection.truncate(0);
         self.clear_textbuffer_messages(cx, makepad_storage);
+        self.dirty_paths.truncate(0);
+        cx.stop_timer(&mut self.build_timer);
         
         let hub_ui = makepad_storage.hub_ui.as_mut().unwrap();
         self.exec_when_done = makepad_storage.settings.exec_when_done;
         for ab in &mut self.active_builds {
-            ab.build_result = None;
-            if let Some(build_uid) = ab.build_uid {
-                hub_ui.route_send.send(ToHubMsg {
-                    to: HubMsgTo::Builder(ab.build_target.builder.clone()),
-                    msg: HubMsg::BuildKill {
-                        uid: build_uid,
-                    }
-                });
-                ab.build_uid = None
-            }
-            if let Some(run_uid) = ab.run_uid {
-                hub_ui.route_send.send(ToHubMsg {
-                    to: HubMsgTo::Builder(ab.build_target.builder.clone()),
-                    msg: HubMsg::ProgramKill {
-                        uid: run_uid,
-                    }
-                });
-                ab.run_uid = None
-            }
+            Self::cancel_build(hub_ui, ab);
         }
         
         // lets reset active targets
         self.active_builds.truncate(0);
         
         for build_target in &makepad_storage.settings.builds {
+            self.active_builds.push(ActiveBuild {
+                build_target: build_target.clone(),
+                build_result: None,
+                build_uid: None,
+                run_uid: None,
+                queued: true,
+                loc_items: Vec::new()
+            })
+        }
+        self.pump_build_queue(cx, makepad_storage);
+    }
+    
+    // a save waits for more saves, then only the targets they touch rebuild
+    pub fn schedule_build(&mut self, cx: &mut Cx, makepad_storage: &MakepadStorage, path: &str) {
+        if !cx.platform_type.is_desktop() {
+            return
+        }
+        if !self.dirty_paths.iter().any( | dirty | dirty == path) {
+            self.dirty_paths.push(path.to_string());
+        }
+        cx.stop_timer(&mut self.build_timer);
+        self.build_timer = cx.start_timer(makepad_storage.settings.build_debounce, false);
+        cx.send_signal(self.signal, BuildManager::status_queue_update());
+    }
+    
+    pub fn handle_build_timer(&mut self, cx: &mut Cx, te: &TimerEvent, makepad_storage: &mut MakepadStorage) {
+        if self.build_timer.is_timer(te) {
+            self.build_timer = Timer::empty();
+            let dirty_paths = std::mem::replace(&mut self.dirty_paths, Vec::new());
+            self.rebuild_affected(cx, makepad_storage, &dirty_paths);
+        }
+    }
+    
+    pub fn is_affected_by(build_target: &BuildTarget, makepad_storage: &MakepadStorage, paths: &[String]) -> bool {
+        let workspace = format!("{}/{}/", build_target.builder, build_target.workspace);
+        let packages = makepad_storage.packages.get(&build_target.builder).map( | p | p.as_slice()).unwrap_or(&[]);
+        paths.iter().any( | path | makepad_storage.sync_paths(path).iter().any( | path | {
+            if let Some(rel_path) = path.strip_prefix(&workspace) {
+                match HubPackage::owning(packages, &build_target.workspace, rel_path) {
+                    Some(package) => package.package_name == build_target.package,
+                    // the workspace Cargo.toml, Cargo.lock and the like touch every package
+                    None => true
+                }
+            }
+            else {
+                false
+            }
+        }))
+    }
+    
+    pub fn rebuild_affected(&mut self, cx: &mut Cx, makepad_storage: &mut MakepadStorage, paths: &[String]) {
+        let affected: Vec<bool> = self.active_builds.iter().map( | ab | Self::is_affected_by(&ab.build_target, makepad_storage, paths)).collect();
+        if self.active_builds.len() == 0 || affected.iter().all( | a | *a) {
+            return self.restart_build(cx, makepad_storage)
+        }
+        if !affected.iter().any( | a | *a) {
+            cx.send_signal(self.signal, BuildManager::status_queue_update());
+            return
+        }
+        
+        let hub_ui = makepad_storage.hub_ui.as_mut().unwrap();
+        let mut cancelled = Vec::new();
+        for (ab, affected) in self.active_builds.iter_mut().zip(affected.iter()) {
+            if *affected {
+                if let Some(build_uid) = ab.build_uid {
+                    cancelled.push(build_uid);
+                }
+                Self::cancel_build(hub_ui, ab);
+                ab.queued = true;
+            }
+        }
+        // keep what the other targets found
+        self.test_results.retain( | test | !cancelled.contains(&test.uid));
+        self.log_items.truncate(0);
+        self.clear_textbuffer_messages(cx, makepad_storage);
+        let kept: Vec<HubLogItem> = self.active_builds.iter().flat_map( | ab | ab.loc_items.iter().cloned()).collect();
+        for item in kept {
+            if let Some(loc_message) = item.get_loc_message() {
+                let level = match item {
+                    HubLogItem::LocError(_) => TextBufferMessageLevel::Error,
+                    HubLogItem::LocWarning(_) => TextBufferMessageLevel::Warning,
+                    _ => TextBufferMessageLevel::Log,
+                };
+                self.process_loc_message_for_textbuffers(cx, loc_message, level, makepad_storage);
+            }
+            self.log_items.push(item);
+        }
+        self.exec_when_done = makepad_storage.settings.exec_when_done;
+        self.pump_build_queue(cx, makepad_storage);
+        cx.send_signal(self.signal, BuildManager::status_new_log_item());
+    }
+    
+    // starts queued builds in order, each builder runs at most max_builds_per_builder at once
+    pub fn pump_build_queue(&mut self, cx: &mut Cx, makepad_storage: &mut MakepadStorage) {
+        let max_builds = makepad_storage.settings.max_builds_per_builder.max(1);
+        let hub_ui = makepad_storage.hub_ui.as_mut().unwrap();
+        for i in 0..self.active_builds.len() {
+            if !self.active_builds[i].queued {
+                continue
+            }
+            let builder = self.active_builds[i].build_target.builder.clone();
+            let running = self.active_builds.iter().filter( | ab | ab.build_uid.is_some() && ab.build_target.builder == builder).count();
+            if running >= max_builds {
+                continue
+            }
+            let ab = &mut self.active_builds[i];
             let uid = hub_ui.route_send.alloc_uid();
             hub_ui.route_send.send(ToHubMsg {
-                to: HubMsgTo::Builder(build_target.builder.clone()),
+                to: HubMsgTo::Builder(builder),
                 msg: HubMsg::Build {
-                    uid: uid.clone(),
-                    workspace: build_target.workspace.clone(),
-                    package: build_target.package.clone(),
-                    config: build_target.config.clone()
+                    uid: uid,
+                    workspace: ab.build_target.workspace.clone(),
+                    package: ab.build_target.package.clone(),
+                    config: ab.build_target.config.clone()
                 }
             });
-            self.active_builds.push(ActiveBuild {
-                build_target: build_target.clone(),
-                build_result: None,
-                build_uid: Some(uid),
-                run_uid: None
-            })
+            ab.build_uid = Some(uid);
+            ab.queued = false;
         }
+        cx.send_signal(self.signal, BuildManager::status_queue_update());
+    }
+    
+    pub fn queued_builds(&self) -> impl Iterator<Item = &BuildTarget> {
+        self.active_builds.iter().filter( | ab | ab.queued).map( | ab | &ab.build_target)
     }
 }
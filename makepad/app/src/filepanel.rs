@@ -12,7 +12,13 @@ use crate::filetree::*;
 pub struct FilePanel {
     pub file_tree: FileTree,
     pub new_file_btn: NormalButton,
-} 
+    pub new_folder_btn: NormalButton,
+    pub rename_btn: NormalButton,
+    pub delete_btn: NormalButton,
+    pub name_input: TextInput,
+    // the action waiting for a name or a confirmation, and the path it works on
+    pub pending_action: Option<(FileTreeAction, String)>,
+}
 /*
 #[derive(Clone, PartialEq)]
 pub enum FilePanelEvent {
@@ -24,20 +30,158 @@ pub enum FilePanelEvent {
 impl FilePanel {
     pub fn new(cx: &mut Cx) -> Self {
         Self {
-            file_tree: FileTree::new(cx), 
+            file_tree: FileTree::new(cx),
             new_file_btn: NormalButton::new(cx),
+            new_folder_btn: NormalButton::new(cx),
+            rename_btn: NormalButton::new(cx),
+            delete_btn: NormalButton::new(cx),
+            name_input: TextInput::new(cx, TextInputOptions {multiline: false, read_only: false, empty_message: "name".to_string()}),
+            pending_action: None,
         }
     }
-    
+
+    pub fn style(cx: &mut Cx) {
+        live_body!(cx, {
+            self::button_layout_bg: Layout {
+                align: all(0.5),
+                walk: Walk {
+                    width: Compute,
+                    height: Compute,
+                    margin: {t: 2., l: 2., r: 0., b: 0.},
+                },
+                padding: {l: 6.0, t: 3.0, r: 6.0, b: 3.0},
+            }
+
+            self::style_button: Style {
+                makepad_widget::normalbutton::layout_bg: self::button_layout_bg;
+            }
+
+            self::text_input_layout_bg: Layout {
+                walk: Walk {
+                    width: Compute,
+                    height: Compute,
+                    margin: {t: 2., l: 4., r: 0., b: 0.}
+                },
+                padding: all(5.),
+            }
+            self::text_input_color_bg: #34;
+
+            self::style_text_input: Style {
+                makepad_widget::texteditor::layout_bg: self::text_input_layout_bg;
+                makepad_widget::texteditor::color_bg: self::text_input_color_bg;
+            }
+        })
+    }
+
     pub fn handle_file_panel(&mut self, cx: &mut Cx, event: &mut Event) -> FileTreeEvent {
-        //self.new_file_btn.handle_button(cx, event);
-        self.file_tree.handle_file_tree(cx, event)
+        if self.pending_action.is_some() {
+            match self.name_input.handle_text_input(cx, event) {
+                TextEditorEvent::Escape | TextEditorEvent::KeyFocusLost => {
+                    self.end_action(cx);
+                    return FileTreeEvent::None
+                },
+                _ => ()
+            }
+            if let Event::KeyDown(ke) = event {
+                if ke.key_code == KeyCode::Return && self.name_input.text_editor.has_key_focus(cx) {
+                    return self.confirm_action(cx)
+                }
+            }
+        }
+        else {
+            let mut buttons = [
+                (&mut self.new_file_btn, FileTreeAction::NewFile),
+                (&mut self.new_folder_btn, FileTreeAction::NewFolder),
+                (&mut self.rename_btn, FileTreeAction::Rename),
+                (&mut self.delete_btn, FileTreeAction::Delete)
+            ];
+            let mut clicked = None;
+            for (button, action) in buttons.iter_mut() {
+                if button.handle_normal_button(cx, event) == ButtonEvent::Clicked {
+                    clicked = Some(*action);
+                }
+            }
+            if let Some(action) = clicked {
+                if let FileTreeEvent::Action {action, path} = self.file_tree.marked_action(action) {
+                    self.begin_action(cx, action, path);
+                }
+                return FileTreeEvent::None
+            }
+        }
+        match self.file_tree.handle_file_tree(cx, event) {
+            FileTreeEvent::Action {action, path} => {
+                self.begin_action(cx, action, path);
+                FileTreeEvent::None
+            },
+            file_tree_event => file_tree_event
+        }
+    }
+
+    pub fn begin_action(&mut self, cx: &mut Cx, action: FileTreeAction, path: String) {
+        // the builder and workspace folders are not ours to rename or delete
+        if path.find('/').is_none() && (action == FileTreeAction::Rename || action == FileTreeAction::Delete) {
+            return
+        }
+        let name = path.rsplit('/').next().unwrap_or("").to_string();
+        let (value, empty_message) = match action {
+            FileTreeAction::NewFile => (String::new(), format!("new file in {}", name)),
+            FileTreeAction::NewFolder => (String::new(), format!("new folder in {}", name)),
+            FileTreeAction::Rename => (name, String::new()),
+            FileTreeAction::Delete => (String::new(), format!("enter deletes {}", name))
+        };
+        self.name_input.empty_message = empty_message;
+        self.name_input.set_value(cx, &value);
+        self.name_input.select_all(cx);
+        self.name_input.text_editor.set_key_focus(cx);
+        self.pending_action = Some((action, path));
+        cx.redraw_child_area(Area::All);
+    }
+
+    pub fn end_action(&mut self, cx: &mut Cx) {
+        if self.pending_action.take().is_some() {
+            cx.revert_key_focus();
+            cx.redraw_child_area(Area::All);
+        }
+    }
+
+    pub fn confirm_action(&mut self, cx: &mut Cx) -> FileTreeEvent {
+        let (action, path) = if let Some(pending) = self.pending_action.clone() {pending} else {return FileTreeEvent::None};
+        let name = self.name_input.get_value().trim().to_string();
+        self.end_action(cx);
+        if action != FileTreeAction::Delete && (name.is_empty() || name.contains('/') || name == "." || name == "..") {
+            return FileTreeEvent::None
+        }
+        match action {
+            FileTreeAction::NewFile => FileTreeEvent::CreateFile {path: format!("{}/{}", path, name)},
+            FileTreeAction::NewFolder => FileTreeEvent::CreateFolder {path: format!("{}/{}", path, name)},
+            FileTreeAction::Rename => {
+                let folder = &path[0..path.rfind('/').unwrap_or(0)];
+                let new_path = format!("{}/{}", folder, name);
+                if new_path == path {
+                    return FileTreeEvent::None
+                }
+                FileTreeEvent::RenamePath {path: path, new_path: new_path}
+            },
+            FileTreeAction::Delete => FileTreeEvent::DeletePath {path: path}
+        }
     }
-    
-    pub fn draw_file_panel_tab(&mut self, _cx: &mut Cx){
-        //self.new_file_btn.draw_button(cx, "HELLO");
+
+    pub fn draw_file_panel_tab(&mut self, cx: &mut Cx){
+        if self.pending_action.is_some() {
+            live_style_begin!(cx, self::style_text_input);
+            self.name_input.draw_text_input(cx);
+            live_style_end!(cx, self::style_text_input);
+        }
+        else {
+            live_style_begin!(cx, self::style_button);
+            self.new_file_btn.draw_normal_button(cx, "New");
+            self.new_folder_btn.draw_normal_button(cx, "Folder");
+            self.rename_btn.draw_normal_button(cx, "Rename");
+            self.delete_btn.draw_normal_button(cx, "Delete");
+            live_style_end!(cx, self::style_button);
+        }
     }
-    
+
     pub fn draw_file_panel(&mut self, cx: &mut Cx) {
         self.file_tree.draw_file_tree(cx)
     }
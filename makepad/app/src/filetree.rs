@@ -43,7 +43,21 @@ pub enum FileTreeEvent {
     DragEnd {fe: FingerUpEvent, paths: Vec<String>},
     DragOut,
     SelectFile {path: String},
-    SelectFolder {path: String}
+    SelectFolder {path: String},
+    // an action on the marked node, the file panel asks for a name or a confirmation first
+    Action {action: FileTreeAction, path: String},
+    CreateFile {path: String},
+    CreateFolder {path: String},
+    RenamePath {path: String, new_path: String},
+    DeletePath {path: String}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileTreeAction {
+    NewFile,
+    NewFolder,
+    Rename,
+    Delete
 }
 
 impl FileTree {
@@ -293,8 +307,52 @@ impl FileTree {
         paths
     }
     
+    // the marked node and whether it is a folder
+    pub fn get_marked_node(root: &mut FileNode) -> Option<(String, bool)> {
+        let mut file_walker = FileWalker::new(root);
+        while let Some((_depth, _index, _len, node)) = file_walker.walk() {
+            let is_folder = if let FileNode::Folder {..} = node {true} else {false};
+            let node_draw = if let Some(node_draw) = node.get_draw() {node_draw}else {continue};
+            if node_draw.marked != 0 {
+                return Some((file_walker.current_path(), is_folder));
+            }
+        }
+        None
+    }
+    
+    // new files and folders go next to a marked file, or in a marked folder
+    pub fn marked_action(&mut self, action: FileTreeAction) -> FileTreeEvent {
+        if let Some((path, is_folder)) = Self::get_marked_node(&mut self.root_node) {
+            let path = match action {
+                FileTreeAction::NewFile | FileTreeAction::NewFolder if !is_folder => {
+                    if let Some(pos) = path.rfind('/') {path[0..pos].to_string()} else {return FileTreeEvent::None}
+                }
+                _ => path
+            };
+            return FileTreeEvent::Action {action: action, path: path}
+        }
+        FileTreeEvent::None
+    }
+    
     pub fn handle_file_tree(&mut self, cx: &mut Cx, event: &mut Event) -> FileTreeEvent {
         
+        if let Event::KeyDown(ke) = event.hits(cx, self.view.area(), HitOpt::default()) {
+            let action = match ke.key_code {
+                KeyCode::Delete | KeyCode::Backspace => Some(FileTreeAction::Delete),
+                KeyCode::F2 => Some(FileTreeAction::Rename),
+                KeyCode::KeyN if ke.modifiers.logo || ke.modifiers.control => if ke.modifiers.shift {
+                    Some(FileTreeAction::NewFolder)
+                }
+                else {
+                    Some(FileTreeAction::NewFile)
+                },
+                _ => None
+            };
+            if let Some(action) = action {
+                return self.marked_action(action);
+            }
+        }
+        
         // alright. someone clicking on the tree items.
         let mut file_walker = FileWalker::new(&mut self.root_node);
         let mut counter = 0;
@@ -327,6 +385,8 @@ impl FileTree {
                         select_node = 2;
                     }
                     node_draw.marked = cx.event_id;
+                    // so the file actions can be done with the keyboard
+                    cx.set_key_focus(self.view.area());
                     
                     unmark_nodes = true;
                     node_draw.animator.play_anim(cx, Self::get_over_anim(cx, counter, node_draw.marked != 0));
@@ -12,6 +12,7 @@ pub mod buildmanager;
 pub mod makepadstyle;
 pub mod searchindex;
 pub mod searchresults;
+pub mod testresults;
 
 pub mod codeicon;
 pub mod rusteditor;
@@ -10,6 +10,8 @@ pub struct LogList {
     pub view: ScrollView,
     pub item_draw: LogItemDraw,
     pub list: ListLogic,
+    // the fix buttons drawn last time, with the log item they fix
+    pub fix_buttons: Vec<(Area, usize)>,
 }
 
 #[derive(Clone)]
@@ -21,6 +23,10 @@ pub enum LogListEvent {
     SelectMessages {
         items: String
     },
+    ApplyFix {
+        loc_message: LocMessage,
+        suggestion: LocSuggestion
+    },
     None,
 }
 
@@ -31,6 +37,7 @@ impl LogList {
             list: ListLogic::default()
                 .with_multi_select(true),
             view: ScrollView::new_standard_hv(cx),
+            fix_buttons: Vec::new(),
         }
     }
     
@@ -56,6 +63,17 @@ impl LogList {
             self::color_bg_marked_over: #11466F;
             self::color_bg_selected_over: #3d;
             self::color_bg_odd_over: #38;
+            self::color_fix_bg: #0e639c;
+            self::color_fix_text: #f;
+            
+            self::layout_fix: Layout {
+                walk: Walk {
+                    width: Compute,
+                    height: Compute,
+                    margin: {t: 0., l: 0., r: 4., b: 0.}
+                },
+                padding: {l: 4., t: 1., r: 4., b: 1.},
+            }
             
             self::layout_bg: Layout {
                 walk: Walk {
@@ -130,6 +148,35 @@ impl LogList {
             bm.tail_log_items = false;
         }
         
+        for (area, index) in &self.fix_buttons {
+            match event.hits(cx, *area, HitOpt::default()) {
+                Event::FingerHover(_) => {
+                    cx.set_hover_mouse_cursor(MouseCursor::Hand);
+                },
+                Event::FingerDown(_) => if let Some(loc_message) = bm.log_items.get(*index).and_then( | item | item.get_loc_message()) {
+                    if let Some(suggestion) = loc_message.suggestions.iter().find( | suggestion | suggestion.is_quick_fix()) {
+                        return LogListEvent::ApplyFix {
+                            loc_message: loc_message.clone(),
+                            suggestion: suggestion.clone()
+                        }
+                    }
+                },
+                _ => ()
+            }
+        }
+        
+        // typing into the log goes to the programs that run interactive
+        match event.hits(cx, self.view.area(), HitOpt::default()) {
+            Event::TextInput(te) => bm.program_stdin(makepad_storage, &te.input),
+            Event::KeyDown(ke) => match ke.key_code {
+                KeyCode::Return => bm.program_stdin(makepad_storage, "\n"),
+                KeyCode::Backspace => bm.program_stdin(makepad_storage, "\x7f"),
+                KeyCode::KeyC if ke.modifiers.control => bm.program_stdin(makepad_storage, "\x03"),
+                _ => ()
+            },
+            _ => ()
+        }
+        
         let mut select = ListSelect::None;
         let mut select_at_end = false;
         // global key handle
@@ -197,6 +244,10 @@ impl LogList {
             }
         });
         
+        if let ListEvent::SelectSingle(_) = &le {
+            cx.set_key_focus(self.view.area());
+        }
+        
         match le {
             ListEvent::SelectSingle(select_index) => {
                 self.view.redraw_view(cx);
@@ -275,8 +326,11 @@ impl LogList {
         if self.list.begin_list(cx, &mut self.view, bm.tail_log_items, row_height).is_err() {return}
         
         let mut counter = 0;
+        self.fix_buttons.truncate(0);
         for i in self.list.start_item..self.list.end_item {
-            self.item_draw.draw_log_item(cx, i, &mut self.list.list_items[i], &bm.log_items[i]);
+            if let Some(fix_area) = self.item_draw.draw_log_item(cx, i, &mut self.list.list_items[i], &bm.log_items[i]) {
+                self.fix_buttons.push((fix_area, i));
+            }
             counter += 1;
         }
         
@@ -303,6 +357,7 @@ impl LogList {
 pub struct LogItemDraw {
     pub text: DrawText,
     pub item_bg: DrawColor,
+    pub fix_bg: DrawColor,
     pub code_icon: CodeIcon,
     pub shadow: ScrollShadow,
 }
@@ -311,6 +366,7 @@ impl LogItemDraw {
     pub fn new(cx: &mut Cx) -> Self {
         Self {
             item_bg: DrawColor::new(cx, default_shader!()),
+            fix_bg: DrawColor::new(cx, default_shader!()),
             text: DrawText::new(cx, default_shader!())
                 .with_wrapping(Wrapping::Word),
             code_icon: CodeIcon::new(cx),
@@ -341,7 +397,20 @@ impl LogItemDraw {
         }
     }
     
-    pub fn draw_log_item(&mut self, cx: &mut Cx, index: usize, list_item: &mut ListItem, log_item: &HubLogItem) {
+    // a one click fix in front of the message, only when the compiler is sure about it
+    pub fn draw_log_fix(&mut self, cx: &mut Cx, loc_msg: &LocMessage) -> Option<Area> {
+        if !loc_msg.suggestions.iter().any( | suggestion | suggestion.is_quick_fix()) {
+            return None
+        }
+        self.fix_bg.color = live_vec4!(cx, self::color_fix_bg);
+        self.fix_bg.begin_quad(cx, live_layout!(cx, self::layout_fix));
+        self.text.color = live_vec4!(cx, self::color_fix_text);
+        self.text.draw_text_walk(cx, "fix");
+        self.fix_bg.end_quad(cx);
+        Some(self.fix_bg.area())
+    }
+    
+    pub fn draw_log_item(&mut self, cx: &mut Cx, index: usize, list_item: &mut ListItem, log_item: &HubLogItem) -> Option<Area> {
         
         if list_item.animator.need_init(cx) {
             list_item.animator.init(cx, LogList::get_default_anim(cx, index, false));
@@ -353,6 +422,7 @@ impl LogItemDraw {
         self.item_bg.begin_quad(cx, live_layout!(cx, self::layout_item)); //&self.get_line_layout());
         list_item.area = self.item_bg.area();
         
+        let mut fix_area = None;
         match log_item {
             HubLogItem::LocPanic(loc_msg) => {
                 self.code_icon.draw_icon(cx, CodeIconType::Panic);
@@ -363,12 +433,14 @@ impl LogItemDraw {
             HubLogItem::LocError(loc_msg) => {
                 self.code_icon.draw_icon(cx, CodeIconType::Error);
                 cx.turtle_align_y();
+                fix_area = self.draw_log_fix(cx, loc_msg);
                 self.draw_log_path(cx, &loc_msg.path, loc_msg.line);
                 self.draw_log_body(cx, &loc_msg.body);
             },
             HubLogItem::LocWarning(loc_msg) => {
                 self.code_icon.draw_icon(cx, CodeIconType::Warning);
                 cx.turtle_align_y();
+                fix_area = self.draw_log_fix(cx, loc_msg);
                 self.draw_log_path(cx, &loc_msg.path, loc_msg.line);
                 self.draw_log_body(cx, &loc_msg.body);
             },
@@ -393,6 +465,7 @@ impl LogItemDraw {
         
         self.item_bg.end_quad(cx);
         list_item.area = self.item_bg.area();
+        fix_area
     }
     
     pub fn draw_status_line(&mut self, cx: &mut Cx, counter: usize, bm: &BuildManager) {
@@ -405,7 +478,14 @@ impl LogItemDraw {
         
         self.item_bg.begin_quad(cx, live_layout!(cx, self::layout_item));
         
-        if !bm.is_any_cargo_running() {
+        if !bm.build_timer.is_empty() {
+            // saves are coming in, the build starts when they stop
+            self.code_icon.draw_icon(cx, CodeIconType::Wait);
+            cx.turtle_align_y();
+            self.text.color = live_vec4!(cx, self::color_path);
+            self.text.draw_text_walk(cx, &format!("Waiting for saves ({} changed)", bm.dirty_paths.len()));
+        }
+        else if !bm.is_any_cargo_running() {
             self.text.color = live_vec4!(cx, self::color_path);
             self.code_icon.draw_icon(cx, CodeIconType::Ok);
             cx.turtle_align_y();
@@ -437,6 +517,12 @@ impl LogItemDraw {
                     self.text.draw_text_walk(cx, &format!("{}/{}/{}:{} ", bt.builder, bt.workspace, bt.package, bt.config));
                 }
             }
+            if bm.queued_builds().next().is_some() {
+                self.text.draw_text_walk(cx, "- queued ");
+                for bt in bm.queued_builds() {
+                    self.text.draw_text_walk(cx, &format!("{}/{}/{}:{} ", bt.builder, bt.workspace, bt.package, bt.config));
+                }
+            }
             if bm.exec_when_done {
                 self.text.draw_text_walk(cx, " - starting when done");
             }
@@ -199,6 +199,11 @@ impl MakepadApp {
                                         title: "Log".to_string(),
                                         item: Panel::LogList
                                     },
+                                    DockTab {
+                                        closeable: false,
+                                        title: "Tests".to_string(),
+                                        item: Panel::TestResults
+                                    },
                                 ]
                             }),
                             last: Box::new(DockItem::TabControl {
@@ -315,7 +320,8 @@ impl MakepadApp {
                     }
                 }
                 if let Some(_statusses) = se.signals.get(&self.makepad_storage.settings_changed) {
-                    if self.makepad_storage.settings_old.builders != self.makepad_storage.settings.builders {
+                    if self.makepad_storage.settings_old.builders != self.makepad_storage.settings.builders
+                        || self.makepad_storage.settings_old.build_profiles != self.makepad_storage.settings.build_profiles {
                         self.makepad_storage.reload_builders();
                     }
                     /*
@@ -328,6 +334,9 @@ impl MakepadApp {
                     }
                 }
             },
+            Event::Timer(te) => {
+                self.build_manager.handle_build_timer(cx, te, &mut self.makepad_storage);
+            },
             Event::LiveRecompile(re) => {
                 self.makepad_storage.handle_live_recompile_event(cx, re);
                 self.build_manager.handle_live_recompile_event(cx, re, &mut self.makepad_storage);
@@ -9,16 +9,24 @@ use crate::filetree::*;
 use crate::fileeditor::*;
 use crate::buildmanager::*;
 use std::collections::{HashMap, HashSet, BTreeSet};
+use std::sync::{Arc, Mutex};
 use crate::builder;
 use crate::liveitems::*;
 
 #[derive(Debug, Clone, SerRon, DeRon)]
+#[nserde(default)]
 pub struct MakepadSettings {
     pub build_on_save: bool,
+    // seconds a save waits for more saves before building
+    pub build_debounce: f64,
+    // cargo locks the target dir, so more only helps across workspaces
+    pub max_builds_per_builder: usize,
     pub exec_when_done: bool,
     pub live_on_self: bool,
     pub hub_server: HubServerConfig,
     pub builders: HashMap<String, HubBuilderConfig>,
+    // shared by all builders, a builder's own profiles override these by name
+    pub build_profiles: Vec<BuildProfile>,
     pub builds: Vec<BuildTarget>,
     pub sync: HashMap<String, Vec<String >>,
 }
@@ -30,8 +38,11 @@ impl Default for MakepadSettings {
             exec_when_done: false,
             live_on_self: true,
             build_on_save: true,
+            build_debounce: 0.3,
+            max_builds_per_builder: 1,
             hub_server: HubServerConfig::Offline,
             builders: HashMap::new(),
+            build_profiles: vec![],
             sync: HashMap::new(),
             builds: vec![]
         }
@@ -43,6 +54,8 @@ impl MakepadSettings {
         Self {
             exec_when_done: false,
             build_on_save: true,
+            build_debounce: 0.3,
+            max_builds_per_builder: 1,
             live_on_self: true,
             hub_server: HubServerConfig::Offline,
             builders: {
@@ -53,10 +66,12 @@ impl MakepadSettings {
                         let mut workspace = HashMap::new();
                         workspace.insert("makepad".to_string(), ".".to_string());
                         workspace
-                    }
+                    },
+                    profiles: vec![]
                 });
                 cfg
             },
+            build_profiles: BuildProfile::defaults(),
             sync: {
                 let sync = HashMap::new();
                 //sync.insert("main/makepad".to_string(), vec!["windows/makepad".to_string()]);
@@ -67,11 +82,27 @@ impl MakepadSettings {
                     builder: "main".to_string(),
                     workspace: "makepad".to_string(),
                     package: "webgl_example_wasm".to_string(),
-                    config: "release".to_string()
+                    config: "release".to_string(),
+                    run_options: ProgramOptions::default()
                 }
             ]
         }
     }
+    
+    pub fn builder_config(&self, builder: &str) -> Option<HubBuilderConfig> {
+        let mut config = self.builders.get(builder) ?.clone();
+        let mut profiles = self.build_profiles.clone();
+        for profile in config.profiles {
+            if let Some(shared) = profiles.iter_mut().find( | p | p.name == profile.name) {
+                *shared = profile;
+            }
+            else {
+                profiles.push(profile);
+            }
+        }
+        config.profiles = profiles;
+        Some(config)
+    }
 }
 
 #[derive(Clone, Debug, SerRon, DeRon, PartialEq)]
@@ -79,7 +110,10 @@ pub struct BuildTarget {
     pub builder: String,
     pub workspace: String,
     pub package: String,
-    pub config: String
+    pub config: String,
+    // how the built executable is run, pty and stdin make it interactive
+    #[nserde(default)]
+    pub run_options: ProgramOptions
 }
 
 pub struct MakepadStorage {
@@ -101,6 +135,9 @@ pub struct MakepadStorage {
     pub text_buffer_path_to_id: HashMap<String, MakepadTextBufferId>,
     pub text_buffer_id_to_path: HashMap<MakepadTextBufferId, String>,
     pub text_buffers: Vec<MakepadTextBuffer>,
+    pub syncs: Vec<HubSync>,
+    // the packages of each builder, saves only restart the builds of the package they touch
+    pub packages: HashMap<String, Vec<HubPackage>>,
     pub xr_channel: XRChannel,
 }
 
@@ -110,7 +147,22 @@ pub struct MakepadTextBuffer {
     pub full_path: String,
     pub text_buffer: TextBuffer,
     pub text_buffer_id: MakepadTextBufferId,
-    pub live_items_list: LiveItemsList
+    pub live_items_list: LiveItemsList,
+    // digest of what we last loaded from or saved to disk
+    pub disk_digest: Option<Digest>,
+    // changed on disk while we had unsaved edits, the first save after that is held back
+    pub conflicted: bool,
+    // a save was held back, the next one overwrites what is on disk
+    pub save_held: bool
+}
+
+impl MakepadTextBuffer {
+    pub fn is_dirty(&self) -> bool {
+        if let Some(disk_digest) = &self.disk_digest {
+            return Digest::from_buffer(self.text_buffer.get_as_string().as_bytes()) != *disk_digest
+        }
+        false
+    }
 }
 
 #[derive(Clone, SerBin, DeBin)]
@@ -152,6 +204,8 @@ impl MakepadStorage {
             text_buffer_path_to_id: HashMap::new(),
             text_buffer_id_to_path: HashMap::new(),
             text_buffers: Vec::new(),
+            syncs: Vec::new(),
+            packages: HashMap::new(),
             file_tree_file_read: FileRead::default(),
             state_file_read: FileRead::default(),
             settings_file_read: FileRead::default(),
@@ -399,25 +453,38 @@ impl MakepadStorage {
         }
         
         if let Some(hub_router) = &mut self.hub_router {
-            let digest = Self::read_or_generate_key_ron();
+            let keyring = Self::read_or_generate_keys_ron();
             // start the server
-            self.hub_server = HubServer::start_hub_server(digest, &self.settings.hub_server, hub_router);
+            self.hub_server = HubServer::start_hub_server(Arc::new(Mutex::new(keyring)), &self.settings.hub_server, hub_router, HubLog::All);
+            if let Some(hub_server) = &mut self.hub_server {
+                hub_server.watch_keyring(&hub_config_path("keys.ron"));
+            }
         }
     }
     
-    pub fn read_or_generate_key_ron() -> Digest {
-        // read or generate key.ron
-        if let Ok(utf8_data) = std::fs::read_to_string("key.ron") {
-            if let Ok(digest) = DeRon::deserialize_ron(&utf8_data) {
-                return digest
+    pub fn read_or_generate_keys_ron() -> HubKeyring {
+        // keys.ron holds every builder key the hub accepts, outside the served workspaces
+        let keys_path = hub_config_path("keys.ron");
+        match HubKeyring::load_if_exists(&keys_path) {
+            Ok(Some(keyring)) => return keyring,
+            Ok(None) => (),
+            Err(err) => {
+                // a broken keyring is for the user to fix, until then no builder key is accepted
+                println!("{}, leaving it untouched", err.msg);
+                return HubKeyring::default()
             }
         }
-        let digest = Digest::generate();
-        let utf8_data = digest.serialize_ron();
-        if std::fs::write("key.ron", utf8_data.as_bytes()).is_err() {
-            println!("Cannot generate key.ron");
+        // first start, make a key for builders on this machine and put it in key.ron for them
+        let key_path = hub_config_path("key.ron");
+        let mut keyring = HubKeyring::default();
+        let hub_key = keyring.generate("builder");
+        if keyring.save(&keys_path).is_err() || hub_key.save(&key_path).is_err() {
+            println!("Cannot generate {} and {}", keys_path, key_path);
         }
-        digest
+        else {
+            println!("Generated {}, builders connect with {}", keys_path, key_path);
+        }
+        keyring
     }
     
     pub fn save_state(&mut self, cx: &mut Cx, makepad_state: &MakepadState) {
@@ -425,6 +492,21 @@ impl MakepadStorage {
         cx.file_write("makepad_state.ron", ron.as_bytes());
     }
     
+    // the path and all the places it is synced to
+    pub fn sync_paths(&self, path: &str) -> Vec<String> {
+        let path = self.remap_sync_path(path);
+        let mut paths = vec![path.clone()];
+        for (key, sync_to) in &self.settings.sync {
+            if path.starts_with(key.as_str()) {
+                for sync in sync_to {
+                    paths.push(format!("{}{}", sync, &path[key.len()..]));
+                }
+                break
+            }
+        }
+        paths
+    }
+    
     pub fn remap_sync_path(&self, path: &str) -> String {
         let mut path = path.to_string();
         for (key, sync_to) in &self.settings.sync {
@@ -471,6 +553,9 @@ impl MakepadStorage {
                     read_msg: None,
                     full_path: path.to_string(),
                     text_buffer_id: tb_id,
+                    disk_digest: None,
+                    conflicted: false,
+                    save_held: false,
                     text_buffer: TextBuffer {
                         signal: cx.new_signal(),
                         ..TextBuffer::default()
@@ -507,6 +592,9 @@ impl MakepadStorage {
                     read_msg: Some(msg),
                     full_path: path.to_string(),
                     text_buffer_id: tb_id,
+                    disk_digest: None,
+                    conflicted: false,
+                    save_held: false,
                     text_buffer: TextBuffer {
                         signal: cx.new_signal(),
                         ..TextBuffer::default()
@@ -518,13 +606,27 @@ impl MakepadStorage {
         }
     }
     
-    pub fn text_buffer_file_write(&mut self, cx: &mut Cx, path: &str) {
+    // returns false when the write was held back
+    pub fn text_buffer_file_write(&mut self, cx: &mut Cx, build_manager: &mut BuildManager, path: &str) -> bool {
         if cx.platform_type.is_desktop() {
             if path.find('/').is_some() {
                 if let Some(tb_id) = self.text_buffer_path_to_id.get(path) {
-                    let atb = &self.text_buffers[tb_id.as_index()];
+                    let atb = &mut self.text_buffers[tb_id.as_index()];
+                    // dont silently overwrite a change on disk, saving once more confirms it
+                    if atb.conflicted && !atb.save_held {
+                        atb.save_held = true;
+                        build_manager.add_log_item(cx, Self::file_warning(
+                            path,
+                            format!("{} changed on disk, did not save it. Edit it again to overwrite the version on disk", path)
+                        ));
+                        return false
+                    }
                     let hub_ui = self.hub_ui.as_mut().unwrap();
                     let utf8_data = atb.text_buffer.get_as_string();
+                    // what we write is what is on disk now, a conflict is resolved in our favour
+                    atb.disk_digest = Some(Digest::from_buffer(utf8_data.as_bytes()));
+                    atb.conflicted = false;
+                    atb.save_held = false;
                     fn send_file_write_request(hub_ui: &HubUI, uid: HubUid, path: &str, data: &Vec<u8>) {
                         if let Some(builder_pos) = path.find('/') {
                             let (builder, rest) = path.split_at(builder_pos);
@@ -544,14 +646,10 @@ impl MakepadStorage {
                     let uid = hub_ui.route_send.alloc_uid();
                     let utf8_bytes = utf8_data.into_bytes();
                     send_file_write_request(hub_ui, uid, path, &utf8_bytes);
-                    // lets send our file write to all sync points.
-                    for (sync, points) in &self.settings.sync {
-                        if path.starts_with(sync) {
-                            for point in points {
-                                let mut sync_path = path.to_string();
-                                sync_path.replace_range(0..sync.len(), point);
-                                send_file_write_request(hub_ui, uid, &sync_path, &utf8_bytes);
-                            }
+                    // the sync points only get the blocks that changed
+                    for sync in &mut self.syncs {
+                        if let Some(rel) = sync.source.rel_path(path) {
+                            sync.sync_file(&mut hub_ui.route_send, &rel);
                         }
                     }
                 }
@@ -568,6 +666,32 @@ impl MakepadStorage {
                 }
             }
         }
+        true
+    }
+    
+    // clicking it in the log opens the file
+    fn file_warning(path: &str, body: String) -> HubLogItem {
+        HubLogItem::LocWarning(LocMessage {
+            path: path.to_string(),
+            line: 1,
+            column: 1,
+            body: body,
+            range: None,
+            rendered: None,
+            explanation: None,
+            suggestions: Vec::new()
+        })
+    }
+    
+    fn text_buffer_set_conflicted(cx: &mut Cx, build_manager: &mut BuildManager, mtb: &mut MakepadTextBuffer, path: &str) {
+        if !mtb.conflicted {
+            build_manager.add_log_item(cx, Self::file_warning(
+                path,
+                format!("{} changed on disk and has unsaved edits, not reloading it", path)
+            ));
+        }
+        mtb.conflicted = true;
+        mtb.save_held = false;
     }
     
     pub fn text_buffer_handle_file_read(&mut self, cx: &mut Cx, fr: &FileReadEvent) {
@@ -600,6 +724,105 @@ impl MakepadStorage {
         }
     }
     
+    // a file changed on the builder behind our back, reload it unless it is what we wrote ourselves
+    pub fn text_buffer_file_changed(&mut self, cx: &mut Cx, build_manager: &mut BuildManager, path: &str, digest: &Option<Box<Digest >>) {
+        let hub_ui = self.hub_ui.as_mut().unwrap();
+        if let Some(tb_id) = self.text_buffer_path_to_id.get(path) {
+            let mtb = &mut self.text_buffers[tb_id.as_index()];
+            // when its gone the new file tree drops it
+            let digest = if let Some(digest) = digest {digest} else {return};
+            if mtb.read_msg.is_none() {
+                if mtb.disk_digest.as_ref() == Some(&**digest) {
+                    return
+                }
+                // never throw away unsaved edits
+                if mtb.is_dirty() {
+                    Self::text_buffer_set_conflicted(cx, build_manager, mtb, path);
+                    return
+                }
+            }
+            let builder_pos = path.find('/').unwrap();
+            let (builder, rest) = path.split_at(builder_pos);
+            let (_, rest) = rest.split_at(1);
+            let msg = ToHubMsg {
+                to: HubMsgTo::Builder(builder.to_string()),
+                msg: HubMsg::FileReadRequest {
+                    uid: hub_ui.route_send.alloc_uid(),
+                    path: rest.to_string()
+                }
+            };
+            hub_ui.route_send.send(msg.clone());
+            // an older read still in flight is ignored
+            mtb.read_msg = Some(msg);
+        }
+    }
+    
+    // the file tree actions go to the builder, its file watcher sends back the new tree
+    pub fn file_tree_action(&mut self, file_tree_event: &FileTreeEvent) {
+        fn split_builder_path(path: &str) -> Option<(String, String)> {
+            let builder_pos = path.find('/') ?;
+            let (builder, rest) = path.split_at(builder_pos);
+            let (_, rest) = rest.split_at(1);
+            Some((builder.to_string(), rest.to_string()))
+        }
+        let hub_ui = self.hub_ui.as_mut().unwrap();
+        let uid = hub_ui.route_send.alloc_uid();
+        let (builder, msg) = match file_tree_event {
+            FileTreeEvent::CreateFile {path} => if let Some((builder, path)) = split_builder_path(path) {
+                (builder, HubMsg::FileCreateRequest {uid: uid, path: path, data: Vec::new()})
+            } else {return},
+            FileTreeEvent::CreateFolder {path} => if let Some((builder, path)) = split_builder_path(path) {
+                (builder, HubMsg::FileMkdirRequest {uid: uid, path: path})
+            } else {return},
+            FileTreeEvent::DeletePath {path} => if let Some((builder, path)) = split_builder_path(path) {
+                (builder, HubMsg::FileDeleteRequest {uid: uid, path: path})
+            } else {return},
+            FileTreeEvent::RenamePath {path, new_path} => match (split_builder_path(path), split_builder_path(new_path)) {
+                (Some((builder, path)), Some((new_builder, new_path))) if builder == new_builder => {
+                    (builder, HubMsg::FileRenameRequest {uid: uid, path: path, new_path: new_path})
+                },
+                _ => return
+            },
+            _ => return
+        };
+        hub_ui.route_send.send(ToHubMsg {
+            to: HubMsgTo::Builder(builder),
+            msg: msg
+        });
+    }
+    
+    pub fn load_builder_file_tree(
+        &mut self,
+        cx: &mut Cx,
+        tree: &BuilderFileTreeNode,
+        makepad_windows: &mut Vec<MakepadWindow>,
+        makepad_state: &MakepadState
+    ) {
+        // replace a workspace node
+        if let BuilderFileTreeNode::Folder {name, ..} = &tree {
+            let workspace = name.clone();
+            // insert each filetree at the right childnode
+            for (window_index, window) in makepad_windows.iter_mut().enumerate() {
+                let mut paths = Vec::new();
+                if let FileNode::Folder {folder, ..} = &mut window.file_panel.file_tree.root_node {
+                    for node in folder.iter_mut() {
+                        if let FileNode::Folder {name, ..} = node {
+                            if *name == workspace {
+                                *node = hub_to_tree(&tree, "", &mut paths);
+                                break
+                            }
+                        }
+                    }
+                }
+                // lets load the file
+                for path in &paths {
+                    self.text_buffer_from_path(cx, path);
+                }
+                window.file_panel.file_tree.load_open_folders(cx, &makepad_state.windows[window_index].open_folders);
+            }
+        }
+    }
+    
     pub fn reload_builders(&mut self) {
         let hub_ui = self.hub_ui.as_mut().unwrap();
         let uid = hub_ui.route_send.alloc_uid();
@@ -620,6 +843,11 @@ impl MakepadStorage {
         build_manager: &mut BuildManager
     ) {
         let hub_ui = self.hub_ui.as_mut().unwrap();
+        for sync in &mut self.syncs {
+            for item in sync.handle_hub_msg(&mut hub_ui.route_send, htc) {
+                build_manager.add_log_item(cx, item);
+            }
+        }
         // only in ConnectUI of ourselves do we list the workspaces
         match &htc.msg {
             // our own connectUI message, means we are ready to talk to the hub
@@ -638,10 +866,10 @@ impl MakepadStorage {
                 for builder in builders {
                     // lets look up a workspace and configure it!
                     // lets config it
-                    if let Some(builder_config) = self.settings.builders.get(builder) {
+                    if let Some(builder_config) = self.settings.builder_config(builder) {
                         hub_ui.route_send.send(ToHubMsg {
                             to: HubMsgTo::Builder(builder.clone()),
-                            msg: HubMsg::BuilderConfig {uid: uid, config: builder_config.clone()}
+                            msg: HubMsg::BuilderConfig {uid: uid, config: builder_config}
                         });
                     }
                     hub_ui.route_send.send(ToHubMsg {
@@ -654,6 +882,28 @@ impl MakepadStorage {
                     });
                 }
                 self.builders_request_uid = uid;
+                // mirror the sync points of the builders that are around, after they got their config
+                for (source, points) in &self.settings.sync {
+                    for point in points {
+                        let (source, target) = match (HubSyncPoint::from_path(source), HubSyncPoint::from_path(point)) {
+                            (Some(source), Some(target)) => (source, target),
+                            _ => continue
+                        };
+                        if !builders.contains(&source.builder) || !builders.contains(&target.builder) {
+                            continue
+                        }
+                        let pos = if let Some(pos) = self.syncs.iter().position( | sync | sync.source == source && sync.target == target) {pos} else {
+                            let mut sync = HubSync::new(source, target);
+                            // without the last baseline every edit on the target looks new
+                            if let Err(err) = sync.load_baseline(&hub_config_path(&sync.baseline_file_name())) {
+                                build_manager.add_log_item(cx, HubLogItem::Error(err.msg));
+                            }
+                            self.syncs.push(sync);
+                            self.syncs.len() - 1
+                        };
+                        self.syncs[pos].start(&mut hub_ui.route_send);
+                    }
+                }
                 // add all workspace nodes
                 for window in makepad_windows {
                     window.file_panel.file_tree.root_node = FileNode::Folder {
@@ -681,30 +931,24 @@ impl MakepadStorage {
                     }
                 }
             },
+            HubMsg::ListPackagesResponse {uid, builder, packages} => if *uid == self.builders_request_uid {
+                self.packages.insert(builder.clone(), packages.clone());
+            },
             HubMsg::BuilderFileTreeResponse {uid, tree} => if *uid == self.builders_request_uid {
-                // replace a workspace node
-                if let BuilderFileTreeNode::Folder {name, ..} = &tree {
-                    let workspace = name.clone();
-                    // insert each filetree at the right childnode
-                    for (window_index, window) in makepad_windows.iter_mut().enumerate() {
-                        let mut paths = Vec::new();
-                        if let FileNode::Folder {folder, ..} = &mut window.file_panel.file_tree.root_node {
-                            for node in folder.iter_mut() {
-                                if let FileNode::Folder {name, ..} = node {
-                                    if *name == workspace {
-                                        *node = hub_to_tree(&tree, "", &mut paths);
-                                        break
-                                    }
-                                }
-                            }
-                        }
-                        // lets load the file
-                        for path in &paths {
-                            self.text_buffer_from_path(cx, path);
-                        }
-                        window.file_panel.file_tree.load_open_folders(cx, &makepad_state.windows[window_index].open_folders);
+                self.load_builder_file_tree(cx, tree, makepad_windows, makepad_state);
+            },
+            HubMsg::FileTreeChanged {tree} => {
+                self.load_builder_file_tree(cx, tree, makepad_windows, makepad_state);
+            },
+            HubMsg::FileChanged {builder, path, digest} => {
+                let path = format!("{}/{}", builder, path);
+                // edits from outside makepad get mirrored too
+                for sync in &mut self.syncs {
+                    if let Some(rel) = sync.source.rel_path(&path) {
+                        sync.sync_file(&mut hub_ui.route_send, &rel);
                     }
                 }
+                self.text_buffer_file_changed(cx, build_manager, &path, digest);
             },
             HubMsg::FileReadResponse {uid, data, ..} => {
                 for (path, tb_id) in &mut self.text_buffer_path_to_id {
@@ -714,7 +958,15 @@ impl MakepadStorage {
                             if *read_uid == *uid {
                                 mtb.read_msg = None;
                                 if let Some(data) = data {
+                                    // edited while the reload was on its way
+                                    if mtb.is_dirty() {
+                                        Self::text_buffer_set_conflicted(cx, build_manager, mtb, path);
+                                        break
+                                    }
                                     if let Ok(utf8_data) = std::str::from_utf8(data) {
+                                        mtb.disk_digest = Some(Digest::from_buffer(data));
+                                        mtb.conflicted = false;
+                                        mtb.save_held = false;
                                         mtb.text_buffer.load_from_utf8(&utf8_data);
                                         mtb.text_buffer.send_textbuffer_loaded_signal(cx);
                                         // initialize the live blocks from the live-block target
@@ -723,8 +975,10 @@ impl MakepadStorage {
                                     }
                                 }
                                 else {
-                                    // DO SOMETHING HERE
-                                    println!("FILE READ FAILED!")
+                                    build_manager.add_log_item(cx, Self::file_warning(
+                                        path,
+                                        format!("Cannot reload {}, it changed on disk but reading it failed", path)
+                                    ));
                                 }
                                 break
                             }
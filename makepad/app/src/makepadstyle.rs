@@ -1,10 +1,12 @@
 use makepad_render::*;
 
 use crate::filetree::*;
+use crate::filepanel::*;
 use crate::loglist::*;
 use crate::homepage::*;
 use crate::codeicon::*;
 use crate::searchresults::*;
+use crate::testresults::*;
 use crate::itemdisplay::*;
 use crate::liveitems::*;
 use crate::colorpicker::*;
@@ -14,8 +16,10 @@ pub fn set_makepad_style(cx: &mut Cx) {
     CodeIcon::style(cx);
     HomePage::style(cx);
     FileTree::style(cx);
+    FilePanel::style(cx);
     LogList::style(cx);
     SearchResults::style(cx);
+    TestResults::style(cx);
     ItemDisplay::style(cx);
     ColorPicker::style(cx);
     FloatSlider::style(cx);
@@ -17,6 +17,7 @@ use crate::keyboard::*;
 use crate::buildmanager::*;
 use crate::homepage::*;
 use crate::searchresults::*;
+use crate::testresults::*;
 use crate::rusteditor::*;
 use crate::jseditor::*;
 use crate::plaineditor::*;
@@ -24,6 +25,7 @@ use crate::plaineditor::*;
 #[derive(Debug, Clone, SerRon, DeRon)]
 pub enum Panel {
     LogList,
+    TestResults,
     SearchResults,
     ItemDisplay,
     Keyboard,
@@ -42,6 +44,7 @@ pub struct MakepadWindow {
     pub item_display: ItemDisplay,
     pub log_list: LogList,
     pub search_results: SearchResults,
+    pub test_results: TestResults,
     pub keyboard: Keyboard,
     pub file_editors: FileEditors,
     pub xr_control: XRControl,
@@ -81,6 +84,7 @@ impl MakepadWindow {
             item_display: ItemDisplay::new(cx),
             log_list: LogList::new(cx),
             search_results: SearchResults::new(cx),
+            test_results: TestResults::new(cx),
             file_panel: FilePanel::new(cx),
             xr_control: XRControl::new(cx),
             world_view: WorldView::new(cx),
@@ -163,13 +167,7 @@ impl MakepadWindow {
                     if build_manager.active_builds.len() == 0 {
                         build_manager.restart_build(cx, makepad_storage);
                     }
-                    let mut clear = true;
-                    for ab in &build_manager.active_builds {
-                        if !ab.build_uid.is_none() {
-                            clear = false;
-                        }
-                    }
-                    if clear {
+                    if !build_manager.is_any_cargo_running() {
                         build_manager.tail_log_items = true;
                         build_manager.log_items.truncate(0);
                     }
@@ -217,9 +215,34 @@ impl MakepadWindow {
                             self.item_display.display_plain_text(cx, &items);
                             show_item_display_tab = true;
                         }
+                        LogListEvent::ApplyFix {loc_message, suggestion} => {
+                            // goes through the undo stack like any edit, and gets saved like one
+                            let path = makepad_storage.remap_sync_path(&loc_message.path);
+                            let atb = makepad_storage.text_buffer_from_path(cx, &path);
+                            let fix = BuildManager::loc_suggestion_to_fix(&atb.text_buffer, &suggestion);
+                            let mut cursors = TextCursorSet::new();
+                            cursors.apply_fix(&fix, &mut atb.text_buffer);
+                            cx.send_signal(atb.text_buffer.signal, TextBuffer::status_data_update());
+                            if makepad_storage.text_buffer_file_write(cx, build_manager, &path) && makepad_storage.settings.build_on_save {
+                                build_manager.schedule_build(cx, makepad_storage, &path);
+                            }
+                        }
                         _ => ()
                     }
                 }
+                Panel::TestResults => {
+                    match self.test_results.handle_test_results(cx, event, makepad_storage, build_manager) {
+                        TestResultsEvent::SelectTest {loc_message, jump_to_offset, output} => {
+                            if let Some(loc_message) = loc_message {
+                                file_tree_event = FileTreeEvent::SelectFile {path: makepad_storage.remap_sync_path(&loc_message.path)};
+                                set_last_cursor = Some((jump_to_offset, jump_to_offset));
+                            }
+                            self.item_display.display_plain_text(cx, &output);
+                            show_item_display_tab = true;
+                        },
+                        TestResultsEvent::None => ()
+                    }
+                },
                 Panel::WorldView => {
                     
                 },
@@ -282,9 +305,8 @@ impl MakepadWindow {
                                 //do_search = Some((None, MakepadTextBufferId(0), false, false));
                             }
                             TextEditorEvent::LagChange => {
-                                makepad_storage.text_buffer_file_write(cx, path);
-                                if makepad_storage.settings.build_on_save {
-                                    build_manager.restart_build(cx, makepad_storage);
+                                if makepad_storage.text_buffer_file_write(cx, build_manager, path) && makepad_storage.settings.build_on_save {
+                                    build_manager.schedule_build(cx, makepad_storage, path);
                                 }
                             },
                             TextEditorEvent::CursorMove => {
@@ -378,6 +400,17 @@ impl MakepadWindow {
                 makepad_state.windows[window_index].open_folders = self.file_panel.file_tree.save_open_folders();
                 makepad_storage.save_state(cx, makepad_state);
             }
+            FileTreeEvent::CreateFile {ref path} => {
+                makepad_storage.file_tree_action(&file_tree_event);
+                // the read goes to the builder after the create, so the new file opens empty
+                if self.focus_or_new_editor(cx, window_index, makepad_state, path, None) {
+                    makepad_storage.save_state(cx, makepad_state);
+                    self.ensure_unique_tab_title_for_file_editors(cx, window_index, makepad_state);
+                }
+            }
+            FileTreeEvent::CreateFolder {..} | FileTreeEvent::RenamePath {..} | FileTreeEvent::DeletePath {..} => {
+                makepad_storage.file_tree_action(&file_tree_event);
+            }
             _ => {}
         }
         
@@ -466,6 +499,9 @@ impl MakepadWindow {
                 Panel::LogList => {
                     self.log_list.draw_log_list(cx, build_manager);
                 }
+                Panel::TestResults => {
+                    self.test_results.draw_test_results(cx, build_manager);
+                }
                 Panel::SearchResults => {
                     search_results.draw_search_results(cx, makepad_storage);
                 }
@@ -0,0 +1,298 @@
+use makepad_render::*;
+use makepad_widget::*;
+use makepad_hub::*;
+use crate::makepadstorage::*;
+use crate::buildmanager::*;
+use crate::codeicon::*;
+
+#[derive(Clone)]
+pub struct TestResults {
+    pub view: ScrollView,
+    pub item_draw: TestResultDraw,
+    pub list: ListLogic,
+}
+
+#[derive(Clone)]
+pub enum TestResultsEvent {
+    SelectTest {
+        loc_message: Option<LocMessage>,
+        jump_to_offset: usize,
+        output: String
+    },
+    None,
+}
+
+impl TestResults {
+    pub fn new(cx: &mut Cx) -> Self {
+        Self {
+            item_draw: TestResultDraw::new(cx),
+            list: ListLogic::default(),
+            view: ScrollView::new_standard_hv(cx),
+        }
+    }
+    
+    pub fn style(cx: &mut Cx) {
+        live_body!(cx, {
+            self::layout_item: Layout {
+                walk: Walk {width: Fill, height: Fix(20.)},
+                align: {fx: 0.0, fy: 0.5},
+                padding: all(0),
+                line_wrap: None,
+            }
+            
+            self::text_style_item: TextStyle {
+                ..makepad_widget::widgetstyle::text_style_normal
+            }
+            
+            self::color_suite: #9;
+            self::color_name: #b;
+            self::color_bg_marked: #x11466E;
+            self::color_bg_selected: #28;
+            self::color_bg_odd: #25;
+            self::color_bg_marked_over: #11466F;
+            self::color_bg_selected_over: #3d;
+            self::color_bg_odd_over: #38;
+        })
+    }
+    
+    pub fn get_default_anim(cx: &Cx, counter: usize, marked: bool) -> Anim {
+        let default_color = if marked {
+            live_vec4!(cx, self::color_bg_marked)
+        } else if counter & 1 == 0 {
+            live_vec4!(cx, self::color_bg_selected)
+        } else {
+            live_vec4!(cx, self::color_bg_odd)
+        };
+        Anim {
+            play: Play::Chain {duration: 0.01},
+            tracks: vec![
+                Track::Vec4 {
+                    bind_to: live_item_id!(makepad_render::drawcolor::DrawColor::color),
+                    ease: Ease::Lin,
+                    keys: vec![
+                        (1.0, default_color)
+                    ],
+                    cut_init: None
+                }
+            ]
+        }
+    }
+    
+    pub fn get_over_anim(cx: &Cx, counter: usize, marked: bool) -> Anim {
+        let over_color = if marked {
+            live_vec4!(cx, self::color_bg_marked_over)
+        } else if counter & 1 == 0 {
+            live_vec4!(cx, self::color_bg_selected_over)
+        } else {
+            live_vec4!(cx, self::color_bg_odd_over)
+        };
+        Anim {
+            play: Play::Cut {duration: 0.02},
+            tracks: vec![
+                Track::Vec4 {
+                    bind_to: live_item_id!(makepad_render::drawcolor::DrawColor::color),
+                    ease: Ease::Lin,
+                    keys: vec![
+                        (0., over_color),
+                        (1., over_color)
+                    ],
+                    cut_init: None
+                }
+            ]
+        }
+    }
+    
+    pub fn handle_test_results(&mut self, cx: &mut Cx, event: &mut Event, makepad_storage: &mut MakepadStorage, bm: &mut BuildManager) -> TestResultsEvent {
+        
+        self.list.set_list_len(bm.test_results.len());
+        
+        self.list.handle_list_scroll_bars(cx, event, &mut self.view);
+        
+        if let Event::Signal(se) = event {
+            if let Some(_) = se.signals.get(&bm.signal) {
+                self.view.redraw_view(cx);
+            }
+        }
+        
+        let item_draw = &mut self.item_draw;
+        let le = self.list.handle_list_logic(cx, event, ListSelect::None, false, | cx, item_event, item, item_index | match item_event {
+            ListLogicEvent::Animate(ae) => {
+                item_draw.animate(cx, item.area, &mut item.animator, ae.time);
+            },
+            ListLogicEvent::AnimEnded => {
+                item.animator.end();
+            },
+            ListLogicEvent::Select => {
+                item.animator.play_anim(cx, Self::get_over_anim(cx, item_index, true));
+            },
+            ListLogicEvent::Deselect => {
+                item.animator.play_anim(cx, Self::get_default_anim(cx, item_index, false));
+            },
+            ListLogicEvent::Cleanup => {
+                item.animator.play_anim(cx, Self::get_default_anim(cx, item_index, item.is_selected));
+            },
+            ListLogicEvent::Over => {
+                item.animator.play_anim(cx, Self::get_over_anim(cx, item_index, item.is_selected));
+            },
+            ListLogicEvent::Out => {
+                item.animator.play_anim(cx, Self::get_default_anim(cx, item_index, item.is_selected));
+            }
+        });
+        
+        match le {
+            ListEvent::SelectSingle(select_index) => {
+                self.view.redraw_view(cx);
+                let test = &bm.test_results[select_index];
+                let output = if let Some(reason) = &test.reason {
+                    format!("{}\n{}", reason, test.stdout)
+                }
+                else {
+                    test.stdout.clone()
+                };
+                // failing assertions jump to where they panicked
+                let jump_to_offset = if let Some(panic) = &test.panic {
+                    let text_buffer = &makepad_storage.text_buffer_from_path(cx, &makepad_storage.remap_sync_path(&panic.path)).text_buffer;
+                    text_buffer.text_pos_to_offset(TextPos {row: panic.line.max(1) - 1, col: panic.column.max(1) - 1})
+                }
+                else {
+                    0
+                };
+                TestResultsEvent::SelectTest {
+                    loc_message: test.panic.clone(),
+                    jump_to_offset: jump_to_offset,
+                    output: output
+                }
+            },
+            _ => TestResultsEvent::None
+        }
+    }
+    
+    pub fn draw_test_results(&mut self, cx: &mut Cx, bm: &BuildManager) {
+        
+        self.list.set_list_len(bm.test_results.len());
+        
+        self.item_draw.text.text_style = live_text_style!(cx, self::text_style_item);
+        
+        let row_height = live_layout!(cx, self::layout_item).walk.height.fixed();
+        
+        if self.list.begin_list(cx, &mut self.view, false, row_height).is_err() {return}
+        
+        let mut counter = 0;
+        for i in self.list.start_item..self.list.end_item {
+            self.item_draw.draw_test_result(cx, i, &mut self.list.list_items[i], &bm.test_results[i]);
+            counter += 1;
+        }
+        
+        self.list.walk_turtle_to_end(cx, row_height);
+        
+        self.item_draw.draw_status_line(cx, counter, &bm.test_results);
+        counter += 1;
+        
+        for _ in (self.list.end_item + 1)..self.list.end_fill {
+            self.item_draw.draw_filler(cx, counter);
+            counter += 1;
+        }
+        
+        self.item_draw.shadow.draw_shadow_left(cx);
+        self.item_draw.shadow.draw_shadow_top(cx);
+        
+        self.list.end_list(cx, &mut self.view);
+    }
+}
+
+#[derive(Clone)]
+pub struct TestResultDraw {
+    pub text: DrawText,
+    pub item_bg: DrawColor,
+    pub code_icon: CodeIcon,
+    pub shadow: ScrollShadow,
+}
+
+impl TestResultDraw {
+    pub fn new(cx: &mut Cx) -> Self {
+        Self {
+            item_bg: DrawColor::new(cx, default_shader!()),
+            text: DrawText::new(cx, default_shader!())
+                .with_wrapping(Wrapping::Word),
+            code_icon: CodeIcon::new(cx),
+            shadow: ScrollShadow::new(cx)
+                .with_draw_depth(0.01)
+        }
+    }
+    
+    pub fn animate(&mut self, cx: &mut Cx, area: Area, animator: &mut Animator, time: f64) {
+        self.item_bg.set_area(area);
+        self.item_bg.animate(cx, animator, time);
+    }
+    
+    pub fn draw_test_result(&mut self, cx: &mut Cx, index: usize, list_item: &mut ListItem, test: &TestResult) {
+        if list_item.animator.need_init(cx) {
+            list_item.animator.init(cx, TestResults::get_default_anim(cx, index, false));
+        }
+        self.item_bg.last_animate(&list_item.animator);
+        
+        self.item_bg.set_area(list_item.area);
+        self.item_bg.begin_quad(cx, live_layout!(cx, self::layout_item));
+        
+        self.code_icon.draw_icon(cx, match test.state {
+            TestState::Running => CodeIconType::Wait,
+            TestState::Passed => CodeIconType::Ok,
+            TestState::Failed => CodeIconType::Error,
+            TestState::Ignored => CodeIconType::Warning,
+        });
+        cx.turtle_align_y();
+        self.text.color = live_vec4!(cx, self::color_suite);
+        self.text.draw_text_walk(cx, &format!("{} - ", test.suite));
+        self.text.color = live_vec4!(cx, self::color_name);
+        self.text.draw_text_walk(cx, &test.name);
+        if let Some(panic) = &test.panic {
+            self.text.color = live_vec4!(cx, self::color_suite);
+            self.text.draw_text_walk(cx, &format!(" - {}:{} {}", panic.path, panic.line, panic.body));
+        }
+        
+        self.item_bg.end_quad(cx);
+        list_item.area = self.item_bg.area();
+    }
+    
+    pub fn draw_status_line(&mut self, cx: &mut Cx, counter: usize, tests: &[TestResult]) {
+        self.item_bg.color = if counter & 1 == 0 {
+            live_vec4!(cx, self::color_bg_selected)
+        }else {
+            live_vec4!(cx, self::color_bg_odd)
+        };
+        
+        self.item_bg.begin_quad(cx, live_layout!(cx, self::layout_item));
+        let count = | state | tests.iter().filter( | test | test.state == state).count();
+        let running = count(TestState::Running);
+        self.code_icon.draw_icon(cx, if running > 0 {
+            CodeIconType::Wait
+        } else if count(TestState::Failed) > 0 {
+            CodeIconType::Error
+        } else {
+            CodeIconType::Ok
+        });
+        cx.turtle_align_y();
+        self.text.color = live_vec4!(cx, self::color_suite);
+        self.text.draw_text_walk(cx, &format!(
+            "{} passed, {} failed, {} ignored",
+            count(TestState::Passed),
+            count(TestState::Failed),
+            count(TestState::Ignored)
+        ));
+        if running > 0 {
+            self.text.draw_text_walk(cx, &format!(" - {} running", running));
+        }
+        self.item_bg.end_quad(cx);
+    }
+    
+    pub fn draw_filler(&mut self, cx: &mut Cx, counter: usize) {
+        let view_total = cx.get_turtle_bounds();
+        self.item_bg.color = if counter & 1 == 0 {
+            live_vec4!(cx, self::color_bg_selected)
+        } else {
+            live_vec4!(cx, self::color_bg_odd)
+        };
+        self.item_bg.draw_quad_walk(cx, live_layout!(cx, self::layout_item).walk);
+        cx.set_turtle_bounds(view_total);
+    }
+}
@@ -1,9 +1,10 @@
-use std::net::{TcpStream, Shutdown};
+use std::net::Shutdown;
 use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex, mpsc};
 use std::collections::HashMap;
 use crate::httputil::*; 
 use crate::websocket::*;
+use crate::server::{HttpRequest, HttpResponse, HttpReply, HttpStream};
 use std::io::prelude::*;
 
 
@@ -35,9 +36,20 @@ impl WebSocketChannels{
         }
     }
     
+    // answers a websocket upgrade from the http server with this channel set
+    pub fn upgrade(self, request: &HttpRequest) -> HttpReply {
+        let key = if let Some(key) = request.header("Sec-WebSocket-Key") {key.to_string()} else {
+            return HttpReply::Response(HttpResponse::new(400))
+        };
+        let url = request.path.clone();
+        HttpReply::Upgrade(Box::new(move | mut tcp_stream | {
+            self.handle_websocket(&mut tcp_stream, &url, &key)
+        }))
+    }
+    
     pub fn handle_websocket(
         self,
-        tcp_stream: &mut TcpStream,
+        tcp_stream: &mut Box<dyn HttpStream>,
         url: &str,
         key: &str,
     ) {
@@ -46,9 +58,10 @@ impl WebSocketChannels{
         write_bytes_to_tcp_stream_no_error(tcp_stream, upgrade_response.as_bytes());
         
         // start a thread for the write side
-        let mut write_tcp_stream = tcp_stream.try_clone().unwrap();
+        let mut write_tcp_stream = if let Ok(s) = tcp_stream.try_clone_box() {s} else {return};
 
         let (tx_socket, rx_socket) = mpsc::channel::<Vec<u8>>();
+        let tx_reply = tx_socket.clone();
         
         let (socket_id, tx_bus) = self.add_socket_tx(url, tx_socket);
         
@@ -83,11 +96,23 @@ impl WebSocketChannels{
                                 tx_bus.send((socket_id.clone(),data)).unwrap();
                                 //let s = std::str::from_utf8(&data);
                             },
+                            WebSocketResult::Text(text)=>{
+                                tx_bus.send((socket_id.clone(),text.into_bytes())).unwrap();
+                            },
                             WebSocketResult::Error(_)=>{},
-                            WebSocketResult::Close=>{
+                            WebSocketResult::Close(_, _)=>{
                             }
                         }
                     }
+                    // pongs and the close handshake go out through the write thread
+                    for reply in web_socket.take_replies(){
+                        let _ = tx_reply.send(reply);
+                    }
+                    if web_socket.is_closed(){
+                        // the write thread shuts the socket down once the close reply is out
+                        self.remove_socket(url, socket_id);
+                        return
+                    }
                 }
                 Err(_) => {
                     let _ = tcp_stream.shutdown(Shutdown::Both);
@@ -3,7 +3,7 @@ use std::io::Write;
 use std::io::BufReader;
 use std::io::prelude::*;
 
-pub fn write_bytes_to_tcp_stream_no_error(tcp_stream: &mut TcpStream, bytes: &[u8]) {
+pub fn write_bytes_to_tcp_stream_no_error<W: Write + ?Sized>(tcp_stream: &mut W, bytes: &[u8]) {
     let bytes_total = bytes.len();
     let mut bytes_left = bytes_total;
     while bytes_left > 0 {
@@ -26,6 +26,61 @@ pub fn http_error_out(mut tcp_stream: TcpStream, code: usize) {
 }
 
 
+pub fn mime_type_for_path(path: &str) -> &'static str {
+    let ext = match path.rfind('.') {
+        Some(pos) if !path[pos..].contains('/') => path[pos + 1..].to_ascii_lowercase(),
+        _ => String::new()
+    };
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "wasm" => "application/wasm",
+        "ron" | "rs" | "toml" | "txt" | "md" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream"
+    }
+}
+
+// formats seconds since the unix epoch as an IMF-fixdate, the format http headers use
+pub fn http_date(unix_secs: u64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let days = unix_secs / 86400;
+    let secs = unix_secs % 86400;
+    // civil from days, see Howard Hinnant's date algorithms
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = yoe + era * 400 + if month <= 2 {1} else {0};
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
 pub fn split_header_line<'a>(inp: &'a str, what: &str) -> Option<&'a str> {
     let mut what_lc = what.to_string();
     what_lc.make_ascii_lowercase();
@@ -1,4 +1,5 @@
  pub mod channel;
  pub mod digest;
  pub mod httputil;
+ pub mod server;
  pub mod websocket;
@@ -0,0 +1,541 @@
+use std::net::{TcpListener, TcpStream, SocketAddr, Shutdown};
+use std::sync::{mpsc, Arc, Mutex};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::time::Duration;
+use std::panic::{self, AssertUnwindSafe};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    // percent decoded path without the query string
+    pub path: String,
+    pub search: Option<String>,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        for (key, value) in &self.headers {
+            if key.eq_ignore_ascii_case(name) {
+                return Some(value)
+            }
+        }
+        None
+    }
+
+    pub fn path_no_slash(&self) -> &str {
+        &self.path[1..]
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header("Connection").map( | v | v.to_ascii_lowercase()).unwrap_or_default();
+        if self.version == "HTTP/1.0" {
+            connection.contains("keep-alive")
+        }
+        else {
+            !connection.contains("close")
+        }
+    }
+
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.header("Sec-WebSocket-Key").is_some()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16) -> HttpResponse {
+        HttpResponse {status, headers: Vec::new(), body: Vec::new()}
+    }
+
+    pub fn ok(content_type: &str, body: Vec<u8>) -> HttpResponse {
+        HttpResponse::new(200).with_header("Content-Type", content_type).with_body(body)
+    }
+
+    pub fn not_found() -> HttpResponse {
+        HttpResponse::new(404)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> HttpResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> HttpResponse {
+        self.body = body;
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        for (key, value) in &self.headers {
+            if key.eq_ignore_ascii_case(name) {
+                return Some(value)
+            }
+        }
+        None
+    }
+}
+
+pub enum HttpReply {
+    Response(HttpResponse),
+    // the handler takes over the connection on its own thread, used for websockets
+    Upgrade(Box<dyn FnOnce(Box<dyn HttpStream>) + Send>),
+}
+
+// what a connection runs over, a plain TcpStream or something like tls wrapped around one
+pub trait HttpStream: Read + Write + Send {
+    // a second handle on the same connection, for a separate write thread
+    fn try_clone_box(&self) -> std::io::Result<Box<dyn HttpStream >>;
+    
+    fn tcp_stream(&self) -> &TcpStream;
+    
+    fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        self.tcp_stream().shutdown(how)
+    }
+}
+
+impl HttpStream for TcpStream {
+    fn try_clone_box(&self) -> std::io::Result<Box<dyn HttpStream >> {
+        Ok(Box::new(self.try_clone() ?))
+    }
+    
+    fn tcp_stream(&self) -> &TcpStream {
+        self
+    }
+}
+
+// turns an accepted socket into the stream the server reads requests from
+pub type HttpStreamWrap = Arc<dyn Fn(TcpStream) -> std::io::Result<Box<dyn HttpStream >> + Send + Sync>;
+
+pub trait HttpHandler: Send + Sync {
+    fn handle(&self, request: &HttpRequest) -> HttpReply;
+}
+
+impl<F> HttpHandler for F where F: Fn(&HttpRequest) -> HttpReply + Send + Sync {
+    fn handle(&self, request: &HttpRequest) -> HttpReply {
+        self(request)
+    }
+}
+
+struct HttpRoute {
+    method: String,
+    pattern: String,
+    handler: Arc<dyn HttpHandler>,
+}
+
+// Routes are tried in the order they were added. A pattern ending in * matches
+// every path with that prefix. The method * matches any method, GET routes also
+// answer HEAD, and the method WEBSOCKET only matches websocket upgrade requests.
+#[derive(Clone, Default)]
+pub struct HttpRouter {
+    routes: Arc<Vec<HttpRoute>>,
+}
+
+impl HttpRouter {
+    pub fn new() -> HttpRouter {
+        HttpRouter::default()
+    }
+
+    pub fn add<H: HttpHandler + 'static>(mut self, method: &str, pattern: &str, handler: H) -> HttpRouter {
+        Arc::get_mut(&mut self.routes).expect("Cannot add routes to a running router").push(HttpRoute {
+            method: method.to_string(),
+            pattern: pattern.to_string(),
+            handler: Arc::new(handler)
+        });
+        self
+    }
+
+    fn route_matches(route: &HttpRoute, request: &HttpRequest) -> bool {
+        let method_matches = match route.method.as_str() {
+            "*" => true,
+            "WEBSOCKET" => request.method == "GET" && request.is_websocket_upgrade(),
+            "GET" => (request.method == "GET" || request.method == "HEAD") && !request.is_websocket_upgrade(),
+            method => request.method == method
+        };
+        if !method_matches {
+            return false
+        }
+        if let Some(prefix) = route.pattern.strip_suffix('*') {
+            request.path.starts_with(prefix)
+        }
+        else {
+            request.path == route.pattern
+        }
+    }
+
+    pub fn handle(&self, request: &HttpRequest) -> HttpReply {
+        for route in self.routes.iter() {
+            if Self::route_matches(route, request) {
+                return route.handler.handle(request)
+            }
+        }
+        HttpReply::Response(HttpResponse::not_found())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpServerOptions {
+    pub workers: usize,
+    // connections waiting for a worker before accept blocks
+    pub queue_len: usize,
+    pub keep_alive_timeout: Duration,
+    pub max_header_size: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for HttpServerOptions {
+    fn default() -> HttpServerOptions {
+        HttpServerOptions {
+            workers: 16,
+            queue_len: 64,
+            keep_alive_timeout: Duration::from_secs(5),
+            max_header_size: 64 * 1024,
+            max_body_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Default)]
+struct HttpServerShared {
+    terminate: bool,
+}
+
+pub struct HttpServer {
+    pub listen_address: SocketAddr,
+    listen_thread: Option<std::thread::JoinHandle<() >>,
+    shared: Arc<Mutex<HttpServerShared >>,
+}
+
+impl HttpServer {
+    pub fn start(listen_address: SocketAddr, router: HttpRouter, options: HttpServerOptions) -> std::io::Result<HttpServer> {
+        Self::start_wrapped(listen_address, router, options, Arc::new( | tcp_stream | Ok(Box::new(tcp_stream) as Box<dyn HttpStream>)))
+    }
+
+    // the wrap runs on the worker, so a slow tls handshake only holds up its own connection
+    pub fn start_wrapped(listen_address: SocketAddr, router: HttpRouter, options: HttpServerOptions, wrap: HttpStreamWrap) -> std::io::Result<HttpServer> {
+        let listener = TcpListener::bind(listen_address) ?;
+        // when binding port 0 we want to know what we got
+        let listen_address = listener.local_addr() ?;
+        let shared = Arc::new(Mutex::new(HttpServerShared::default()));
+
+        let (tx_conn, rx_conn) = mpsc::sync_channel::<TcpStream>(options.queue_len);
+        let rx_conn = Arc::new(Mutex::new(rx_conn));
+        for _ in 0..options.workers.max(1) {
+            let rx_conn = Arc::clone(&rx_conn);
+            let router = router.clone();
+            let options = options.clone();
+            let wrap = Arc::clone(&wrap);
+            std::thread::spawn(move || {
+                loop {
+                    let tcp_stream = if let Ok(rx_conn) = rx_conn.lock() {
+                        rx_conn.recv()
+                    }
+                    else {
+                        return
+                    };
+                    match tcp_stream {
+                        // a panic only costs that connection, never the worker
+                        Ok(tcp_stream) => {
+                            let _ = tcp_stream.set_read_timeout(Some(options.keep_alive_timeout));
+                            if let Ok(stream) = wrap(tcp_stream) {
+                                let _ = panic::catch_unwind(AssertUnwindSafe( || Self::handle_connection(stream, &router, &options)));
+                            }
+                        },
+                        Err(_) => return
+                    }
+                }
+            });
+        }
+
+        let listen_thread = {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || {
+                for tcp_stream in listener.incoming() {
+                    if let Ok(shared) = shared.lock() {
+                        if shared.terminate {
+                            return
+                        }
+                    }
+                    if let Ok(tcp_stream) = tcp_stream {
+                        if tx_conn.send(tcp_stream).is_err() {
+                            return
+                        }
+                    }
+                }
+            })
+        };
+
+        Ok(HttpServer {
+            listen_address,
+            listen_thread: Some(listen_thread),
+            shared
+        })
+    }
+
+    pub fn join(&mut self) {
+        if let Some(listen_thread) = self.listen_thread.take() {
+            let _ = listen_thread.join();
+        }
+    }
+
+    pub fn terminate(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.terminate = true;
+        }
+        // wake up the accept loop so it sees the flag
+        if TcpStream::connect(self.listen_address).is_ok() {
+            self.join();
+        }
+        // the workers stop by themselves once their connection ends and the queue is gone
+
+    }
+
+    fn handle_connection(stream: Box<dyn HttpStream>, router: &HttpRouter, options: &HttpServerOptions) {
+        let mut write_stream = if let Ok(s) = stream.try_clone_box() {s} else {return};
+        let mut reader = BufReader::new(stream);
+        loop {
+            let request = match read_http_request(&mut reader, &mut write_stream, options) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(status) => {
+                    let _ = write_http_response(&mut write_stream, "GET", HttpResponse::new(status), false);
+                    break;
+                }
+            };
+            let keep_alive = request.keep_alive();
+            let reply = match panic::catch_unwind(AssertUnwindSafe( || router.handle(&request))) {
+                Ok(reply) => reply,
+                Err(_) => {
+                    let _ = write_http_response(&mut write_stream, &request.method, HttpResponse::new(500), false);
+                    break;
+                }
+            };
+            match reply {
+                HttpReply::Response(response) => {
+                    if write_http_response(&mut write_stream, &request.method, response, keep_alive).is_err() || !keep_alive {
+                        break;
+                    }
+                },
+                HttpReply::Upgrade(upgrade) => {
+                    let _ = write_stream.tcp_stream().set_read_timeout(None);
+                    std::thread::spawn(move || upgrade(write_stream));
+                    return
+                }
+            }
+        }
+        let _ = write_stream.shutdown(Shutdown::Both);
+    }
+}
+
+pub fn http_reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Unknown"
+    }
+}
+
+pub fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3) ?;
+            out.push(u8::from_str_radix(hex, 16).ok() ?);
+            i += 3;
+        }
+        else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+// reads a line including the \r\n, None on a clean end of the connection
+fn read_line_limited<R: BufRead>(reader: &mut R, line: &mut Vec<u8>, limit: usize) -> Result<bool, u16> {
+    line.truncate(0);
+    loop {
+        let (done, used) = {
+            let buf = match reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(_) => return if line.is_empty() {Ok(false)} else {Err(408)}
+            };
+            if buf.is_empty() {
+                return if line.is_empty() {Ok(false)} else {Err(400)}
+            }
+            match buf.iter().position( | b | *b == b'\n') {
+                Some(pos) => {
+                    line.extend_from_slice(&buf[..pos + 1]);
+                    (true, pos + 1)
+                },
+                None => {
+                    line.extend_from_slice(buf);
+                    (false, buf.len())
+                }
+            }
+        };
+        reader.consume(used);
+        if line.len() > limit {
+            return Err(431)
+        }
+        if done {
+            return Ok(true)
+        }
+    }
+}
+
+fn trim_line(line: &[u8]) -> Result<&str, u16> {
+    let line = std::str::from_utf8(line).map_err( | _ | 400u16) ?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]))
+}
+
+// Ok(None) means the client closed or went idle between requests, Err is the status to answer with
+pub fn read_http_request<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, options: &HttpServerOptions) -> Result<Option<HttpRequest>, u16> {
+    let mut line = Vec::new();
+    let mut header_size = 0;
+    // tolerate empty lines between requests
+    loop {
+        if !read_line_limited(reader, &mut line, options.max_header_size) ? {
+            return Ok(None)
+        }
+        if !trim_line(&line) ?.is_empty() {
+            break;
+        }
+    }
+    let request_line = trim_line(&line) ?.to_string();
+    let mut parts = request_line.split(' ');
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().ok_or(400u16) ?;
+    let version = parts.next().unwrap_or("HTTP/1.0").to_string();
+    if method.is_empty() || !version.starts_with("HTTP/1.") {
+        return Err(400)
+    }
+    let (path, search) = match target.find('?') {
+        Some(pos) => (&target[..pos], Some(target[pos..].to_string())),
+        None => (target, None)
+    };
+    let path = percent_decode(path).ok_or(400u16) ?;
+    // nothing gets to walk out of the served tree
+    if !path.starts_with('/') || path.starts_with("//") || path.split('/').any( | seg | seg == "..") || path.contains('\\') {
+        return Err(400)
+    }
+
+    let mut headers = Vec::new();
+    loop {
+        if !read_line_limited(reader, &mut line, options.max_header_size) ? {
+            return Err(400)
+        }
+        header_size += line.len();
+        if header_size > options.max_header_size {
+            return Err(431)
+        }
+        let header_line = trim_line(&line) ?;
+        if header_line.is_empty() {
+            break;
+        }
+        let colon = header_line.find(':').ok_or(400u16) ?;
+        headers.push((header_line[..colon].trim().to_string(), header_line[colon + 1..].trim().to_string()));
+    }
+    let mut request = HttpRequest {method, path, search, version, headers, body: Vec::new()};
+
+    let chunked = request.header("Transfer-Encoding").map( | v | v.to_ascii_lowercase().contains("chunked")).unwrap_or(false);
+    let content_length = match request.header("Content-Length") {
+        Some(len) => Some(len.parse::<usize>().map_err( | _ | 400u16) ?),
+        None => None
+    };
+    if !chunked && content_length.unwrap_or(0) == 0 {
+        return Ok(Some(request))
+    }
+    if content_length.unwrap_or(0) > options.max_body_size {
+        return Err(413)
+    }
+    if request.header("Expect").map( | v | v.eq_ignore_ascii_case("100-continue")).unwrap_or(false) {
+        let _ = writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+    if chunked {
+        loop {
+            if !read_line_limited(reader, &mut line, options.max_header_size) ? {
+                return Err(400)
+            }
+            let size_line = trim_line(&line) ?;
+            let size = size_line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16).map_err( | _ | 400u16) ?;
+            if size == 0 {
+                // skip the trailers
+                loop {
+                    if !read_line_limited(reader, &mut line, options.max_header_size) ? || trim_line(&line) ?.is_empty() {
+                        break;
+                    }
+                }
+                break;
+            }
+            // a chunk size near usize::MAX must not wrap around the limit
+            if request.body.len().checked_add(size).map( | len | len > options.max_body_size).unwrap_or(true) {
+                return Err(413)
+            }
+            let start = request.body.len();
+            request.body.resize(start + size, 0);
+            reader.read_exact(&mut request.body[start..]).map_err( | _ | 400u16) ?;
+            read_line_limited(reader, &mut line, options.max_header_size) ?;
+        }
+    }
+    else if let Some(len) = content_length {
+        request.body.resize(len, 0);
+        reader.read_exact(&mut request.body).map_err( | _ | 400u16) ?;
+    }
+    Ok(Some(request))
+}
+
+pub fn write_http_response<W: Write>(writer: &mut W, method: &str, response: HttpResponse, keep_alive: bool) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, http_reason_phrase(response.status));
+    // these never carry a body, a length would make the client wait for one
+    let no_body = response.status == 204 || response.status == 304;
+    for (name, value) in &response.headers {
+        if no_body && name.eq_ignore_ascii_case("Content-Length") {
+            continue
+        }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !no_body && response.header("Content-Length").is_none() {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    if response.header("Connection").is_none() {
+        head.push_str(if keep_alive {"Connection: keep-alive\r\n"} else {"Connection: close\r\n"});
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes()) ?;
+    if method != "HEAD" && !no_body {
+        writer.write_all(&response.body) ?;
+    }
+    writer.flush()
+}
@@ -1,6 +1,21 @@
 use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
 use crate::digest::{Sha1, base64_encode};
 
+pub const WEBSOCKET_CLOSE_NORMAL: u16 = 1000;
+pub const WEBSOCKET_CLOSE_GOING_AWAY: u16 = 1001;
+pub const WEBSOCKET_CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const WEBSOCKET_CLOSE_NO_STATUS: u16 = 1005;
+pub const WEBSOCKET_CLOSE_INVALID_DATA: u16 = 1007;
+pub const WEBSOCKET_CLOSE_TOO_BIG: u16 = 1009;
+
+const OPCODE_CONTINUATION: u8 = 0;
+const OPCODE_TEXT: u8 = 1;
+const OPCODE_BINARY: u8 = 2;
+const OPCODE_CLOSE: u8 = 8;
+const OPCODE_PING: u8 = 9;
+const OPCODE_PONG: u8 = 10;
+
 #[derive(Debug, PartialEq)]
 pub enum WebSocketState {
     Opcode,
@@ -8,7 +23,8 @@ pub enum WebSocketState {
     Len2,
     Len8,
     Data,
-    Mask
+    Mask,
+    Closed
 }
 
 impl WebSocketState {
@@ -19,7 +35,8 @@ impl WebSocketState {
             WebSocketState::Len2 => 2,
             WebSocketState::Len8 => 8,
             WebSocketState::Data => 0,
-            WebSocketState::Mask => 4
+            WebSocketState::Mask => 4,
+            WebSocketState::Closed => 0
         }
     }
 }
@@ -32,50 +49,123 @@ pub struct WebSocket {
     data_len: usize,
     input_read: usize,
     mask_counter: usize,
-    is_ping: bool,
-    is_pong: bool,
-    is_partial: bool,
+    opcode: u8,
+    is_final: bool,
     is_masked: bool,
+    // a fragmented message being reassembled, message_opcode is 0 when there is none
+    message: Vec<u8>,
+    message_opcode: u8,
+    max_message_size: usize,
+    mask_rng: Option<WebSocketMaskRng>,
+    close_sent: bool,
+    replies: Vec<Vec<u8>>,
     state: WebSocketState
 }
 
+#[derive(Debug, PartialEq)]
 pub enum WebSocketResult {
     Ping(Vec<u8>),
     Pong(Vec<u8>),
     Data(Vec<u8>),
+    Text(String),
     Error(String),
-    Close
+    Close(u16, String)
+}
+
+// frame masks only have to be unpredictable to intermediaries, not cryptographically strong
+pub struct WebSocketMaskRng(u64);
+
+impl WebSocketMaskRng {
+    pub fn new(seed: &[u8]) -> WebSocketMaskRng {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map( | d | d.as_nanos() as u64).unwrap_or(0);
+        let mut state = nanos | 1;
+        for byte in seed {
+            state = (state ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+        WebSocketMaskRng(state | 1)
+    }
+    
+    pub fn next_mask(&mut self) -> [u8; 4] {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 as u32).to_le_bytes()
+    }
 }
 
 pub struct WebSocketMessage{
     pub check_len: usize,
-    pub data:Vec<u8>
+    pub data:Vec<u8>,
+    mask: Option<[u8; 4]>,
+    mask_counter: usize
 }
 
 impl WebSocketMessage{
     pub fn new_binary(len: usize)->WebSocketMessage{
+        Self::new_frame(OPCODE_BINARY, len, None)
+    }
+    
+    pub fn new_text(len: usize)->WebSocketMessage{
+        Self::new_frame(OPCODE_TEXT, len, None)
+    }
+    
+    // clients have to mask every frame they send
+    pub fn new_binary_masked(len: usize, mask: [u8; 4])->WebSocketMessage{
+        Self::new_frame(OPCODE_BINARY, len, Some(mask))
+    }
+    
+    pub fn new_text_masked(len: usize, mask: [u8; 4])->WebSocketMessage{
+        Self::new_frame(OPCODE_TEXT, len, Some(mask))
+    }
+    
+    pub fn new_close(code: u16, reason: &str, mask: Option<[u8; 4]>)->WebSocketMessage{
+        // 1005 means no status was given, so it never goes on the wire
+        if code == WEBSOCKET_CLOSE_NO_STATUS{
+            return Self::new_frame(OPCODE_CLOSE, 0, mask)
+        }
+        let mut msg = Self::new_frame(OPCODE_CLOSE, 2 + reason.len(), mask);
+        msg.append(&code.to_be_bytes());
+        msg.append(reason.as_bytes());
+        msg
+    }
+    
+    pub fn new_frame(opcode: u8, len: usize, mask: Option<[u8; 4]>)->WebSocketMessage{
         let mut data = Vec::new();
-        let check_len;
-        data.push(128 | 2); // binary single message
+        let mut check_len;
+        let mask_bit = if mask.is_some(){128}else{0};
+        data.push(128 | opcode); // single final frame
         if len < 126{
-            data.push(len as u8);
+            data.push(mask_bit | len as u8);
             check_len = len + 2;
         }
         else if len < 65536{
-            data.push(126); 
+            data.push(mask_bit | 126); 
             data.extend_from_slice(&(len as u16).to_be_bytes());
             check_len = len + 4;
         }
         else{
-            data.push(127);
+            data.push(mask_bit | 127);
             data.extend_from_slice(&(len as u64).to_be_bytes());
             check_len = len + 10;
         }
-        WebSocketMessage{data, check_len}
+        if let Some(mask) = mask{
+            data.extend_from_slice(&mask);
+            check_len += 4;
+        }
+        WebSocketMessage{data, check_len, mask, mask_counter: 0}
     }
     
     pub fn append(&mut self, data:&[u8]){
-        self.data.extend_from_slice(data);
+        if let Some(mask) = self.mask{
+            for byte in data{
+                self.data.push(byte ^ mask[self.mask_counter]);
+                self.mask_counter = (self.mask_counter + 1) & 3;
+            }
+        }
+        else{
+            self.data.extend_from_slice(data);
+        }
     }
     
     pub fn take(self)->Vec<u8>{
@@ -97,27 +187,101 @@ impl WebSocket {
             data_len: 0,
             input_read: 0,
             mask_counter: 0,
-            is_ping: false,
-            is_pong: false,
+            opcode: 0,
+            is_final: false,
             is_masked: false,
-            is_partial: false,
+            message: Vec::new(),
+            message_opcode: 0,
+            max_message_size: 64 * 1024 * 1024,
+            mask_rng: None,
+            close_sent: false,
+            replies: Vec::new(),
             state: WebSocketState::Opcode
         }
     }
     
-    pub fn create_upgrade_response(key: &str) -> String {
+    // the client side masks the pongs and closes it queues up
+    pub fn new_client() -> Self {
+        Self {
+            mask_rng: Some(WebSocketMaskRng::new(b"client")),
+            ..Self::new()
+        }
+    }
+    
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+    
+    pub fn accept_key(key: &str) -> String {
         let to_hash = format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key);
         let mut sha1 = Sha1::new();
         sha1.update(to_hash.as_bytes());
         let out_bytes = sha1.finalise();
-        let base64 = base64_encode(&out_bytes);
+        base64_encode(&out_bytes)
+    }
+    
+    pub fn create_upgrade_response(key: &str) -> String {
         let response_ack = format!(
             "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
-            base64
+            Self::accept_key(key)
         );
         response_ack
     }
     
+    // the key only has to be unique per connection, so hashing something that changes will do
+    pub fn create_client_key(seed: &[u8]) -> String {
+        let mut sha1 = Sha1::new();
+        sha1.update(seed);
+        let out_bytes = sha1.finalise();
+        base64_encode(&out_bytes[0..16])
+    }
+    
+    pub fn create_upgrade_request(host: &str, path: &str, key: &str) -> String {
+        format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path,
+            host,
+            key
+        )
+    }
+    
+    // frames the parser wants sent back, pongs for pings and the close handshake
+    pub fn take_replies(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.replies)
+    }
+    
+    pub fn is_closed(&self) -> bool {
+        self.state == WebSocketState::Closed
+    }
+    
+    // starts the close handshake from our side, the returned frame has to be sent
+    pub fn close(&mut self, code: u16, reason: &str) -> Vec<u8> {
+        self.close_sent = true;
+        let mask = self.next_mask();
+        WebSocketMessage::new_close(code, reason, mask).take()
+    }
+    
+    fn next_mask(&mut self) -> Option<[u8; 4]> {
+        self.mask_rng.as_mut().map( | rng | rng.next_mask())
+    }
+    
+    fn reply(&mut self, opcode: u8, data: &[u8]) {
+        let mask = self.next_mask();
+        let mut msg = WebSocketMessage::new_frame(opcode, data.len(), mask);
+        msg.append(data);
+        self.replies.push(msg.take());
+    }
+    
+    fn fail(&mut self, results: &mut Vec<WebSocketResult>, code: u16, error: String) {
+        results.push(WebSocketResult::Error(error));
+        if !self.close_sent {
+            let close = self.close(code, "");
+            self.replies.push(close);
+        }
+        self.to_state(WebSocketState::Closed);
+    }
+    
     fn parse_head(&mut self, input: &[u8]) -> bool {
         while self.head_expected > 0
             && self.input_read < input.len()
@@ -138,9 +302,8 @@ impl WebSocket {
                 self.data.truncate(0);
             }
             WebSocketState::Opcode => {
-                self.is_ping = false;
-                self.is_pong = false;
-                self.is_partial = false;
+                self.opcode = 0;
+                self.is_final = false;
                 self.is_masked = false;
             },
             _ => ()
@@ -150,37 +313,123 @@ impl WebSocket {
         self.state = state;
     }
     
+    fn is_control(&self) -> bool {
+        self.opcode >= OPCODE_CLOSE
+    }
+    
+    fn to_mask_or_data(&mut self, results: &mut Vec<WebSocketResult>) {
+        if !self.is_control() && self.message.len().checked_add(self.data_len).map( | len | len > self.max_message_size).unwrap_or(true) {
+            self.fail(results, WEBSOCKET_CLOSE_TOO_BIG, format!("Message too large, the maximum is {} bytes", self.max_message_size));
+        }
+        else if self.is_masked {
+            self.to_state(WebSocketState::Mask);
+        }
+        else {
+            self.to_state(WebSocketState::Data);
+        }
+    }
+    
+    fn frame_done(&mut self, results: &mut Vec<WebSocketResult>) {
+        let data = std::mem::take(&mut self.data);
+        match self.opcode {
+            OPCODE_PING => {
+                if !self.close_sent {
+                    self.reply(OPCODE_PONG, &data);
+                }
+                results.push(WebSocketResult::Ping(data));
+            },
+            OPCODE_PONG => {
+                results.push(WebSocketResult::Pong(data));
+            },
+            OPCODE_CLOSE => {
+                if data.len() == 1 {
+                    return self.fail(results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Close frame with a truncated status code".to_string());
+                }
+                let (code, reason) = if data.is_empty() {
+                    (WEBSOCKET_CLOSE_NO_STATUS, String::new())
+                }
+                else {
+                    let code = u16::from_be_bytes(data[0..2].try_into().unwrap());
+                    match String::from_utf8(data[2..].to_vec()) {
+                        Ok(reason) => (code, reason),
+                        Err(_) => return self.fail(results, WEBSOCKET_CLOSE_INVALID_DATA, "Close reason is not utf8".to_string())
+                    }
+                };
+                // echo the close if we didnt start it
+                if !self.close_sent {
+                    let close = self.close(code, "");
+                    self.replies.push(close);
+                }
+                results.push(WebSocketResult::Close(code, reason));
+                return self.to_state(WebSocketState::Closed);
+            },
+            _ => {
+                if self.opcode != OPCODE_CONTINUATION {
+                    self.message_opcode = self.opcode;
+                }
+                if self.message.is_empty() && self.is_final {
+                    self.message = data;
+                }
+                else {
+                    self.message.extend_from_slice(&data);
+                }
+                if self.is_final {
+                    let message = std::mem::take(&mut self.message);
+                    let message_opcode = self.message_opcode;
+                    self.message_opcode = 0;
+                    if message_opcode == OPCODE_TEXT {
+                        match String::from_utf8(message) {
+                            Ok(text) => results.push(WebSocketResult::Text(text)),
+                            Err(_) => return self.fail(results, WEBSOCKET_CLOSE_INVALID_DATA, "Text message is not utf8".to_string())
+                        }
+                    }
+                    else {
+                        results.push(WebSocketResult::Data(message));
+                    }
+                }
+            }
+        }
+        self.to_state(WebSocketState::Opcode);
+    }
+    
     pub fn parse(&mut self, input: &[u8]) -> Vec<WebSocketResult> {
         self.input_read = 0;
         let mut results = Vec::new();
         // parse a header
         loop {
             match self.state {
+                WebSocketState::Closed => {
+                    break;
+                },
                 WebSocketState::Opcode => {
                     if self.parse_head(input) {
                         break;
                     }
-                    let opcode = self.head[0] & 15;
-                    if opcode <= 2 {
-                        self.is_partial = (self.head[0] & 128) != 0;
-                        self.to_state(WebSocketState::Len1);
-                    }
-                    else if opcode == 8 {
-                        results.push(WebSocketResult::Close);
+                    if self.head[0] & 0x70 != 0 {
+                        self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Reserved bits set without an extension".to_string());
                         break;
                     }
-                    else if opcode == 9 {
-                        self.is_ping = true;
-                        self.to_state(WebSocketState::Len1);
-                    }
-                    else if opcode == 10 {
-                        self.is_pong = true;
-                        self.to_state(WebSocketState::Len1);
-                    }
-                    else {
-                        results.push(WebSocketResult::Error(format!("Opcode not supported {}", opcode)));
-                        break;
+                    self.opcode = self.head[0] & 15;
+                    self.is_final = (self.head[0] & 128) != 0;
+                    match self.opcode {
+                        OPCODE_CONTINUATION => if self.message_opcode == 0 {
+                            self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Continuation frame without a message to continue".to_string());
+                            break;
+                        },
+                        OPCODE_TEXT | OPCODE_BINARY => if self.message_opcode != 0 {
+                            self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "New message before the fragmented message was finished".to_string());
+                            break;
+                        },
+                        OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG => if !self.is_final {
+                            self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Fragmented control frame".to_string());
+                            break;
+                        },
+                        opcode => {
+                            self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, format!("Opcode not supported {}", opcode));
+                            break;
+                        }
                     }
+                    self.to_state(WebSocketState::Len1);
                 },
                 WebSocketState::Len1 => {
                     if self.parse_head(input) {
@@ -188,21 +437,19 @@ impl WebSocket {
                     }
                     self.is_masked = (self.head[0] & 128) > 0;
                     let len_type = self.head[0] & 127;
-                    if len_type < 126 {
-                        if len_type == 0 {
-                            // emit a size 0 datapacket
-                            results.push(WebSocketResult::Data(Vec::new()));
-                            self.to_state(WebSocketState::Opcode)
-                        }
-                        else {
-                            self.data_len = len_type as usize;
-                            if !self.is_masked {
-                                self.to_state(WebSocketState::Data);
-                            }
-                            else {
-                                self.to_state(WebSocketState::Mask);
-                            }
-                        }
+                    // rfc 6455 5.1, clients mask every frame and servers never do
+                    if self.mask_rng.is_none() && !self.is_masked {
+                        self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Frame from the client is not masked".to_string());
+                    }
+                    else if self.mask_rng.is_some() && self.is_masked {
+                        self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Frame from the server is masked".to_string());
+                    }
+                    else if self.is_control() && len_type > 125 {
+                        self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Control frame longer than 125 bytes".to_string());
+                    }
+                    else if len_type < 126 {
+                        self.data_len = len_type as usize;
+                        self.to_mask_or_data(&mut results);
                     }
                     else if len_type == 126 {
                         self.to_state(WebSocketState::Len2);
@@ -218,26 +465,22 @@ impl WebSocket {
                     self.data_len = u16::from_be_bytes(
                         self.head[0..2].try_into().unwrap()
                     ) as usize;
-                    if self.is_masked {
-                        self.to_state(WebSocketState::Mask);
-                    }
-                    else {
-                        self.to_state(WebSocketState::Data);
-                    }
+                    self.to_mask_or_data(&mut results);
                 },
                 WebSocketState::Len8 => {
                     if self.parse_head(input) {
                         break;
                     }
-                    self.data_len = u64::from_be_bytes(
+                    let data_len = u64::from_be_bytes(
                         self.head[0..8].try_into().unwrap()
-                    ) as usize;
-                    if self.is_masked {
-                        self.to_state(WebSocketState::Mask);
-                    }
-                    else {
-                        self.to_state(WebSocketState::Data);
+                    );
+                    // rfc 6455 5.2, the most significant bit must be 0
+                    if data_len & (1 << 63) != 0 {
+                        self.fail(&mut results, WEBSOCKET_CLOSE_PROTOCOL_ERROR, "Frame length with the most significant bit set".to_string());
+                        break;
                     }
+                    self.data_len = if data_len > usize::MAX as u64 {usize::MAX} else {data_len as usize};
+                    self.to_mask_or_data(&mut results);
                 },
                 WebSocketState::Mask => {
                     if self.parse_head(input) {
@@ -263,17 +506,7 @@ impl WebSocket {
                         break;
                     }
                     else {
-                        if self.is_ping {
-                            results.push(WebSocketResult::Ping(self.data.clone()))
-                        }
-                        else if self.is_pong {
-                            results.push(WebSocketResult::Pong(self.data.clone()))
-                        }
-                        else {
-                            results.push(WebSocketResult::Data(self.data.clone()));
-                        }
-                        
-                        self.to_state(WebSocketState::Opcode);
+                        self.frame_done(&mut results);
                     }
                 },
             }
@@ -282,4 +515,3 @@ impl WebSocket {
     }
     
 }
-
@@ -0,0 +1,158 @@
+use makepad_http::server::*;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+fn start(router: HttpRouter, options: HttpServerOptions) -> HttpServer {
+    HttpServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), router, options).expect("Cannot start server")
+}
+
+fn read_response(reader: &mut BufReader<TcpStream>) -> (u16, Vec<(String, String)>, Vec<u8>) {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let colon = line.find(':').unwrap();
+        headers.push((line[..colon].to_string(), line[colon + 1..].trim().to_string()));
+    }
+    let len = headers.iter().find( | (k, _) | k == "Content-Length").map( | (_, v) | v.parse().unwrap()).unwrap_or(0);
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).unwrap();
+    (status, headers, body)
+}
+
+fn echo_router() -> HttpRouter {
+    HttpRouter::new()
+        .add("GET", "/hello", | _: &HttpRequest | HttpReply::Response(HttpResponse::ok("text/plain", b"hello".to_vec())))
+        .add("POST", "/echo", | request: &HttpRequest | HttpReply::Response(HttpResponse::ok("text/plain", request.body.clone())))
+        .add("GET", "/empty", | _: &HttpRequest | HttpReply::Response(HttpResponse {status: 204, ..HttpResponse::ok("text/plain", b"gone".to_vec())}))
+        .add("GET", "/same", | _: &HttpRequest | {
+            HttpReply::Response(HttpResponse {status: 304, ..HttpResponse::ok("text/plain", b"cached".to_vec()).with_header("Content-Length", "6")})
+        })
+        .add("GET", "/files/*", | request: &HttpRequest | {
+            HttpReply::Response(HttpResponse::ok("text/plain", request.path_no_slash().as_bytes().to_vec()))
+        })
+}
+
+#[test]
+fn keep_alive_and_routes() {
+    let server = start(echo_router(), HttpServerOptions::default());
+    let stream = TcpStream::connect(server.listen_address).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+
+    // several requests over one connection
+    writer.write_all(b"GET /hello HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+    let (status, headers, body) = read_response(&mut reader);
+    assert_eq!((status, body), (200, b"hello".to_vec()));
+    assert!(headers.contains(&("Connection".to_string(), "keep-alive".to_string())));
+
+    writer.write_all(b"POST /echo HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody").unwrap();
+    assert_eq!(read_response(&mut reader).2, b"body".to_vec());
+
+    writer.write_all(b"POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;ext=1\r\nde\r\n0\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut reader).2, b"abcde".to_vec());
+
+    writer.write_all(b"GET /files/a%20b.txt?x=1 HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut reader).2, b"files/a b.txt".to_vec());
+
+    writer.write_all(b"GET /nothing HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut reader).0, 404);
+
+    // 204 and 304 end at the headers, whatever the handler put in the body
+    for path in ["/empty", "/same"] {
+        writer.write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes()).unwrap();
+        let (_, headers, body) = read_response(&mut reader);
+        assert!(!headers.iter().any( | (k, _) | k == "Content-Length"));
+        assert!(body.is_empty());
+    }
+
+    // HEAD gets the headers of GET without a body
+    writer.write_all(b"HEAD /hello HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert!(rest.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(rest.contains("Content-Length: 5\r\n"));
+    assert!(rest.ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn rejects_bad_requests() {
+    let options = HttpServerOptions {max_body_size: 8, ..HttpServerOptions::default()};
+    let server = start(echo_router(), options);
+    for (request, status) in &[
+        (&b"GET /../secret HTTP/1.1\r\n\r\n"[..], 400),
+        (&b"GET /files/%2e%2e/secret HTTP/1.1\r\n\r\n"[..], 400),
+        (&b"GET //etc/passwd HTTP/1.1\r\n\r\n"[..], 400),
+        (&b"POST /echo HTTP/1.1\r\nContent-Length: 9\r\n\r\n123456789"[..], 413),
+        (&b"NONSENSE\r\n\r\n"[..], 400),
+    ] {
+        let mut stream = TcpStream::connect(server.listen_address).unwrap();
+        stream.write_all(request).unwrap();
+        let mut reader = BufReader::new(stream);
+        assert_eq!(read_response(&mut reader).0, *status, "{}", String::from_utf8_lossy(request));
+    }
+}
+
+#[test]
+fn rejects_huge_chunks() {
+    let server = start(echo_router(), HttpServerOptions::default());
+    let mut stream = TcpStream::connect(server.listen_address).unwrap();
+    let request = format!("POST /echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n{:x}\r\n", usize::MAX - 1);
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut reader = BufReader::new(stream);
+    assert_eq!(read_response(&mut reader).0, 413);
+}
+
+#[test]
+fn survives_panicking_handlers() {
+    let router = echo_router().add("GET", "/panic", | _: &HttpRequest | -> HttpReply {panic!("handler failed")});
+    let server = start(router, HttpServerOptions {workers: 1, ..HttpServerOptions::default()});
+    for _ in 0..2 {
+        let mut stream = TcpStream::connect(server.listen_address).unwrap();
+        stream.write_all(b"GET /panic HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_response(&mut BufReader::new(stream)).0, 500);
+    }
+    // the only worker is still there
+    let mut stream = TcpStream::connect(server.listen_address).unwrap();
+    stream.write_all(b"GET /hello HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut BufReader::new(stream)).2, b"hello".to_vec());
+}
+
+#[test]
+fn bounded_workers() {
+    // one worker and an idle keep-alive connection must not starve the next client forever
+    let options = HttpServerOptions {workers: 1, keep_alive_timeout: Duration::from_millis(200), ..HttpServerOptions::default()};
+    let mut server = start(echo_router(), options);
+    let _idle = TcpStream::connect(server.listen_address).unwrap();
+    let mut stream = TcpStream::connect(server.listen_address).unwrap();
+    stream.write_all(b"GET /hello HTTP/1.1\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream);
+    assert_eq!(read_response(&mut reader).2, b"hello".to_vec());
+    server.terminate();
+    assert!(TcpStream::connect(server.listen_address).is_err());
+}
+
+#[test]
+fn websocket_upgrade() {
+    use makepad_http::channel::WebSocketChannels;
+    let channels = WebSocketChannels::default();
+    let router = HttpRouter::new()
+        .add("WEBSOCKET", "/*", move | request: &HttpRequest | channels.clone().upgrade(request))
+        .add("GET", "/*", | _: &HttpRequest | HttpReply::Response(HttpResponse::not_found()));
+    let server = start(router, HttpServerOptions::default());
+    let mut stream = TcpStream::connect(server.listen_address).unwrap();
+    stream.write_all(b"GET /channel HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream);
+    let (status, headers, _) = read_response(&mut reader);
+    assert_eq!(status, 101);
+    assert!(headers.contains(&("Sec-WebSocket-Accept".to_string(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string())));
+}
@@ -0,0 +1,242 @@
+use makepad_http::websocket::*;
+
+// the example frames from section 5.7 of RFC 6455
+const HELLO_TEXT: [u8; 7] = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+const HELLO_TEXT_MASKED: [u8; 11] = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+const HEL_FRAGMENT: [u8; 5] = [0x01, 0x03, 0x48, 0x65, 0x6c];
+const LO_FRAGMENT: [u8; 4] = [0x80, 0x02, 0x6c, 0x6f];
+const PING_HELLO: [u8; 7] = [0x89, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+const PONG_HELLO_MASKED: [u8; 11] = [0x8a, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+
+// clients mask their frames, the frames above are written unmasked like a server sends them
+fn masked(frame: &[u8]) -> Vec<u8> {
+    let head_len = match frame[1] & 127 {126 => 4, 127 => 10, _ => 2};
+    let mask = [0x37, 0xfa, 0x21, 0x3d];
+    let mut out = frame[..head_len].to_vec();
+    out[1] |= 0x80;
+    out.extend_from_slice(&mask);
+    out.extend(frame[head_len..].iter().enumerate().map( | (i, byte) | byte ^ mask[i & 3]));
+    out
+}
+
+fn parse_all(web_socket: &mut WebSocket, frames: &[&[u8]]) -> Vec<WebSocketResult> {
+    let mut results = Vec::new();
+    for frame in frames {
+        results.extend(web_socket.parse(frame));
+    }
+    results
+}
+
+fn parse_bytewise(web_socket: &mut WebSocket, input: &[u8]) -> Vec<WebSocketResult> {
+    let mut results = Vec::new();
+    for byte in input {
+        results.extend(web_socket.parse(&[*byte]));
+    }
+    results
+}
+
+#[test]
+fn rfc_examples() {
+    let mut ws = WebSocket::new();
+    assert_eq!(ws.parse(&HELLO_TEXT_MASKED), vec![WebSocketResult::Text("Hello".to_string())]);
+    assert_eq!(ws.parse(&PONG_HELLO_MASKED), vec![WebSocketResult::Pong(b"Hello".to_vec())]);
+    assert!(ws.take_replies().is_empty());
+
+    // the unmasked ones are what a client gets from the server
+    let mut ws = WebSocket::new_client();
+    assert_eq!(ws.parse(&HELLO_TEXT), vec![WebSocketResult::Text("Hello".to_string())]);
+    assert_eq!(parse_all(&mut ws, &[&HEL_FRAGMENT, &LO_FRAGMENT]), vec![WebSocketResult::Text("Hello".to_string())]);
+
+    let mut binary_256 = vec![0x82, 0x7e, 0x01, 0x00];
+    binary_256.extend_from_slice(&[7u8; 256]);
+    assert_eq!(ws.parse(&binary_256), vec![WebSocketResult::Data(vec![7u8; 256])]);
+
+    let mut binary_64k = vec![0x82, 0x7f, 0, 0, 0, 0, 0, 1, 0, 0];
+    binary_64k.extend_from_slice(&vec![9u8; 65536]);
+    assert_eq!(ws.parse(&binary_64k), vec![WebSocketResult::Data(vec![9u8; 65536])]);
+    assert!(ws.take_replies().is_empty());
+}
+
+#[test]
+fn split_input() {
+    let mut input = Vec::new();
+    input.extend_from_slice(&HELLO_TEXT_MASKED);
+    input.extend_from_slice(&masked(&HEL_FRAGMENT));
+    input.extend_from_slice(&masked(&PING_HELLO));
+    input.extend_from_slice(&masked(&LO_FRAGMENT));
+    let expected = vec![
+        WebSocketResult::Text("Hello".to_string()),
+        WebSocketResult::Ping(b"Hello".to_vec()),
+        WebSocketResult::Text("Hello".to_string()),
+    ];
+    assert_eq!(parse_bytewise(&mut WebSocket::new(), &input), expected);
+    assert_eq!(WebSocket::new().parse(&input), expected);
+}
+
+#[test]
+fn fragmented_binary() {
+    let mut ws = WebSocket::new();
+    let results = parse_all(&mut ws, &[
+        &masked(&[0x02, 0x02, 1, 2]),
+        &masked(&[0x00, 0x01, 3]),
+        &masked(&[0x8a, 0x00]),
+        &masked(&[0x80, 0x02, 4, 5]),
+    ]);
+    assert_eq!(results, vec![
+        WebSocketResult::Pong(Vec::new()),
+        WebSocketResult::Data(vec![1, 2, 3, 4, 5]),
+    ]);
+}
+
+#[test]
+fn empty_frames() {
+    let mut ws = WebSocket::new();
+    // a masked empty frame still carries its mask key
+    let results = parse_all(&mut ws, &[&[0x82, 0x80, 1, 2, 3, 4], &masked(&[0x81, 0x00]), &HELLO_TEXT_MASKED]);
+    assert_eq!(results, vec![
+        WebSocketResult::Data(Vec::new()),
+        WebSocketResult::Text(String::new()),
+        WebSocketResult::Text("Hello".to_string()),
+    ]);
+}
+
+#[test]
+fn automatic_pong() {
+    let mut ws = WebSocket::new();
+    assert_eq!(ws.parse(&masked(&PING_HELLO)), vec![WebSocketResult::Ping(b"Hello".to_vec())]);
+    assert_eq!(ws.take_replies(), vec![vec![0x8a, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]]);
+    assert!(ws.take_replies().is_empty());
+}
+
+#[test]
+fn client_replies_are_masked() {
+    let mut client = WebSocket::new_client();
+    client.parse(&PING_HELLO);
+    let replies = client.take_replies();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0][1] & 0x80, 0x80);
+    let mut server = WebSocket::new();
+    assert_eq!(server.parse(&replies[0]), vec![WebSocketResult::Pong(b"Hello".to_vec())]);
+}
+
+#[test]
+fn close_handshake() {
+    let mut ws = WebSocket::new();
+    let mut close = vec![0x88, 0x06, 0x03, 0xe8];
+    close.extend_from_slice(b"done");
+    let results = parse_all(&mut ws, &[&masked(&close), &HELLO_TEXT_MASKED]);
+    assert_eq!(results, vec![WebSocketResult::Close(WEBSOCKET_CLOSE_NORMAL, "done".to_string())]);
+    assert!(ws.is_closed());
+    // the close is echoed with the same code
+    assert_eq!(ws.take_replies(), vec![vec![0x88, 0x02, 0x03, 0xe8]]);
+
+    let mut ws = WebSocket::new();
+    assert_eq!(ws.parse(&masked(&[0x88, 0x00])), vec![WebSocketResult::Close(WEBSOCKET_CLOSE_NO_STATUS, String::new())]);
+    assert_eq!(ws.take_replies(), vec![vec![0x88, 0x00]]);
+}
+
+#[test]
+fn close_started_locally() {
+    let mut ws = WebSocket::new();
+    assert_eq!(ws.close(WEBSOCKET_CLOSE_GOING_AWAY, "bye"), vec![0x88, 0x05, 0x03, 0xe9, b'b', b'y', b'e']);
+    // no pongs after we sent a close, and the answer to our close is not echoed
+    assert_eq!(parse_all(&mut ws, &[&masked(&PING_HELLO), &masked(&[0x88, 0x02, 0x03, 0xe9])]), vec![
+        WebSocketResult::Ping(b"Hello".to_vec()),
+        WebSocketResult::Close(WEBSOCKET_CLOSE_GOING_AWAY, String::new()),
+    ]);
+    assert!(ws.take_replies().is_empty());
+}
+
+fn assert_protocol_error(frames: &[&[u8]], code: u16) {
+    let mut ws = WebSocket::new();
+    let input: Vec<u8> = frames.iter().flat_map( | frame | masked(frame)).collect();
+    let results = ws.parse(&input);
+    assert_eq!(results.len(), 1, "{:?}", results);
+    assert!(matches!(&results[0], WebSocketResult::Error(_)), "{:?}", results);
+    assert!(ws.is_closed());
+    assert_eq!(ws.take_replies(), vec![WebSocketMessage::new_close(code, "", None).take()]);
+}
+
+#[test]
+fn protocol_errors() {
+    // continuation without a message
+    assert_protocol_error(&[&[0x80, 0x00]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // new message while a fragmented one is open
+    assert_protocol_error(&[&[0x01, 0x01, b'a'], &[0x81, 0x01, b'b']], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // fragmented ping
+    assert_protocol_error(&[&[0x09, 0x00]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // control frame over 125 bytes
+    assert_protocol_error(&[&[0x89, 0x7e, 0x00, 0x80]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // reserved bits and opcodes
+    assert_protocol_error(&[&[0xc1, 0x00]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    assert_protocol_error(&[&[0x83, 0x00]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // close with a single byte payload
+    assert_protocol_error(&[&[0x88, 0x01, 0x03]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // invalid utf8, also when split over fragments
+    assert_protocol_error(&[&[0x81, 0x02, 0xc3, 0x28]], WEBSOCKET_CLOSE_INVALID_DATA);
+    assert_protocol_error(&[&[0x01, 0x01, 0xe2], &[0x80, 0x01, 0x82]], WEBSOCKET_CLOSE_INVALID_DATA);
+}
+
+#[test]
+fn masking_direction() {
+    // rfc 6455 5.1, a server closes on an unmasked client frame
+    let mut ws = WebSocket::new();
+    let results = ws.parse(&HELLO_TEXT);
+    assert!(matches!(&results[..], [WebSocketResult::Error(_)]), "{:?}", results);
+    assert!(ws.is_closed());
+    assert_eq!(ws.take_replies(), vec![vec![0x88, 0x02, 0x03, 0xea]]);
+    // and a client on a masked server frame
+    let mut ws = WebSocket::new_client();
+    let results = ws.parse(&HELLO_TEXT_MASKED);
+    assert!(matches!(&results[..], [WebSocketResult::Error(_)]), "{:?}", results);
+    assert!(ws.is_closed());
+    let replies = ws.take_replies();
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0][0], 0x88);
+}
+
+#[test]
+fn split_utf8_across_fragments() {
+    let mut ws = WebSocket::new();
+    // the euro sign split over two frames is fine once reassembled
+    assert_eq!(parse_all(&mut ws, &[&masked(&[0x01, 0x02, 0xe2, 0x82]), &masked(&[0x80, 0x01, 0xac])]), vec![WebSocketResult::Text("€".to_string())]);
+}
+
+#[test]
+fn max_message_size() {
+    let mut ws = WebSocket::new().with_max_message_size(4);
+    assert_eq!(ws.parse(&masked(&[0x82, 0x04, 1, 2, 3, 4])), vec![WebSocketResult::Data(vec![1, 2, 3, 4])]);
+    // the limit covers the reassembled message, not just one frame
+    let results = parse_all(&mut ws, &[&masked(&[0x02, 0x03, 1, 2, 3]), &masked(&[0x80, 0x02, 4, 5])]);
+    assert_eq!(results.len(), 1);
+    assert!(matches!(&results[0], WebSocketResult::Error(_)));
+    assert_eq!(ws.take_replies(), vec![vec![0x88, 0x02, 0x03, 0xf1]]);
+
+    // a huge announced length is refused before any data arrives
+    let mut ws = WebSocket::new().with_max_message_size(1024);
+    let results = ws.parse(&masked(&[0x82, 0x7f, 0x7f, 0xff, 0, 0, 0, 0, 0, 0]));
+    assert!(matches!(&results[..], [WebSocketResult::Error(_)]));
+    assert_eq!(ws.take_replies(), vec![WebSocketMessage::new_close(WEBSOCKET_CLOSE_TOO_BIG, "", None).take()]);
+}
+
+#[test]
+fn length_with_msb_set() {
+    assert_protocol_error(&[&[0x82, 0x7f, 0xff, 0, 0, 0, 0, 0, 0, 0]], WEBSOCKET_CLOSE_PROTOCOL_ERROR);
+    // a continuation cannot wrap the message length around, even without a limit
+    let mut ws = WebSocket::new().with_max_message_size(usize::MAX);
+    let results = parse_all(&mut ws, &[&masked(&[0x02, 0x01, 1]), &masked(&[0x80, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff])]);
+    assert!(matches!(&results[..], [WebSocketResult::Error(_)]));
+    assert!(ws.is_closed());
+}
+
+#[test]
+fn message_builder() {
+    let mut msg = WebSocketMessage::new_text(5);
+    msg.append(b"Hello");
+    assert_eq!(msg.take(), HELLO_TEXT.to_vec());
+
+    let mut msg = WebSocketMessage::new_text_masked(5, [0x37, 0xfa, 0x21, 0x3d]);
+    msg.append(b"Hel");
+    msg.append(b"lo");
+    assert_eq!(msg.take(), HELLO_TEXT_MASKED.to_vec());
+}
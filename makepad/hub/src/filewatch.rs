@@ -0,0 +1,320 @@
+// watches the builder workspaces for changes made behind our back (git checkout, another editor).
+// linux uses inotify, everything else (or a linux that ran out of watches) scans the tree on an interval
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, SystemTime};
+
+pub const FILE_WATCH_POLL_INTERVAL: Duration = Duration::from_millis(1000);
+// editors save with a few writes and renames, wait for it to go quiet before reporting
+pub const FILE_WATCH_SETTLE: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileWatchChanges {
+    // absolute paths of the files that were written, created or removed
+    pub files: Vec<String>,
+    // files or folders came or went
+    pub tree_changed: bool
+}
+
+impl FileWatchChanges {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && !self.tree_changed
+    }
+
+    fn add_file(&mut self, path: String) {
+        if !self.files.contains(&path) {
+            self.files.push(path);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileWatchBackend {
+    Inotify,
+    Poll
+}
+
+pub struct FileWatcher {
+    pub backend: FileWatchBackend,
+    tx_terminate: Option<mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>
+}
+
+impl FileWatcher {
+
+    pub fn start<F>(roots: Vec<String>, dir_ex: &[&str], on_change: F) -> FileWatcher
+    where F: FnMut(FileWatchChanges) + Send + 'static {
+        let dir_ex: Vec<String> = dir_ex.iter().map( | v | v.to_string()).collect();
+        #[cfg(target_os = "linux")]
+        {
+            match inotify::Inotify::new(&roots, &dir_ex) {
+                Ok(inotify) => return Self::start_inotify(inotify, roots, dir_ex, on_change),
+                Err(e) => println!("File watcher cannot use inotify ({}), polling instead", e)
+            }
+        }
+        Self::start_polling_with(roots, dir_ex, FILE_WATCH_POLL_INTERVAL, on_change)
+    }
+
+    pub fn start_polling<F>(roots: Vec<String>, dir_ex: &[&str], interval: Duration, on_change: F) -> FileWatcher
+    where F: FnMut(FileWatchChanges) + Send + 'static {
+        let dir_ex: Vec<String> = dir_ex.iter().map( | v | v.to_string()).collect();
+        Self::start_polling_with(roots, dir_ex, interval, on_change)
+    }
+
+    fn start_polling_with<F>(roots: Vec<String>, dir_ex: Vec<String>, interval: Duration, mut on_change: F) -> FileWatcher
+    where F: FnMut(FileWatchChanges) + Send + 'static {
+        let (tx_terminate, rx_terminate) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut snapshot = FileSnapshot::scan(&roots, &dir_ex);
+            while let Err(RecvTimeoutError::Timeout) = rx_terminate.recv_timeout(interval) {
+                let new_snapshot = FileSnapshot::scan(&roots, &dir_ex);
+                let changes = snapshot.diff(&new_snapshot);
+                snapshot = new_snapshot;
+                if !changes.is_empty() {
+                    on_change(changes);
+                }
+            }
+        });
+        FileWatcher {
+            backend: FileWatchBackend::Poll,
+            tx_terminate: Some(tx_terminate),
+            thread: Some(thread)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn start_inotify<F>(mut inotify: inotify::Inotify, roots: Vec<String>, dir_ex: Vec<String>, mut on_change: F) -> FileWatcher
+    where F: FnMut(FileWatchChanges) + Send + 'static {
+        let (tx_terminate, rx_terminate) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            while let Err(mpsc::TryRecvError::Empty) = rx_terminate.try_recv() {
+                if !inotify.wait(Duration::from_millis(100)) {
+                    continue;
+                }
+                let mut changes = FileWatchChanges::default();
+                let mut overflowed = inotify.read_events(&mut changes);
+                while inotify.wait(FILE_WATCH_SETTLE) {
+                    overflowed |= inotify.read_events(&mut changes);
+                }
+                if overflowed {
+                    // we lost events, report everything and let the receiver sort out what really changed
+                    for path in FileSnapshot::scan(&roots, &dir_ex).files.keys() {
+                        changes.add_file(path.clone());
+                    }
+                    changes.tree_changed = true;
+                }
+                if !changes.is_empty() {
+                    on_change(changes);
+                }
+            }
+        });
+        FileWatcher {
+            backend: FileWatchBackend::Inotify,
+            tx_terminate: Some(tx_terminate),
+            thread: Some(thread)
+        }
+    }
+
+    pub fn terminate(&mut self) {
+        if let Some(tx_terminate) = self.tx_terminate.take() {
+            let _ = tx_terminate.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.terminate();
+    }
+}
+
+#[derive(Default)]
+struct FileSnapshot {
+    files: HashMap<String, (Option<SystemTime>, u64)>,
+    dirs: HashSet<String>
+}
+
+impl FileSnapshot {
+    fn scan(roots: &[String], dir_ex: &[String]) -> FileSnapshot {
+        fn scan_recur(path: &str, dir_ex: &[String], snapshot: &mut FileSnapshot) {
+            if let Ok(read_dir) = fs::read_dir(path) {
+                for entry in read_dir.flatten() {
+                    if let (Ok(ty), Ok(name)) = (entry.file_type(), entry.file_name().into_string()) {
+                        let entry_path = format!("{}/{}", path, name);
+                        if ty.is_dir() {
+                            if dir_ex.contains(&name) {
+                                continue
+                            }
+                            scan_recur(&entry_path, dir_ex, snapshot);
+                            snapshot.dirs.insert(entry_path);
+                        }
+                        else if let Ok(metadata) = entry.metadata() {
+                            snapshot.files.insert(entry_path, (metadata.modified().ok(), metadata.len()));
+                        }
+                    }
+                }
+            }
+        }
+        let mut snapshot = FileSnapshot::default();
+        for root in roots {
+            scan_recur(root, dir_ex, &mut snapshot);
+        }
+        snapshot
+    }
+
+    fn diff(&self, new: &FileSnapshot) -> FileWatchChanges {
+        let mut changes = FileWatchChanges::default();
+        for (path, stamp) in &new.files {
+            match self.files.get(path) {
+                Some(old_stamp) => if old_stamp != stamp {
+                    changes.add_file(path.clone());
+                },
+                None => {
+                    changes.add_file(path.clone());
+                    changes.tree_changed = true;
+                }
+            }
+        }
+        for path in self.files.keys() {
+            if !new.files.contains_key(path) {
+                changes.add_file(path.clone());
+                changes.tree_changed = true;
+            }
+        }
+        if self.dirs != new.dirs {
+            changes.tree_changed = true;
+        }
+        changes.files.sort();
+        changes
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use super::FileWatchChanges;
+    use std::collections::HashMap;
+    use std::ffi::CString;
+    use std::fs;
+    use std::time::Duration;
+
+    const WATCH_MASK: u32 = libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_CREATE | libc::IN_DELETE
+        | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+    const TREE_MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+    const EVENT_HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    pub struct Inotify {
+        fd: i32,
+        watches: HashMap<i32, String>,
+        dir_ex: Vec<String>
+    }
+
+    impl Inotify {
+        pub fn new(roots: &[String], dir_ex: &[String]) -> std::io::Result<Inotify> {
+            let fd = unsafe {libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC)};
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error())
+            }
+            let mut inotify = Inotify {
+                fd,
+                watches: HashMap::new(),
+                dir_ex: dir_ex.to_vec()
+            };
+            for root in roots {
+                inotify.watch_recur(root) ?;
+            }
+            Ok(inotify)
+        }
+
+        // runs out with ENOSPC when the user watch limit is reached, the caller polls instead
+        fn watch_recur(&mut self, path: &str) -> std::io::Result<()> {
+            let c_path = CString::new(path).map_err( | _ | std::io::Error::from(std::io::ErrorKind::InvalidInput)) ?;
+            let wd = unsafe {libc::inotify_add_watch(self.fd, c_path.as_ptr(), WATCH_MASK)};
+            if wd < 0 {
+                return Err(std::io::Error::last_os_error())
+            }
+            self.watches.insert(wd, path.to_string());
+            if let Ok(read_dir) = fs::read_dir(path) {
+                for entry in read_dir.flatten() {
+                    if let (Ok(ty), Ok(name)) = (entry.file_type(), entry.file_name().into_string()) {
+                        if ty.is_dir() && !self.dir_ex.contains(&name) {
+                            self.watch_recur(&format!("{}/{}", path, name)) ?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        pub fn wait(&self, timeout: Duration) -> bool {
+            let mut poll_fd = libc::pollfd {fd: self.fd, events: libc::POLLIN, revents: 0};
+            unsafe {libc::poll(&mut poll_fd, 1, timeout.as_millis() as i32) > 0}
+        }
+
+        // returns true when the kernel queue overflowed and events were lost
+        pub fn read_events(&mut self, changes: &mut FileWatchChanges) -> bool {
+            let mut overflowed = false;
+            let mut buf = [0u8; 4096];
+            loop {
+                let len = unsafe {libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())};
+                if len <= 0 {
+                    return overflowed
+                }
+                let len = len as usize;
+                let mut pos = 0;
+                while pos + EVENT_HEADER <= len {
+                    let event = unsafe {std::ptr::read_unaligned(buf.as_ptr().add(pos) as *const libc::inotify_event)};
+                    let name_bytes = &buf[pos + EVENT_HEADER..(pos + EVENT_HEADER + event.len as usize).min(len)];
+                    pos += EVENT_HEADER + event.len as usize;
+                    // the name is padded with zeroes
+                    let name_len = name_bytes.iter().position( | c | *c == 0).unwrap_or(name_bytes.len());
+                    let name = String::from_utf8_lossy(&name_bytes[0..name_len]).to_string();
+
+                    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                        overflowed = true;
+                        continue
+                    }
+                    if event.mask & libc::IN_IGNORED != 0 {
+                        self.watches.remove(&event.wd);
+                        continue
+                    }
+                    let path = match self.watches.get(&event.wd) {
+                        Some(dir) if name.is_empty() => dir.clone(),
+                        Some(dir) => format!("{}/{}", dir, name),
+                        None => continue
+                    };
+                    if event.mask & libc::IN_ISDIR != 0 {
+                        if self.dir_ex.contains(&name) {
+                            continue
+                        }
+                        if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                            if let Err(e) = self.watch_recur(&path) {
+                                println!("File watcher cannot watch {}: {}", path, e);
+                            }
+                        }
+                        if event.mask & TREE_MASK != 0 {
+                            changes.tree_changed = true;
+                        }
+                    }
+                    else {
+                        if event.mask & TREE_MASK != 0 {
+                            changes.tree_changed = true;
+                        }
+                        changes.add_file(path);
+                    }
+                }
+            }
+        }
+    }
+
+    impl Drop for Inotify {
+        fn drop(&mut self) {
+            unsafe {libc::close(self.fd);}
+        }
+    }
+}
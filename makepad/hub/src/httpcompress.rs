@@ -0,0 +1,218 @@
+// content encoding for the hub http server, precompressed files next to the original win,
+// everything else is compressed on the fly and cached on the digest of the file
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+use makepad_http::digest::Sha1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HttpEncoding {
+    Identity,
+    Deflate,
+    Gzip,
+    Brotli
+}
+
+impl HttpEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HttpEncoding::Identity => "identity",
+            HttpEncoding::Deflate => "deflate",
+            HttpEncoding::Gzip => "gzip",
+            HttpEncoding::Brotli => "br",
+        }
+    }
+
+    // the extension of a precompressed file sitting next to the original
+    pub fn precompressed_ext(&self) -> Option<&'static str> {
+        match self {
+            HttpEncoding::Gzip => Some(".gz"),
+            HttpEncoding::Brotli => Some(".br"),
+            _ => None
+        }
+    }
+
+    // what we can produce ourselves, brotli needs the brotli feature
+    pub fn on_the_fly() -> &'static [HttpEncoding] {
+        if cfg!(feature = "brotli") {
+            &[HttpEncoding::Brotli, HttpEncoding::Gzip, HttpEncoding::Deflate]
+        }
+        else {
+            &[HttpEncoding::Gzip, HttpEncoding::Deflate]
+        }
+    }
+
+    // picks the encoding with the highest q value out of the available ones,
+    // ties go to whatever comes first in available
+    pub fn negotiate(accept_encoding: &str, available: &[HttpEncoding]) -> HttpEncoding {
+        let accepted: Vec<(&str, f32)> = accept_encoding.split(',').map( | item | {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let q = parts
+                .filter_map( | param | param.trim().strip_prefix("q="))
+                .filter_map( | value | value.trim().parse::<f32>().ok())
+                .next().unwrap_or(1.0);
+            (name, q)
+        }).collect();
+        let mut best = HttpEncoding::Identity;
+        let mut best_q = 0.0;
+        for encoding in available {
+            // a named encoding overrides the wildcard
+            let q = accepted.iter().find( | (name, _) | name.eq_ignore_ascii_case(encoding.name()))
+                .or_else( || accepted.iter().find( | (name, _) | *name == "*"))
+                .map( | (_, q) | *q).unwrap_or(0.0);
+            if q > best_q {
+                best = *encoding;
+                best_q = q;
+            }
+        }
+        best
+    }
+}
+
+pub fn is_compressible_mime_type(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || mime_type.starts_with("application/javascript")
+        || mime_type.starts_with("application/json")
+        || mime_type.starts_with("application/xml")
+        || mime_type == "application/wasm"
+        || mime_type == "image/svg+xml"
+        || mime_type == "image/x-icon"
+        || mime_type == "font/ttf"
+        || mime_type == "font/otf"
+}
+
+// None when we can't produce the encoding, the caller then serves the plain data
+pub fn compress_bytes(data: &[u8], encoding: HttpEncoding) -> Option<Vec<u8 >> {
+    match encoding {
+        HttpEncoding::Identity => Some(data.to_vec()),
+        // the http deflate encoding is the zlib format, not raw deflate
+        HttpEncoding::Deflate => Some(deflate::deflate_bytes_zlib(data)),
+        HttpEncoding::Gzip => {
+            let mut result = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+            result.extend_from_slice(&deflate::deflate_bytes(data));
+            result.extend_from_slice(&crc32(data).to_le_bytes());
+            result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            Some(result)
+        },
+        #[cfg(feature = "brotli")]
+        HttpEncoding::Brotli => {
+            use std::io::Write;
+            let mut result = Vec::new();
+            {
+                // quality 11 takes seconds on a wasm file, precompress with .br for that
+                let mut writer = brotli::CompressorWriter::new(&mut result, 4096, 5, 22);
+                writer.write_all(data).ok() ?;
+            }
+            Some(result)
+        },
+        #[cfg(not(feature = "brotli"))]
+        HttpEncoding::Brotli => None
+    }
+}
+
+// built at compile time, const fns only have while loops
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            c = if c & 1 != 0 {0xedb8_8320 ^ (c >> 1)} else {c >> 1};
+            bit += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xffff_ffff
+}
+
+pub type FileDigest = [u8; 20];
+
+pub struct HttpCompressCache {
+    // the digest only gets recomputed when the size or modification time of a file changes
+    digests: HashMap<String, (u64, SystemTime, FileDigest)>,
+    entries: HashMap<(FileDigest, HttpEncoding), Arc<Vec<u8 >> >,
+    total_size: usize,
+    pub max_size: usize,
+}
+
+impl Default for HttpCompressCache {
+    fn default() -> Self {
+        HttpCompressCache {
+            digests: HashMap::new(),
+            entries: HashMap::new(),
+            total_size: 0,
+            max_size: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl HttpCompressCache {
+    pub fn file_digest(&mut self, path: &str, len: u64, modified: SystemTime, data: &[u8]) -> FileDigest {
+        if let Some((old_len, old_modified, digest)) = self.digests.get(path) {
+            if *old_len == len && *old_modified == modified {
+                return *digest
+            }
+        }
+        let mut sha1 = Sha1::new();
+        sha1.update(data);
+        let digest = sha1.finalise();
+        // the old contents of this file are not coming back
+        if let Some((_, _, old_digest)) = self.digests.insert(path.to_string(), (len, modified, digest)) {
+            if old_digest != digest && !self.digests.values().any( | (_, _, d) | *d == old_digest) {
+                self.remove_digest(&old_digest);
+            }
+        }
+        digest
+    }
+
+    pub fn cached_digest(&self, path: &str, len: u64, modified: SystemTime) -> Option<FileDigest> {
+        match self.digests.get(path) {
+            Some((old_len, old_modified, digest)) if *old_len == len && *old_modified == modified => Some(*digest),
+            _ => None
+        }
+    }
+
+    pub fn get(&self, digest: &FileDigest, encoding: HttpEncoding) -> Option<Arc<Vec<u8 >> > {
+        self.entries.get(&(*digest, encoding)).cloned()
+    }
+
+    pub fn insert(&mut self, digest: FileDigest, encoding: HttpEncoding, data: Arc<Vec<u8 >>) {
+        if data.len() > self.max_size {
+            return
+        }
+        if self.total_size + data.len() > self.max_size {
+            self.entries.clear();
+            self.total_size = 0;
+        }
+        self.total_size += data.len();
+        if let Some(old) = self.entries.insert((digest, encoding), data) {
+            self.total_size -= old.len();
+        }
+    }
+
+    fn remove_digest(&mut self, digest: &FileDigest) {
+        let total_size = &mut self.total_size;
+        self.entries.retain( | (entry_digest, _), data | {
+            if entry_digest == digest {
+                *total_size -= data.len();
+                false
+            }
+            else {
+                true
+            }
+        });
+    }
+}
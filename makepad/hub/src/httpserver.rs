@@ -1,32 +1,81 @@
-use std::net::{TcpListener, TcpStream, SocketAddr, Shutdown};
+use std::net::{SocketAddr, Shutdown};
 use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::fs::File;
 use std::io::prelude::*;
-use std::str;
-use std::time::Duration;
+use std::io::SeekFrom;
 use std::collections::HashMap;
 use makepad_microserde::*;
 use makepad_http::httputil::*;
 use makepad_http::channel::*;
+use makepad_http::websocket::*;
+use makepad_http::server;
+use crate::httpcompress::*;
+use crate::hubtls::*;
+use crate::hubauth::is_hub_key_file;
+use makepad_http::server::{HttpRouter, HttpRequest, HttpResponse, HttpReply, HttpServerOptions, HttpStream};
 
 #[derive(Debug, Clone, SerBin, DeBin, PartialEq, SerRon, DeRon)]
 pub enum HttpServerConfig {
     Offline,
     Localhost(u16),
     Network(u16),
-    InterfaceV4(u16, [u8; 4])
+    InterfaceV4(u16, [u8; 4]),
+    Tls(Box<HttpServerConfig>, HubTlsConfig), // https on any of the above
+}
+
+impl HttpServerConfig {
+    pub fn listen_address(&self) -> Option<SocketAddr> {
+        match self {
+            HttpServerConfig::Offline => None,
+            HttpServerConfig::Localhost(port) => Some(SocketAddr::from(([127, 0, 0, 1], *port))),
+            HttpServerConfig::Network(port) => Some(SocketAddr::from(([0, 0, 0, 0], *port))),
+            HttpServerConfig::InterfaceV4(port, ip) => Some(SocketAddr::from((*ip, *port))),
+            HttpServerConfig::Tls(config, _) => config.listen_address(),
+        }
+    }
+}
+
+// what the hub tells the browsers connected to /$livereload, as json text frames
+#[derive(Debug, Clone, PartialEq, SerJson, DeJson)]
+#[nserde(tag = "type")]
+pub enum LiveReloadMsg {
+    #[nserde(rename = "file_change")]
+    FileChange {path: String},
+    #[nserde(rename = "build_start")]
+    BuildStart,
+    #[nserde(rename = "build_finished")]
+    BuildFinished {errors: Vec<LiveReloadError>},
+    #[nserde(rename = "wasm_ready")]
+    WasmReady {path: String},
+}
+
+#[derive(Debug, Clone, PartialEq, SerJson, DeJson)]
+pub struct LiveReloadError {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+pub struct LiveReloadSocket {
+    pub id: u64,
+    pub tx: mpsc::Sender<Vec<u8 >>,
+    // the files the page runs, from /$livereload?files=a,b. older pages dont say
+    pub files: Option<Vec<String >>,
 }
 
 #[derive(Default)]
 pub struct HttpServerShared {
     pub terminate: bool,
-    pub watcher_id: u64,
-    pub watch_pending: Vec<(u64, mpsc::Sender<String>)>,
+    pub live_reload_id: u64,
+    pub live_reload_sockets: Vec<LiveReloadSocket>,
     pub files_read: Vec<String>,
 }
 
 #[derive(Default)]
 pub struct HttpServer {
-    pub listen_thread: Option<std::thread::JoinHandle<() >>,
+    pub server: Option<server::HttpServer>,
     pub listen_address: Option<SocketAddr>,
     pub shared: Arc<Mutex<HttpServerShared >>,
 }
@@ -38,200 +87,439 @@ impl HttpServer {
         workspaces_arc: Arc<Mutex<HashMap<String, String >> >
     ) -> Option<HttpServer> {
         
-        let listen_address = match config {
-            HttpServerConfig::Offline => return None,
-            HttpServerConfig::Localhost(port) => SocketAddr::from(([127, 0, 0, 1], *port)),
-            HttpServerConfig::Network(port) => SocketAddr::from(([0, 0, 0, 0], *port)),
-            HttpServerConfig::InterfaceV4(port, ip) => SocketAddr::from((*ip, *port)),
+        let listen_address = config.listen_address() ?;
+        let tls_config = if let HttpServerConfig::Tls(_, tls) = config {
+            match tls.server_config() {
+                Ok(tls_config) => Some(tls_config),
+                Err(e) => {
+                    println!("Cannot load http server tls config: {}", e.msg);
+                    return None
+                }
+            }
+        }
+        else {
+            None
         };
-        
-        let listener = if let Ok(listener) = TcpListener::bind(listen_address.clone()) {listener} else {println!("Cannot bind http server port"); return None};
-        let workspaces = Arc::clone(&workspaces_arc);
         let shared = Arc::new(Mutex::new(HttpServerShared::default()));
         
-        let listen_thread = {
-            let shared = Arc::clone(&shared);
-            std::thread::spawn(move || {
-                for tcp_stream in listener.incoming() {
-                    if let Ok(shared) = shared.lock() {
-                        if shared.terminate {
-                            return
-                        }
-                    }
-                    let mut tcp_stream = tcp_stream.expect("Incoming stream failure");
-                    let (tx_write, rx_write) = mpsc::channel::<String>();
-                    let workspaces = Arc::clone(&workspaces);
-                    let shared = Arc::clone(&shared);
-                    let websocket_channels = websocket_channels.clone();
-                    let _read_thread = std::thread::spawn(move || {
-                        let header = HttpHeader::from_tcp_stream(tcp_stream.try_clone().expect("Cannot clone tcp stream"));
-                        if header.is_none() {
-                            return http_error_out(tcp_stream, 500)
-                        }
-                        let header = header.unwrap();
-                        
-                        if let Some(key) = header.sec_websocket_key {
-                            return websocket_channels.handle_websocket(&mut tcp_stream, &header.path, &key);
-                        }
-                        
-                        if header.path.ends_with("/key.ron") || header.path.find("..").is_some() || header.path_no_slash.starts_with("/") {
-                            return http_error_out(tcp_stream, 500)
-                        }
-                        
-                        if header.path.starts_with("/$watch") { // its a watcher wait for the finish
-                            let mut watcher_id = 0;
-                            if let Ok(mut shared) = shared.lock() {
-                                shared.watcher_id += 1;
-                                watcher_id = shared.watcher_id;
-                                shared.watch_pending.push((watcher_id, tx_write));
-                            };
-                            match rx_write.recv_timeout(Duration::from_secs(30)) {
-                                Ok(msg) => { // let the watcher know
-                                    write_bytes_to_tcp_stream_no_error(&mut tcp_stream, msg.as_bytes());
-                                    let _ = tcp_stream.shutdown(Shutdown::Both);
-                                },
-                                Err(_) => { // close gracefully
-                                    write_bytes_to_tcp_stream_no_error(&mut tcp_stream, "HTTP/1.1 201 Retry\r\n\r\n".as_bytes());
-                                    let _ = tcp_stream.shutdown(Shutdown::Both);
-                                }
-                            }
-                            
-                            if let Ok(mut shared) = shared.lock() {
-                                for i in 0..shared.watch_pending.len() {
-                                    let (id, _) = &shared.watch_pending[i];
-                                    if *id == watcher_id {
-                                        shared.watch_pending.remove(i);
-                                        break
-                                    }
-                                }
-                            };
-                            return
-                        }
-                        
-                        if header.path.ends_with("favicon.ico") {
-                            let header = "HTTP/1.1 200 OK\r\nContent-Type: image/x-icon\r\nTransfer-encoding: identity\r\nContent-Length: 0\r\n: close\r\n\r\n";
-                            write_bytes_to_tcp_stream_no_error(&mut tcp_stream, header.as_bytes());
-                            let _ = tcp_stream.shutdown(Shutdown::Both);
-                            return
-                        }
+        let router = HttpRouter::new()
+            .add("WEBSOCKET", "/$livereload", {
+                let shared = Arc::clone(&shared);
+                move | request: &HttpRequest | Self::handle_live_reload(request, &shared)
+            })
+            .add("WEBSOCKET", "/*", move | request: &HttpRequest | {
+                websocket_channels.clone().upgrade(request)
+            })
 
-                        let file_path = if let Some(file_pos) = header.path_no_slash.find('/') {
-                            let (workspace, rest) = header.path_no_slash.split_at(file_pos);
-                            let (_, rest) = rest.split_at(1);
-                            if let Ok(workspaces) = workspaces.lock() {
-                                if let Some(abs_path) = workspaces.get(workspace) {
-                                    Some(format!("{}/{}", abs_path, rest))
-                                }
-                                else {None}
+            .add("GET", "/favicon.ico", | _request: &HttpRequest | {
+                HttpReply::Response(HttpResponse::ok("image/x-icon", Vec::new()))
+            })
+            .add("GET", "/*", {
+                let shared = Arc::clone(&shared);
+                let compress_cache = Arc::new(Mutex::new(HttpCompressCache::default()));
+                move | request: &HttpRequest | HttpReply::Response(Self::handle_file(request, &shared, &workspaces_arc, &compress_cache))
+            });
+        
+        // browsers open a handful of keep-alive connections per tab, leave room for a few tabs
+        let options = HttpServerOptions {workers: 32, ..HttpServerOptions::default()};
+        // with tls every accepted socket speaks tls before the first request is read
+        let server = if let Some(tls_config) = tls_config {
+            let wrap: server::HttpStreamWrap = Arc::new(move | tcp_stream | Ok(Box::new(TlsStream::accept(tcp_stream, &tls_config)) as Box<dyn HttpStream>));
+            server::HttpServer::start_wrapped(listen_address, router, options, wrap)
+        }
+        else {
+            server::HttpServer::start(listen_address, router, options)
+        };
+        let server = match server {
+            Ok(server) => server,
+            Err(_) => {
+                println!("Cannot bind http server port");
+                return None
+            }
+        };
+        
+        Some(HttpServer {
+            listen_address: Some(server.listen_address),
+            server: Some(server),
+            shared: shared,
+        })
+    }
+    
+    fn handle_live_reload(request: &HttpRequest, shared: &Arc<Mutex<HttpServerShared >>) -> HttpReply {
+        let key = if let Some(key) = request.header("Sec-WebSocket-Key") {key.to_string()} else {
+            return HttpReply::Response(HttpResponse::new(400))
+        };
+        let files = Self::live_reload_files(request);
+        let shared = Arc::clone(shared);
+        HttpReply::Upgrade(Box::new(move | mut tcp_stream | {
+            // subscribe before answering so nothing sent right after the handshake gets lost
+            let (tx_socket, rx_socket) = mpsc::channel::<Vec<u8 >> ();
+            let socket_id = if let Ok(mut shared) = shared.lock() {
+                if shared.terminate {
+                    return
+                }
+                shared.live_reload_id += 1;
+                let socket_id = shared.live_reload_id;
+                shared.live_reload_sockets.push(LiveReloadSocket {id: socket_id, tx: tx_socket.clone(), files});
+                socket_id
+            }
+            else {
+                return
+            };
+            
+            if let Ok(mut write_stream) = tcp_stream.try_clone_box() {
+                if tcp_stream.write_all(WebSocket::create_upgrade_response(&key).as_bytes()).is_ok() {
+                    std::thread::spawn(move || {
+                        while let Ok(data) = rx_socket.recv() {
+                            if write_stream.write_all(&data).is_err() {
+                                break;
                             }
-                            else {None}
                         }
-                        else {None};
-
-                        if file_path.is_none() {
-                            let _ = tcp_stream.shutdown(Shutdown::Both);
-                            return
-                        }
-                        let file_path = file_path.unwrap();
-                        let file_path = if file_path.ends_with("/") {
-                            format!("{}/{}", file_path, "index.html")
+                        let _ = write_stream.shutdown(Shutdown::Both);
+                    });
+                    
+                    // browsers have nothing to say besides pings and the close handshake
+                    let mut web_socket = WebSocket::new();
+                    let mut data = [0u8; 1024];
+                    while let Ok(n) = tcp_stream.read(&mut data) {
+                        if n == 0 {
+                            break;
                         }
-                        else {
-                            file_path
-                        };
-                        
-                        if let Ok(mut shared) = shared.lock() {
-                            if shared.files_read.iter().find( | v | **v == header.path_no_slash).is_none() {
-                                shared.files_read.push(header.path_no_slash.to_string());
-                            }
-                        };
-                        
-                        if let Ok(data) = std::fs::read(&file_path) {
-                            let mime_type = if file_path.ends_with(".html") {"text/html"}
-                            else if file_path.ends_with(".wasm") {"application/wasm"}
-                            else if file_path.ends_with(".js") {"text/javascript"}
-                            else {"application/octet-stream"};
-                            
-                            // write the header
-                            let header = format!(
-                                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-encoding: identity\r\nTransfer-encoding: identity\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
-                                mime_type,
-                                data.len()
-                            );
-                            write_bytes_to_tcp_stream_no_error(&mut tcp_stream, header.as_bytes());
-                            write_bytes_to_tcp_stream_no_error(&mut tcp_stream, &data);
-                            let _ = tcp_stream.shutdown(Shutdown::Both);
+                        web_socket.parse(&data[0..n]);
+                        for reply in web_socket.take_replies() {
+                            let _ = tx_socket.send(reply);
                         }
-                        else { // 404
-                            write_bytes_to_tcp_stream_no_error(&mut tcp_stream, "HTTP/1.1 404 NotFound\r\n".as_bytes());
-                            let _ = tcp_stream.shutdown(Shutdown::Both);
+                        if web_socket.is_closed() {
+                            break;
                         }
-                    });
+                    }
                 }
-            })
+            }
+            // dropping the last sender lets the write thread finish and close the socket
+            if let Ok(mut shared) = shared.lock() {
+                shared.live_reload_sockets.retain( | socket | socket.id != socket_id);
+            }
+        }))
+    }
+    
+    fn live_reload_files(request: &HttpRequest) -> Option<Vec<String >> {
+        let search = request.search.as_ref() ?;
+        let files = search.trim_start_matches('?').split('&').find_map( | param | param.strip_prefix("files=")) ?;
+        Some(files.split(',').filter_map( | file | {
+            let file = server::percent_decode(file) ?;
+            Some(file.trim_start_matches('/').to_string())
+        }).filter( | file | !file.is_empty()).collect())
+    }
+    
+    // maps workspace/sub/path to a file, never the key files and never outside the workspace
+    fn workspace_file_path(path_no_slash: &str, workspaces: &Arc<Mutex<HashMap<String, String >> >) -> Option<String> {
+        let mut segments = path_no_slash.split('/');
+        let workspace = segments.next() ?;
+        let segments: Vec<&str> = segments.collect();
+        if segments.is_empty() {
+            return None
+        }
+        for (index, segment) in segments.iter().enumerate() {
+            // only the last one can be empty, that is a folder index
+            if (segment.is_empty() && index != segments.len() - 1) || *segment == "." || *segment == ".." || segment.contains('\\') {
+                return None
+            }
+        }
+        if is_hub_key_file(path_no_slash) {
+            return None
+        }
+        let workspaces = workspaces.lock().ok() ?;
+        let abs_path = workspaces.get(workspace) ?;
+        Some(format!("{}/{}", abs_path, segments.join("/")))
+    }
+    
+    fn handle_file(
+        request: &HttpRequest,
+        shared: &Arc<Mutex<HttpServerShared >>,
+        workspaces: &Arc<Mutex<HashMap<String, String >> >,
+        compress_cache: &Arc<Mutex<HttpCompressCache >>
+    ) -> HttpResponse {
+        let path_no_slash = request.path_no_slash();
+        let file_path = if let Some(file_path) = Self::workspace_file_path(path_no_slash, workspaces) {file_path} else {
+            return HttpResponse::not_found()
         };
-        Some(HttpServer {
-            listen_thread: Some(listen_thread),
-            listen_address: Some(listen_address.clone()),
-            shared: shared,
-        })
+        let file_path = if file_path.ends_with("/") {
+            format!("{}{}", file_path, "index.html")
+        }
+        else {
+            file_path
+        };
+        
+        if let Ok(mut shared) = shared.lock() {
+            if shared.files_read.iter().find( | v | **v == path_no_slash).is_none() {
+                shared.files_read.push(path_no_slash.to_string());
+            }
+        };
+        
+        let metadata = match std::fs::metadata(&file_path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return HttpResponse::not_found()
+        };
+        let file_len = metadata.len();
+        let modified_time = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let modified = modified_time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let last_modified = http_date(modified.as_secs());
+        let mime_type = mime_type_for_path(&file_path);
+        
+        // ranges always address the plain file, so those skip compression
+        let compressible = is_compressible_mime_type(mime_type);
+        let (encoding, precompressed) = if compressible && request.header("Range").is_none() {
+            Self::negotiate_encoding(request, &file_path, modified_time)
+        }
+        else {
+            (HttpEncoding::Identity, None)
+        };
+        let (etag, response) = Self::file_response(file_len, modified, &last_modified, encoding, compressible);
+        
+        if Self::is_not_modified(request, &etag, &last_modified) {
+            return HttpResponse {status: 304, ..response}
+        }
+        
+        // a stale If-Range means the client wants the whole new file
+        let range = match request.header("If-Range") {
+            Some(if_range) if if_range != etag && if_range != last_modified => None,
+            _ => request.header("Range").and_then( | range | parse_http_range(range, file_len))
+        };
+        
+        let response = response.with_header("Content-Type", mime_type);
+        if encoding != HttpEncoding::Identity {
+            let data = if let Some(precompressed) = precompressed {
+                std::fs::read(&precompressed).ok()
+            }
+            else {
+                Self::compress_file(&file_path, file_len, modified_time, encoding, compress_cache)
+            };
+            if let Some(data) = data {
+                return response.with_header("Content-Encoding", encoding.name()).with_body(data)
+            }
+            // an unreadable precompressed file or an encoding we can't produce, serve the plain file
+            let (_, response) = Self::file_response(file_len, modified, &last_modified, HttpEncoding::Identity, compressible);
+            return match std::fs::read(&file_path) {
+                Ok(data) => response.with_header("Content-Type", mime_type).with_body(data),
+                Err(_) => HttpResponse::not_found()
+            }
+        }
+        match range {
+            Some(Ok((start, end))) => {
+                match read_file_range(&file_path, start, end - start + 1) {
+                    Ok(data) => HttpResponse {status: 206, ..response}
+                        .with_header("Content-Range", &format!("bytes {}-{}/{}", start, end, file_len))
+                        .with_body(data),
+                    Err(_) => HttpResponse::not_found()
+                }
+            },
+            Some(Err(())) => HttpResponse::new(416)
+                .with_header("Content-Range", &format!("bytes */{}", file_len)),
+            None => match std::fs::read(&file_path) {
+                Ok(data) => response.with_body(data),
+                Err(_) => HttpResponse::not_found()
+            }
+        }
     }
     
-    pub fn send_json_message(&mut self, json_msg: &str) {
-        if let Ok(shared) = self.shared.lock() {
-            for (_, tx) in &shared.watch_pending {
-                let msg = format!(
-                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-encoding: identity\r\nTransfer-encoding: identity\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-                    json_msg.len(),
-                    json_msg
-                );
-                let _ = tx.send(msg);
+    // the etag carries the nanoseconds, a save within the same second still changes it
+    fn file_response(file_len: u64, modified: Duration, last_modified: &str, encoding: HttpEncoding, compressible: bool) -> (String, HttpResponse) {
+        let etag = if encoding == HttpEncoding::Identity {
+            format!("\"{:x}-{:x}\"", file_len, modified.as_nanos())
+        }
+        else {
+            format!("\"{:x}-{:x}-{}\"", file_len, modified.as_nanos(), encoding.name())
+        };
+        // no-cache still lets the browser keep the file, it just has to ask us with the etag first
+        let mut response = HttpResponse::new(200)
+            .with_header("ETag", &etag)
+            .with_header("Last-Modified", last_modified)
+            .with_header("Cache-Control", "no-cache")
+            .with_header("Accept-Ranges", "bytes");
+        if compressible {
+            response = response.with_header("Vary", "Accept-Encoding");
+        }
+        (etag, response)
+    }
+    
+    // a fresh precompressed file next to the original beats compressing it ourselves
+    fn negotiate_encoding(request: &HttpRequest, file_path: &str, modified: SystemTime) -> (HttpEncoding, Option<String>) {
+        let accept_encoding = if let Some(accept_encoding) = request.header("Accept-Encoding") {
+            accept_encoding
+        }
+        else {
+            return (HttpEncoding::Identity, None)
+        };
+        let mut available = Vec::new();
+        let mut precompressed = Vec::new();
+        for encoding in &[HttpEncoding::Brotli, HttpEncoding::Gzip] {
+            let path = format!("{}{}", file_path, encoding.precompressed_ext().unwrap());
+            let fresh = std::fs::metadata(&path).ok()
+                .and_then( | metadata | metadata.modified().ok())
+                .map( | precompressed_modified | precompressed_modified >= modified).unwrap_or(false);
+            if fresh {
+                available.push(*encoding);
+                precompressed.push((*encoding, path));
+            }
+        }
+        for encoding in HttpEncoding::on_the_fly() {
+            if !available.contains(encoding) {
+                available.push(*encoding);
             }
         }
+        let encoding = HttpEncoding::negotiate(accept_encoding, &available);
+        let path = precompressed.into_iter().find( | (e, _) | *e == encoding).map( | (_, path) | path);
+        (encoding, path)
+    }
+    
+    fn compress_file(
+        file_path: &str,
+        file_len: u64,
+        modified: SystemTime,
+        encoding: HttpEncoding,
+        compress_cache: &Arc<Mutex<HttpCompressCache >>
+    ) -> Option<Vec<u8 >> {
+        if let Ok(compress_cache) = compress_cache.lock() {
+            if let Some(digest) = compress_cache.cached_digest(file_path, file_len, modified) {
+                if let Some(data) = compress_cache.get(&digest, encoding) {
+                    return Some(data.to_vec())
+                }
+            }
+        }
+        let data = std::fs::read(file_path).ok() ?;
+        let digest = if let Ok(mut compress_cache) = compress_cache.lock() {
+            let digest = compress_cache.file_digest(file_path, file_len, modified, &data);
+            // the same contents might live under another name
+            if let Some(data) = compress_cache.get(&digest, encoding) {
+                return Some(data.to_vec())
+            }
+            digest
+        }
+        else {
+            return compress_bytes(&data, encoding)
+        };
+        // compress outside of the lock, other files should not wait for this one
+        let compressed = Arc::new(compress_bytes(&data, encoding) ?);
+        if let Ok(mut compress_cache) = compress_cache.lock() {
+            compress_cache.insert(digest, encoding, compressed.clone());
+        }
+        Some(compressed.to_vec())
+    }
+    
+    fn is_not_modified(request: &HttpRequest, etag: &str, last_modified: &str) -> bool {
+        if let Some(if_none_match) = request.header("If-None-Match") {
+            return if_none_match.split(',').any( | tag | {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+        }
+        // browsers send back exactly what we gave them, so comparing the string is enough
+        if let Some(if_modified_since) = request.header("If-Modified-Since") {
+            return if_modified_since == last_modified
+        }
+        false
+    }
+    
+    pub fn send_live_reload(&mut self, msg: &LiveReloadMsg) {
+        let json = msg.serialize_json();
+        let mut ws_msg = WebSocketMessage::new_text(json.len());
+        ws_msg.append(json.as_bytes());
+        let frame = ws_msg.take();
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.live_reload_sockets.retain( | socket | socket.tx.send(frame.clone()).is_ok());
+        }
     }
     
     pub fn send_file_change(&mut self, path: &str) {
+        // only files a browser actually loaded from us are worth a reload
         if let Ok(shared) = self.shared.lock() {
             if shared.files_read.iter().find( | v | **v == path).is_none() {
                 return
             }
         }
-        self.send_json_message(&format!("{{\"type\":\"file_change\",\"path\":\"{}\"}}", path));
+        self.send_live_reload(&LiveReloadMsg::FileChange {path: path.to_string()});
     }
     
     pub fn send_build_start(&mut self) {
-        //self.send_json_message(&format!("{{\"type\":\"build_start\"}}"));
+        self.send_live_reload(&LiveReloadMsg::BuildStart);
+    }
+    
+    pub fn send_build_finished(&mut self, errors: Vec<LiveReloadError>) {
+        self.send_live_reload(&LiveReloadMsg::BuildFinished {errors});
+    }
+    
+    // only pages running this wasm file reload, a page that didnt say which it runs goes by files_read
+    pub fn send_wasm_ready(&mut self, path: &str) {
+        let json = LiveReloadMsg::WasmReady {path: path.to_string()}.serialize_json();
+        let mut ws_msg = WebSocketMessage::new_text(json.len());
+        ws_msg.append(json.as_bytes());
+        let frame = ws_msg.take();
+        if let Ok(mut shared) = self.shared.lock() {
+            let was_read = shared.files_read.iter().any( | v | *v == path);
+            shared.live_reload_sockets.retain( | socket | {
+                let runs_path = match &socket.files {
+                    Some(files) => files.iter().any( | file | *file == path),
+                    None => was_read
+                };
+                !runs_path || socket.tx.send(frame.clone()).is_ok()
+            });
+        }
     }
     
     pub fn terminate(&mut self) {
         if let Ok(mut shared) = self.shared.lock() {
             shared.terminate = true;
-            for (_, tx) in &shared.watch_pending {
-                let _ = tx.send("HTTP/1.1 201 Retry\r\n\r\n".to_string());
+            let close = WebSocketMessage::new_close(WEBSOCKET_CLOSE_GOING_AWAY, "", None).take();
+            for socket in shared.live_reload_sockets.drain(..) {
+                let _ = socket.tx.send(close.clone());
             }
         }
-        if let Some(listen_address) = self.listen_address {
-            self.listen_address = None;
-            if let Ok(_) = TcpStream::connect(listen_address) {
-                self.listen_thread.take().expect("cant take listen thread").join().expect("cant join listen thread");
-            }
+        if let Some(mut server) = self.server.take() {
+            server.terminate();
         }
+        self.listen_address = None;
     }
 }
 
-fn write_bytes_to_tcp_stream_no_error(tcp_stream: &mut TcpStream, bytes: &[u8]) {
-    let bytes_total = bytes.len();
-    let mut bytes_left = bytes_total;
-    while bytes_left > 0 {
-        let buf = &bytes[(bytes_total - bytes_left)..bytes_total];
-        if let Ok(bytes_written) = tcp_stream.write(buf) {
-            if bytes_written == 0 {
-                return
-            }
-            bytes_left -= bytes_written;
+// parses a single byte range into inclusive start and end, None ignores the header
+// and serves the whole file, Err means the range cannot be satisfied
+pub fn parse_http_range(range: &str, file_len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = range.trim().strip_prefix("bytes=") ?;
+    // multiple ranges would need a multipart response, the whole file is a valid answer too
+    if spec.contains(',') {
+        return None
+    }
+    let dash = spec.find('-') ?;
+    let (start, end) = (spec[..dash].trim(), spec[dash + 1..].trim());
+    if start.is_empty() {
+        // the last n bytes
+        let suffix = end.parse::<u64>().ok() ?;
+        if suffix == 0 || file_len == 0 {
+            return Some(Err(()))
         }
-        else {
-            return
+        return Some(Ok((file_len.saturating_sub(suffix), file_len - 1)))
+    }
+    let start = start.parse::<u64>().ok() ?;
+    let end = if end.is_empty() {
+        u64::MAX
+    }
+    else {
+        let end = end.parse::<u64>().ok() ?;
+        if end < start {
+            return None
         }
+        end
+    };
+    if start >= file_len {
+        return Some(Err(()))
     }
+    Some(Ok((start, end.min(file_len - 1))))
+}
+
+fn read_file_range(path: &str, start: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path) ?;
+    file.seek(SeekFrom::Start(start)) ?;
+    let mut data = vec![0u8; len as usize];
+    file.read_exact(&mut data) ?;
+    Ok(data)
 }
@@ -0,0 +1,318 @@
+// builder authentication. Every hub connection starts with a challenge-response handshake,
+// HMAC-SHA256 over nonces from both sides, so the secret never crosses the wire. Afterwards
+// every block carries an HMAC with a sequence number under a key derived for that session
+// and direction, a replayed, reordered or tampered block ends the connection
+use crate::hubmsg::*;
+use crate::hubclient::*;
+use crate::hubtls::*;
+use makepad_microserde::*;
+
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const HUB_AUTH_MAGIC: &[u8; 8] = b"MKPDHUB2";
+pub const HUB_AUTH_NONCE_LEN: usize = 32;
+pub const HUB_AUTH_TAG_LEN: usize = 32;
+pub const HUB_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+const HUB_AUTH_MAX_KEY_ID: usize = 256;
+
+// never served or synced, wherever they end up
+pub const HUB_KEY_FILES: &[&str] = &["key.ron", "keys.ron"];
+
+// the last segment of a path names a key file, in any case
+pub fn is_hub_key_file(path: &str) -> bool {
+    let path = path.trim_end_matches(['/', '\\']);
+    let file_name = path.rsplit(['/', '\\']).next().unwrap_or("").to_ascii_lowercase();
+    HUB_KEY_FILES.iter().any( | key_file | *key_file == file_name)
+}
+
+// the keyrings live in the users config directory, away from anything the http server serves
+pub fn hub_config_path(file_name: &str) -> String {
+    let dir = if let Ok(dir) = std::env::var("MAKEPAD_CONFIG_DIR") {
+        std::path::PathBuf::from(dir)
+    }
+    else if let Ok(dir) = std::env::var("XDG_CONFIG_HOME") {
+        std::path::Path::new(&dir).join("makepad")
+    }
+    else if let Ok(dir) = std::env::var("APPDATA") {
+        std::path::Path::new(&dir).join("makepad")
+    }
+    else if let Ok(home) = std::env::var("HOME") {
+        std::path::Path::new(&home).join(".config").join("makepad")
+    }
+    else {
+        return file_name.to_string()
+    };
+    let _ = std::fs::create_dir_all(&dir);
+    dir.join(file_name).to_string_lossy().to_string()
+}
+const HUB_AUTH_ACCEPTED: u8 = 1;
+const HUB_AUTH_REJECTED: u8 = 0;
+
+// a builder key, the secret is 32 random bytes in hex
+#[derive(Debug, Clone, SerRon, DeRon, PartialEq)]
+pub struct HubKey {
+    pub id: String,
+    pub secret: String,
+}
+
+impl HubKey {
+    pub fn generate(id: &str) -> HubKey {
+        let mut secret = [0u8; 32];
+        SystemRandom::new().fill(&mut secret).expect("Cannot generate random key");
+        HubKey {
+            id: id.to_string(),
+            secret: secret.iter().map( | b | format!("{:02x}", b)).collect()
+        }
+    }
+
+    pub fn load(path: &str) -> Result<HubKey, HubError> {
+        let utf8_data = std::fs::read_to_string(path).map_err( | e | HubError::new(&format!("Cannot read key file {}: {}", path, e))) ?;
+        let key: HubKey = DeRon::deserialize_ron(&utf8_data).map_err( | _ | HubError::new(&format!("Cannot parse key file {}", path))) ?;
+        key.secret_bytes() ?;
+        Ok(key)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), HubError> {
+        std::fs::write(path, self.serialize_ron().as_bytes()).map_err( | e | HubError::new(&format!("Cannot write key file {}: {}", path, e)))
+    }
+
+    // is_multiple_of needs a newer rust than the hub builds with
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn secret_bytes(&self) -> Result<Vec<u8>, HubError> {
+        let secret = self.secret.as_bytes();
+        if secret.is_empty() || secret.len() % 2 != 0 {
+            return Err(HubError::new(&format!("Key {} has an invalid secret", self.id)))
+        }
+        secret.chunks(2).map( | pair | {
+            std::str::from_utf8(pair).ok().and_then( | pair | u8::from_str_radix(pair, 16).ok())
+                .ok_or_else( || HubError::new(&format!("Key {} has an invalid secret", self.id)))
+        }).collect()
+    }
+
+    fn hmac_key(&self) -> Result<hmac::Key, HubError> {
+        Ok(hmac::Key::new(hmac::HMAC_SHA256, &self.secret_bytes() ?))
+    }
+}
+
+// the keys a hub accepts. Revoked ids stay listed so they cannot quietly come back
+#[derive(Debug, Clone, Default, SerRon, DeRon, PartialEq)]
+pub struct HubKeyring {
+    pub keys: Vec<HubKey>,
+    pub revoked: Vec<String>,
+}
+
+impl HubKeyring {
+    pub fn load(path: &str) -> Result<HubKeyring, HubError> {
+        let utf8_data = std::fs::read_to_string(path).map_err( | e | HubError::new(&format!("Cannot read keyring {}: {}", path, e))) ?;
+        DeRon::deserialize_ron(&utf8_data).map_err( | _ | HubError::new(&format!("Cannot parse keyring {}", path)))
+    }
+
+    // None when there is no keyring yet. one that cannot be read or parsed is an error,
+    // never a reason to start over and overwrite the keys in it
+    pub fn load_if_exists(path: &str) -> Result<Option<HubKeyring>, HubError> {
+        match std::fs::metadata(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            _ => Self::load(path).map(Some)
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), HubError> {
+        std::fs::write(path, self.serialize_ron().as_bytes()).map_err( | e | HubError::new(&format!("Cannot write keyring {}: {}", path, e)))
+    }
+
+    // a new key for a builder, replacing an older one with the same id
+    pub fn generate(&mut self, id: &str) -> HubKey {
+        let key = HubKey::generate(id);
+        self.keys.retain( | k | k.id != id);
+        self.revoked.retain( | r | r != id);
+        self.keys.push(key.clone());
+        key
+    }
+
+    pub fn revoke(&mut self, id: &str) -> bool {
+        let known = self.keys.iter().any( | k | k.id == id);
+        self.keys.retain( | k | k.id != id);
+        if !self.revoked.iter().any( | r | r == id) {
+            self.revoked.push(id.to_string());
+        }
+        known
+    }
+
+    pub fn is_revoked(&self, id: &str) -> bool {
+        self.revoked.iter().any( | r | r == id)
+    }
+
+    pub fn find(&self, id: &str) -> Option<&HubKey> {
+        if self.is_revoked(id) {
+            return None
+        }
+        self.keys.iter().find( | k | k.id == id)
+    }
+}
+
+// signs or checks the blocks going one way over a connection
+pub struct HubBlockKey {
+    key: hmac::Key,
+    seq: u64,
+}
+
+impl HubBlockKey {
+    pub fn sign(&mut self, data: &[u8]) -> hmac::Tag {
+        let mut context = hmac::Context::with_key(&self.key);
+        context.update(&self.seq.to_le_bytes());
+        context.update(&(data.len() as u64).to_le_bytes());
+        context.update(data);
+        self.seq += 1;
+        context.sign()
+    }
+
+    pub fn verify(&mut self, data: &[u8], tag: &[u8]) -> bool {
+        let expected = self.sign(data);
+        ring::constant_time::verify_slices_are_equal(expected.as_ref(), tag).is_ok()
+    }
+}
+
+pub struct HubSession {
+    pub key_id: String,
+    pub read_key: HubBlockKey,
+    pub write_key: HubBlockKey,
+}
+
+impl HubSession {
+    fn new(key: &hmac::Key, key_id: &str, server_nonce: &[u8], client_nonce: &[u8], is_server: bool) -> HubSession {
+        let derive = | label: &[u8] | {
+            let mut context = hmac::Context::with_key(key);
+            context.update(label);
+            context.update(server_nonce);
+            context.update(client_nonce);
+            HubBlockKey {
+                key: hmac::Key::new(hmac::HMAC_SHA256, context.sign().as_ref()),
+                seq: 0
+            }
+        };
+        let to_server = derive(b"makepad hub to server");
+        let to_client = derive(b"makepad hub to client");
+        let (read_key, write_key) = if is_server {(to_server, to_client)} else {(to_client, to_server)};
+        HubSession {
+            key_id: key_id.to_string(),
+            read_key,
+            write_key
+        }
+    }
+}
+
+fn handshake_proof(key: &hmac::Key, label: &[u8], first_nonce: &[u8], second_nonce: &[u8], key_id: &str) -> hmac::Tag {
+    let mut context = hmac::Context::with_key(key);
+    context.update(label);
+    context.update(first_nonce);
+    context.update(second_nonce);
+    context.update(key_id.as_bytes());
+    context.sign()
+}
+
+fn generate_nonce() -> Result<[u8; HUB_AUTH_NONCE_LEN], HubError> {
+    let mut nonce = [0u8; HUB_AUTH_NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err( | _ | HubError::new("Cannot generate nonce")) ?;
+    Ok(nonce)
+}
+
+fn write_handshake(tcp_stream: &mut HubStream, data: &[u8]) -> Result<(), HubError> {
+    write_exact_bytes_to_tcp_stream(tcp_stream, data)
+}
+
+fn read_handshake(tcp_stream: &mut HubStream, len: usize) -> Result<Vec<u8>, HubError> {
+    let mut data = vec![0u8; len];
+    read_exact_bytes_from_tcp_stream(tcp_stream, &mut data) ?;
+    Ok(data)
+}
+
+// the hub side, a connection gets HUB_AUTH_TIMEOUT to prove it holds a key in the keyring
+pub fn hub_auth_accept(tcp_stream: &mut HubStream, keyring: &Arc<Mutex<HubKeyring>>) -> Result<HubSession, HubError> {
+    let _ = tcp_stream.tcp_stream().set_read_timeout(Some(HUB_AUTH_TIMEOUT));
+    let server_nonce = generate_nonce() ?;
+    let mut hello = HUB_AUTH_MAGIC.to_vec();
+    hello.extend_from_slice(&server_nonce);
+    write_handshake(tcp_stream, &hello) ?;
+
+    let id_len = read_handshake(tcp_stream, 2) ?;
+    let id_len = u16::from_le_bytes([id_len[0], id_len[1]]) as usize;
+    if id_len == 0 || id_len > HUB_AUTH_MAX_KEY_ID {
+        return Err(HubError::new("Invalid key id"))
+    }
+    let key_id = String::from_utf8(read_handshake(tcp_stream, id_len) ?).map_err( | _ | HubError::new("Invalid key id")) ?;
+    let client_nonce = read_handshake(tcp_stream, HUB_AUTH_NONCE_LEN) ?;
+    let client_proof = read_handshake(tcp_stream, HUB_AUTH_TAG_LEN) ?;
+
+    let (key, revoked) = if let Ok(keyring) = keyring.lock() {
+        (keyring.find(&key_id).cloned(), keyring.is_revoked(&key_id))
+    }
+    else {
+        (None, false)
+    };
+    let rejected = | reason: String, tcp_stream: &mut HubStream | {
+        let _ = write_handshake(tcp_stream, &[HUB_AUTH_REJECTED]);
+        Err(HubError::new(&reason))
+    };
+    let key = match key {
+        Some(key) => key.hmac_key() ?,
+        None if revoked => return rejected(format!("Revoked key {}", key_id), tcp_stream),
+        None => return rejected(format!("Unknown key {}", key_id), tcp_stream),
+    };
+    let expected = handshake_proof(&key, b"makepad hub client", &server_nonce, &client_nonce, &key_id);
+    if ring::constant_time::verify_slices_are_equal(expected.as_ref(), &client_proof).is_err() {
+        return rejected(format!("Wrong secret for key {}", key_id), tcp_stream)
+    }
+
+    // prove we know the secret too
+    let mut accept = vec![HUB_AUTH_ACCEPTED];
+    accept.extend_from_slice(handshake_proof(&key, b"makepad hub server", &client_nonce, &server_nonce, &key_id).as_ref());
+    write_handshake(tcp_stream, &accept) ?;
+    let _ = tcp_stream.tcp_stream().set_read_timeout(None);
+    Ok(HubSession::new(&key, &key_id, &server_nonce, &client_nonce, true))
+}
+
+// the builder side
+pub fn hub_auth_connect(tcp_stream: &mut HubStream, hub_key: &HubKey) -> Result<HubSession, HubError> {
+    let key = hub_key.hmac_key() ?;
+    if hub_key.id.is_empty() || hub_key.id.len() > HUB_AUTH_MAX_KEY_ID {
+        return Err(HubError::new("Invalid key id"))
+    }
+    let _ = tcp_stream.tcp_stream().set_read_timeout(Some(HUB_AUTH_TIMEOUT));
+    let hello = read_handshake(tcp_stream, HUB_AUTH_MAGIC.len() + HUB_AUTH_NONCE_LEN) ?;
+    if &hello[0..HUB_AUTH_MAGIC.len()] != HUB_AUTH_MAGIC {
+        return Err(HubError::new("Not a makepad hub"))
+    }
+    let server_nonce = &hello[HUB_AUTH_MAGIC.len()..];
+    let client_nonce = generate_nonce() ?;
+
+    let mut response = (hub_key.id.len() as u16).to_le_bytes().to_vec();
+    response.extend_from_slice(hub_key.id.as_bytes());
+    response.extend_from_slice(&client_nonce);
+    response.extend_from_slice(handshake_proof(&key, b"makepad hub client", server_nonce, &client_nonce, &hub_key.id).as_ref());
+    write_handshake(tcp_stream, &response) ?;
+
+    let status = read_handshake(tcp_stream, 1).map_err( | _ | HubError::new("Hub closed the connection during authentication")) ?;
+    if status[0] != HUB_AUTH_ACCEPTED {
+        return Err(HubError::new(&format!("Hub rejected key {}", hub_key.id)))
+    }
+    let server_proof = read_handshake(tcp_stream, HUB_AUTH_TAG_LEN) ?;
+    let expected = handshake_proof(&key, b"makepad hub server", &client_nonce, server_nonce, &hub_key.id);
+    if ring::constant_time::verify_slices_are_equal(expected.as_ref(), &server_proof).is_err() {
+        return Err(HubError::new("Hub does not know our key"))
+    }
+    let _ = tcp_stream.tcp_stream().set_read_timeout(None);
+    Ok(HubSession::new(&key, &hub_key.id, server_nonce, &client_nonce, false))
+}
+
+// what happened to builder connections, kept on the server and printed through the HubLog
+#[derive(Debug, Clone, PartialEq)]
+pub enum HubAuditEvent {
+    Connected {addr: HubAddr, key_id: String},
+    Disconnected {addr: HubAddr, key_id: String},
+    Rejected {addr: HubAddr, reason: String},
+    Revoked {key_id: String},
+}
@@ -2,23 +2,31 @@ use crate::process::*;
 use crate::hubmsg::*;
 use crate::hubrouter::*;
 use crate::hubclient::*;
+use crate::hubtls::*;
+use crate::hubauth::*;
 use crate::httpserver::*;
 use crate::wasmstrip::*;
+use crate::filewatch::*;
+use crate::hubsync::*;
 
 use makepad_microserde::*;
 use makepad_http::channel::*;
 
 use std::sync::{Arc, Mutex};
 use std::fs;
+use std::io::Write;
 use std::sync::{mpsc};
 use std::sync::mpsc::RecvTimeoutError;
 use std::collections::HashMap;
 use std::net::{SocketAddr};
 
+#[derive(Clone)]
 pub struct HubBuilder {
     pub route_send: HubRouteSend,
     pub http_server: Arc<Mutex<Option<HttpServer >> >,
+    pub file_watcher: Arc<Mutex<Option<FileWatcher >> >,
     pub workspaces: Arc<Mutex<HashMap<String, String >> >,
+    pub profiles: Arc<Mutex<Vec<BuildProfile >> >,
     pub websocket_channels: WebSocketChannels,
     pub builder: String,
     pub abs_cwd_path: String,
@@ -41,7 +49,7 @@ pub enum HubWsError {
 }
 
 const INCLUDED_FILES: &[&'static str] = &[".json", ".toml", ".js", ".rs", ".txt", ".text", ".ron", ".html"];
-const EXCLUDED_FILES: &[&'static str] = &["key.ron", "makepad_state.ron"];
+const EXCLUDED_FILES: &[&'static str] = &["key.ron", "keys.ron", "makepad_state.ron"];
 const EXCLUDED_DIRS: &[&'static str] = &["target", ".git", ".github", "edit_repo"];
 
 impl HubBuilder {
@@ -55,8 +63,10 @@ impl HubBuilder {
     where F: Fn(&mut HubBuilder, FromHubMsg) -> Result<(), HubWsError> + Clone + Send + 'static {
         let workspaces = Arc::new(Mutex::new(HashMap::<String, String>::new()));
         let http_server = Arc::new(Mutex::new(None));
+        let file_watcher = Arc::new(Mutex::new(None));
         let abs_cwd_path = format!("{}", std::env::current_dir().unwrap().display());
         let processes = Arc::new(Mutex::new(Vec::<HubProcess>::new()));
+        let profiles = Arc::new(Mutex::new(Vec::<BuildProfile>::new()));
         
         let (tx_write, rx_write) = mpsc::channel::<FromHubMsg>();
         
@@ -81,7 +91,9 @@ impl HubBuilder {
                             route_send: route_send.clone(),
                             websocket_channels: websocket_channels.clone(),
                             http_server: Arc::clone(&http_server),
+                            file_watcher: Arc::clone(&file_watcher),
                             workspaces: Arc::clone(&workspaces),
+                            profiles: Arc::clone(&profiles),
                             processes: Arc::clone(&processes),
                             builder: builder.to_string(),
                             abs_cwd_path: abs_cwd_path.clone(),
@@ -116,82 +128,102 @@ impl HubBuilder {
         route_send.clone()
     }
     
-    pub fn run_builder_networked<F>(digest: Digest, in_address: SocketAddr, builder: &str, hub_log: HubLog, event_handler: F)
+    pub fn run_builder_networked<F>(hub_key: HubKey, in_address: SocketAddr, tls: Option<HubTlsClientConfig>, builder: &str, hub_log: HubLog, event_handler: F)
     where F: Fn(&mut HubBuilder, FromHubMsg) -> Result<(), HubWsError> + Clone + Send + 'static {
         
         let workspaces = Arc::new(Mutex::new(HashMap::<String, String>::new()));
         let http_server = Arc::new(Mutex::new(None));
+        let file_watcher = Arc::new(Mutex::new(None));
         let abs_cwd_path = format!("{}", std::env::current_dir().unwrap().display());
         let processes = Arc::new(Mutex::new(Vec::<HubProcess>::new()));
+        let profiles = Arc::new(Mutex::new(Vec::<BuildProfile>::new()));
         
-        loop {
-            
+        let options = HubClientOptions::reconnecting();
+        let mut attempt = 0;
+        let mut hub_client = loop {
             hub_log.msg("Builder connecting to {:?}", &in_address);
-            
-            let mut hub_client = if let Ok(hub_client) = HubClient::connect_to_server(digest.clone(), in_address, hub_log.clone()) {
-                hub_client
-            }
-            else {
-                println!("Builder cannot connect to to {:?}, retrying", in_address);
-                std::thread::sleep(std::time::Duration::from_millis(500));
-                continue;
-            };
-            
-            println!("Builder connected to {:?}", hub_client.own_addr);
-            
-            let route_send = hub_client.get_route_send();
-            
-            route_send.send(ToHubMsg {
-                to: HubMsgTo::All,
-                msg: HubMsg::ConnectBuilder(builder.to_string())
-            });
-            
-            let rx_read = hub_client.rx_read.take().unwrap();
-            
-            while let Ok(htc) = rx_read.recv() {
-                match &htc.msg {
-                    HubMsg::ConnectionError(_e) => {
-                        println!("Got connection error, need to restart loop TODO kill all processes!");
-                        break;
-                    },
-                    _ => ()
+            match HubClient::connect_with_options(&hub_key, in_address, tls.as_ref(), options.clone(), hub_log.clone()) {
+                Ok(hub_client) => break hub_client,
+                Err(e) => {
+                    println!("Builder cannot connect to to {:?}: {}, retrying", in_address, e.msg);
+                    std::thread::sleep(options.backoff(attempt));
+                    attempt += 1;
                 }
-                let is_blocking = htc.msg.is_blocking();
-                let thread = {
-                    let event_handler = event_handler.clone();
-                    let mut hub_builder = HubBuilder {
-                        route_send: route_send.clone(),
-                        websocket_channels: WebSocketChannels::default(),
-                        http_server: Arc::clone(&http_server),
-                        workspaces: Arc::clone(&workspaces),
-                        processes: Arc::clone(&processes),
-                        builder: builder.to_string(),
-                        abs_cwd_path: abs_cwd_path.clone(),
-                    };
-                    std::thread::spawn(move || {
-                        let is_build_uid = if let HubMsg::Build {uid, ..} = &htc.msg {Some(*uid)}else {None};
-                        
-                        let result = event_handler(&mut hub_builder, htc);
-                        
-                        if let Some(is_build_uid) = is_build_uid {
-                            if result.is_ok() {
-                                hub_builder.route_send.send(ToHubMsg {
-                                    to: HubMsgTo::UI,
-                                    msg: HubMsg::BuildFailure {uid: is_build_uid}
-                                });
-                            }
-                            else {
-                                hub_builder.route_send.send(ToHubMsg {
-                                    to: HubMsgTo::UI,
-                                    msg: HubMsg::BuildSuccess {uid: is_build_uid}
-                                });
-                            }
-                        }
-                    })
+            }
+        };
+        
+        println!("Builder connected to {:?}", hub_client.own_addr);
+        
+        let route_send = hub_client.get_route_send();
+        
+        route_send.send(ToHubMsg {
+            to: HubMsgTo::All,
+            msg: HubMsg::ConnectBuilder(builder.to_string())
+        });
+        
+        let rx_read = hub_client.rx_read.take().unwrap();
+        // builds and programs keep running whilst the client reconnects, the ui replays its
+        // requests when we are back so these must not start twice
+        let running = Arc::new(Mutex::new(Vec::<HubUid>::new()));
+        
+        while let Ok(htc) = rx_read.recv() {
+            match &htc.msg {
+                HubMsg::ConnectionError(e) => {
+                    println!("Builder lost the connection to {:?}: {}, reconnecting", in_address, e.msg);
+                    continue;
+                },
+                HubMsg::Build {uid, ..} | HubMsg::ProgramRun {uid, ..} => if let Ok(mut running) = running.lock() {
+                    if running.contains(uid) {
+                        continue;
+                    }
+                    running.push(*uid);
+                },
+                _ => ()
+            }
+            let is_blocking = htc.msg.is_blocking();
+            let thread = {
+                let event_handler = event_handler.clone();
+                let running = Arc::clone(&running);
+                let mut hub_builder = HubBuilder {
+                    route_send: route_send.clone(),
+                    websocket_channels: WebSocketChannels::default(),
+                    http_server: Arc::clone(&http_server),
+                    file_watcher: Arc::clone(&file_watcher),
+                    workspaces: Arc::clone(&workspaces),
+                    profiles: Arc::clone(&profiles),
+                    processes: Arc::clone(&processes),
+                    builder: builder.to_string(),
+                    abs_cwd_path: abs_cwd_path.clone(),
                 };
-                if is_blocking {
-                    let _ = thread.join();
-                }
+                std::thread::spawn(move || {
+                    let is_build_uid = if let HubMsg::Build {uid, ..} = &htc.msg {Some(*uid)}else {None};
+                    let request_uid = htc.msg.request_uid();
+                    
+                    let result = event_handler(&mut hub_builder, htc);
+                    
+                    if let Some(is_build_uid) = is_build_uid {
+                        if result.is_ok() {
+                            hub_builder.route_send.send(ToHubMsg {
+                                to: HubMsgTo::UI,
+                                msg: HubMsg::BuildFailure {uid: is_build_uid}
+                            });
+                        }
+                        else {
+                            hub_builder.route_send.send(ToHubMsg {
+                                to: HubMsgTo::UI,
+                                msg: HubMsg::BuildSuccess {uid: is_build_uid}
+                            });
+                        }
+                    }
+                    if let Some(request_uid) = request_uid {
+                        if let Ok(mut running) = running.lock() {
+                            running.retain( | uid | *uid != request_uid);
+                        }
+                    }
+                })
+            };
+            if is_blocking {
+                let _ = thread.join();
             }
         }
     }
@@ -205,6 +237,18 @@ impl HubBuilder {
             println!("cargo run -p builder -- connect <ip>:<port> <key.ron> <workspace>");
             println!("example: cargo run -p builder -- connect 127.0.0.1:7243 key.ron windows");
             println!("");
+            println!("Connect to a hub server over tls, trusting the certificate in <ca.pem>");
+            println!("cargo run -p builder -- connect <ip>:<port> <key.ron> <workspace> <ca.pem> <server name>");
+            println!("example: cargo run -p builder -- connect 10.0.0.2:7243 key.ron windows hub.pem localhost");
+            println!("");
+            println!("Make a key for a builder in the keyring of a hub, and write it to <key.ron> for that builder");
+            println!("cargo run -p builder -- genkey <keys.ron> <id> <key.ron>");
+            println!("example: cargo run -p builder -- genkey keys.ron windows windows_key.ron");
+            println!("");
+            println!("Revoke the key of a builder, a running hub drops its connections");
+            println!("cargo run -p builder -- revoke <keys.ron> <id>");
+            println!("example: cargo run -p builder -- revoke keys.ron windows");
+            println!("");
             println!("Build a specific package");
             println!("cargo run -p builder -- build <path> <package> <config>");
             println!("example: cargo run -p builder -- build edit_repo makepad release");
@@ -225,16 +269,49 @@ impl HubBuilder {
         let (message, path, mount) = match args[1].as_ref() {
             
             "connect" => {
-                if args.len() != 5 {
+                if args.len() != 5 && args.len() != 7 {
                     return print_help();
                 }
                 let addr = args[2].parse().expect("cant parse address");
                 let key_file = args[3].to_string();
                 let builder = args[4].to_string();
-                let utf8_data = std::fs::read_to_string(key_file).expect("Can't read key file");
-                let digest: Digest = DeRon::deserialize_ron(&utf8_data).expect("Can't load key file");
+                let hub_key = HubKey::load(&key_file).expect("Can't load key file");
+                let tls = if args.len() == 7 {
+                    Some(HubTlsClientConfig {ca: args[5].clone(), server_name: args[6].clone()})
+                }
+                else {
+                    None
+                };
                 println!("Starting workspace connecting to ip");
-                Self::run_builder_networked(digest, addr, &builder, HubLog::None, event_handler);
+                Self::run_builder_networked(hub_key, addr, tls, &builder, HubLog::None, event_handler);
+                return
+            },
+            "genkey" => {
+                if args.len() != 5 {
+                    return print_help();
+                }
+                let mut keyring = match HubKeyring::load_if_exists(&args[2]) {
+                    Ok(keyring) => keyring.unwrap_or_default(),
+                    Err(err) => {
+                        println!("{}, leaving it untouched", err.msg);
+                        return
+                    }
+                };
+                let hub_key = keyring.generate(&args[3]);
+                keyring.save(&args[2]).expect("Can't write keyring");
+                hub_key.save(&args[4]).expect("Can't write key file");
+                println!("Added key {} to {}, written to {}", args[3], args[2], args[4]);
+                return
+            },
+            "revoke" => {
+                if args.len() != 4 {
+                    return print_help();
+                }
+                let mut keyring = HubKeyring::load(&args[2]).expect("Can't load keyring");
+                if !keyring.revoke(&args[3]) {
+                    println!("No key {} in {}, revoked it anyway", args[3], args[2]);
+                }
+                keyring.save(&args[2]).expect("Can't write keyring");
                 return
             },
             "list" => {
@@ -272,7 +349,9 @@ impl HubBuilder {
         
         let workspaces = Arc::new(Mutex::new(HashMap::<String, String>::new()));
         let http_server = Arc::new(Mutex::new(None));
+        let file_watcher = Arc::new(Mutex::new(None));
         let processes = Arc::new(Mutex::new(Vec::<HubProcess>::new()));
+        let profiles = Arc::new(Mutex::new(Vec::<BuildProfile>::new()));
         let abs_cwd_path = format!("{}", std::env::current_dir().unwrap().display());
         
         if let Ok(mut workspaces) = workspaces.lock() {
@@ -292,9 +371,11 @@ impl HubBuilder {
             },
             websocket_channels: WebSocketChannels::default(),
             http_server: Arc::clone(&http_server),
+            file_watcher: Arc::clone(&file_watcher),
             builder: "".to_string(),
             processes: Arc::clone(&processes),
             workspaces: Arc::clone(&workspaces),
+            profiles: Arc::clone(&profiles),
             abs_cwd_path: abs_cwd_path.clone()
         };
         
@@ -385,6 +466,10 @@ impl HubBuilder {
             }
         };
         
+        if let Ok(mut profiles) = self.profiles.lock() {
+            *profiles = config.profiles;
+        };
+        
         let workspaces = Arc::clone(&self.workspaces);
         
         if let Ok(mut http_server) = self.http_server.lock() {
@@ -392,13 +477,69 @@ impl HubBuilder {
                 http_server.terminate();
             }
             
-            *http_server = HttpServer::start_http_server(&config.http_server, self.websocket_channels.clone(), workspaces);
+            *http_server = HttpServer::start_http_server(&config.http_server, self.websocket_channels.clone(), Arc::clone(&workspaces));
         }
         
+        if let Ok(mut file_watcher) = self.file_watcher.lock() {
+            if let Some(file_watcher) = &mut *file_watcher {
+                file_watcher.terminate();
+            }
+            let roots = if let Ok(workspaces) = workspaces.lock() {workspaces.values().cloned().collect()} else {Vec::new()};
+            let mut hub_builder = self.clone();
+            *file_watcher = Some(FileWatcher::start(roots, EXCLUDED_DIRS, move | changes | hub_builder.file_watch_changes(changes)));
+        }
         
         Ok(())
     }
     
+    // something touched the workspaces on disk, tell the ui and the browsers
+    pub fn file_watch_changes(&mut self, changes: FileWatchChanges) {
+        if changes.tree_changed {
+            let tree = self.workspace_file_tree(
+                true,
+                INCLUDED_FILES,
+                EXCLUDED_FILES,
+                EXCLUDED_DIRS
+            );
+            self.route_send.send(ToHubMsg {
+                to: HubMsgTo::UI,
+                msg: HubMsg::FileTreeChanged {tree}
+            });
+        }
+        for abs_path in &changes.files {
+            let path = if let Some(path) = self.workspace_path_from_abs(abs_path) {path} else {continue};
+            let name = path.rsplit('/').next().unwrap_or("");
+            if EXCLUDED_FILES.contains(&name) || is_hub_key_file(name) || !INCLUDED_FILES.iter().any( | ext | name.ends_with(ext)) {
+                continue
+            }
+            if let Ok(mut http_server) = self.http_server.lock() {
+                if let Some(http_server) = &mut *http_server {
+                    http_server.send_file_change(&path);
+                }
+            };
+            let digest = std::fs::read(abs_path).ok().map( | data | Box::new(Digest::from_buffer(&data)));
+            self.route_send.send(ToHubMsg {
+                to: HubMsgTo::UI,
+                msg: HubMsg::FileChanged {
+                    builder: self.builder.clone(),
+                    path,
+                    digest
+                }
+            });
+        }
+    }
+    
+    pub fn workspace_path_from_abs(&self, abs_path: &str) -> Option<String> {
+        if let Ok(workspaces) = self.workspaces.lock() {
+            for (workspace, abs_dir) in workspaces.iter() {
+                if abs_path.starts_with(abs_dir.as_str()) && abs_path[abs_dir.len()..].starts_with('/') {
+                    return Some(format!("{}/{}", workspace, project_rel_path(abs_dir, abs_path)))
+                }
+            }
+        }
+        None
+    }
+    
     pub fn default(&mut self, htc: FromHubMsg) -> Result<(), HubWsError> {
         let ws = self;
         match htc.msg {
@@ -429,6 +570,42 @@ impl HubBuilder {
                 ws.file_write(htc.from, uid, &path, data);
                 Ok(())
             },
+            HubMsg::FileTreeDigestRequest {uid, path} => {
+                ws.file_tree_digest(htc.from, uid, &path);
+                Ok(())
+            },
+            HubMsg::FileSignatureRequest {uid, path, block_size} => {
+                ws.file_signature(htc.from, uid, &path, block_size);
+                Ok(())
+            },
+            HubMsg::FileDeltaRequest {uid, path, signature} => {
+                ws.file_delta(htc.from, uid, &path, &signature);
+                Ok(())
+            },
+            HubMsg::FileDeltaWriteRequest {uid, path, delta} => {
+                ws.file_delta_write(htc.from, uid, &path, &delta);
+                Ok(())
+            },
+            HubMsg::FileCreateRequest {uid, path, data} => {
+                ws.file_create(htc.from, uid, &path, &data);
+                Ok(())
+            },
+            HubMsg::FileDeleteRequest {uid, path} => {
+                ws.file_delete(htc.from, uid, &path);
+                Ok(())
+            },
+            HubMsg::FileRenameRequest {uid, path, new_path} => {
+                ws.file_rename(htc.from, uid, &path, &new_path);
+                Ok(())
+            },
+            HubMsg::FileMkdirRequest {uid, path} => {
+                ws.file_mkdir(htc.from, uid, &path);
+                Ok(())
+            },
+            HubMsg::FileStatRequest {uid, path} => {
+                ws.file_stat(htc.from, uid, &path);
+                Ok(())
+            },
             HubMsg::BuildKill {uid} => {
                 ws.process_kill(uid);
                 Ok(())
@@ -437,9 +614,13 @@ impl HubBuilder {
                 ws.process_kill(uid);
                 Ok(())
             },
-            HubMsg::ProgramRun {uid, path, args} => {
+            HubMsg::ProgramRun {uid, path, args, options} => {
                 let v: Vec<&str> = args.iter().map( | v | v.as_ref()).collect();
-                ws.program_run(uid, &path, &v) ?;
+                ws.program_run(uid, &path, &v, &options) ?;
+                Ok(())
+            },
+            HubMsg::ProgramStdin {uid, input, eof} => {
+                ws.program_stdin(uid, &input, eof) ?;
                 Ok(())
             },
             _ => Ok(())
@@ -456,6 +637,30 @@ impl HubBuilder {
         };
     }
     
+    pub fn program_stdin(&mut self, uid: HubUid, input: &str, eof: bool) -> Result<(), HubWsError> {
+        let result = if let Ok(mut procs) = self.processes.lock() {
+            if let Some(proc) = procs.iter_mut().find( | proc | proc.uid == uid) {
+                let result = proc.process.write_stdin(input.as_bytes());
+                if eof {
+                    proc.process.close_stdin();
+                }
+                result.map_err( | e | format!("{:?}", e))
+            }
+            else {
+                Err("not running".to_string())
+            }
+        }
+        else {
+            Err("cannot lock processes".to_string())
+        };
+        if let Err(e) = result {
+            return Err(
+                self.error(uid, format!("Builder {} cannot write to program stdin {}", self.builder, e))
+            );
+        }
+        Ok(())
+    }
+    
     pub fn workspace_split_from_path(&mut self, uid: HubUid, path: &str) -> Result<(String, String, String), HubWsError> {
         if let Some(workspace_pos) = path.find("/") {
             let (workspace, rest) = path.split_at(workspace_pos);
@@ -479,11 +684,11 @@ impl HubBuilder {
         )
     }
     
-    pub fn program_run(&mut self, uid: HubUid, path: &str, args: &[&str]) -> Result<(), HubWsError> {
+    pub fn program_run(&mut self, uid: HubUid, path: &str, args: &[&str], options: &ProgramOptions) -> Result<(), HubWsError> {
         
         let (abs_dir, workspace, sub_path) = self.workspace_split_from_path(uid, path) ?;
         
-        let process = Process::start(&sub_path, args, &abs_dir, &[("RUST_BACKTRACE", "full")]);
+        let process = Process::start_with_options(&sub_path, args, &abs_dir, &[("RUST_BACKTRACE", "full")], options);
         if let Err(e) = process {
             return Err(
                 self.error(uid, format!("Builder {} program run {} {} not found {:?}", self.builder, abs_dir, sub_path, e))
@@ -590,6 +795,7 @@ impl HubBuilder {
                         body: rendered[0].clone(),
                         rendered: Some(rendered.join("")),
                         explanation: Some(panic_stack[1..].join("")),
+                        suggestions: Vec::new(),
                     })
                 }
             });
@@ -629,25 +835,87 @@ impl HubBuilder {
                     }
                 }
             }
+            // a program that keeps printing runs into its limit as well
+            if let Ok(mut processes) = self.processes.lock() {
+                if let Some(proc) = processes.iter_mut().find( | p | p.uid == uid) {
+                    proc.process.check_time_limit();
+                }
+            };
+        }
+        
+        // wait outside the lock, a kill or stdin write for another program must not block on it
+        let process = if let Ok(mut processes) = self.processes.lock() {
+            processes.iter().position( | p | p.uid == uid).map( | index | processes.remove(index))
+        }
+        else {
+            None
+        };
+        let exit = if let Some(mut proc) = process {
+            proc.process.wait()
+        }
+        else {
+            ProgramExit::Unknown
+        };
+        
+        if !exit.is_success() {
+            route_mode.send(ToHubMsg {
+                to: HubMsgTo::UI,
+                msg: HubMsg::LogItem {
+                    uid: uid,
+                    item: HubLogItem::Message(format!("Program {} {}\n", sub_path, exit.describe()))
+                }
+            });
         }
         
         route_mode.send(ToHubMsg {
             to: HubMsgTo::UI,
             msg: HubMsg::ProgramEnd {
-                uid: uid,
+                uid,
+                exit
             }
         });
         
-        if let Ok(mut processes) = self.processes.lock() {
-            if let Some(index) = processes.iter().position( | p | p.uid == uid) {
-                processes.remove(index);
-            }
-        };
-        
         Ok(())
     }
     
     
+    pub fn build_profiles(&self) -> Vec<BuildProfile> {
+        // a builder that was never configured builds like it always did
+        match self.profiles.lock() {
+            Ok(profiles) if !profiles.is_empty() => profiles.clone(),
+            _ => BuildProfile::defaults()
+        }
+    }
+    
+    pub fn package_configs(&self, package: &str) -> Vec<String> {
+        self.build_profiles().iter().filter( | profile | profile.applies_to(package)).map( | profile | profile.name.clone()).collect()
+    }
+    
+    pub fn build(&mut self, uid: HubUid, workspace: &str, package: &str, config: &str) -> Result<(), HubWsError> {
+        let profile = if let Some(profile) = self.build_profiles().into_iter().find( | profile | profile.name == config && profile.applies_to(package)) {
+            profile
+        }
+        else {
+            return self.cannot_find_build(uid, package, config)
+        };
+        let args = profile.cargo_args(package);
+        let env = profile.cargo_env();
+        let build_result = self.cargo(
+            uid,
+            workspace,
+            &args.iter().map( | arg | arg.as_str()).collect::<Vec<&str >> (),
+            &env.iter().map( | (k, v) | (k.as_str(), v.as_str())).collect::<Vec<(&str, &str) >> ()
+        ) ?;
+        for post_step in &profile.post_steps {
+            match post_step {
+                BuildPostStep::WasmStripDebug => if let BuildResult::Wasm {path} = &build_result {
+                    self.wasm_strip_debug(uid, path) ?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
     pub fn cannot_find_build(&mut self, uid: HubUid, package: &str, target: &str) -> Result<(), HubWsError> {
         Err(
             self.error(uid, format!("Builder {} Cannot find package {} and target {}", self.builder, package, target))
@@ -664,8 +932,10 @@ impl HubBuilder {
         
         let abs_root_path = self.get_workspace_abs(uid, workspace) ?;
         
+        // args after -- go to the test binaries, not to cargo
         let mut extargs = args.to_vec();
-        extargs.push("--message-format=json");
+        let cargo_args_end = extargs.iter().position( | arg | *arg == "--").unwrap_or(extargs.len());
+        extargs.insert(cargo_args_end, "--message-format=json");
         let mut process = Process::start("cargo", &extargs, &abs_root_path, env).expect("Cannot start process");
         
         let route_send = self.route_send.clone();
@@ -688,8 +958,19 @@ impl HubBuilder {
         
         let mut errors = Vec::new();
         let mut build_result = BuildResult::NoOutput;
+        let mut test_suite = String::new();
         while let Ok(line) = rx_line.recv() {
             if let Some((is_stderr, line)) = line {
+                if is_stderr {
+                    if let Some(suite) = test_suite_name(&line) {
+                        test_suite = suite;
+                        continue
+                    }
+                }
+                else if let Ok(test) = DeJson::deserialize_json(&line) {
+                    self.libtest_message(uid, workspace, &abs_root_path, &test_suite, test);
+                    continue
+                }
                  if is_stderr && line != "\n"
                     && !line.contains("Finished")
                     && !line.contains("Blocking")
@@ -770,6 +1051,7 @@ impl HubBuilder {
                                     body: msg,
                                     rendered: message.rendered.clone(),
                                     explanation: if let Some(code) = &message.code {code.explanation.clone()}else {None},
+                                    suggestions: message.suggestions(&path),
                                 };
                                 //println!("{:?}", loc_message);
                                 let item = match message.level.as_ref() {
@@ -803,8 +1085,10 @@ impl HubBuilder {
                             });
                             if errors.len() == 0 {
                                 build_result = BuildResult::NoOutput;
+                                // test binaries are run by cargo itself
+                                let is_test = if let Some(profile) = &parsed.profile {profile.test} else {false};
                                 if let Some(executable) = &parsed.executable {
-                                    if !executable.ends_with(".rmeta") && abs_root_path.len() + 1 < executable.len() {
+                                    if !is_test && !executable.ends_with(".rmeta") && abs_root_path.len() + 1 < executable.len() {
                                         let last = executable.clone().split_off(abs_root_path.len() + 1);
                                         
                                         build_result = BuildResult::Executable {path: format!("{}/{}", workspace, last).replace("\\", "/")};
@@ -818,7 +1102,7 @@ impl HubBuilder {
                                             let path = format!("{}/{}", workspace, last).replace("\\", "/");
                                             if let Ok(mut http_server) = self.http_server.lock() {
                                                 if let Some(http_server) = &mut *http_server {
-                                                    http_server.send_file_change(&path);
+                                                    http_server.send_wasm_ready(&path);
                                                 }
                                             };
                                             build_result = BuildResult::Wasm {path: path};
@@ -839,6 +1123,17 @@ impl HubBuilder {
             }
         }
         
+        if let Ok(mut http_server) = self.http_server.lock() {
+            if let Some(http_server) = &mut *http_server {
+                http_server.send_build_finished(errors.iter().map( | loc | LiveReloadError {
+                    path: loc.path.clone(),
+                    line: loc.line,
+                    column: loc.column,
+                    message: loc.body.clone()
+                }).collect());
+            }
+        };
+        
         // process ends as well
         route_send.send(ToHubMsg {
             to: HubMsgTo::UI,
@@ -861,12 +1156,45 @@ impl HubBuilder {
         return Ok(build_result);
     }
     
+    fn libtest_message(&mut self, uid: HubUid, workspace: &str, abs_root_path: &str, suite: &str, test: LibtestMessage) {
+        let msg = match (test.ty.as_ref(), test.event.as_ref(), test.name) {
+            ("test", "started", Some(name)) => HubMsg::TestStarted {uid, suite: suite.to_string(), name},
+            ("test", "ok", Some(name)) => HubMsg::TestPassed {uid, suite: suite.to_string(), name, stdout: test.stdout.unwrap_or_default()},
+            ("test", "failed", Some(name)) => {
+                let stdout = test.stdout.unwrap_or_default();
+                let panic = test_panic_loc(&self.builder, workspace, abs_root_path, &stdout);
+                HubMsg::TestFailed {uid, suite: suite.to_string(), name, stdout, panic}
+            },
+            ("test", "ignored", Some(name)) => HubMsg::TestIgnored {uid, suite: suite.to_string(), name, reason: test.message},
+            ("suite", "ok", _) | ("suite", "failed", _) => {
+                let result = format!(
+                    "{} - {} passed, {} failed, {} ignored, {} filtered out",
+                    suite,
+                    test.passed.unwrap_or(0),
+                    test.failed.unwrap_or(0),
+                    test.ignored.unwrap_or(0),
+                    test.filtered_out.unwrap_or(0)
+                );
+                HubMsg::LogItem {
+                    uid,
+                    item: if test.event == "ok" {HubLogItem::Message(result)} else {HubLogItem::Error(result)}
+                }
+            },
+            _ => return
+        };
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::UI,
+            msg
+        });
+    }
+    
     pub fn packages_response(&mut self, from: HubAddr, uid: HubUid, packages: Vec<HubPackage>) {
         
         self.route_send.send(ToHubMsg {
             to: HubMsgTo::Client(from),
             msg: HubMsg::ListPackagesResponse {
                 uid: uid,
+                builder: self.builder.clone(),
                 packages: packages
             }
         });
@@ -923,7 +1251,8 @@ impl HubBuilder {
         Err(self.error(uid, format!("Cannot read wasm {}", filepath)))
     }
     
-    pub fn read_packages(&mut self, uid: HubUid) -> Vec<(String, String)> {
+    // workspace, package name and the package dir in the workspace
+    pub fn read_packages(&mut self, uid: HubUid) -> Vec<(String, String, String)> {
         
         let mut packages = Vec::new();
         let workspaces = Arc::clone(&self.workspaces);
@@ -946,29 +1275,25 @@ impl HubBuilder {
                     Ok(v) => v
                 };
                 
-                if let Some(Toml::Array(members)) = toml.get("workspace.members") {
-                    for member in members {
-                        if let Toml::Str(member) = member {
-                            let file_path = format!("{}/{}/Cargo.toml", abs_path, member);
-                            let vis_path = format!("{}/{}/{}/Cargo.toml", self.builder, workspace, member);
-                            let cargo = match std::fs::read_to_string(&file_path) {
-                                Err(_) => {
-                                    self.error(uid, format!("Cannot read cargo {}", vis_path));
-                                    continue;
-                                },
-                                Ok(v) => v
-                            };
-                            let toml = match TomlParser::parse(&cargo) {
-                                Err(e) => {
-                                    self.error(uid, format!("Cannot parse cargo {} {:?}", vis_path, e));
-                                    continue;
-                                },
-                                Ok(v) => v
-                            };
-                            if let Some(Toml::Str(name)) = toml.get("package.name") {
-                                packages.push((workspace.clone(), name.clone()));
-                            }
-                        }
+                for member in cargo_workspace_members(&toml, abs_path) {
+                    let file_path = format!("{}/{}/Cargo.toml", abs_path, member);
+                    let vis_path = format!("{}/{}/{}/Cargo.toml", self.builder, workspace, member);
+                    let cargo = match std::fs::read_to_string(&file_path) {
+                        Err(_) => {
+                            self.error(uid, format!("Cannot read cargo {}", vis_path));
+                            continue;
+                        },
+                        Ok(v) => v
+                    };
+                    let toml = match TomlParser::parse(&cargo) {
+                        Err(e) => {
+                            self.error(uid, format!("Cannot parse cargo {} {:?}", vis_path, e));
+                            continue;
+                        },
+                        Ok(v) => v
+                    };
+                    if let Some(Toml::Str(name)) = toml.path("package.name") {
+                        packages.push((workspace.clone(), name.clone(), member));
                     }
                 }
             }
@@ -984,8 +1309,8 @@ impl HubBuilder {
                 self.error(uid, format!("file_read got relative path, ignoring {}", path));
                 return
             }
-            if sub_path.ends_with("key.ron") {
-                self.error(uid, format!("Ends with key.ron, ignoring {}", path));
+            if is_hub_key_file(&sub_path) {
+                self.error(uid, format!("Ends with key.ron or keys.ron, ignoring {}", path));
                 return
             }
             
@@ -1015,13 +1340,17 @@ impl HubBuilder {
                 println!("file_read got relative path, ignoring {}", path);
                 return
             }
-            
-            let done = std::fs::write(format!("{}/{}", abs_dir, sub_path), &data).is_ok();
-            
-            if let Ok(mut http_server) = self.http_server.lock() {
-                if let Some(http_server) = &mut *http_server {
-                    http_server.send_file_change(path);
+            let done = if is_hub_key_file(&sub_path) {
+                self.error(uid, format!("Ends with key.ron or keys.ron, ignoring {}", path));
+                false
+            }
+            else {
+                // the file watcher sees this write and tells the browsers
+                let abs_path = format!("{}/{}", abs_dir, sub_path);
+                if let Some(parent) = std::path::Path::new(&abs_path).parent() {
+                    let _ = std::fs::create_dir_all(parent);
                 }
+                std::fs::write(&abs_path, &data).is_ok()
             };
             
             self.route_send.send(ToHubMsg {
@@ -1035,85 +1364,250 @@ impl HubBuilder {
         }
     }
     
-    pub fn workspace_file_tree(&mut self, create_digest: bool, ext_inc: &[&str], file_ex: &[&str], dir_ex: &[&str]) -> BuilderFileTreeNode {
-        fn digest_folder(create_digest: bool, name: &str, folder: &Vec<BuilderFileTreeNode>) -> Option<Box<Digest >> {
-            if !create_digest {
-                return None;
+    // the absolute path of a file or folder in a workspace, refusing anything that walks out of it or holds keys
+    pub fn workspace_file_abs(&mut self, uid: HubUid, path: &str) -> Option<String> {
+        if path.contains("..") {
+            self.error(uid, format!("Builder {} got relative path, ignoring {}", self.builder, path));
+            return None
+        }
+        if is_hub_key_file(path) {
+            self.error(uid, format!("Ends with key.ron or keys.ron, ignoring {}", path));
+            return None
+        }
+        if path.find('/').is_none() {
+            return self.get_workspace_abs(uid, path).ok()
+        }
+        let (abs_dir, _workspace, sub_path) = self.workspace_split_from_path(uid, path).ok() ?;
+        Some(format!("{}/{}", abs_dir, sub_path))
+    }
+    
+    pub fn file_tree_digest(&mut self, from: HubAddr, uid: HubUid, path: &str) {
+        let nodes = self.workspace_file_abs(uid, path).and_then( | abs_path | {
+            if !std::path::Path::new(&abs_path).is_dir() {
+                return None
             }
-            let mut digest_out = Digest::default();
-            for item in folder {
-                match item {
-                    BuilderFileTreeNode::File {digest, ..} => {
-                        if let Some(digest) = digest {
-                            digest_out.digest_other(&*digest);
-                        }
-                    },
-                    BuilderFileTreeNode::Folder {digest, ..} => {
-                        if let Some(digest) = digest {
-                            digest_out.digest_other(&*digest);
-                        }
-                    },
+            // only the digests of the subfolders travel, the ui asks again for the ones that differ
+            Some(read_file_tree(&abs_path, true, INCLUDED_FILES, EXCLUDED_FILES, EXCLUDED_DIRS).into_iter().map( | node | match node {
+                BuilderFileTreeNode::Folder {name, digest, ..} => BuilderFileTreeNode::Folder {name, digest, folder: Vec::new()},
+                file => file
+            }).collect())
+        });
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileTreeDigestResponse {
+                uid,
+                path: path.to_string(),
+                nodes
+            }
+        });
+    }
+    
+    pub fn file_signature(&mut self, from: HubAddr, uid: HubUid, path: &str, block_size: u32) {
+        let signature = self.workspace_file_abs(uid, path)
+            .and_then( | abs_path | std::fs::read(abs_path).ok())
+            .map( | data | FileSignature::new(&data, block_size));
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileSignatureResponse {
+                uid,
+                path: path.to_string(),
+                signature
+            }
+        });
+    }
+    
+    pub fn file_delta(&mut self, from: HubAddr, uid: HubUid, path: &str, signature: &FileSignature) {
+        let delta = self.workspace_file_abs(uid, path)
+            .and_then( | abs_path | std::fs::read(abs_path).ok())
+            .map( | data | FileDelta::new(signature, &data));
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileDeltaResponse {
+                uid,
+                path: path.to_string(),
+                delta
+            }
+        });
+    }
+    
+    pub fn file_delta_write(&mut self, from: HubAddr, uid: HubUid, path: &str, delta: &FileDelta) {
+        let done = if let Some(abs_path) = self.workspace_file_abs(uid, path) {
+            let base = std::fs::read(&abs_path).unwrap_or(Vec::new());
+            match delta.apply(&base) {
+                Ok(data) => std::fs::write(&abs_path, &data).is_ok(),
+                Err(e) => {
+                    self.error(uid, format!("Builder {} cannot apply delta to {}: {}", self.builder, path, e.msg));
+                    false
                 }
             }
-            digest_out.digest_buffer(name.as_bytes());
-            Some(Box::new(digest_out))
         }
-        
-        fn read_recur(path: &str, create_digest: bool, ext_inc: &Vec<String>, file_ex: &Vec<String>, dir_ex: &Vec<String>) -> Vec<BuilderFileTreeNode> {
-            let mut ret = Vec::new();
-            if let Ok(read_dir) = fs::read_dir(path) {
-                for entry in read_dir {
-                    if let Ok(entry) = entry {
-                        if let Ok(ty) = entry.file_type() {
-                            if let Ok(name) = entry.file_name().into_string() {
-                                if ty.is_dir() {
-                                    if dir_ex.iter().find( | dir | **dir == name).is_some() {
-                                        continue
-                                    }
-                                    let folder = read_recur(&format!("{}/{}", path, name), create_digest, ext_inc, file_ex, dir_ex);
-                                    ret.push(BuilderFileTreeNode::Folder {
-                                        name: name.clone(),
-                                        digest: digest_folder(create_digest, &name, &folder),
-                                        folder: folder
-                                    });
-                                }
-                                else {
-                                    if file_ex.iter().find( | file | **file == name).is_some() {
-                                        continue
-                                    }
-                                    if ext_inc.iter().find( | ext | name.ends_with(*ext)).is_some() {
-                                        if create_digest {
-                                            
-                                        }
-                                        ret.push(BuilderFileTreeNode::File {
-                                            digest: None,
-                                            name: name
-                                        });
-                                    }
-                                }
-                            }
-                        }
+        else {
+            false
+        };
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileWriteResponse {
+                uid,
+                path: path.to_string(),
+                done
+            }
+        });
+    }
+    
+    // like workspace_file_abs, but the workspace folders themselves are not ours to create, move or delete
+    // a file or folder inside a workspace, never the workspace itself or anything above it
+    pub fn workspace_entry_abs(&mut self, uid: HubUid, path: &str) -> Option<String> {
+        let path = path.strip_suffix('/').unwrap_or(path);
+        let mut segments = path.split('/');
+        let workspace = segments.next().unwrap_or("");
+        let segments: Vec<&str> = segments.collect();
+        let is_entry = !workspace.is_empty() && !segments.is_empty() && segments.iter().all( | segment | {
+            !segment.is_empty() && *segment != "." && *segment != ".." && !segment.contains('\\')
+        });
+        if !is_entry {
+            self.error(uid, format!("Builder {} cannot change {}, not an entry in a workspace", self.builder, path));
+            return None
+        }
+        if is_hub_key_file(path) {
+            self.error(uid, format!("Ends with key.ron or keys.ron, ignoring {}", path));
+            return None
+        }
+        let abs_dir = self.get_workspace_abs(uid, workspace).ok() ?;
+        Some(format!("{}/{}", abs_dir, segments.join("/")))
+    }
+    
+    pub fn file_create(&mut self, from: HubAddr, uid: HubUid, path: &str, data: &[u8]) {
+        let done = if let Some(abs_path) = self.workspace_entry_abs(uid, path) {
+            if let Some(parent) = std::path::Path::new(&abs_path).parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            let result = std::fs::OpenOptions::new().write(true).create_new(true).open(&abs_path)
+                .and_then( | mut file | file.write_all(data));
+            if let Err(e) = &result {
+                self.error(uid, format!("Builder {} cannot create {}: {}", self.builder, path, e));
+            }
+            result.is_ok()
+        }
+        else {
+            false
+        };
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileCreateResponse {
+                uid,
+                path: path.to_string(),
+                done
+            }
+        });
+    }
+    
+    pub fn file_delete(&mut self, from: HubAddr, uid: HubUid, path: &str) {
+        let done = if let Some(abs_path) = self.workspace_entry_abs(uid, path) {
+            let result = std::fs::symlink_metadata(&abs_path).and_then( | metadata | if metadata.is_dir() {
+                std::fs::remove_dir_all(&abs_path)
+            }
+            else {
+                std::fs::remove_file(&abs_path)
+            });
+            if let Err(e) = &result {
+                self.error(uid, format!("Builder {} cannot delete {}: {}", self.builder, path, e));
+            }
+            result.is_ok()
+        }
+        else {
+            false
+        };
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileDeleteResponse {
+                uid,
+                path: path.to_string(),
+                done
+            }
+        });
+    }
+    
+    pub fn file_rename(&mut self, from: HubAddr, uid: HubUid, path: &str, new_path: &str) {
+        let done = match (self.workspace_entry_abs(uid, path), self.workspace_entry_abs(uid, new_path)) {
+            (Some(abs_path), Some(new_abs_path)) => {
+                let result = if std::path::Path::new(&new_abs_path).exists() {
+                    Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} already exists", new_path)))
+                }
+                else {
+                    if let Some(parent) = std::path::Path::new(&new_abs_path).parent() {
+                        let _ = std::fs::create_dir_all(parent);
                     }
+                    std::fs::rename(&abs_path, &new_abs_path)
+                };
+                if let Err(e) = &result {
+                    self.error(uid, format!("Builder {} cannot rename {} to {}: {}", self.builder, path, new_path, e));
                 }
+                result.is_ok()
+            },
+            _ => false
+        };
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileRenameResponse {
+                uid,
+                path: path.to_string(),
+                new_path: new_path.to_string(),
+                done
             }
-            ret.sort();
-            
-            ret
+        });
+    }
+    
+    pub fn file_mkdir(&mut self, from: HubAddr, uid: HubUid, path: &str) {
+        let done = if let Some(abs_path) = self.workspace_entry_abs(uid, path) {
+            let result = std::fs::create_dir_all(&abs_path);
+            if let Err(e) = &result {
+                self.error(uid, format!("Builder {} cannot create folder {}: {}", self.builder, path, e));
+            }
+            result.is_ok()
         }
-        
-        let ext_inc: Vec<String> = ext_inc.to_vec().iter().map( | v | v.to_string()).collect();
-        let file_ex: Vec<String> = file_ex.to_vec().iter().map( | v | v.to_string()).collect();
-        let dir_ex: Vec<String> = dir_ex.to_vec().iter().map( | v | v.to_string()).collect();
-        
+        else {
+            false
+        };
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileMkdirResponse {
+                uid,
+                path: path.to_string(),
+                done
+            }
+        });
+    }
+    
+    pub fn file_stat(&mut self, from: HubAddr, uid: HubUid, path: &str) {
+        let stat = self.workspace_file_abs(uid, path)
+            .and_then( | abs_path | std::fs::metadata(abs_path).ok())
+            .map( | metadata | FileStat {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+                is_dir: metadata.is_dir(),
+                readonly: metadata.permissions().readonly(),
+                mode: file_mode(&metadata)
+            });
+        self.route_send.send(ToHubMsg {
+            to: HubMsgTo::Client(from),
+            msg: HubMsg::FileStatResponse {
+                uid,
+                path: path.to_string(),
+                stat
+            }
+        });
+    }
+    
+    pub fn workspace_file_tree(&mut self, create_digest: bool, ext_inc: &[&str], file_ex: &[&str], dir_ex: &[&str]) -> BuilderFileTreeNode {
         let mut root_folder = Vec::new();
         
         if let Ok(workspaces) = self.workspaces.lock() {
             for (project, abs_path) in workspaces.iter() {
                 
-                let folder = read_recur(&abs_path, create_digest, &ext_inc, &file_ex, &dir_ex);
+                let folder = read_file_tree(&abs_path, create_digest, ext_inc, file_ex, dir_ex);
                 let tree = BuilderFileTreeNode::Folder {
                     name: project.clone(),
-                    digest: digest_folder(create_digest, &project, &folder),
+                    digest: digest_file_tree_folder(create_digest, &project, &folder),
                     folder: folder
                 };
                 root_folder.push(tree);
@@ -1121,13 +1615,166 @@ impl HubBuilder {
         }
         let root = BuilderFileTreeNode::Folder {
             name: self.builder.clone(),
-            digest: digest_folder(create_digest, &self.builder, &root_folder),
+            digest: digest_file_tree_folder(create_digest, &self.builder, &root_folder),
             folder: root_folder
         };
         root
     }
 }
 
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &std::fs::Metadata) -> u32 {
+    0
+}
+
+// workspace.members without the ones workspace.exclude leaves out, like cargo does.
+// A member with a * or ? in it is a glob over the folders of the workspace in workspace_dir
+pub fn cargo_workspace_members(toml: &Toml, workspace_dir: &str) -> Vec<String> {
+    fn normalize(path: &str) -> &str {
+        path.trim_start_matches("./").trim_end_matches('/')
+    }
+    let strings = | path: &str | -> Vec<String> {
+        if let Some(Toml::Array(items)) = toml.path(path) {
+            return items.iter().filter_map( | item | if let Toml::Str(item) = item {Some(normalize(item).to_string())} else {None}).collect()
+        }
+        Vec::new()
+    };
+    let excludes = strings("workspace.exclude");
+    let mut members: Vec<String> = Vec::new();
+    for member in strings("workspace.members") {
+        let expanded = if member.contains(['*', '?']) {
+            expand_member_glob(workspace_dir, &member)
+        }
+        else {
+            vec![member]
+        };
+        for member in expanded {
+            if !members.contains(&member) {
+                members.push(member);
+            }
+        }
+    }
+    members.into_iter().filter( | member | {
+        !excludes.iter().any( | exclude | member == exclude || member.starts_with(&format!("{}/", exclude)))
+    }).collect()
+}
+
+// the folders matching a member glob one segment at a time, only the ones that hold a package
+fn expand_member_glob(workspace_dir: &str, pattern: &str) -> Vec<String> {
+    let mut paths = vec![String::new()];
+    for segment in pattern.split('/') {
+        let mut next = Vec::new();
+        for path in paths {
+            let join = | name: &str | if path.is_empty() {name.to_string()} else {format!("{}/{}", path, name)};
+            if !segment.contains(['*', '?']) {
+                next.push(join(segment));
+                continue
+            }
+            if let Ok(read_dir) = fs::read_dir(format!("{}/{}", workspace_dir, path)) {
+                for entry in read_dir.flatten() {
+                    if !entry.file_type().map( | ty | ty.is_dir()).unwrap_or(false) {
+                        continue
+                    }
+                    if let Ok(name) = entry.file_name().into_string() {
+                        if !name.starts_with('.') && glob_match(segment, &name) {
+                            next.push(join(&name));
+                        }
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+    paths.retain( | path | std::path::Path::new(&format!("{}/{}/Cargo.toml", workspace_dir, path)).is_file());
+    paths.sort();
+    paths
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).filter( | i | name.is_char_boundary(*i)).any( | i | glob_match(&pattern[1..], &name[i..])),
+        Some('?') => name.chars().next().is_some_and( | c | glob_match(&pattern[1..], &name[c.len_utf8()..])),
+        Some(c) => name.starts_with(c) && glob_match(&pattern[c.len_utf8()..], &name[c.len_utf8()..])
+    }
+}
+
+fn digest_file_tree_folder(create_digest: bool, name: &str, folder: &Vec<BuilderFileTreeNode>) -> Option<Box<Digest >> {
+    if !create_digest {
+        return None;
+    }
+    let mut digest_out = Digest::default();
+    for item in folder {
+        match item {
+            BuilderFileTreeNode::File {name, digest} => {
+                // a rename has to show up too
+                digest_out.digest_buffer(name.as_bytes());
+                if let Some(digest) = digest {
+                    digest_out.digest_other(&*digest);
+                }
+            },
+            BuilderFileTreeNode::Folder {digest, ..} => {
+                if let Some(digest) = digest {
+                    digest_out.digest_other(&*digest);
+                }
+            },
+        }
+    }
+    digest_out.digest_buffer(name.as_bytes());
+    Some(Box::new(digest_out))
+}
+
+fn read_file_tree(path: &str, create_digest: bool, ext_inc: &[&str], file_ex: &[&str], dir_ex: &[&str]) -> Vec<BuilderFileTreeNode> {
+    let mut ret = Vec::new();
+    if let Ok(read_dir) = fs::read_dir(path) {
+        for entry in read_dir {
+            if let Ok(entry) = entry {
+                if let Ok(ty) = entry.file_type() {
+                    if let Ok(name) = entry.file_name().into_string() {
+                        if ty.is_dir() {
+                            if dir_ex.iter().find( | dir | **dir == name).is_some() {
+                                continue
+                            }
+                            let folder = read_file_tree(&format!("{}/{}", path, name), create_digest, ext_inc, file_ex, dir_ex);
+                            ret.push(BuilderFileTreeNode::Folder {
+                                name: name.clone(),
+                                digest: digest_file_tree_folder(create_digest, &name, &folder),
+                                folder: folder
+                            });
+                        }
+                        else {
+                            if file_ex.iter().find( | file | **file == name).is_some() || is_hub_key_file(&name) {
+                                continue
+                            }
+                            if ext_inc.iter().find( | ext | name.ends_with(*ext)).is_some() {
+                                let digest = if create_digest {
+                                    fs::read(format!("{}/{}", path, name)).ok().map( | data | Box::new(Digest::from_buffer(&data)))
+                                }
+                                else {
+                                    None
+                                };
+                                ret.push(BuilderFileTreeNode::File {
+                                    digest: digest,
+                                    name: name
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    ret.sort();
+    
+    ret
+}
+
 fn rel_to_abs_path(abs_root: &str, path: &str) -> String {
     if path.starts_with("/") {
         return path.to_string();
@@ -1145,6 +1792,52 @@ fn project_rel_path(abs_root:&str, path: &str) -> String {
 }
 
 
+// cargo announces each test binary on stderr before running it
+fn test_suite_name(line: &str) -> Option<String> {
+    let line = line.trim();
+    if let Some(suite) = line.strip_prefix("Running ") {
+        // newer cargo adds the binary path in parens, the source path says enough
+        let suite = if let Some(end) = suite.find(" (") {&suite[0..end]} else {suite};
+        return Some(suite.to_string())
+    }
+    if line.starts_with("Doc-tests ") {
+        return Some(line.to_string())
+    }
+    None
+}
+
+// finds where a failed test panicked in its captured stdout, libtest prints either
+// panicked at 'message', src/lib.rs:1:2 or panicked at src/lib.rs:1:2:\nmessage
+pub fn test_panic_loc(builder: &str, workspace: &str, abs_root_path: &str, stdout: &str) -> Option<LocMessage> {
+    let start = stdout.find("panicked at ") ? + "panicked at ".len();
+    let rest = &stdout[start..];
+    let (location, body) = if rest.starts_with('\'') {
+        let end = rest.find("', ") ?;
+        let location = rest[end + 3..].lines().next().unwrap_or("");
+        (location, rest[1..end].to_string())
+    }
+    else {
+        let mut lines = rest.lines();
+        let location = lines.next().unwrap_or("").trim_end_matches(':');
+        let body: Vec<&str> = lines.take_while( | line | !line.is_empty() && !line.starts_with("note: ")).collect();
+        (location, body.join(" "))
+    };
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next() ?.parse::<usize>().ok() ?;
+    let line = parts.next() ?.parse::<usize>().ok() ?;
+    let path = parts.next() ?.replace("\\", "/");
+    Some(LocMessage {
+        path: format!("{}/{}/{}", builder, workspace, project_rel_path(abs_root_path, &path)),
+        line,
+        column,
+        body,
+        range: None,
+        rendered: Some(stdout.to_string()),
+        explanation: None,
+        suggestions: Vec::new(),
+    })
+}
+
 fn de_relativize_path( path: &str) -> String {
     let splits: Vec<&str> = path.split("/").collect();
     let mut out = Vec::new();
@@ -1246,6 +1939,40 @@ pub struct RustcMessage {
     rendered: Option<String>
 }
 
+impl RustcMessage {
+    // the suggestions of the children, only edits in file_name are kept
+    pub fn suggestions(&self, file_name: &str) -> Vec<LocSuggestion> {
+        let mut suggestions = Vec::new();
+        for child in &self.children {
+            let mut applicability = LocApplicability::MachineApplicable;
+            let mut edits = Vec::new();
+            for span in &child.spans {
+                if let Some(replacement) = &span.suggested_replacement {
+                    if span.file_name != file_name {
+                        continue
+                    }
+                    applicability = applicability.weakest(LocApplicability::from_rustc(span.suggestion_applicability.as_deref()));
+                    edits.push(LocEdit {
+                        line_start: span.line_start as usize,
+                        column_start: span.column_start as usize,
+                        line_end: span.line_end as usize,
+                        column_end: span.column_end as usize,
+                        replacement: replacement.clone()
+                    });
+                }
+            }
+            if !edits.is_empty() {
+                suggestions.push(LocSuggestion {
+                    message: child.message.clone(),
+                    applicability,
+                    edits
+                });
+            }
+        }
+        suggestions
+    }
+}
+
 #[derive(Clone, DeJson, Debug,  Default)]
 pub struct RustcProfile {
     opt_level: String,
@@ -1255,6 +1982,24 @@ pub struct RustcProfile {
     test: bool
 }
 
+// libtest json output, one per line on stdout when tests run with --format json
+#[derive(Clone, DeJson, Debug, Default)]
+pub struct LibtestMessage {
+    #[nserde(rename = "type")]
+    pub ty: String,
+    pub event: String,
+    pub name: Option<String>,
+    pub stdout: Option<String>,
+    pub message: Option<String>,
+    pub exec_time: Option<f64>,
+    pub test_count: Option<u64>,
+    pub passed: Option<u64>,
+    pub failed: Option<u64>,
+    pub ignored: Option<u64>,
+    pub measured: Option<u64>,
+    pub filtered_out: Option<u64>
+}
+
 #[derive(Clone, DeJson, Debug,  Default)]
 pub struct RustcCompilerMessage {
     reason: String,
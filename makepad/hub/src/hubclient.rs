@@ -1,11 +1,14 @@
 use crate::hubmsg::*;
 use crate::hubrouter::*;
+use crate::hubtls::*;
+use crate::hubauth::*;
 use makepad_microserde::*;
 
 use std::net::{TcpStream, SocketAddr, Shutdown};
 use std::io::prelude::*;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 trait ResultMsg<T> {
     fn expect_msg(self, msg: &str) -> Result<T, HubError>;
@@ -19,21 +22,12 @@ impl<T> ResultMsg<T> for Result<T, std::io::Error> {
         }
     }
 }
-/*
-impl<T> ResultMsg<T> for Result<T, snap::Error> {
-    fn expect_msg(self, msg: &str) -> Result<T, HubError> {
-        match self {
-            Err(v) => Err(HubError {msg: format!("{}: {}", msg.to_string(), v.to_string())}),
-            Ok(v) => Ok(v)
-        }
-    }
-}*/
 
 type HubResult<T> = Result<T, HubError>;
 
 pub const HUB_ANNOUNCE_PORT: u16 = 46243;
 
-pub fn read_exact_bytes_from_tcp_stream(tcp_stream: &mut TcpStream, bytes: &mut [u8]) -> HubResult<()> {
+pub fn read_exact_bytes_from_tcp_stream(tcp_stream: &mut HubStream, bytes: &mut [u8]) -> HubResult<()> {
     let bytes_total = bytes.len();
     let mut bytes_left = bytes_total;
     while bytes_left > 0 {
@@ -47,34 +41,34 @@ pub fn read_exact_bytes_from_tcp_stream(tcp_stream: &mut TcpStream, bytes: &mut
     Ok(())
 }
 
-pub fn read_block_from_tcp_stream(tcp_stream: &mut TcpStream, mut check_digest: Digest) -> HubResult<Vec<u8>> {
-    let mut dwd_read = DigestWithData::default();
-    
-    let dwd_u8 = unsafe {std::mem::transmute::<&mut DigestWithData, &mut [u8; 26 * 8]>(&mut dwd_read)};
-    read_exact_bytes_from_tcp_stream(tcp_stream, dwd_u8) ?;
+// reads a whole block and checks its tag, only then is the message decoded
+pub fn read_msg_from_tcp_stream<T: DeBin>(tcp_stream: &mut HubStream, block_key: &mut HubBlockKey) -> HubResult<T> {
+    let mut len_u8 = [0u8; 8];
+    read_exact_bytes_from_tcp_stream(tcp_stream, &mut len_u8) ?;
     
-    let bytes_total = dwd_read.data as usize;
+    let bytes_total = u64::from_le_bytes(len_u8) as usize;
     if bytes_total > 250 * 1024 * 1024 {
-        return Err(HubError::new("read_block_from_tcp_stream: bytes_total more than 250mb"))
+        return Err(HubError::new("read_msg_from_tcp_stream: bytes_total more than 250mb"))
     }
     
-    let mut msg_buf = Vec::new();
-    msg_buf.resize(bytes_total, 0);
+    let mut msg_buf = vec![0u8; bytes_total];
     read_exact_bytes_from_tcp_stream(tcp_stream, &mut msg_buf) ?;
     
-    check_digest.digest_buffer(&msg_buf);
-    
-    if check_digest != dwd_read.digest {
-        return Err(HubError::new("read_block_from_tcp_stream: block digest check failed"))
+    let mut tag = [0u8; HUB_AUTH_TAG_LEN];
+    read_exact_bytes_from_tcp_stream(tcp_stream, &mut tag) ?;
+    if !block_key.verify(&msg_buf, &tag) {
+        return Err(HubError::new("read_msg_from_tcp_stream: block authentication failed"))
     }
     
-    //let mut dec = snap::Decoder::new();
-    let decompressed = msg_buf;//dec.decompress_vec(&msg_buf).expect_msg("read_block_from_tcp_stream: cannot decompress_vec");
-    
-    return Ok(decompressed);
+    let mut o = 0;
+    let msg = T::de_bin(&mut o, &msg_buf).map_err( | e | HubError::new(&format!("read_msg_from_tcp_stream: cannot parse binary: {}", e))) ?;
+    if o != msg_buf.len() {
+        return Err(HubError::new("read_msg_from_tcp_stream: block continues after the message"))
+    }
+    Ok(msg)
 }
 
-pub fn write_exact_bytes_to_tcp_stream(tcp_stream: &mut TcpStream, bytes: &[u8]) -> HubResult<()> {
+pub fn write_exact_bytes_to_tcp_stream(tcp_stream: &mut HubStream, bytes: &[u8]) -> HubResult<()> {
     let bytes_total = bytes.len();
     let mut bytes_left = bytes_total;
     while bytes_left > 0 {
@@ -88,53 +82,177 @@ pub fn write_exact_bytes_to_tcp_stream(tcp_stream: &mut TcpStream, bytes: &[u8])
     Ok(())
 }
 
-pub fn write_block_to_tcp_stream(tcp_stream: &mut TcpStream, msg_buf: &[u8], digest: Digest) -> HubResult<()> {
+pub fn write_block_to_tcp_stream(tcp_stream: &mut HubStream, msg_buf: &[u8], block_key: &mut HubBlockKey) -> HubResult<()> {
     let bytes_total = msg_buf.len();
     
     if bytes_total > 250 * 1024 * 1024 {
-        return Err(HubError::new("read_block_from_tcp_stream: bytes_total more than 250mb"))
+        return Err(HubError::new("write_block_to_tcp_stream: bytes_total more than 250mb"))
     }
     
-    //let mut enc = snap::Encoder::new();
-    let compressed = msg_buf;//enc.compress_vec(msg_buf).expect_msg("read_block_from_tcp_stream: cannot compress msgbuf") ?;
+    let tag = block_key.sign(msg_buf);
     
-    let mut dwd_write = DigestWithData{
-        digest:digest,
-        data: compressed.len() as u64
-    };
+    write_exact_bytes_to_tcp_stream(tcp_stream, &(bytes_total as u64).to_le_bytes()) ?;
+    write_exact_bytes_to_tcp_stream(tcp_stream, msg_buf) ?;
+    write_exact_bytes_to_tcp_stream(tcp_stream, tag.as_ref()) ?;
+    Ok(())
+}
+
+pub const HUB_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+pub const HUB_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// how a client keeps its connection to the hub alive
+#[derive(Clone, Debug)]
+pub struct HubClientOptions {
+    // an idle connection sends a heartbeat this often
+    pub heartbeat_interval: Duration,
+    // and is considered dead when nothing arrived for this long
+    pub heartbeat_timeout: Duration,
+    // reconnect after a connection error instead of giving up
+    pub reconnect: bool,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for HubClientOptions {
+    fn default() -> Self {
+        HubClientOptions {
+            heartbeat_interval: HUB_HEARTBEAT_INTERVAL,
+            heartbeat_timeout: HUB_HEARTBEAT_TIMEOUT,
+            reconnect: false,
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl HubClientOptions {
+    pub fn reconnecting() -> HubClientOptions {
+        HubClientOptions {reconnect: true, ..HubClientOptions::default()}
+    }
     
-    dwd_write.digest.digest_buffer(&compressed);
+    // the wait before reconnect attempt n, doubling up to max_backoff
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.min_backoff.checked_mul(factor).unwrap_or(self.max_backoff).min(self.max_backoff)
+    }
+}
+
+// what a reconnect has to send again. Requests stay here until their final response arrives.
+// requests to a builder only ever go out again through replay_to_builder, once per builder that
+// is there when we come back or that arrives later, so a builder never gets the same one twice
+#[derive(Default)]
+struct HubInFlight {
+    announce: Option<ToHubMsg>,
+    requests: Vec<ToHubMsg>,
+    // our own ListBuildersRequest after a reconnect, its response is not for the client
+    builders_uid: Option<HubUid>,
+    // the builder requests that were in flight when we came back, they wait for that response
+    awaiting_builders: Vec<ToHubMsg>,
+    // the connection of each builder we replayed to, announcing again from it gets nothing new
+    replayed_to: Vec<(String, HubAddr)>,
+}
+
+impl HubInFlight {
+    fn on_send(&mut self, cth_msg: &ToHubMsg) {
+        match &cth_msg.msg {
+            HubMsg::ConnectBuilder(_) | HubMsg::ConnectClone(_) | HubMsg::ConnectUI => {
+                self.announce = Some(cth_msg.clone());
+            },
+            msg => if let Some(uid) = msg.request_uid() {
+                if Some(uid) == self.builders_uid {
+                    return
+                }
+                // a replay is already tracked
+                if !self.requests.iter().any( | r | r.to == cth_msg.to && r.msg.request_uid() == Some(uid) && std::mem::discriminant(&r.msg) == std::mem::discriminant(msg)) {
+                    self.requests.push(cth_msg.clone());
+                }
+            }
+        }
+    }
     
-    let dwd_u8 = unsafe {std::mem::transmute::<&DigestWithData, &[u8; 26 * 8]>(&dwd_write)};
-    write_exact_bytes_to_tcp_stream(tcp_stream, dwd_u8) ?;
-    write_exact_bytes_to_tcp_stream(tcp_stream, &compressed) ?;
-    Ok(())
+    fn on_receive(&mut self, msg: &HubMsg) {
+        if let Some(pos) = self.requests.iter().position( | r | msg.is_final_response_to(&r.msg)) {
+            self.requests.remove(pos);
+        }
+    }
+    
+    fn is_replayed(msg: &HubMsg) -> bool {
+        match msg {
+            HubMsg::ConnectBuilder(_) | HubMsg::ConnectClone(_) | HubMsg::ConnectUI => true,
+            msg => msg.request_uid().is_some()
+        }
+    }
+    
+    // everything but the requests to builders, those wait for the builder list
+    fn replay(&mut self, own_addr: HubAddr) -> Vec<ToHubMsg> {
+        let mut replay: Vec<ToHubMsg> = self.announce.iter()
+            .chain(self.requests.iter().filter( | r | !matches!(r.to, HubMsgTo::Builder(_))))
+            .cloned().collect();
+        self.awaiting_builders = self.requests.iter().filter( | r | matches!(r.to, HubMsgTo::Builder(_))).cloned().collect();
+        self.builders_uid = None;
+        self.replayed_to.clear();
+        if !self.awaiting_builders.is_empty() {
+            // neither uid allocator gets this far
+            let uid = HubUid {addr: own_addr, id: u64::MAX};
+            self.builders_uid = Some(uid);
+            replay.push(ToHubMsg {to: HubMsgTo::Hub, msg: HubMsg::ListBuildersRequest {uid}});
+        }
+        replay
+    }
+    
+    // the builders that were there before us, anything sent after the list went to them already
+    fn replay_to_listed(&mut self, builders: &[String]) -> Vec<ToHubMsg> {
+        self.builders_uid = None;
+        let requests = &self.requests;
+        std::mem::take(&mut self.awaiting_builders).into_iter().filter( | r | {
+            matches!(&r.to, HubMsgTo::Builder(to) if builders.contains(to))
+                && requests.iter().any( | q | q.to == r.to && q.msg.request_uid() == r.msg.request_uid())
+        }).collect()
+    }
+    
+    // a builder came (back), it needs whatever we asked of it
+    fn replay_to_builder(&mut self, builder: &str, from: HubAddr) -> Vec<ToHubMsg> {
+        if self.replayed_to.iter().any( | (name, addr) | name == builder && *addr == from) {
+            return Vec::new()
+        }
+        self.replayed_to.retain( | (name, _) | name != builder);
+        self.replayed_to.push((builder.to_string(), from));
+        self.requests.iter().filter( | r | matches!(&r.to, HubMsgTo::Builder(to) if to == builder)).cloned().collect()
+    }
+}
+
+#[derive(Default)]
+struct HubClientShared {
+    terminate: bool,
+    tcp_stream: Option<HubStream>,
 }
 
 pub struct HubClient {
+    // the address of the first connection, a reconnect updates the route_send instead
     pub own_addr: HubAddr,
     pub server_addr: HubAddr,
     pub uid_alloc: u64,
-    read_thread: Option<thread::JoinHandle<()>>,
-    write_thread: Option<thread::JoinHandle<()>>,
+    connection_thread: Option<thread::JoinHandle<()>>,
+    shared: Arc<Mutex<HubClientShared>>,
+    route_send: HubRouteSend,
     pub tx_read: mpsc::Sender<FromHubMsg>,
     pub rx_read: Option<mpsc::Receiver<FromHubMsg>>,
     pub tx_write: mpsc::Sender<ToHubMsg>
 }
 
-#[derive(Default, Eq, PartialEq, Debug, Clone, SerBin, DeBin)]
-pub struct DigestWithData{
-    pub digest:Digest,
-    pub data: u64
+struct HubConnection {
+    tcp_stream: HubStream,
+    own_addr: HubAddr,
+    server_addr: HubAddr,
+    session: HubSession,
 }
 
-impl HubClient {
-    pub fn connect_to_server(digest: Digest, server_address: SocketAddr, hub_log: HubLog) -> HubResult<HubClient> {
-        
+impl HubConnection {
+    fn open(hub_key: &HubKey, server_address: SocketAddr, tls: Option<&HubTlsClientConfig>) -> HubResult<HubConnection> {
         // first try local address
         let local_address = SocketAddr::from(([127, 0, 0, 1], server_address.port()));
         let server_hubaddr;
-        let mut tcp_stream = if let Ok(stream) = TcpStream::connect(local_address) {
+        let tcp_stream = if let Ok(stream) = TcpStream::connect(local_address) {
             server_hubaddr = HubAddr::from_socket_addr(local_address);
             stream
         }
@@ -143,89 +261,236 @@ impl HubClient {
             TcpStream::connect(server_address).expect_msg("connect_to_hub: cannot connect") ?
         };
         
-        let own_addr = HubAddr::from_socket_addr(tcp_stream.local_addr().expect("Cannot get client local address"));
+        let own_addr = HubAddr::from_socket_addr(tcp_stream.local_addr().expect_msg("connect_to_hub: cannot get client local address") ?);
+        
+        let mut tcp_stream = if let Some(tls) = tls {
+            let tls_config = tls.client_config() ?;
+            HubStream::Tls(TlsStream::connect(tcp_stream, &tls_config, &tls.server_name).expect_msg("connect_to_hub: tls handshake failed") ?)
+        }
+        else {
+            HubStream::Tcp(tcp_stream)
+        };
+        
+        let session = hub_auth_connect(&mut tcp_stream, hub_key) ?;
+        
+        Ok(HubConnection {
+            tcp_stream,
+            own_addr,
+            server_addr: server_hubaddr,
+            session
+        })
+    }
+}
+
+impl HubClient {
+    pub fn connect_to_server(hub_key: &HubKey, server_address: SocketAddr, tls: Option<&HubTlsClientConfig>, hub_log: HubLog) -> HubResult<HubClient> {
+        Self::connect_with_options(hub_key, server_address, tls, HubClientOptions::default(), hub_log)
+    }
+    
+    // the first connection has to succeed, after that a reconnecting client keeps trying in the background
+    // and surfaces every lost connection as a ConnectionError on rx_read
+    pub fn connect_with_options(hub_key: &HubKey, server_address: SocketAddr, tls: Option<&HubTlsClientConfig>, options: HubClientOptions, hub_log: HubLog) -> HubResult<HubClient> {
+        
+        let connection = HubConnection::open(hub_key, server_address, tls) ?;
+        let own_addr = connection.own_addr;
+        let server_addr = connection.server_addr;
         
         let (tx_read, rx_read) = mpsc::channel::<FromHubMsg>();
         let (tx_write, rx_write) = mpsc::channel::<ToHubMsg>();
-        let tx_read_copy = tx_read.clone();
-        let tx_write_copy = tx_write.clone();
         
-        let read_thread = {
-            let mut tcp_stream = tcp_stream.try_clone().expect_msg("connect_to_hub: cannot clone socket") ?;
-            let digest = digest.clone();
-            let server_hubaddr = server_hubaddr.clone();
-            let hub_log = hub_log.clone();
+        let route_send = HubRouteSend::Networked {
+            uid_alloc: Arc::new(Mutex::new(0)),
+            tx_write_arc: Arc::new(Mutex::new(Some(tx_write.clone()))),
+            own_addr_arc: Arc::new(Mutex::new(Some(own_addr)))
+        };
+        let shared = Arc::new(Mutex::new(HubClientShared::default()));
+        
+        let connection_thread = {
+            let hub_key = hub_key.clone();
+            let tls = tls.cloned();
+            let tx_read = tx_read.clone();
+            let tx_write = tx_write.clone();
+            let route_send = route_send.clone();
+            let shared = Arc::clone(&shared);
             std::thread::spawn(move || {
+                let in_flight = Arc::new(Mutex::new(HubInFlight::default()));
+                let mut rx_write = rx_write;
+                let mut pending = Vec::new();
+                let mut connection = Some(connection);
+                let mut attempt = 0;
                 loop {
-                    match read_block_from_tcp_stream(&mut tcp_stream, digest.clone()) {
-                        Ok(msg_buf) => {
-                            let htc_msg: FromHubMsg = DeBin::deserialize_bin(&msg_buf).expect("Cannot parse binary");
-                            hub_log.msg("HubClient received", &htc_msg);
-                            tx_read.send(htc_msg).expect("tx_read.send fails - should never happen");
-                        },
-                        Err(e) => {
-                            let _ = tcp_stream.shutdown(Shutdown::Both);
-                            tx_read.send(FromHubMsg {
-                                from: server_hubaddr.clone(),
-                                msg: HubMsg::ConnectionError(e.clone())
-                            }).expect("tx_read.send fails - should never happen");
-                            // lets break rx write
-                            let _ = tx_write_copy.send(ToHubMsg {
-                                to: HubMsgTo::Hub,
-                                msg: HubMsg::ConnectionError(e)
-                            });
+                    let connection = if let Some(connection) = connection.take() {connection} else {
+                        std::thread::sleep(options.backoff(attempt));
+                        if shared.lock().map( | shared | shared.terminate).unwrap_or(true) {
                             return
                         }
+                        match HubConnection::open(&hub_key, server_address, tls.as_ref()) {
+                            Ok(connection) => {
+                                hub_log.log(&format!("HubClient reconnected to {:?}", server_address));
+                                attempt = 0;
+                                route_send.update_networked_in_place(Some(connection.own_addr), Some(tx_write.clone()));
+                                // announce ourselves again before anything else goes out
+                                if let Ok(mut in_flight) = in_flight.lock() {
+                                    pending.splice(0..0, in_flight.replay(connection.own_addr));
+                                }
+                                connection
+                            },
+                            Err(e) => {
+                                hub_log.log(&format!("HubClient cannot reconnect to {:?}: {}", server_address, e.msg));
+                                attempt = attempt.saturating_add(1);
+                                continue
+                            }
+                        }
+                    };
+                    let server_addr = connection.server_addr;
+                    let (rx_back, pending_back, e) = Self::run_connection(connection, rx_write, pending, &in_flight, &tx_read, &tx_write, &shared, &options, &hub_log);
+                    rx_write = rx_back;
+                    pending = pending_back;
+                    let _ = tx_read.send(FromHubMsg {
+                        from: server_addr,
+                        msg: HubMsg::ConnectionError(e)
+                    });
+                    if !options.reconnect || shared.lock().map( | shared | shared.terminate).unwrap_or(true) {
+                        return
                     }
                 }
             })
         };
         
+        Ok(HubClient {
+            uid_alloc: 0,
+            own_addr: own_addr,
+            server_addr,
+            connection_thread: Some(connection_thread),
+            shared,
+            route_send,
+            tx_read,
+            rx_read: Some(rx_read),
+            tx_write: tx_write
+        })
+    }
+    
+    // runs one connection until it breaks, hands back the write queue for the next one
+    #[allow(clippy::too_many_arguments)]
+    fn run_connection(
+        connection: HubConnection,
+        rx_write: mpsc::Receiver<ToHubMsg>,
+        pending: Vec<ToHubMsg>,
+        in_flight: &Arc<Mutex<HubInFlight >>,
+        tx_read: &mpsc::Sender<FromHubMsg>,
+        tx_write: &mpsc::Sender<ToHubMsg>,
+        shared: &Arc<Mutex<HubClientShared >>,
+        options: &HubClientOptions,
+        hub_log: &HubLog
+    ) -> (mpsc::Receiver<ToHubMsg>, Vec<ToHubMsg>, HubError) {
+        let HubConnection {mut tcp_stream, own_addr, session, ..} = connection;
+        let HubSession {mut read_key, mut write_key, ..} = session;
+        let _ = tcp_stream.tcp_stream().set_read_timeout(Some(options.heartbeat_timeout));
+        
+        let write_stream = tcp_stream.try_clone();
+        if let Ok(mut shared) = shared.lock() {
+            shared.tcp_stream = tcp_stream.try_clone().ok();
+        }
+        let mut write_stream = match write_stream {
+            Ok(write_stream) => write_stream,
+            Err(e) => return (rx_write, pending, HubError::new(&format!("connect_to_hub: cannot clone socket: {}", e)))
+        };
+        
+        let dead = Arc::new(Mutex::new(false));
         let write_thread = {
-            let digest = digest.clone();
-            let tx_read = tx_read_copy.clone();
-            let server_hubaddr = server_hubaddr.clone();
+            let in_flight = Arc::clone(in_flight);
+            let dead = Arc::clone(&dead);
+            let heartbeat_interval = options.heartbeat_interval;
             let hub_log = hub_log.clone();
             std::thread::spawn(move || { // this one cannot send to the read channel.
-                while let Ok(cth_msg) = rx_write.recv() {
-                    hub_log.msg("HubClient sending", &cth_msg);
+                let mut pending = pending.into_iter();
+                loop {
+                    if dead.lock().map( | dead | *dead).unwrap_or(true) {
+                        return (rx_write, pending.collect())
+                    }
+                    let cth_msg = match pending.next().map(Ok).unwrap_or_else( || rx_write.recv_timeout(heartbeat_interval)) {
+                        Ok(cth_msg) => cth_msg,
+                        Err(mpsc::RecvTimeoutError::Timeout) => ToHubMsg {to: HubMsgTo::Hub, msg: HubMsg::Heartbeat},
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return (rx_write, Vec::new())
+                    };
                     match &cth_msg.msg {
-                        HubMsg::ConnectionError(_) => { // we are closed by the read loop
-                            return
-                        },
-                        _ => ()
+                        HubMsg::ConnectionError(_) => continue, // only ever comes from the hub
+                        HubMsg::Heartbeat => (),
+                        _ => {
+                            hub_log.msg("HubClient sending", &cth_msg);
+                            if let Ok(mut in_flight) = in_flight.lock() {
+                                in_flight.on_send(&cth_msg);
+                            }
+                        }
                     }
                     let mut msg_buf = Vec::new();
                     cth_msg.ser_bin(&mut msg_buf);
-                    if let Err(e) = write_block_to_tcp_stream(&mut tcp_stream, &msg_buf, digest.clone()) {
-                        // disconnect the socket and send shutdown
-                        let _ = tcp_stream.shutdown(Shutdown::Both);
-                        let _ = tx_read.send(FromHubMsg {
-                            from: server_hubaddr.clone(),
-                            msg: HubMsg::ConnectionError(e)
-                        });
-                        return
+                    if write_block_to_tcp_stream(&mut write_stream, &msg_buf, &mut write_key).is_err() {
+                        // the read loop notices too, whatever did not go out is sent first next time
+                        let _ = write_stream.shutdown(Shutdown::Both);
+                        return (rx_write, std::iter::once(cth_msg).chain(pending).collect())
                     }
                 }
             })
         };
         
-        Ok(HubClient {
-            uid_alloc: 0,
-            own_addr: own_addr,
-            server_addr: server_hubaddr,
-            read_thread: Some(read_thread),
-            write_thread: Some(write_thread),
-            tx_read: tx_read_copy,
-            rx_read: Some(rx_read),
-            tx_write: tx_write
-        })
+        let error = loop {
+            let htc_msg: FromHubMsg = match read_msg_from_tcp_stream(&mut tcp_stream, &mut read_key) {
+                Ok(htc_msg) => htc_msg,
+                Err(e) => break e
+            };
+            if let HubMsg::Heartbeat = htc_msg.msg {
+                continue
+            }
+            hub_log.msg("HubClient received", &htc_msg);
+            if let Ok(mut in_flight) = in_flight.lock() {
+                in_flight.on_receive(&htc_msg.msg);
+                match &htc_msg.msg {
+                    // the hub had these before our announce went through, they won't announce themselves to us
+                    HubMsg::ListBuildersResponse {uid, builders} if Some(*uid) == in_flight.builders_uid => {
+                        for cth_msg in in_flight.replay_to_listed(builders) {
+                            let _ = tx_write.send(cth_msg);
+                        }
+                        continue
+                    },
+                    // whilst the list is on its way, an arriving builder will be on it too
+                    HubMsg::ConnectBuilder(builder) if htc_msg.from != own_addr && in_flight.builders_uid.is_none() => {
+                        for cth_msg in in_flight.replay_to_builder(builder, htc_msg.from) {
+                            let _ = tx_write.send(cth_msg);
+                        }
+                    },
+                    _ => ()
+                }
+            }
+            if tx_read.send(htc_msg).is_err() {
+                break HubError::new("HubClient dropped")
+            }
+        };
+        
+        let _ = tcp_stream.shutdown(Shutdown::Both);
+        if let Ok(mut dead) = dead.lock() {
+            *dead = true;
+        }
+        // wake the writer up so it sees the flag
+        let _ = tx_write.send(ToHubMsg {to: HubMsgTo::Hub, msg: HubMsg::Heartbeat});
+        let (rx_write, mut pending) = write_thread.join().expect("cant join write thread");
+        // tracked requests and the announce come back through the replay
+        pending.retain( | cth_msg | !matches!(cth_msg.msg, HubMsg::Heartbeat) && !HubInFlight::is_replayed(&cth_msg.msg));
+        (rx_write, pending, error)
     }
     
+    // closes the connection and stops reconnecting
+    pub fn terminate(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.terminate = true;
+            if let Some(tcp_stream) = &shared.tcp_stream {
+                let _ = tcp_stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
     
     pub fn join_threads(&mut self) {
-        self.read_thread.take().expect("cant take read thread").join().expect("cant join read thread");
-        self.write_thread.take().expect("cant take write thread").join().expect("cant join write thread");
+        self.connection_thread.take().expect("cant take connection thread").join().expect("cant join connection thread");
     }
     
     pub fn alloc_uid(&mut self) -> HubUid {
@@ -236,12 +501,9 @@ impl HubClient {
         }
     }
     
+    // follows the client across reconnects
     pub fn get_route_send(&self) -> HubRouteSend {
-        HubRouteSend::Networked {
-            uid_alloc: Arc::new(Mutex::new(0)),
-            tx_write_arc: Arc::new(Mutex::new(Some(self.tx_write.clone()))),
-            own_addr_arc: Arc::new(Mutex::new(Some(self.own_addr)))
-        }
+        self.route_send.clone()
     }
     
     pub fn get_route_send_in_place(&self, route_send: &HubRouteSend) {
@@ -261,14 +523,10 @@ impl Default for Digest {
 
 impl Digest {
     
-    pub fn generate() -> Digest {
-        let mut result = Digest::default();
-        for _ in 0..25 {
-            //result.buf[i] ^= time::precise_time_ns();
-            std::thread::sleep(std::time::Duration::from_millis(1));
-            result.digest_cycle();
-        }
-        result
+    pub fn from_buffer(msg_buf: &[u8]) -> Digest {
+        let mut digest = Digest::default();
+        digest.digest_buffer(msg_buf);
+        digest
     }
     
     pub fn digest_cycle(&mut self){
@@ -298,7 +556,7 @@ impl Digest {
     
 }
 
-// digest function to hash tcp data to enable error checking and multiple servers on one network, found various
+// digest function to hash the builder file trees so the ui can see what changed, not a security primitive. Found various
 // similar versions of this on crates.io and github (as MIT). Not sure which one to attribute it to. Thanks whoever wrote this :)
 
 const RHO: [u32; 24] = [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,];
@@ -2,10 +2,15 @@ use makepad_microserde::*;
 use std::net::SocketAddr;
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::time::SystemTime;
 use crate::httpserver::*;
 use crate::hubclient::*;
+use crate::hubsync::*;
 
+// tagged so builders and clients of different versions can still talk,
+// bump version when adding to it and min_version when older readers cant cope
 #[derive(Clone, Debug, SerBin, DeBin)]
+#[nserde(tagged, version = "8")]
 pub enum HubMsg {
     ConnectBuilder(String),
     ConnectClone(String),
@@ -18,6 +23,9 @@ pub enum HubMsg {
     
     ConnectionError(HubError),
     
+    // keeps an idle connection from timing out, never routed
+    Heartbeat,
+    
     BuilderConfig { 
         uid: HubUid,
         config: HubBuilderConfig
@@ -63,12 +71,42 @@ pub enum HubMsg {
         build_result: BuildResult
     },
     
+    // per test results of a cargo test build, suite is the test binary they ran in
+    TestStarted {
+        uid: HubUid,
+        suite: String,
+        name: String
+    },
+    
+    TestPassed {
+        uid: HubUid,
+        suite: String,
+        name: String,
+        stdout: String
+    },
+    
+    TestFailed {
+        uid: HubUid,
+        suite: String,
+        name: String,
+        stdout: String,
+        panic: Option<LocMessage>
+    },
+    
+    TestIgnored {
+        uid: HubUid,
+        suite: String,
+        name: String,
+        reason: Option<String>
+    },
+    
     ListPackagesRequest {
         uid: HubUid
     },
     
     ListPackagesResponse {
         uid: HubUid,
+        builder: String,
         packages: Vec<HubPackage>
     },
     
@@ -79,7 +117,17 @@ pub enum HubMsg {
     ProgramRun {
         uid: HubUid,
         path: String,
-        args: Vec<String>
+        args: Vec<String>,
+        #[nserde(default)]
+        options: ProgramOptions
+    },
+    
+    // goes to the stdin of a program started with options.stdin or options.pty
+    ProgramStdin {
+        uid: HubUid,
+        input: String,
+        #[nserde(default)]
+        eof: bool
     },
     
     ProgramBegin {
@@ -87,7 +135,9 @@ pub enum HubMsg {
     },
     
     ProgramEnd {
-        uid: HubUid
+        uid: HubUid,
+        #[nserde(default)]
+        exit: ProgramExit
     },
     
     BuilderFileTreeRequest {
@@ -131,6 +181,126 @@ pub enum HubMsg {
         path: String,
         done: bool
     },
+    
+    // pushed by a builder when a file changed on disk behind our back, no digest when it is gone
+    FileChanged {
+        builder: String,
+        path: String,
+        digest: Option<Box<Digest>>
+    },
+    
+    // pushed by a builder when files or folders came or went, the whole tree with digests
+    FileTreeChanged {
+        tree: BuilderFileTreeNode
+    },
+    
+    // one level of a folder with digests, the subfolders come without their content
+    FileTreeDigestRequest {
+        uid: HubUid,
+        path: String
+    },
+    
+    FileTreeDigestResponse {
+        uid: HubUid,
+        path: String,
+        nodes: Option<Vec<BuilderFileTreeNode>>
+    },
+    
+    FileSignatureRequest {
+        uid: HubUid,
+        path: String,
+        block_size: u32
+    },
+    
+    FileSignatureResponse {
+        uid: HubUid,
+        path: String,
+        signature: Option<FileSignature>
+    },
+    
+    FileDeltaRequest {
+        uid: HubUid,
+        path: String,
+        signature: FileSignature
+    },
+    
+    FileDeltaResponse {
+        uid: HubUid,
+        path: String,
+        delta: Option<FileDelta>
+    },
+    
+    // answered with a FileWriteResponse
+    FileDeltaWriteRequest {
+        uid: HubUid,
+        path: String,
+        delta: FileDelta
+    },
+    
+    // refuses to overwrite, so two editors cannot clobber each other
+    FileCreateRequest {
+        uid: HubUid,
+        path: String,
+        data: Vec<u8>
+    },
+    
+    FileCreateResponse {
+        uid: HubUid,
+        path: String,
+        done: bool
+    },
+    
+    // folders go with everything in them
+    FileDeleteRequest {
+        uid: HubUid,
+        path: String
+    },
+    
+    FileDeleteResponse {
+        uid: HubUid,
+        path: String,
+        done: bool
+    },
+    
+    // both paths on the same builder, refuses to overwrite
+    FileRenameRequest {
+        uid: HubUid,
+        path: String,
+        new_path: String
+    },
+    
+    FileRenameResponse {
+        uid: HubUid,
+        path: String,
+        new_path: String,
+        done: bool
+    },
+    
+    FileMkdirRequest {
+        uid: HubUid,
+        path: String
+    },
+    
+    FileMkdirResponse {
+        uid: HubUid,
+        path: String,
+        done: bool
+    },
+    
+    FileStatRequest {
+        uid: HubUid,
+        path: String
+    },
+    
+    FileStatResponse {
+        uid: HubUid,
+        path: String,
+        stat: Option<FileStat>
+    },
+    
+    // a message from a newer version that this one does not know
+    #[nserde(other)]
+    Unknown,
 }
 
 impl HubMsg{
@@ -138,6 +308,57 @@ impl HubMsg{
         match self{
             HubMsg::BuilderConfig{..}=>true,
             HubMsg::FileWriteRequest{..}=>true,
+            HubMsg::FileDeltaWriteRequest{..}=>true,
+            HubMsg::FileCreateRequest{..}=>true,
+            HubMsg::FileDeleteRequest{..}=>true,
+            HubMsg::FileRenameRequest{..}=>true,
+            HubMsg::FileMkdirRequest{..}=>true,
+            _=>false
+        }
+    }
+    
+    // requests that end in a final response with the same uid, a client replays these after a reconnect
+    pub fn request_uid(&self)->Option<HubUid>{
+        match self{
+            HubMsg::Build{uid,..} |
+            HubMsg::ListPackagesRequest{uid} |
+            HubMsg::ProgramRun{uid,..} |
+            HubMsg::BuilderFileTreeRequest{uid,..} |
+            HubMsg::ListBuildersRequest{uid} |
+            HubMsg::FileReadRequest{uid,..} |
+            HubMsg::FileWriteRequest{uid,..} |
+            HubMsg::FileTreeDigestRequest{uid,..} |
+            HubMsg::FileSignatureRequest{uid,..} |
+            HubMsg::FileDeltaRequest{uid,..} |
+            HubMsg::FileDeltaWriteRequest{uid,..} |
+            HubMsg::FileCreateRequest{uid,..} |
+            HubMsg::FileDeleteRequest{uid,..} |
+            HubMsg::FileRenameRequest{uid,..} |
+            HubMsg::FileMkdirRequest{uid,..} |
+            HubMsg::FileStatRequest{uid,..}=>Some(*uid),
+            _=>None
+        }
+    }
+    
+    pub fn is_final_response_to(&self, request:&HubMsg)->bool{
+        match (request, self){
+            (HubMsg::Build{uid,..}, HubMsg::BuildSuccess{uid:response_uid}) |
+            (HubMsg::Build{uid,..}, HubMsg::BuildFailure{uid:response_uid}) |
+            (HubMsg::ListPackagesRequest{uid}, HubMsg::ListPackagesResponse{uid:response_uid,..}) |
+            (HubMsg::ProgramRun{uid,..}, HubMsg::ProgramEnd{uid:response_uid,..}) |
+            (HubMsg::BuilderFileTreeRequest{uid,..}, HubMsg::BuilderFileTreeResponse{uid:response_uid,..}) |
+            (HubMsg::ListBuildersRequest{uid}, HubMsg::ListBuildersResponse{uid:response_uid,..}) |
+            (HubMsg::FileReadRequest{uid,..}, HubMsg::FileReadResponse{uid:response_uid,..}) |
+            (HubMsg::FileWriteRequest{uid,..}, HubMsg::FileWriteResponse{uid:response_uid,..}) |
+            (HubMsg::FileTreeDigestRequest{uid,..}, HubMsg::FileTreeDigestResponse{uid:response_uid,..}) |
+            (HubMsg::FileSignatureRequest{uid,..}, HubMsg::FileSignatureResponse{uid:response_uid,..}) |
+            (HubMsg::FileDeltaRequest{uid,..}, HubMsg::FileDeltaResponse{uid:response_uid,..}) |
+            (HubMsg::FileDeltaWriteRequest{uid,..}, HubMsg::FileWriteResponse{uid:response_uid,..}) |
+            (HubMsg::FileCreateRequest{uid,..}, HubMsg::FileCreateResponse{uid:response_uid,..}) |
+            (HubMsg::FileDeleteRequest{uid,..}, HubMsg::FileDeleteResponse{uid:response_uid,..}) |
+            (HubMsg::FileRenameRequest{uid,..}, HubMsg::FileRenameResponse{uid:response_uid,..}) |
+            (HubMsg::FileMkdirRequest{uid,..}, HubMsg::FileMkdirResponse{uid:response_uid,..}) |
+            (HubMsg::FileStatRequest{uid,..}, HubMsg::FileStatResponse{uid:response_uid,..})=>uid == response_uid,
             _=>false
         }
     }
@@ -183,6 +404,16 @@ impl PartialOrd for BuilderFileTreeNode {
 }
 
 
+#[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
+pub struct FileStat {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub is_dir: bool,
+    pub readonly: bool,
+    // unix permission bits, 0 where there are none
+    pub mode: u32
+}
+
 #[derive(Debug, Clone, SerBin, DeBin)]
 pub enum BuildResult {
     Executable {path: String},
@@ -197,23 +428,197 @@ pub struct HubPackage {
     pub project: String,
     pub package_name: String,
     pub configs: Vec<String>,
+    // the dir of its Cargo.toml relative to the workspace, empty for the root package
+    pub path: String,
 }
 
 impl HubPackage {
-    pub fn new(project: &str, package_name: &str, targets: &[&str]) -> HubPackage {
+    pub fn new(project: &str, package_name: &str, path: &str, targets: &[&str]) -> HubPackage {
         HubPackage {
             project: project.to_string(),
             package_name: package_name.to_string(),
-            configs: targets.iter().map( | v | v.to_string()).collect()
+            configs: targets.iter().map( | v | v.to_string()).collect(),
+            path: path.to_string(),
         }
     }
+    
+    // a file belongs to the package with the deepest manifest dir around it, None when no package has it
+    pub fn owning<'a>(packages: &'a [HubPackage], workspace: &str, rel_path: &str) -> Option<&'a HubPackage> {
+        packages.iter().filter( | package | {
+            package.project == workspace && (package.path.is_empty() || rel_path.starts_with(&format!("{}/", package.path)))
+        }).max_by_key( | package | package.path.len())
+    }
 }
 
 
+#[derive(Debug, Clone, Default, SerBin, DeBin, PartialEq, SerRon, DeRon)]
+#[nserde(tagged, version = "1", default)]
+pub struct ProgramOptions {
+    // run on a pseudo terminal, stdout and stderr arrive as one stream. unix only
+    pub pty: bool,
+    pub stdin: bool,
+    pub time_limit_ms: Option<u64>,
+    // cpu seconds, unix only. elsewhere the process refuses to start
+    pub cpu_limit_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, SerBin, DeBin, PartialEq, SerRon, DeRon)]
+pub enum ProgramExit {
+    Code(i32),
+    Signal(i32),
+    Killed,
+    TimeLimit,
+    CpuLimit,
+    #[default]
+    Unknown
+}
+
+impl ProgramExit {
+    pub fn is_success(&self) -> bool {
+        *self == ProgramExit::Code(0)
+    }
+    
+    pub fn describe(&self) -> String {
+        match self {
+            ProgramExit::Code(code) => format!("exited with code {}", code),
+            ProgramExit::Signal(signal) => format!("terminated by signal {}", signal),
+            ProgramExit::Killed => "killed".to_string(),
+            ProgramExit::TimeLimit => "killed, time limit reached".to_string(),
+            ProgramExit::CpuLimit => "killed, cpu limit reached".to_string(),
+            ProgramExit::Unknown => "ended".to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, SerBin, DeBin, PartialEq, SerRon, DeRon)]
+#[nserde(tagged, version = "2")]
 pub struct HubBuilderConfig {
     pub http_server: HttpServerConfig,
     pub workspaces: HashMap<String, String>,
+    // when empty the builder falls back to BuildProfile::defaults
+    #[nserde(default)]
+    pub profiles: Vec<BuildProfile>,
+}
+
+// a named way to build packages, BuildTarget.config picks one by name
+#[derive(Debug, Clone, SerBin, DeBin, PartialEq, SerRon, DeRon)]
+#[nserde(tagged, version = "1", default)]
+pub struct BuildProfile {
+    pub name: String,
+    // the cargo subcommand, build check clippy test or anything else cargo knows
+    pub command: String,
+    pub release: bool,
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+    // packages ending in wasm build for wasm32-unknown-unknown when this is None
+    pub target: Option<String>,
+    pub args: Vec<String>,
+    // passed after --, to the test binaries or to clippy
+    pub run_args: Vec<String>,
+    pub env: HashMap<String, String>,
+    // only offered for these packages, empty is all of them
+    pub packages: Vec<String>,
+    pub post_steps: Vec<BuildPostStep>,
+    // runs the test binaries with libtest's unstable json output so results show up per test,
+    // this needs RUSTC_BOOTSTRAP=1 on a stable toolchain
+    pub json_tests: bool,
+}
+
+#[derive(Debug, Clone, Copy, SerBin, DeBin, PartialEq, SerRon, DeRon)]
+pub enum BuildPostStep {
+    // strips the debug sections off a wasm build
+    WasmStripDebug
+}
+
+impl Default for BuildProfile {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            command: "build".to_string(),
+            release: false,
+            features: Vec::new(),
+            no_default_features: false,
+            target: None,
+            args: Vec::new(),
+            run_args: Vec::new(),
+            env: HashMap::new(),
+            packages: Vec::new(),
+            post_steps: Vec::new(),
+            json_tests: false,
+        }
+    }
+}
+
+impl BuildProfile {
+    pub fn new(name: &str, command: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            ..Self::default()
+        }
+    }
+    
+    // the configs builders had before they were configurable
+    pub fn defaults() -> Vec<BuildProfile> {
+        vec![
+            BuildProfile::new("check", "check"),
+            BuildProfile::new("debug", "build"),
+            BuildProfile {release: true, ..BuildProfile::new("release", "build")},
+            BuildProfile {
+                release: true,
+                env: {
+                    let mut env = HashMap::new();
+                    env.insert("RUSTFLAGS".to_string(), "-C opt-level=z -C panic=abort -C codegen-units=1".to_string());
+                    env
+                },
+                post_steps: vec![BuildPostStep::WasmStripDebug],
+                ..BuildProfile::new("small", "build")
+            },
+            BuildProfile {json_tests: true, ..BuildProfile::new("test", "test")},
+        ]
+    }
+    
+    pub fn applies_to(&self, package: &str) -> bool {
+        self.packages.is_empty() || self.packages.iter().any( | p | p == package)
+    }
+    
+    pub fn cargo_args(&self, package: &str) -> Vec<String> {
+        let mut args = vec![self.command.clone(), "-p".to_string(), package.to_string()];
+        if self.release {
+            args.push("--release".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        if !self.features.is_empty() {
+            args.push(format!("--features={}", self.features.join(",")));
+        }
+        if let Some(target) = &self.target {
+            args.push(format!("--target={}", target));
+        }
+        else if package.ends_with("wasm") {
+            args.push("--target=wasm32-unknown-unknown".to_string());
+        }
+        args.extend(self.args.iter().cloned());
+        let mut run_args = self.run_args.clone();
+        if self.json_tests {
+            run_args.extend(["-Z", "unstable-options", "--format", "json"].iter().map( | s | s.to_string()));
+        }
+        if !run_args.is_empty() {
+            args.push("--".to_string());
+            args.extend(run_args);
+        }
+        args
+    }
+    
+    pub fn cargo_env(&self) -> Vec<(String, String)> {
+        let mut env: Vec<(String, String)> = self.env.iter().map( | (k, v) | (k.clone(), v.clone())).collect();
+        if self.json_tests && !self.env.contains_key("RUSTC_BOOTSTRAP") {
+            env.push(("RUSTC_BOOTSTRAP".to_string(), "1".to_string()));
+        }
+        env.sort();
+        env
+    }
 }
 
 
@@ -226,6 +631,56 @@ pub struct LocMessage {
     pub range: Option<(usize, usize)>,
     pub rendered: Option<String>,
     pub explanation: Option<String>,
+    // last so older readers skip it
+    pub suggestions: Vec<LocSuggestion>,
+}
+
+// a compiler suggestion, lines and columns are 1 based and columns count chars like rustc does
+#[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
+pub struct LocSuggestion {
+    pub message: String,
+    pub applicability: LocApplicability,
+    pub edits: Vec<LocEdit>,
+}
+
+#[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
+pub struct LocEdit {
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, SerBin, DeBin)]
+pub enum LocApplicability {
+    MachineApplicable,
+    MaybeIncorrect,
+    HasPlaceholders,
+    Unspecified
+}
+
+impl LocApplicability {
+    pub fn from_rustc(applicability: Option<&str>) -> LocApplicability {
+        match applicability {
+            Some("MachineApplicable") => LocApplicability::MachineApplicable,
+            Some("MaybeIncorrect") => LocApplicability::MaybeIncorrect,
+            Some("HasPlaceholders") => LocApplicability::HasPlaceholders,
+            _ => LocApplicability::Unspecified
+        }
+    }
+    
+    // the weaker of the two, a suggestion is only as safe as its least safe edit
+    pub fn weakest(self, other: LocApplicability) -> LocApplicability {
+        if (self as usize) > (other as usize) {self} else {other}
+    }
+}
+
+impl LocSuggestion {
+    // only these get applied without a human looking at them first
+    pub fn is_quick_fix(&self) -> bool {
+        self.applicability == LocApplicability::MachineApplicable && !self.edits.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
@@ -308,7 +763,7 @@ impl HubAddr {
     }
 }
 
-#[derive(Debug, Clone, SerBin, DeBin)]
+#[derive(Debug, Clone, PartialEq, SerBin, DeBin)]
 pub enum HubMsgTo {
     Client(HubAddr),
     Builder(String),
@@ -1,6 +1,7 @@
-use std::net::{TcpStream, Shutdown};
+use std::net::Shutdown;
 use std::sync::{mpsc, Arc, Mutex};
 use crate::hubmsg::*;
+use crate::hubtls::*;
 
 #[derive(PartialEq)]
 pub enum HubRouteType{
@@ -119,7 +120,7 @@ impl HubRouteSend{
 pub struct HubRoute {
     pub peer_addr: HubAddr,
     pub tx_write: mpsc::Sender<FromHubMsg>,
-    pub tcp_stream: Option<TcpStream>,
+    pub tcp_stream: Option<HubStream>,
     pub route_type: HubRouteType
 }
 
@@ -268,7 +269,19 @@ impl HubRouter{
                                                     }
                                                 }
                                             };
+                                            let lost_builder = matches!(routes[pos].route_type, HubRouteType::Builder(_));
                                             routes.remove(pos);
+                                            // the ui gets the error itself too, its requests to that builder are stuck until it comes back
+                                            if lost_builder {
+                                                for route in routes.iter() {
+                                                    if route.route_type == HubRouteType::UI {
+                                                        route.tx_write.send(FromHubMsg {
+                                                            from: htc_msg.from,
+                                                            msg: HubMsg::ConnectionError(e.clone())
+                                                        }).expect("Could not tx_write.send");
+                                                    }
+                                                }
+                                            }
                                             for route in routes.iter() {
                                                 route.tx_write.send(msg.clone()).expect("Could not tx_write.send");
                                             }
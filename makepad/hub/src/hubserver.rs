@@ -1,9 +1,12 @@
 use std::net::{TcpListener, TcpStream, SocketAddr, Shutdown};
 use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 use crate::hubmsg::*;
 use crate::hubclient::*;
 use crate::hubrouter::*;
+use crate::hubtls::*;
+use crate::hubauth::*;
 use makepad_microserde::*;
 
 #[derive(Debug, Clone, SerBin, DeBin, SerRon, DeRon, PartialEq)]
@@ -11,28 +14,67 @@ pub enum HubServerConfig {
     Offline, // no network connectivity
     Network(u16), // 0.0.0.0:port
     Localhost(u16), // 127.0.0.1:port
-    InterfaceV4(u16, [u8; 4])
+    InterfaceV4(u16, [u8; 4]),
+    Tls(Box<HubServerConfig>, HubTlsConfig), // any of the above with a certificate
+}
+
+impl HubServerConfig {
+    pub fn listen_address(&self) -> Option<SocketAddr> {
+        match self {
+            HubServerConfig::Offline => None,
+            HubServerConfig::Localhost(port) => Some(SocketAddr::from(([127, 0, 0, 1], *port))),
+            HubServerConfig::Network(port) => Some(SocketAddr::from(([0, 0, 0, 0], *port))),
+            HubServerConfig::InterfaceV4(port, ip) => Some(SocketAddr::from((*ip, *port))),
+            HubServerConfig::Tls(config, _) => config.listen_address(),
+        }
+    }
+    
+    pub fn tls(&self) -> Option<&HubTlsConfig> {
+        match self {
+            HubServerConfig::Tls(_, tls) => Some(tls),
+            _ => None
+        }
+    }
 }
 
 pub struct HubServerShared {
     pub terminate: bool,
-    pub connections: Vec<(HubAddr, TcpStream)>
+    pub connections: Vec<(HubAddr, String, HubStream)>,
+    pub audit: Vec<HubAuditEvent>,
+}
+
+impl HubServerShared {
+    pub fn audit(&mut self, hub_log: &HubLog, event: HubAuditEvent) {
+        hub_log.log(&format!("HubServer audit {:?}", event));
+        // the oldest half goes once the log gets long, a hub can run for weeks
+        if self.audit.len() >= HUB_AUDIT_MAX {
+            self.audit.drain(0..HUB_AUDIT_MAX / 2);
+        }
+        self.audit.push(event);
+    }
 }
 
+pub const HUB_AUDIT_MAX: usize = 4096;
+pub const HUB_KEYRING_POLL: Duration = Duration::from_millis(500);
+
 pub struct HubServer {
     pub shared: Arc<Mutex<HubServerShared>>,
+    pub keyring: Arc<Mutex<HubKeyring>>,
+    pub keyring_path: Option<String>,
+    pub hub_log: HubLog,
     pub listen_address: Option<SocketAddr>,
-    pub listen_thread: Option<std::thread::JoinHandle<()>>
+    pub listen_thread: Option<std::thread::JoinHandle<()>>,
+    keyring_watch: Option<(mpsc::Sender<()>, std::thread::JoinHandle<()>)>,
 }
 
 impl HubServer {
-    pub fn start_hub_server(digest: Digest, config: &HubServerConfig, hub_router: &HubRouter) -> Option<HubServer> {
+    pub fn start_hub_server(keyring: Arc<Mutex<HubKeyring>>, config: &HubServerConfig, hub_router: &HubRouter, hub_log: HubLog) -> Option<HubServer> {
         
-        let listen_address = match config {
-            HubServerConfig::Offline => return None,
-            HubServerConfig::Localhost(port) => SocketAddr::from(([127, 0, 0, 1], *port)),
-            HubServerConfig::Network(port) => SocketAddr::from(([0, 0, 0, 0], *port)),
-            HubServerConfig::InterfaceV4(port, ip) => SocketAddr::from((*ip, *port)),
+        let listen_address = config.listen_address() ?;
+        let tls_config = match config.tls().map( | tls | tls.server_config()) {
+            Some(Ok(tls_config)) => Some(tls_config),
+            Some(Err(e)) => {println!("start_hub_server cannot load tls config: {}", e.msg); return None},
+            None => None
         };
         
         let listener = if let Ok(listener) = TcpListener::bind(listen_address) {listener}else {println!("start_hub_server bind server address"); return None};
@@ -42,127 +84,282 @@ impl HubServer {
         let routes = Arc::clone(&hub_router.routes); //Arc::new(Mutex::new(Vec::<HubServerConnection>::new()));
         let shared = Arc::new(Mutex::new(HubServerShared {
             connections: Vec::new(),
-            terminate: false
+            terminate: false,
+            audit: Vec::new(),
         }));
         
         let listen_thread = {
-            //let hub_log = hub_log.clone();
+            let hub_log = hub_log.clone();
             let routes = Arc::clone(&routes);
             let shared = Arc::clone(&shared);
-            let digest = digest.clone();
+            let keyring = Arc::clone(&keyring);
             std::thread::spawn(move || {
                 for tcp_stream in listener.incoming() {
-                    let tcp_stream = tcp_stream.expect("Incoming stream failure");
-                    let peer_addr = HubAddr::from_socket_addr(tcp_stream.peer_addr().expect("No peer address"));
+                    let tcp_stream = if let Ok(tcp_stream) = tcp_stream {tcp_stream} else {continue};
                     
                     if let Ok(mut shared) = shared.lock() {
                         if shared.terminate {
-                            for (_, tcp_stream) in &mut shared.connections {
+                            // lets disconnect all our connections
+                            for (_, _, tcp_stream) in &mut shared.connections {
                                 let _ = tcp_stream.shutdown(Shutdown::Both);
                             }
-                            // lets disconnect all our connections
                             return
                         }
-                        let tcp_stream = tcp_stream.try_clone().expect("Cannot clone tcp stream");
-                        shared.connections.push((peer_addr, tcp_stream));
                     }
                     
-                    let (tx_write, rx_write) = mpsc::channel::<FromHubMsg>();
-                    let tx_write_copy = tx_write.clone();
-                    // clone our transmit-to-pump
-                    let _read_thread = {
-                        let tx_pump = tx_pump.clone();
-                        let digest = digest.clone();
-                        let peer_addr = peer_addr.clone();
-                        let mut tcp_stream = tcp_stream.try_clone().expect("Cannot clone tcp stream");
-                        //let hub_log = hub_log.clone();
-                        std::thread::spawn(move || {
-                            loop {
-                                match read_block_from_tcp_stream(&mut tcp_stream, digest.clone()) {
-                                    Ok(msg_buf) => {
-                                        let cth_msg: ToHubMsg = DeBin::deserialize_bin(&msg_buf).expect("Can't parse binary");
-                                        tx_pump.send((peer_addr.clone(), cth_msg)).expect("tx_pump.send fails - should never happen");
-                                    }
-                                    Err(e) => {
-                                        let _ = tcp_stream.shutdown(Shutdown::Both);
-                                        let _ = tx_pump.send((peer_addr.clone(), ToHubMsg {
-                                            to: HubMsgTo::Hub,
-                                            msg: HubMsg::ConnectionError(e.clone())
-                                        })).expect("tx_pump.send fails - should never happen");
-                                        // lets break rx write
-                                        let _ = tx_write_copy.send(FromHubMsg {
-                                            from: peer_addr.clone(),
-                                            msg: HubMsg::ConnectionError(e)
-                                        });
-                                        return
-                                    }
-                                }
-                            }
-                        })
-                    };
-                    let _write_thread = {
-                        let digest = digest.clone();
-                        let peer_addr = peer_addr.clone();
-                        let tx_pump = tx_pump.clone();
-                        let shared = Arc::clone(&shared);
-                        let mut tcp_stream = tcp_stream.try_clone().expect("Cannot clone tcp stream");
-                        //let hub_log = hub_log.clone();
-                        std::thread::spawn(move || {
-                            while let Ok(htc_msg) = rx_write.recv() {
-                                match &htc_msg.msg {
-                                    HubMsg::ConnectionError(_) => { // we are closed by the read loop
-                                        let _ = tcp_stream.shutdown(Shutdown::Both);
-                                        break
-                                    },
-                                    _ => ()
-                                }
-                                let mut msg_buf = Vec::new(); 
-                                htc_msg.ser_bin(&mut msg_buf);
-                                
-                                if let Err(e) = write_block_to_tcp_stream(&mut tcp_stream, &msg_buf, digest.clone()) {
-                                    // disconnect the socket and send shutdown
-                                    let _ = tcp_stream.shutdown(Shutdown::Both);
-                                    tx_pump.send((peer_addr.clone(), ToHubMsg {
-                                        to: HubMsgTo::Hub,
-                                        msg: HubMsg::ConnectionError(e)
-                                    })).expect("tx_pump.send fails - should never happen");
-                                }
-                            }
-                            // remove tx_write from our shared pool
-                            if let Ok(mut shared) = shared.lock() {
-                                while let Some(position) = shared.connections.iter().position( | (addr, _) | *addr == peer_addr) {
-                                    shared.connections.remove(position);
-                                }
-                            }
-                        })
+                    let peer_addr = if let Ok(peer_addr) = tcp_stream.peer_addr() {HubAddr::from_socket_addr(peer_addr)} else {continue};
+                    // the tls handshake happens on the first io of the authentication
+                    let tcp_stream = match &tls_config {
+                        Some(tls_config) => HubStream::Tls(TlsStream::accept(tcp_stream, tls_config)),
+                        None => HubStream::Tcp(tcp_stream)
                     };
                     
-                    if let Ok(mut routes) = routes.lock() {
-                        routes.push(HubRoute {
-                            route_type: HubRouteType::Unknown,
-                            peer_addr: peer_addr.clone(),
-                            tcp_stream: Some(tcp_stream),
-                            tx_write: tx_write
-                        })
-                    };
+                    // authentication waits on the peer, so it gets its own thread
+                    let routes = Arc::clone(&routes);
+                    let shared = Arc::clone(&shared);
+                    let keyring = Arc::clone(&keyring);
+                    let tx_pump = tx_pump.clone();
+                    let hub_log = hub_log.clone();
+                    std::thread::spawn(move || {
+                        Self::accept_connection(tcp_stream, peer_addr, &keyring, &shared, &routes, tx_pump, hub_log);
+                    });
                 }
             })
         };
         
         let hub_server = HubServer {
             shared: shared,
+            keyring,
+            keyring_path: None,
+            hub_log,
             listen_address: Some(listen_address),
             listen_thread: Some(listen_thread),
+            keyring_watch: None,
         };
         
         
         return Some(hub_server);
     }
     
+    fn accept_connection(
+        mut tcp_stream: HubStream,
+        peer_addr: HubAddr,
+        keyring: &Arc<Mutex<HubKeyring>>,
+        shared: &Arc<Mutex<HubServerShared>>,
+        routes: &Arc<Mutex<Vec<HubRoute>>>,
+        tx_pump: mpsc::Sender<(HubAddr, ToHubMsg)>,
+        hub_log: HubLog
+    ) {
+        let HubSession {key_id, mut read_key, mut write_key} = match hub_auth_accept(&mut tcp_stream, keyring) {
+            Ok(session) => session,
+            Err(e) => {
+                let _ = tcp_stream.shutdown(Shutdown::Both);
+                if let Ok(mut shared) = shared.lock() {
+                    shared.audit(&hub_log, HubAuditEvent::Rejected {addr: peer_addr, reason: e.msg});
+                }
+                return
+            }
+        };
+        
+        if let Ok(mut shared) = shared.lock() {
+            // the key can be revoked whilst we were still talking
+            let revoked = keyring.lock().map( | keyring | keyring.find(&key_id).is_none()).unwrap_or(true);
+            if shared.terminate || revoked {
+                let _ = tcp_stream.shutdown(Shutdown::Both);
+                return
+            }
+            let tcp_stream = tcp_stream.try_clone().expect("Cannot clone tcp stream");
+            shared.connections.push((peer_addr, key_id.clone(), tcp_stream));
+            shared.audit(&hub_log, HubAuditEvent::Connected {addr: peer_addr, key_id: key_id.clone()});
+        }
+        
+        let (tx_write, rx_write) = mpsc::channel::<FromHubMsg>();
+        let tx_write_copy = tx_write.clone();
+        // clone our transmit-to-pump
+        let _read_thread = {
+            let tx_pump = tx_pump.clone();
+            let peer_addr = peer_addr.clone();
+            let mut tcp_stream = tcp_stream.try_clone().expect("Cannot clone tcp stream");
+            std::thread::spawn(move || {
+                // clients send heartbeats when idle, silence means the connection is gone
+                let _ = tcp_stream.tcp_stream().set_read_timeout(Some(HUB_HEARTBEAT_TIMEOUT));
+                loop {
+                    // a message we cannot parse ends the connection like a read error does
+                    let cth_msg = read_msg_from_tcp_stream::<ToHubMsg>(&mut tcp_stream, &mut read_key);
+                    match cth_msg {
+                        Ok(cth_msg) => {
+                            if let HubMsg::Heartbeat = cth_msg.msg {
+                                continue
+                            }
+                            tx_pump.send((peer_addr.clone(), cth_msg)).expect("tx_pump.send fails - should never happen");
+                        }
+                        Err(e) => {
+                            let _ = tcp_stream.shutdown(Shutdown::Both);
+                            let _ = tx_pump.send((peer_addr.clone(), ToHubMsg {
+                                to: HubMsgTo::Hub,
+                                msg: HubMsg::ConnectionError(e.clone())
+                            })).expect("tx_pump.send fails - should never happen");
+                            // lets break rx write
+                            let _ = tx_write_copy.send(FromHubMsg {
+                                from: peer_addr.clone(),
+                                msg: HubMsg::ConnectionError(e)
+                            });
+                            return
+                        }
+                    }
+                }
+            })
+        };
+        let _write_thread = {
+            let peer_addr = peer_addr.clone();
+            let tx_pump = tx_pump.clone();
+            let shared = Arc::clone(shared);
+            let mut tcp_stream = tcp_stream.try_clone().expect("Cannot clone tcp stream");
+            std::thread::spawn(move || {
+                loop {
+                    let htc_msg = match rx_write.recv_timeout(HUB_HEARTBEAT_INTERVAL) {
+                        Ok(htc_msg) => htc_msg,
+                        Err(mpsc::RecvTimeoutError::Timeout) => FromHubMsg {from: HubAddr::None, msg: HubMsg::Heartbeat},
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break
+                    };
+                    match &htc_msg.msg {
+                        HubMsg::ConnectionError(_) => { // we are closed by the read loop
+                            let _ = tcp_stream.shutdown(Shutdown::Both);
+                            break
+                        },
+                        _ => ()
+                    }
+                    let mut msg_buf = Vec::new();
+                    htc_msg.ser_bin(&mut msg_buf);
+                    
+                    if let Err(e) = write_block_to_tcp_stream(&mut tcp_stream, &msg_buf, &mut write_key) {
+                        // disconnect the socket and send shutdown
+                        let _ = tcp_stream.shutdown(Shutdown::Both);
+                        tx_pump.send((peer_addr.clone(), ToHubMsg {
+                            to: HubMsgTo::Hub,
+                            msg: HubMsg::ConnectionError(e)
+                        })).expect("tx_pump.send fails - should never happen");
+                    }
+                }
+                // remove tx_write from our shared pool
+                if let Ok(mut shared) = shared.lock() {
+                    while let Some(position) = shared.connections.iter().position( | (addr, _, _) | *addr == peer_addr) {
+                        shared.connections.remove(position);
+                    }
+                    shared.audit(&hub_log, HubAuditEvent::Disconnected {addr: peer_addr, key_id});
+                }
+            })
+        };
+        
+        if let Ok(mut routes) = routes.lock() {
+            routes.push(HubRoute {
+                route_type: HubRouteType::Unknown,
+                peer_addr: peer_addr.clone(),
+                tcp_stream: Some(tcp_stream),
+                tx_write: tx_write
+            })
+        };
+    }
+    
+    // keeps the keyring in step with keys.ron, so a key revoked there (by `builder revoke`)
+    // drops its connections without a restart. revoke_key writes to it from now on
+    pub fn watch_keyring(&mut self, path: &str) {
+        self.keyring_path = Some(path.to_string());
+        if let Some((tx_terminate, thread)) = self.keyring_watch.take() {
+            let _ = tx_terminate.send(());
+            let _ = thread.join();
+        }
+        let (tx_terminate, rx_terminate) = mpsc::channel();
+        let path = path.to_string();
+        let keyring = Arc::clone(&self.keyring);
+        let shared = Arc::clone(&self.shared);
+        let hub_log = self.hub_log.clone();
+        let thread = std::thread::spawn(move || {
+            let stamp = | path: &str | -> Option<(SystemTime, u64)> {
+                let metadata = std::fs::metadata(path).ok() ?;
+                Some((metadata.modified().ok() ?, metadata.len()))
+            };
+            let mut last_stamp = stamp(&path);
+            while let Err(mpsc::RecvTimeoutError::Timeout) = rx_terminate.recv_timeout(HUB_KEYRING_POLL) {
+                let new_stamp = stamp(&path);
+                if new_stamp == last_stamp {
+                    continue
+                }
+                last_stamp = new_stamp;
+                match HubKeyring::load(&path) {
+                    Ok(new_keyring) => {
+                        if let Ok(mut keyring) = keyring.lock() {
+                            *keyring = new_keyring;
+                        }
+                        Self::drop_revoked(&keyring, &shared, &hub_log);
+                    },
+                    // half written or broken, the keys we have stay until it parses again
+                    Err(e) => hub_log.log(&format!("HubServer keeps its keyring, {}", e.msg))
+                }
+            }
+        });
+        self.keyring_watch = Some((tx_terminate, thread));
+    }
+    
+    // hangs up on every connection whose key is no longer in the keyring
+    fn drop_revoked(keyring: &Arc<Mutex<HubKeyring>>, shared: &Arc<Mutex<HubServerShared>>, hub_log: &HubLog) {
+        let keyring = if let Ok(keyring) = keyring.lock() {keyring.clone()} else {return};
+        if let Ok(mut shared) = shared.lock() {
+            let mut revoked = Vec::new();
+            for (_, key_id, tcp_stream) in &shared.connections {
+                if keyring.find(key_id).is_none() {
+                    let _ = tcp_stream.shutdown(Shutdown::Both);
+                    if !revoked.contains(key_id) {
+                        revoked.push(key_id.clone());
+                    }
+                }
+            }
+            for key_id in revoked {
+                shared.audit(hub_log, HubAuditEvent::Revoked {key_id});
+            }
+        }
+    }
+    
+    // takes a builder key out of the keyring and drops whoever is connected with it
+    pub fn revoke_key(&self, key_id: &str) -> bool {
+        let known = if let Ok(mut keyring) = self.keyring.lock() {
+            let known = keyring.revoke(key_id);
+            if let Some(keyring_path) = &self.keyring_path {
+                if let Err(e) = keyring.save(keyring_path) {
+                    self.hub_log.log(&format!("HubServer revoked {} until it restarts, {}", key_id, e.msg));
+                }
+            }
+            known
+        }
+        else {
+            false
+        };
+        if let Ok(mut shared) = self.shared.lock() {
+            for (_, connection_key_id, tcp_stream) in &shared.connections {
+                if connection_key_id == key_id {
+                    let _ = tcp_stream.shutdown(Shutdown::Both);
+                }
+            }
+            shared.audit(&self.hub_log, HubAuditEvent::Revoked {key_id: key_id.to_string()});
+        }
+        known
+    }
+    
+    pub fn audit_log(&self) -> Vec<HubAuditEvent> {
+        if let Ok(shared) = self.shared.lock() {
+            return shared.audit.clone()
+        }
+        Vec::new()
+    }
+    
     pub fn terminate(&mut self) {
         if let Ok(mut shared) = self.shared.lock() {
             shared.terminate = true;
         }
+        if let Some((tx_terminate, thread)) = self.keyring_watch.take() {
+            let _ = tx_terminate.send(());
+            let _ = thread.join();
+        }
         if let Some(listen_address) = self.listen_address {
             self.listen_address = None;
             // just do a single connection to the listen address to break the wait.
@@ -0,0 +1,523 @@
+// rsync style mirroring of a workspace folder from one builder to another. The ui drives it:
+// it compares folder digests one level at a time, only walks into folders that differ and
+// only sends the blocks of a file the other side does not have yet.
+
+use makepad_microserde::*;
+use std::collections::{HashMap, HashSet};
+use crate::hubmsg::*;
+use crate::hubclient::*;
+use crate::hubrouter::*;
+
+pub const HUB_SYNC_BLOCK_SIZE: u32 = 2048;
+
+#[derive(Clone, Copy, Debug, PartialEq, SerBin, DeBin)]
+pub struct FileBlockHash {
+    pub weak: u32,
+    pub strong: u64
+}
+
+// what the receiving side has, so the sending side can leave out what matches
+#[derive(Clone, Debug, PartialEq, SerBin, DeBin)]
+pub struct FileSignature {
+    pub digest: Digest,
+    pub block_size: u32,
+    pub blocks: Vec<FileBlockHash>
+}
+
+#[derive(Clone, Debug, PartialEq, SerBin, DeBin)]
+pub enum FileDeltaOp {
+    Copy {block: u32, count: u32},
+    Data(Vec<u8>)
+}
+
+#[derive(Clone, Debug, PartialEq, SerBin, DeBin)]
+pub struct FileDelta {
+    // the file the delta applies to, and what comes out
+    pub base: Digest,
+    pub digest: Digest,
+    pub block_size: u32,
+    pub ops: Vec<FileDeltaOp>
+}
+
+// the rsync rolling checksum, slides over the data a byte at a time
+struct RollingHash {
+    a: u32,
+    b: u32,
+    len: u32
+}
+
+impl RollingHash {
+    fn new(data: &[u8]) -> RollingHash {
+        let mut a = 0u32;
+        let mut b = 0u32;
+        let len = data.len() as u32;
+        for (i, byte) in data.iter().enumerate() {
+            a = a.wrapping_add(*byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(*byte as u32));
+        }
+        RollingHash {a: a & 0xffff, b: b & 0xffff, len}
+    }
+
+    fn roll(&mut self, out_byte: u8, in_byte: u8) {
+        self.a = self.a.wrapping_sub(out_byte as u32).wrapping_add(in_byte as u32) & 0xffff;
+        self.b = self.b.wrapping_sub(self.len.wrapping_mul(out_byte as u32)).wrapping_add(self.a) & 0xffff;
+    }
+
+    fn value(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+}
+
+fn push_copy(ops: &mut Vec<FileDeltaOp>, literal: &mut Vec<u8>, block: u32) {
+    if !literal.is_empty() {
+        ops.push(FileDeltaOp::Data(std::mem::take(literal)));
+    }
+    match ops.last_mut() {
+        Some(FileDeltaOp::Copy {block: first, count}) if *first + *count == block => *count += 1,
+        _ => ops.push(FileDeltaOp::Copy {block, count: 1})
+    }
+}
+
+fn strong_hash(block: &[u8]) -> u64 {
+    Digest::from_buffer(block).buf[0]
+}
+
+impl FileSignature {
+    pub fn new(data: &[u8], block_size: u32) -> FileSignature {
+        FileSignature {
+            digest: Digest::from_buffer(data),
+            block_size,
+            blocks: data.chunks(block_size.max(1) as usize).map( | block | FileBlockHash {
+                weak: RollingHash::new(block).value(),
+                strong: strong_hash(block)
+            }).collect()
+        }
+    }
+}
+
+impl FileDelta {
+    pub fn new(signature: &FileSignature, data: &[u8]) -> FileDelta {
+        let block_size = signature.block_size.max(1) as usize;
+        let mut lookup = HashMap::<u32, Vec<u32 >>::new();
+        for (index, block) in signature.blocks.iter().enumerate() {
+            lookup.entry(block.weak).or_default().push(index as u32);
+        }
+
+        let mut ops = Vec::new();
+        let mut literal = Vec::new();
+        let mut pos = 0;
+        let mut rolling = if data.len() >= block_size {Some(RollingHash::new(&data[0..block_size]))} else {None};
+        while let Some(hash) = &mut rolling {
+            let found = lookup.get(&hash.value()).and_then( | candidates | {
+                let strong = strong_hash(&data[pos..pos + block_size]);
+                candidates.iter().find( | index | signature.blocks[**index as usize].strong == strong).cloned()
+            });
+            if let Some(block) = found {
+                push_copy(&mut ops, &mut literal, block);
+                pos += block_size;
+                rolling = if pos + block_size <= data.len() {Some(RollingHash::new(&data[pos..pos + block_size]))} else {None};
+                continue;
+            }
+            literal.push(data[pos]);
+            if pos + block_size < data.len() {
+                hash.roll(data[pos], data[pos + block_size]);
+            }
+            else {
+                rolling = None;
+            }
+            pos += 1;
+        }
+        // the tail is shorter than a block, it can still be the short last block of the signature
+        let tail = &data[pos..];
+        if let Some(last) = signature.blocks.last() {
+            if !tail.is_empty() && tail.len() < block_size && last.strong == strong_hash(tail) && last.weak == RollingHash::new(tail).value() {
+                push_copy(&mut ops, &mut literal, signature.blocks.len() as u32 - 1);
+                return FileDelta {base: signature.digest.clone(), digest: Digest::from_buffer(data), block_size: signature.block_size, ops}
+            }
+        }
+        literal.extend_from_slice(tail);
+        if !literal.is_empty() {
+            ops.push(FileDeltaOp::Data(literal));
+        }
+        FileDelta {base: signature.digest.clone(), digest: Digest::from_buffer(data), block_size: signature.block_size, ops}
+    }
+
+    pub fn apply(&self, base: &[u8]) -> Result<Vec<u8>, HubError> {
+        if Digest::from_buffer(base) != self.base {
+            return Err(HubError::new("File changed since its signature was made"))
+        }
+        let block_size = self.block_size.max(1) as usize;
+        let mut out = Vec::new();
+        for op in &self.ops {
+            match op {
+                FileDeltaOp::Copy {block, count} => {
+                    let start = *block as usize * block_size;
+                    let end = (start + *count as usize * block_size).min(base.len());
+                    if start > end {
+                        return Err(HubError::new("File delta copies past the end of the file"))
+                    }
+                    out.extend_from_slice(&base[start..end]);
+                },
+                FileDeltaOp::Data(data) => out.extend_from_slice(data)
+            }
+        }
+        if Digest::from_buffer(&out) != self.digest {
+            return Err(HubError::new("File delta did not produce the expected file"))
+        }
+        Ok(out)
+    }
+}
+
+// a builder and a folder on it, written as builder/workspace/sub like the makepad sync settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct HubSyncPoint {
+    pub builder: String,
+    pub path: String
+}
+
+impl HubSyncPoint {
+    pub fn from_path(path: &str) -> Option<HubSyncPoint> {
+        let builder_pos = path.find('/') ?;
+        let (builder, rest) = path.split_at(builder_pos);
+        let rest = rest[1..].trim_end_matches('/');
+        if builder.is_empty() || rest.is_empty() {
+            return None
+        }
+        Some(HubSyncPoint {builder: builder.to_string(), path: rest.to_string()})
+    }
+
+    pub fn sub_path(&self, rel: &str) -> String {
+        if rel.is_empty() {self.path.clone()} else {format!("{}/{}", self.path, rel)}
+    }
+
+    // the path relative to this point if the builder path lies inside it
+    pub fn rel_path(&self, full_path: &str) -> Option<String> {
+        let rest = full_path.strip_prefix(&self.builder) ?.strip_prefix('/') ?;
+        if rest == self.path {
+            return Some(String::new())
+        }
+        rest.strip_prefix(&self.path) ?.strip_prefix('/').map( | rel | rel.to_string())
+    }
+}
+
+impl std::fmt::Display for HubSyncPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.builder, self.path)
+    }
+}
+
+enum HubSyncStep {
+    Folder {
+        rel: String,
+        source_uid: HubUid,
+        target_uid: HubUid,
+        source: Option<Option<Vec<BuilderFileTreeNode >> >,
+        target: Option<Option<Vec<BuilderFileTreeNode >> >
+    },
+    Signature {rel: String, uid: HubUid},
+    Delta {rel: String, uid: HubUid},
+    Read {rel: String, uid: HubUid},
+    Write {rel: String, uid: HubUid, digest: Digest}
+}
+
+impl HubSyncStep {
+    fn rel(&self) -> &str {
+        match self {
+            HubSyncStep::Folder {rel, ..} |
+            HubSyncStep::Signature {rel, ..} |
+            HubSyncStep::Delta {rel, ..} |
+            HubSyncStep::Read {rel, ..} |
+            HubSyncStep::Write {rel, ..} => rel
+        }
+    }
+
+    fn has_uid(&self, response_uid: &HubUid) -> bool {
+        match self {
+            HubSyncStep::Folder {source_uid, target_uid, ..} => source_uid == response_uid || target_uid == response_uid,
+            HubSyncStep::Signature {uid, ..} |
+            HubSyncStep::Delta {uid, ..} |
+            HubSyncStep::Read {uid, ..} |
+            HubSyncStep::Write {uid, ..} => uid == response_uid
+        }
+    }
+}
+
+// the synced digests on disk, so a restart still knows which side edited a file
+#[derive(Clone, Debug, Default, SerBin, DeBin)]
+pub struct HubSyncBaseline {
+    pub source: String,
+    pub synced: HashMap<String, Digest>
+}
+
+pub struct HubSync {
+    pub source: HubSyncPoint,
+    pub target: HubSyncPoint,
+    // what both sides had after the last sync, a target that moved away from this was edited there
+    pub synced: HashMap<String, Digest>,
+    pub baseline_path: Option<String>,
+    pub files_synced: usize,
+    baseline_changed: bool,
+    // conflicts that are in the log already, a rerun does not repeat them
+    warned: HashSet<String>,
+    steps: Vec<HubSyncStep>
+}
+
+impl HubSync {
+    pub fn new(source: HubSyncPoint, target: HubSyncPoint) -> HubSync {
+        HubSync {
+            source,
+            target,
+            synced: HashMap::new(),
+            baseline_path: None,
+            files_synced: 0,
+            baseline_changed: false,
+            warned: HashSet::new(),
+            steps: Vec::new()
+        }
+    }
+
+    // one baseline per target, like sync_b%2fws.bin for b/ws
+    pub fn baseline_file_name(&self) -> String {
+        let mut name = "sync_".to_string();
+        for byte in self.target.to_string().bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' {
+                name.push(byte as char);
+            }
+            else {
+                name.push_str(&format!("%{:02x}", byte));
+            }
+        }
+        name.push_str(".bin");
+        name
+    }
+
+    // a missing file is an empty baseline, one we cannot parse is left alone and not written over
+    pub fn load_baseline(&mut self, path: &str) -> Result<(), HubError> {
+        match std::fs::read(path) {
+            Ok(data) => {
+                let baseline: HubSyncBaseline = DeBin::deserialize_bin(&data)
+                    .map_err( | _ | HubError::new(&format!("Cannot parse sync baseline {}", path))) ?;
+                // a baseline from another source says nothing about this one
+                if baseline.source == self.source.to_string() {
+                    self.synced = baseline.synced;
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(HubError::new(&format!("Cannot read sync baseline {}: {}", path, e)))
+        }
+        self.baseline_path = Some(path.to_string());
+        Ok(())
+    }
+
+    pub fn save_baseline(&mut self) -> Result<(), HubError> {
+        let path = if let Some(path) = &self.baseline_path {path} else {
+            return Ok(())
+        };
+        let baseline = HubSyncBaseline {source: self.source.to_string(), synced: self.synced.clone()};
+        std::fs::write(path, baseline.serialize_bin()).map_err( | e | HubError::new(&format!("Cannot write sync baseline {}: {}", path, e))) ?;
+        self.baseline_changed = false;
+        Ok(())
+    }
+
+    fn set_synced(&mut self, rel: String, digest: Digest) {
+        self.warned.remove(&self.target.sub_path(&rel));
+        if self.synced.get(&rel) != Some(&digest) {
+            self.synced.insert(rel, digest);
+            self.baseline_changed = true;
+        }
+    }
+
+    fn warn_once(&mut self, path: String, msg: String, log: &mut Vec<HubLogItem>) {
+        if self.warned.insert(path) {
+            log.push(HubLogItem::Warning(msg));
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.steps.is_empty()
+    }
+
+    // walks the whole tree, skipping the folders whose digests already match
+    pub fn start(&mut self, route_send: &mut HubRouteSend) {
+        if !self.steps.iter().any( | step | matches!(step, HubSyncStep::Folder {rel, ..} if rel.is_empty())) {
+            self.compare_folder(route_send, "");
+        }
+    }
+
+    pub fn sync_file(&mut self, route_send: &mut HubRouteSend, rel: &str) {
+        if self.steps.iter().any( | step | !matches!(step, HubSyncStep::Folder {..}) && step.rel() == rel) {
+            return
+        }
+        let uid = route_send.alloc_uid();
+        route_send.send(ToHubMsg {
+            to: HubMsgTo::Builder(self.target.builder.clone()),
+            msg: HubMsg::FileSignatureRequest {uid, path: self.target.sub_path(rel), block_size: HUB_SYNC_BLOCK_SIZE}
+        });
+        self.steps.push(HubSyncStep::Signature {rel: rel.to_string(), uid});
+    }
+
+    fn compare_folder(&mut self, route_send: &mut HubRouteSend, rel: &str) {
+        let source_uid = route_send.alloc_uid();
+        let target_uid = route_send.alloc_uid();
+        route_send.send(ToHubMsg {
+            to: HubMsgTo::Builder(self.source.builder.clone()),
+            msg: HubMsg::FileTreeDigestRequest {uid: source_uid, path: self.source.sub_path(rel)}
+        });
+        route_send.send(ToHubMsg {
+            to: HubMsgTo::Builder(self.target.builder.clone()),
+            msg: HubMsg::FileTreeDigestRequest {uid: target_uid, path: self.target.sub_path(rel)}
+        });
+        self.steps.push(HubSyncStep::Folder {rel: rel.to_string(), source_uid, target_uid, source: None, target: None});
+    }
+
+    fn compare_nodes(&mut self, route_send: &mut HubRouteSend, rel: &str, source: Option<Vec<BuilderFileTreeNode>>, target: Option<Vec<BuilderFileTreeNode>>, log: &mut Vec<HubLogItem>) {
+        let source = if let Some(source) = source {source} else {
+            log.push(HubLogItem::Error(format!("Sync cannot read {}", self.source.sub_path(rel))));
+            return
+        };
+        let target = target.unwrap_or_default();
+        let join = | name: &str | if rel.is_empty() {name.to_string()} else {format!("{}/{}", rel, name)};
+        for node in &source {
+            let other = target.iter().find( | other | node_name(other) == node_name(node));
+            match (node, other) {
+                (BuilderFileTreeNode::File {name, digest}, Some(BuilderFileTreeNode::File {digest: other_digest, ..})) => {
+                    if digest != other_digest {
+                        self.sync_file(route_send, &join(name));
+                    }
+                    else if let Some(digest) = digest {
+                        self.set_synced(join(name), (**digest).clone());
+                    }
+                },
+                (BuilderFileTreeNode::File {name, ..}, None) => self.sync_file(route_send, &join(name)),
+                (BuilderFileTreeNode::Folder {name, digest, ..}, Some(BuilderFileTreeNode::Folder {digest: other_digest, ..})) => {
+                    if digest != other_digest {
+                        self.compare_folder(route_send, &join(name));
+                    }
+                },
+                (BuilderFileTreeNode::Folder {name, ..}, None) => self.compare_folder(route_send, &join(name)),
+                (_, Some(_)) => {
+                    let path = self.target.sub_path(&join(node_name(node)));
+                    let msg = format!("Sync conflict: {} is a file on one side and a folder on the other, left alone", path);
+                    self.warn_once(path, msg, log);
+                }
+            }
+        }
+        for other in &target {
+            if !source.iter().any( | node | node_name(node) == node_name(other)) {
+                let path = self.target.sub_path(&join(node_name(other)));
+                let msg = format!("Sync conflict: {} only exists on {}, left alone", path, self.target.builder);
+                self.warn_once(path, msg, log);
+            }
+        }
+    }
+
+    // feed every message from the hub through here, returns what should show up in the log
+    pub fn handle_hub_msg(&mut self, route_send: &mut HubRouteSend, htc: &FromHubMsg) -> Vec<HubLogItem> {
+        let mut log = Vec::new();
+        let response_uid = match &htc.msg {
+            HubMsg::FileTreeDigestResponse {uid, ..} |
+            HubMsg::FileSignatureResponse {uid, ..} |
+            HubMsg::FileDeltaResponse {uid, ..} |
+            HubMsg::FileReadResponse {uid, ..} |
+            HubMsg::FileWriteResponse {uid, ..} => *uid,
+            _ => return log
+        };
+        let index = if let Some(index) = self.steps.iter().position( | step | step.has_uid(&response_uid)) {index} else {
+            return log
+        };
+        let step = self.steps.remove(index);
+        match (step, &htc.msg) {
+            (HubSyncStep::Folder {rel, source_uid, target_uid, source, target}, HubMsg::FileTreeDigestResponse {nodes, ..}) => {
+                let (source, target) = if response_uid == source_uid {(Some(nodes.clone()), target)} else {(source, Some(nodes.clone()))};
+                match (source, target) {
+                    (Some(source), Some(target)) => self.compare_nodes(route_send, &rel, source, target, &mut log),
+                    (source, target) => self.steps.push(HubSyncStep::Folder {rel, source_uid, target_uid, source, target})
+                }
+            },
+            (HubSyncStep::Signature {rel, ..}, HubMsg::FileSignatureResponse {signature, ..}) => {
+                let base = self.synced.get(&rel);
+                match signature {
+                    Some(signature) => if base.is_some() && base != Some(&signature.digest) {
+                        log.push(HubLogItem::Warning(format!(
+                            "Sync conflict: {} changed on {} since the last sync, not overwriting it",
+                            self.target.sub_path(&rel),
+                            self.target.builder
+                        )));
+                    }
+                    else {
+                        let uid = route_send.alloc_uid();
+                        route_send.send(ToHubMsg {
+                            to: HubMsgTo::Builder(self.source.builder.clone()),
+                            msg: HubMsg::FileDeltaRequest {uid, path: self.source.sub_path(&rel), signature: signature.clone()}
+                        });
+                        self.steps.push(HubSyncStep::Delta {rel, uid});
+                    },
+                    None => if base.is_some() {
+                        log.push(HubLogItem::Warning(format!(
+                            "Sync conflict: {} was deleted on {}, not bringing it back",
+                            self.target.sub_path(&rel),
+                            self.target.builder
+                        )));
+                    }
+                    else {
+                        let uid = route_send.alloc_uid();
+                        route_send.send(ToHubMsg {
+                            to: HubMsgTo::Builder(self.source.builder.clone()),
+                            msg: HubMsg::FileReadRequest {uid, path: self.source.sub_path(&rel)}
+                        });
+                        self.steps.push(HubSyncStep::Read {rel, uid});
+                    }
+                }
+            },
+            (HubSyncStep::Delta {rel, ..}, HubMsg::FileDeltaResponse {delta, ..}) => match delta {
+                Some(delta) if delta.digest == delta.base => {
+                    self.set_synced(rel, delta.digest.clone());
+                },
+                Some(delta) => {
+                    let uid = route_send.alloc_uid();
+                    route_send.send(ToHubMsg {
+                        to: HubMsgTo::Builder(self.target.builder.clone()),
+                        msg: HubMsg::FileDeltaWriteRequest {uid, path: self.target.sub_path(&rel), delta: delta.clone()}
+                    });
+                    self.steps.push(HubSyncStep::Write {rel, uid, digest: delta.digest.clone()});
+                },
+                None => log.push(HubLogItem::Error(format!("Sync cannot read {}", self.source.sub_path(&rel))))
+            },
+            (HubSyncStep::Read {rel, ..}, HubMsg::FileReadResponse {data, ..}) => match data {
+                Some(data) => {
+                    let uid = route_send.alloc_uid();
+                    route_send.send(ToHubMsg {
+                        to: HubMsgTo::Builder(self.target.builder.clone()),
+                        msg: HubMsg::FileWriteRequest {uid, path: self.target.sub_path(&rel), data: data.clone()}
+                    });
+                    self.steps.push(HubSyncStep::Write {rel, uid, digest: Digest::from_buffer(data)});
+                },
+                None => log.push(HubLogItem::Error(format!("Sync cannot read {}", self.source.sub_path(&rel))))
+            },
+            (HubSyncStep::Write {rel, digest, ..}, HubMsg::FileWriteResponse {done, ..}) => if *done {
+                self.set_synced(rel, digest);
+                self.files_synced += 1;
+            }
+            else {
+                log.push(HubLogItem::Error(format!("Sync cannot write {}", self.target.sub_path(&rel))));
+            },
+            (step, _) => self.steps.push(step)
+        }
+        if self.steps.is_empty() && self.files_synced > 0 {
+            log.push(HubLogItem::Message(format!("Synced {} files from {} to {}", self.files_synced, self.source, self.target)));
+            self.files_synced = 0;
+        }
+        if self.steps.is_empty() && self.baseline_changed {
+            if let Err(err) = self.save_baseline() {
+                log.push(HubLogItem::Error(err.msg));
+            }
+        }
+        log
+    }
+}
+
+fn node_name(node: &BuilderFileTreeNode) -> &str {
+    match node {
+        BuilderFileTreeNode::File {name, ..} | BuilderFileTreeNode::Folder {name, ..} => name
+    }
+}
@@ -0,0 +1,283 @@
+// optional tls for hub connections and the http server. A TlsStream can be cloned into a read
+// and a write thread like a TcpStream, the socket io happens outside the session lock. The
+// records go out in order under a separate send lock, which a reader never waits for
+use crate::hubmsg::*;
+use makepad_microserde::*;
+use makepad_http::server::HttpStream;
+
+use std::net::{TcpStream, Shutdown};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::sync::{Arc, Mutex};
+
+// pem files on disk, the key can be pkcs8 or rsa
+#[derive(Debug, Clone, SerBin, DeBin, SerRon, DeRon, PartialEq)]
+pub struct HubTlsConfig {
+    pub cert: String,
+    pub key: String,
+}
+
+// what a client needs to trust a hub, the ca file can hold a self signed certificate
+#[derive(Debug, Clone, SerBin, DeBin, SerRon, DeRon, PartialEq)]
+pub struct HubTlsClientConfig {
+    pub ca: String,
+    pub server_name: String,
+}
+
+impl HubTlsConfig {
+    pub fn server_config(&self) -> Result<Arc<rustls::ServerConfig>, HubError> {
+        let certs = read_pem(&self.cert, rustls::internal::pemfile::certs) ?;
+        if certs.is_empty() {
+            return Err(HubError::new(&format!("No certificates in {}", self.cert)))
+        }
+        let mut keys = read_pem(&self.key, rustls::internal::pemfile::pkcs8_private_keys) ?;
+        if keys.is_empty() {
+            keys = read_pem(&self.key, rustls::internal::pemfile::rsa_private_keys) ?;
+        }
+        let key = keys.into_iter().next().ok_or_else( || HubError::new(&format!("No private key in {}", self.key))) ?;
+        let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        config.set_single_cert(certs, key).map_err( | e | HubError::new(&format!("Invalid certificate or key: {}", e))) ?;
+        Ok(Arc::new(config))
+    }
+}
+
+impl HubTlsClientConfig {
+    pub fn client_config(&self) -> Result<Arc<rustls::ClientConfig>, HubError> {
+        let mut config = rustls::ClientConfig::new();
+        for cert in read_pem(&self.ca, rustls::internal::pemfile::certs) ? {
+            config.root_store.add(&cert).map_err( | e | HubError::new(&format!("Invalid certificate in {}: {:?}", self.ca, e))) ?;
+        }
+        Ok(Arc::new(config))
+    }
+}
+
+fn read_pem<T>(path: &str, parse: fn(&mut dyn BufRead) -> Result<Vec<T>, ()>) -> Result<Vec<T>, HubError> {
+    let file = std::fs::File::open(path).map_err( | e | HubError::new(&format!("Cannot open {}: {}", path, e))) ?;
+    parse(&mut BufReader::new(file)).map_err( | _ | HubError::new(&format!("Cannot parse pem file {}", path)))
+}
+
+pub struct TlsStream {
+    tcp_stream: TcpStream,
+    session: Arc<Mutex<Box<dyn rustls::Session >> >,
+    sending: Arc<Mutex<()>>,
+}
+
+impl TlsStream {
+    pub fn accept(tcp_stream: TcpStream, config: &Arc<rustls::ServerConfig>) -> TlsStream {
+        TlsStream {
+            tcp_stream,
+            session: Arc::new(Mutex::new(Box::new(rustls::ServerSession::new(config)))),
+            sending: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn connect(tcp_stream: TcpStream, config: &Arc<rustls::ClientConfig>, server_name: &str) -> std::io::Result<TlsStream> {
+        let dns_name = webpki::DNSNameRef::try_from_ascii_str(server_name)
+            .map_err( | _ | std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid tls server name {}", server_name))) ?;
+        let mut stream = TlsStream {
+            tcp_stream,
+            session: Arc::new(Mutex::new(Box::new(rustls::ClientSession::new(config, dns_name)))),
+            sending: Arc::new(Mutex::new(())),
+        };
+        // finish the handshake here so a bad certificate fails the connect, not the first message
+        stream.complete_handshake() ?;
+        Ok(stream)
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<TlsStream> {
+        Ok(TlsStream {
+            tcp_stream: self.tcp_stream.try_clone() ?,
+            session: Arc::clone(&self.session),
+            sending: Arc::clone(&self.sending),
+        })
+    }
+
+    pub fn tcp_stream(&self) -> &TcpStream {
+        &self.tcp_stream
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        if let Ok(mut session) = self.session.lock() {
+            session.send_close_notify();
+        }
+        // a writer stuck on the socket sends the close_notify after its data, if ever
+        let _ = self.send_tls(false);
+        self.tcp_stream.shutdown(how)
+    }
+
+    fn complete_handshake(&mut self) -> std::io::Result<()> {
+        loop {
+            self.send_tls(true) ?;
+            let handshaking = if let Ok(session) = self.session.lock() {
+                session.is_handshaking()
+            }
+            else {false};
+            if !handshaking {
+                return Ok(())
+            }
+            if self.read_tls() ? == 0 {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Connection closed during tls handshake"))
+            }
+        }
+    }
+
+    // encrypts into memory under the session lock and writes to the socket after letting go of it.
+    // The send lock keeps the records in order, whoever holds it sends what others queued meanwhile.
+    // A reader does not wait for it, so it keeps draining the socket while a writer is blocked
+    fn send_tls(&self, wait: bool) -> std::io::Result<()> {
+        let sending = if wait {
+            self.sending.lock().map_err( | _ | std::io::Error::other("Tls send lock poisoned")) ?
+        }
+        else {
+            match self.sending.try_lock() {
+                Ok(sending) => sending,
+                Err(std::sync::TryLockError::WouldBlock) => return Ok(()),
+                Err(std::sync::TryLockError::Poisoned(_)) => return Err(std::io::Error::other("Tls send lock poisoned"))
+            }
+        };
+        loop {
+            let mut data = Vec::new();
+            {
+                let mut session = self.session.lock().map_err( | _ | std::io::Error::other("Tls session lock poisoned")) ?;
+                while session.wants_write() {
+                    session.write_tls(&mut data) ?;
+                }
+                if data.is_empty() {
+                    // let go of the send lock while nobody can queue anything
+                    drop(sending);
+                    return Ok(())
+                }
+            }
+            (&self.tcp_stream).write_all(&data) ?;
+        }
+    }
+
+    // blocks on the socket without holding the session
+    fn read_tls(&mut self) -> std::io::Result<usize> {
+        let mut data = [0u8; 16384];
+        let n = (&self.tcp_stream).read(&mut data) ?;
+        let mut error = None;
+        if let Ok(mut session) = self.session.lock() {
+            let mut input = &data[0..n];
+            while !input.is_empty() {
+                session.read_tls(&mut input) ?;
+                if let Err(e) = session.process_new_packets() {
+                    error = Some(e);
+                    break
+                }
+            }
+        }
+        if let Some(e) = error {
+            // let the other side know why before giving up
+            let _ = self.send_tls(false);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        }
+        self.send_tls(false) ?;
+        Ok(n)
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Ok(mut session) = self.session.lock() {
+                match session.read(buf) {
+                    Ok(0) => (),
+                    Ok(n) => return Ok(n),
+                    // a clean close_notify from the other side
+                    Err(e) if e.kind() == std::io::ErrorKind::ConnectionAborted => return Ok(0),
+                    Err(e) => return Err(e)
+                }
+            }
+            if self.read_tls() ? == 0 {
+                return Ok(0)
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = if let Ok(mut session) = self.session.lock() {
+            session.write(buf) ?
+        }
+        else {
+            return Err(std::io::Error::other("Tls session lock poisoned"))
+        };
+        self.send_tls(true) ?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// a hub connection, either plain or over tls
+pub enum HubStream {
+    Tcp(TcpStream),
+    Tls(TlsStream)
+}
+
+impl HubStream {
+    pub fn try_clone(&self) -> std::io::Result<HubStream> {
+        match self {
+            HubStream::Tcp(tcp_stream) => Ok(HubStream::Tcp(tcp_stream.try_clone() ?)),
+            HubStream::Tls(tls_stream) => Ok(HubStream::Tls(tls_stream.try_clone() ?)),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        match self {
+            HubStream::Tcp(tcp_stream) => tcp_stream.shutdown(how),
+            HubStream::Tls(tls_stream) => tls_stream.shutdown(how),
+        }
+    }
+
+    pub fn tcp_stream(&self) -> &TcpStream {
+        match self {
+            HubStream::Tcp(tcp_stream) => tcp_stream,
+            HubStream::Tls(tls_stream) => tls_stream.tcp_stream(),
+        }
+    }
+}
+
+impl Read for HubStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            HubStream::Tcp(tcp_stream) => tcp_stream.read(buf),
+            HubStream::Tls(tls_stream) => tls_stream.read(buf),
+        }
+    }
+}
+
+impl Write for HubStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            HubStream::Tcp(tcp_stream) => tcp_stream.write(buf),
+            HubStream::Tls(tls_stream) => tls_stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            HubStream::Tcp(tcp_stream) => tcp_stream.flush(),
+            HubStream::Tls(tls_stream) => tls_stream.flush(),
+        }
+    }
+}
+
+// lets the http server speak https on the sockets it accepts
+impl HttpStream for TlsStream {
+    fn try_clone_box(&self) -> std::io::Result<Box<dyn HttpStream >> {
+        Ok(Box::new(self.try_clone() ?))
+    }
+
+    fn tcp_stream(&self) -> &TcpStream {
+        &self.tcp_stream
+    }
+
+    fn shutdown(&self, how: Shutdown) -> std::io::Result<()> {
+        TlsStream::shutdown(self, how)
+    }
+}
@@ -10,17 +10,32 @@ pub use crate::process::*;
 mod hubclient;
 pub use crate::hubclient::*;
 
+mod hubtls;
+pub use crate::hubtls::*;
+
+mod hubauth;
+pub use crate::hubauth::*;
+
 mod hubserver;
 pub use crate::hubserver::*;
 
 mod hubrouter;
 pub use crate::hubrouter::*;
 
+mod hubsync;
+pub use crate::hubsync::*;
+
 mod hubmsg;
 pub use crate::hubmsg::*;
 
 mod httpserver;
 pub use crate::httpserver::*;
 
+mod httpcompress;
+pub use crate::httpcompress::*;
+
+mod filewatch;
+pub use crate::filewatch::*;
+
 mod wasmstrip;
 pub use crate::wasmstrip::*;
@@ -1,116 +1,251 @@
-//use closefds::*;
-use std::process::{Command, Child, Stdio};
-//use std::os::unix::process::{CommandExt};
+use std::process::{Command, Child, Stdio, ExitStatus};
+#[cfg(unix)]
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::sync::{mpsc};
-use std::io::{Read};
-use std::str;
+use std::io::{Read, Write};
+use std::time::Instant;
+use crate::hubmsg::{ProgramOptions, ProgramExit};
 
 pub struct Process {
     pub child: Option<Child>,
     pub rx_line: Option<mpsc::Receiver<Option<(bool, String)>>>,
+    pub stdin: Option<Box<dyn Write + Send>>,
+    pub deadline: Option<Instant>,
+    pub exit: Option<ProgramExit>,
 }
 
 impl Process {
     
     pub fn start(cmd: &str, args: &[&str], current_dir: &str, env: &[(&str, &str)]) -> Result<Process, std::io::Error> {
-        fn create_process(cmd: &str, args: &[&str], current_dir: &str, env: &[(&str, &str)]) -> Result<Child, std::io::Error> {
-            let mut cbuild = if cmd.find("/").is_some() {
-                Command::new(&format!("{}/{}", current_dir, cmd))
-            }
-            else {
-                Command::new(cmd)
-            };
-            cbuild.args(args)
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .current_dir(current_dir);
-            for (key, value) in env {
-                cbuild.env(key, value);
-            }
-            cbuild.spawn()
+        Self::start_with_options(cmd, args, current_dir, env, &ProgramOptions::default())
+    }
+    
+    pub fn start_with_options(cmd: &str, args: &[&str], current_dir: &str, env: &[(&str, &str)], options: &ProgramOptions) -> Result<Process, std::io::Error> {
+        let mut cbuild = if cmd.find("/").is_some() {
+            Command::new(format!("{}/{}", current_dir, cmd))
+        }
+        else {
+            Command::new(cmd)
+        };
+        cbuild.args(args).current_dir(current_dir);
+        for (key, value) in env {
+            cbuild.env(key, value);
         }
-        
-        let mut child = create_process(cmd, args, current_dir, env) ?;
         
         let (tx_line, rx_line) = mpsc::channel();
-        let tx_err = tx_line.clone();
-        let mut stdout = child.stdout.take().expect("stdout cannot be taken!");
-        let mut stderr = child.stderr.take().expect("stderr cannot be taken!");
         
-        let _stdout_thread = {
-            std::thread::spawn(move || {
-                let mut storage = Vec::new();
-                loop {
-                    let offset = storage.len();
-                    storage.resize(offset + 1024, 0u8);
-                    let new_len = storage.len();
-                    let n_bytes_read = stdout.read(&mut storage[offset..new_len]).expect("cannot read");
-                    if n_bytes_read == 0 {
-                        tx_line.send(None).expect("tx_line cannot send - unexpected");
-                        return;
-                    }
-                    storage.resize(offset + n_bytes_read, 0u8);
-                    let mut start = 0;
-                    for (index, ch) in storage.iter().enumerate() {
-                        if *ch == '\n' as u8 {
-                            // emit a line
-                            if let Ok(line) = str::from_utf8(&storage[start..(index + 1)]) {
-                                tx_line.send(Some((false, line.to_string()))).expect("tx_line cannot send - unexpected");
-                            }
-                            start = index + 1;
+        #[cfg(not(unix))]
+        {
+            if options.pty || options.cpu_limit_secs.is_some() {
+                return Err(std::io::Error::other("pty and cpu limit are only supported on unix"))
+            }
+        }
+        
+        #[cfg(unix)]
+        {
+            let pty = options.pty;
+            let cpu_limit = options.cpu_limit_secs;
+            unsafe {
+                cbuild.pre_exec(move || {
+                    // own process group so a kill takes the whole tree with it
+                    if pty {
+                        if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                            return Err(std::io::Error::last_os_error());
                         }
                     }
-                    storage.drain(0..start);
-                }
-            })
-        };
-        
-        let _stderr_thread = {
-            std::thread::spawn(move || {
-                let mut storage = Vec::new();
-                loop {
-                    let offset = storage.len();
-                    storage.resize(offset + 1024, 0u8);
-                    let new_len = storage.len();
-                    let n_bytes_read = stderr.read(&mut storage[offset..new_len]).expect("cannot read");
-                    if n_bytes_read == 0 {
-                        return;
+                    else if libc::setpgid(0, 0) == -1 {
+                        return Err(std::io::Error::last_os_error());
                     }
-                    storage.resize(offset + n_bytes_read, 0u8);
-                    let mut start = 0;
-                    for (index, ch) in storage.iter().enumerate() {
-                        if *ch == '\n' as u8 {
-                            // emit a line
-                            if let Ok(line) = str::from_utf8(&storage[start..(index + 1)]) {
-                                tx_err.send(Some((true, line.to_string()))).expect("tx_err cannot send - unexpected");
-                            }
-                            start = index + 1;
+                    if let Some(secs) = cpu_limit {
+                        let limit = libc::rlimit {rlim_cur: secs as libc::rlim_t, rlim_max: secs.saturating_add(1) as libc::rlim_t};
+                        if libc::setrlimit(libc::RLIMIT_CPU, &limit) == -1 {
+                            return Err(std::io::Error::last_os_error());
                         }
                     }
-                    storage.drain(0..start);
-                }
-            })
-        };
+                    Ok(())
+                });
+            }
+            if pty {
+                let (master, slave) = open_pty() ?;
+                cbuild.stdin(Stdio::from(slave.try_clone() ?))
+                    .stdout(Stdio::from(slave.try_clone() ?))
+                    .stderr(Stdio::from(slave));
+                let child = cbuild.spawn() ?;
+                // the child holds the only slave now, so reads fail once it is gone
+                drop(cbuild);
+                let stdin = master.try_clone() ?;
+                std::thread::spawn(move || read_lines(master, false, true, tx_line));
+                return Ok(Self::new(child, rx_line, Some(Box::new(stdin)), options))
+            }
+        }
+        
+        cbuild.stdin(if options.stdin {Stdio::piped()} else {Stdio::null()})
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = cbuild.spawn() ?;
         
-        Ok(Process {
+        let stdin = child.stdin.take().map( | stdin | Box::new(stdin) as Box<dyn Write + Send>);
+        if let Some(stdout) = child.stdout.take() {
+            let tx_line = tx_line.clone();
+            std::thread::spawn(move || read_lines(stdout, false, false, tx_line));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            std::thread::spawn(move || read_lines(stderr, true, false, tx_line));
+        }
+        Ok(Self::new(child, rx_line, stdin, options))
+    }
+    
+    fn new(child: Child, rx_line: mpsc::Receiver<Option<(bool, String)>>, stdin: Option<Box<dyn Write + Send>>, options: &ProgramOptions) -> Process {
+        Process {
             child: Some(child),
             rx_line: Some(rx_line),
-        })
+            stdin,
+            deadline: options.time_limit_ms.map( | ms | Instant::now() + std::time::Duration::from_millis(ms)),
+            exit: None,
+        }
+    }
+    
+    pub fn write_stdin(&mut self, input: &[u8]) -> Result<(), std::io::Error> {
+        if let Some(stdin) = &mut self.stdin {
+            stdin.write_all(input) ?;
+            stdin.flush() ?;
+            return Ok(())
+        }
+        Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "process has no stdin"))
+    }
+    
+    pub fn close_stdin(&mut self) {
+        self.stdin = None;
+    }
+    
+    // kills the process when it ran past its time limit
+    pub fn check_time_limit(&mut self) -> bool {
+        if let Some(deadline) = self.deadline {
+            if self.child.is_some() && Instant::now() >= deadline {
+                self.terminate(ProgramExit::TimeLimit);
+                return true
+            }
+        }
+        false
     }
     
-    pub fn wait(&mut self) {
+    pub fn wait(&mut self) -> ProgramExit {
         if let Some(child) = &mut self.child {
-            let _ = child.wait();
+            self.exit = Some(match child.wait() {
+                Ok(status) => exit_from_status(status),
+                Err(_) => ProgramExit::Unknown
+            });
             self.child = None;
         }
+        self.exit.clone().unwrap_or(ProgramExit::Unknown)
     }
     
     pub fn kill(&mut self) {
+        self.terminate(ProgramExit::Killed);
+    }
+    
+    fn terminate(&mut self, exit: ProgramExit) {
         if let Some(child) = &mut self.child {
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
             let _ = child.kill();
             let _ = child.wait();
             self.child = None;
+            self.stdin = None;
+            self.exit = Some(exit);
+        }
+    }
+}
+
+fn exit_from_status(status: ExitStatus) -> ProgramExit {
+    if let Some(code) = status.code() {
+        return ProgramExit::Code(code)
+    }
+    #[cfg(unix)]
+    {
+        if let Some(signal) = status.signal() {
+            if signal == libc::SIGXCPU {
+                return ProgramExit::CpuLimit
+            }
+            return ProgramExit::Signal(signal)
+        }
+    }
+    ProgramExit::Unknown
+}
+
+#[cfg(unix)]
+fn open_pty() -> Result<(std::fs::File, std::fs::File), std::io::Error> {
+    let mut master = 0;
+    let mut slave = 0;
+    let size = libc::winsize {ws_row: 25, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0};
+    unsafe {
+        if libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null_mut(), &size) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let (master, slave) = (std::fs::File::from_raw_fd(master), std::fs::File::from_raw_fd(slave));
+        // a program spawned at the same time must not inherit them, or the master never sees the end
+        for fd in [master.as_raw_fd(), slave.as_raw_fd()] {
+            if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok((master, slave))
+    }
+}
+
+// splits output into lines, a terminal also flushes what it has so prompts show up
+fn read_lines<R: Read>(mut reader: R, is_stderr: bool, flush_partial: bool, tx_line: mpsc::Sender<Option<(bool, String)>>) {
+    let mut storage = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        // a pty reports EIO when the other side is gone, that is the end too
+        let n_bytes_read = match reader.read(&mut buf) {
+            Ok(n) => n,
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => 0
+        };
+        if n_bytes_read == 0 {
+            if !storage.is_empty() {
+                let _ = tx_line.send(Some((is_stderr, String::from_utf8_lossy(&storage).to_string())));
+            }
+            if !is_stderr {
+                let _ = tx_line.send(None);
+            }
+            return;
+        }
+        storage.extend_from_slice(&buf[0..n_bytes_read]);
+        let mut start = 0;
+        for index in 0..storage.len() {
+            if storage[index] == b'\n' {
+                // emit a line
+                let mut line = String::from_utf8_lossy(&storage[start..(index + 1)]).to_string();
+                if flush_partial && line.ends_with("\r\n") {
+                    line.remove(line.len() - 2);
+                }
+                if tx_line.send(Some((is_stderr, line))).is_err() {
+                    return
+                }
+                start = index + 1;
+            }
+        }
+        storage.drain(0..start);
+        if flush_partial && !storage.is_empty() {
+            // keep a utf8 sequence that was cut in half for the next read
+            let valid = match std::str::from_utf8(&storage) {
+                Ok(s) => s.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => storage.len()
+            };
+            if valid > 0 {
+                if tx_line.send(Some((is_stderr, String::from_utf8_lossy(&storage[0..valid]).to_string()))).is_err() {
+                    return
+                }
+                storage.drain(0..valid);
+            }
         }
     }
 }
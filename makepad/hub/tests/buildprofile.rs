@@ -0,0 +1,76 @@
+use makepad_hub::*;
+use makepad_microserde::*;
+use std::collections::HashMap;
+
+fn profile<'a>(profiles: &'a [BuildProfile], name: &str) -> &'a BuildProfile {
+    profiles.iter().find( | p | p.name == name).unwrap()
+}
+
+#[test]
+fn defaults_build_like_before() {
+    let profiles = BuildProfile::defaults();
+    let names: Vec<&str> = profiles.iter().map( | p | p.name.as_str()).collect();
+    assert_eq!(names, vec!["check", "debug", "release", "small", "test"]);
+
+    assert_eq!(profile(&profiles, "check").cargo_args("app"), vec!["check", "-p", "app"]);
+    assert_eq!(profile(&profiles, "release").cargo_args("app"), vec!["build", "-p", "app", "--release"]);
+    assert_eq!(
+        profile(&profiles, "small").cargo_args("example_wasm"),
+        vec!["build", "-p", "example_wasm", "--release", "--target=wasm32-unknown-unknown"]
+    );
+    assert_eq!(
+        profile(&profiles, "small").cargo_env(),
+        vec![("RUSTFLAGS".to_string(), "-C opt-level=z -C panic=abort -C codegen-units=1".to_string())]
+    );
+    assert_eq!(profile(&profiles, "small").post_steps, vec![BuildPostStep::WasmStripDebug]);
+    assert_eq!(
+        profile(&profiles, "test").cargo_args("app"),
+        vec!["test", "-p", "app", "--", "-Z", "unstable-options", "--format", "json"]
+    );
+    assert_eq!(profile(&profiles, "test").cargo_env(), vec![("RUSTC_BOOTSTRAP".to_string(), "1".to_string())]);
+
+    // plain test profiles stay on stable libtest
+    let plain = BuildProfile::new("plain", "test");
+    assert_eq!(plain.cargo_args("app"), vec!["test", "-p", "app"]);
+    assert!(plain.cargo_env().is_empty());
+}
+
+#[test]
+fn custom_profile_args() {
+    let mut env = HashMap::new();
+    env.insert("CC".to_string(), "clang".to_string());
+    let profile = BuildProfile {
+        features: vec!["serde".to_string(), "simd".to_string()],
+        no_default_features: true,
+        target: Some("aarch64-unknown-linux-gnu".to_string()),
+        args: vec!["--all-targets".to_string()],
+        run_args: vec!["-D".to_string(), "warnings".to_string()],
+        env,
+        packages: vec!["app".to_string()],
+        ..BuildProfile::new("lint", "clippy")
+    };
+    assert_eq!(profile.cargo_args("app_wasm"), vec![
+        "clippy", "-p", "app_wasm", "--no-default-features", "--features=serde,simd",
+        "--target=aarch64-unknown-linux-gnu", "--all-targets", "--", "-D", "warnings"
+    ]);
+    assert_eq!(profile.cargo_env(), vec![("CC".to_string(), "clang".to_string())]);
+    assert!(profile.applies_to("app"));
+    assert!(!profile.applies_to("other"));
+    assert!(BuildProfile::new("any", "build").applies_to("other"));
+}
+
+#[test]
+fn profiles_survive_the_settings_file() {
+    let config = HubBuilderConfig {
+        http_server: HttpServerConfig::Offline,
+        workspaces: HashMap::new(),
+        profiles: BuildProfile::defaults()
+    };
+    let ron = config.serialize_ron();
+    let back: HubBuilderConfig = DeRon::deserialize_ron(&ron).unwrap();
+    assert_eq!(back, config);
+
+    // a profile only needs what differs from a plain cargo build
+    let back: BuildProfile = DeRon::deserialize_ron(r#"(name: "fast", release: true, features: ["simd"])"#).unwrap();
+    assert_eq!(back, BuildProfile {release: true, features: vec!["simd".to_string()], ..BuildProfile::new("fast", "build")});
+}
@@ -0,0 +1,94 @@
+use makepad_hub::*;
+use makepad_microserde::*;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Duration;
+
+mod common;
+use common::*;
+
+fn test_crate(name: &str) -> TestDir {
+    test_dir("cargotest", name, &[
+        ("Cargo.toml", b"[package]\nname = \"cargotest\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[workspace]\n"),
+        ("src/lib.rs", concat!(
+            "#[test]\nfn passes() {\n    println!(\"hello\");\n}\n\n",
+            "#[test]\nfn fails() {\n    assert_eq!(1, 2);\n}\n\n",
+            "#[test]\n#[ignore]\nfn ignored() {\n}\n"
+        ).as_bytes())
+    ])
+}
+
+#[test]
+fn parses_libtest_json() {
+    let started: LibtestMessage = DeJson::deserialize_json(r#"{ "type": "test", "event": "started", "name": "tests::a" }"#).unwrap();
+    assert_eq!((started.ty.as_ref(), started.event.as_ref(), started.name), ("test", "started", Some("tests::a".to_string())));
+    let suite: LibtestMessage = DeJson::deserialize_json(
+        r#"{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 1, "measured": 0, "filtered_out": 0, "exec_time": 0.001 }"#
+    ).unwrap();
+    assert_eq!((suite.passed, suite.failed, suite.ignored), (Some(1), Some(1), Some(1)));
+    // compiler output is not ours
+    assert!(<LibtestMessage as DeJson>::deserialize_json(r#"{"reason":"build-finished","success":true}"#).is_err());
+}
+
+#[test]
+fn finds_panic_locations() {
+    let loc = test_panic_loc("main", "ws", "/abs/ws", "thread 'fails' panicked at src/lib.rs:8:5:\nassertion `left == right` failed\n  left: 1\n right: 2\nnote: run with `RUST_BACKTRACE=1`\n").unwrap();
+    assert_eq!((loc.path.as_ref(), loc.line, loc.column), ("main/ws/src/lib.rs", 8, 5));
+    assert_eq!(loc.body, "assertion `left == right` failed   left: 1  right: 2");
+
+    let loc = test_panic_loc("main", "ws", "/abs/ws", "thread 'fails' panicked at 'assertion failed: false', /abs/ws/render/src/lib.rs:3:1\n").unwrap();
+    assert_eq!((loc.path.as_ref(), loc.line, loc.column), ("main/ws/render/src/lib.rs", 3, 1));
+    assert_eq!(loc.body, "assertion failed: false");
+
+    assert!(test_panic_loc("main", "ws", "/abs/ws", "no panic here\n").is_none());
+}
+
+#[test]
+fn reports_each_test() {
+    let test_crate = test_crate("run");
+    let mut hub_router = HubRouter::start_hub_router(HubLog::None);
+    let (tx_ui, rx_ui) = mpsc::channel();
+    let mut ui_send = hub_router.connect_direct(HubRouteType::UI, tx_ui);
+    HubBuilder::run_builder_direct("local", Default::default(), &mut hub_router, | hub_builder, htc | match htc.msg {
+        HubMsg::Build {uid, workspace, ..} => {
+            hub_builder.cargo(uid, &workspace, &["test", "--", "-Z", "unstable-options", "--format", "json", "--include-ignored"], &[("RUSTC_BOOTSTRAP", "1")]) ?;
+            Ok(())
+        },
+        _ => hub_builder.default(htc)
+    });
+    let mut workspaces = HashMap::new();
+    workspaces.insert("ws".to_string(), test_crate.dir.clone());
+    let uid = ui_send.alloc_uid();
+    ui_send.send(ToHubMsg {
+        to: HubMsgTo::Builder("local".to_string()),
+        msg: HubMsg::BuilderConfig {uid, config: HubBuilderConfig {http_server: HttpServerConfig::Offline, workspaces, profiles: Vec::new()}}
+    });
+    let uid = ui_send.alloc_uid();
+    ui_send.send(ToHubMsg {
+        to: HubMsgTo::Builder("local".to_string()),
+        msg: HubMsg::Build {uid, workspace: "ws".to_string(), package: "cargotest".to_string(), config: "test".to_string()}
+    });
+
+    let mut started = Vec::new();
+    let mut passed = None;
+    let mut failed = None;
+    loop {
+        let htc = rx_ui.recv_timeout(Duration::from_secs(120)).expect("cargo test did not finish");
+        match htc.msg {
+            HubMsg::TestStarted {name, ..} => started.push(name),
+            HubMsg::TestPassed {name, stdout, ..} if name == "passes" => passed = Some(stdout),
+            HubMsg::TestFailed {name, panic, ..} if name == "fails" => failed = panic,
+            HubMsg::CargoEnd {build_result, ..} => {
+                // the test binary is not something to run afterwards
+                assert!(matches!(build_result, BuildResult::NoOutput));
+                break
+            },
+            _ => ()
+        }
+    }
+    started.sort();
+    assert_eq!(started, vec!["fails", "ignored", "passes"]);
+    assert!(passed.is_some());
+    let failed = failed.expect("no panic location for the failing test");
+    assert_eq!((failed.path.as_ref(), failed.line), ("local/ws/src/lib.rs", 8));
+}
@@ -0,0 +1,61 @@
+use makepad_hub::*;
+use makepad_microserde::*;
+
+#[test]
+fn members_skip_excluded() {
+    let toml = TomlParser::parse(r#"
+[workspace]
+members = ["app", "./widget", "examples/hello", "examples/broken", "tools/gen/"]
+exclude = ["examples/broken", "tools"]
+"#).unwrap();
+    assert_eq!(cargo_workspace_members(&toml, ""), vec!["app", "widget", "examples/hello"]);
+
+    // no exclude keeps every member
+    let toml = TomlParser::parse("[workspace]\nmembers = [\"a\", \"b\"]\n").unwrap();
+    assert_eq!(cargo_workspace_members(&toml, ""), vec!["a", "b"]);
+    assert!(cargo_workspace_members(&TomlParser::parse("[package]\nname = \"x\"\n").unwrap(), "").is_empty());
+}
+
+#[test]
+fn members_expand_globs() {
+    let dir = std::env::temp_dir().join(format!("makepad_hub_cargo_glob_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for package in ["app", "crates/render", "crates/widget", "crates/broken", "examples/hello_example", "examples/notes"] {
+        std::fs::create_dir_all(dir.join(package)).unwrap();
+        std::fs::write(dir.join(package).join("Cargo.toml"), "[package]\n").unwrap();
+    }
+    // a folder without a package is not a member
+    std::fs::create_dir_all(dir.join("crates/assets")).unwrap();
+
+    let toml = TomlParser::parse(r#"
+[workspace]
+members = ["app", "crates/*", "examples/*_example"]
+exclude = ["crates/broken"]
+"#).unwrap();
+    let members = cargo_workspace_members(&toml, dir.to_str().unwrap());
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(members, vec!["app", "crates/render", "crates/widget", "examples/hello_example"]);
+}
+
+#[test]
+fn saves_touch_only_their_package() {
+    let packages = vec![
+        HubPackage::new("ws", "app", "app", &[]),
+        HubPackage::new("ws", "render", "crates/render", &[]),
+        HubPackage::new("ws", "render_ext", "crates/render_ext", &[]),
+        HubPackage::new("ws", "render_shader", "crates/render/shader", &[]),
+        HubPackage::new("other", "app", "app", &[]),
+    ];
+    let owner = | workspace: &str, path: &str | HubPackage::owning(&packages, workspace, path).map( | p | p.package_name.as_str());
+
+    // a save in render leaves the sibling builds running
+    assert_eq!(owner("ws", "crates/render/src/cx.rs"), Some("render"));
+    assert_eq!(owner("ws", "crates/render_ext/src/lib.rs"), Some("render_ext"));
+    assert_eq!(owner("ws", "app/src/main.rs"), Some("app"));
+    // a nested package beats the one around it
+    assert_eq!(owner("ws", "crates/render/shader/src/lib.rs"), Some("render_shader"));
+    // files outside every package, like Cargo.lock, belong to none
+    assert_eq!(owner("ws", "Cargo.lock"), None);
+    assert_eq!(owner("ws", "crates/README.md"), None);
+    assert_eq!(owner("missing", "app/src/main.rs"), None);
+}
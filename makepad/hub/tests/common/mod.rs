@@ -0,0 +1,25 @@
+// shared by the integration tests, each one pulls it in with mod common
+
+// a scratch directory that is gone again when the test ends
+pub struct TestDir {
+    pub dir: String
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// the directory is unique per test binary, kind and name, files are written relative to it
+pub fn test_dir(kind: &str, name: &str, files: &[(&str, &[u8])]) -> TestDir {
+    let dir = std::env::temp_dir().join(format!("makepad_hub_{}_{}_{}", kind, name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (path, data) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+    TestDir {dir: dir.to_string_lossy().to_string()}
+}
@@ -0,0 +1,140 @@
+use makepad_hub::*;
+use std::sync::mpsc;
+use std::time::Duration;
+
+mod common;
+use common::*;
+
+fn watch_dir(name: &str) -> TestDir {
+    let test_dir = test_dir("watch", name, &[("src/lib.rs", b"fn main(){}")]);
+    std::fs::create_dir_all(format!("{}/target", test_dir.dir)).unwrap();
+    test_dir
+}
+
+// collects changes until one matches, the watchers may split a burst in several reports
+fn wait_for(rx_changes: &mpsc::Receiver<FileWatchChanges>, check: impl Fn(&FileWatchChanges) -> bool) -> FileWatchChanges {
+    let mut all = FileWatchChanges::default();
+    while let Ok(changes) = rx_changes.recv_timeout(Duration::from_secs(5)) {
+        for file in changes.files {
+            if !all.files.contains(&file) {
+                all.files.push(file);
+            }
+        }
+        all.tree_changed |= changes.tree_changed;
+        if check(&all) {
+            return all
+        }
+    }
+    panic!("Watcher did not report the change, got {:?}", all);
+}
+
+fn watch_changes(test_dir: &TestDir, polling: bool) -> (FileWatcher, mpsc::Receiver<FileWatchChanges>) {
+    let (tx_changes, rx_changes) = mpsc::channel();
+    let roots = vec![test_dir.dir.clone()];
+    let on_change = move | changes | {let _ = tx_changes.send(changes);};
+    let watcher = if polling {
+        FileWatcher::start_polling(roots, &["target"], Duration::from_millis(20), on_change)
+    }
+    else {
+        FileWatcher::start(roots, &["target"], on_change)
+    };
+    (watcher, rx_changes)
+}
+
+fn watches_changes(polling: bool) {
+    let test_dir = watch_dir(if polling {"poll"} else {"native"});
+    let (mut watcher, rx_changes) = watch_changes(&test_dir, polling);
+    if cfg!(target_os = "linux") && !polling {
+        assert_eq!(watcher.backend, FileWatchBackend::Inotify);
+    }
+    // let the poller take its first snapshot
+    std::thread::sleep(Duration::from_millis(100));
+    let lib_rs = format!("{}/src/lib.rs", test_dir.dir);
+    let new_rs = format!("{}/src/new.rs", test_dir.dir);
+
+    std::fs::write(&lib_rs, "fn main(){println!(\"changed\")}").unwrap();
+    let changes = wait_for(&rx_changes, | changes | changes.files.contains(&lib_rs));
+    assert!(!changes.tree_changed);
+
+    std::fs::write(&new_rs, "").unwrap();
+    wait_for(&rx_changes, | changes | changes.files.contains(&new_rs) && changes.tree_changed);
+
+    std::fs::remove_file(&new_rs).unwrap();
+    wait_for(&rx_changes, | changes | changes.files.contains(&new_rs) && changes.tree_changed);
+
+    // folders that show up later are watched too
+    std::fs::create_dir_all(format!("{}/src/sub", test_dir.dir)).unwrap();
+    wait_for(&rx_changes, | changes | changes.tree_changed);
+    std::thread::sleep(Duration::from_millis(100));
+    let sub_rs = format!("{}/src/sub/mod.rs", test_dir.dir);
+    std::fs::write(&sub_rs, "").unwrap();
+    wait_for(&rx_changes, | changes | changes.files.contains(&sub_rs));
+
+    // build output is none of our business
+    std::fs::write(format!("{}/target/out.rs", test_dir.dir), "").unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    watcher.terminate();
+    while let Ok(changes) = rx_changes.try_recv() {
+        assert!(changes.files.iter().all( | file | !file.contains("/target/")));
+    }
+}
+
+#[test]
+fn native_watcher() {
+    watches_changes(false);
+}
+
+#[test]
+fn polling_watcher() {
+    watches_changes(true);
+}
+
+#[test]
+fn terminate_stops_reporting() {
+    let test_dir = watch_dir("terminate");
+    let (mut watcher, rx_changes) = watch_changes(&test_dir, false);
+    watcher.terminate();
+    std::fs::write(format!("{}/src/lib.rs", test_dir.dir), "changed").unwrap();
+    assert!(rx_changes.recv_timeout(Duration::from_millis(300)).is_err());
+}
+
+#[test]
+fn builder_pushes_changes_to_the_ui() {
+    let test_dir = watch_dir("builder");
+    let mut hub_router = HubRouter::start_hub_router(HubLog::None);
+    let (tx_ui, rx_ui) = mpsc::channel();
+    let mut ui_send = hub_router.connect_direct(HubRouteType::UI, tx_ui);
+    let _builder_send = HubBuilder::run_builder_direct("local", Default::default(), &mut hub_router, | hub_builder, htc | hub_builder.default(htc));
+
+    let mut workspaces = std::collections::HashMap::new();
+    workspaces.insert("ws".to_string(), test_dir.dir.clone());
+    let uid = ui_send.alloc_uid();
+    ui_send.send(ToHubMsg {
+        to: HubMsgTo::Builder("local".to_string()),
+        msg: HubMsg::BuilderConfig {uid, config: HubBuilderConfig {http_server: HttpServerConfig::Offline, workspaces, profiles: Vec::new()}}
+    });
+    std::thread::sleep(Duration::from_millis(200));
+
+    let recv_msg = | check: &dyn Fn(&HubMsg) -> bool | loop {
+        let htc = rx_ui.recv_timeout(Duration::from_secs(5)).expect("Builder did not push the change");
+        if check(&htc.msg) {
+            return htc.msg
+        }
+    };
+
+    std::fs::write(format!("{}/src/lib.rs", test_dir.dir), "changed").unwrap();
+    let msg = recv_msg(&| msg | matches!(msg, HubMsg::FileChanged {..}));
+    assert!(matches!(msg, HubMsg::FileChanged {builder, path, digest: Some(digest)}
+        if builder == "local" && path == "ws/src/lib.rs" && *digest == Digest::from_buffer(b"changed")));
+
+    // keys never leave the builder, and new files come with a new tree
+    std::fs::write(format!("{}/key.ron", test_dir.dir), "secret").unwrap();
+    std::fs::write(format!("{}/src/new.rs", test_dir.dir), "").unwrap();
+    let msg = recv_msg(&| msg | matches!(msg, HubMsg::FileTreeChanged {..}));
+    if let HubMsg::FileTreeChanged {tree: BuilderFileTreeNode::Folder {name, folder, ..}} = msg {
+        assert_eq!(name, "local");
+        assert!(matches!(&folder[0], BuilderFileTreeNode::Folder {name, digest: Some(_), ..} if name == "ws"));
+    }
+    let msg = recv_msg(&| msg | matches!(msg, HubMsg::FileChanged {path, ..} if path != "ws/src/lib.rs"));
+    assert!(matches!(msg, HubMsg::FileChanged {path, ..} if path == "ws/src/new.rs"));
+}
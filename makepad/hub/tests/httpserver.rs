@@ -0,0 +1,376 @@
+use makepad_http::channel::WebSocketChannels;
+use makepad_http::websocket::*;
+use makepad_microserde::*;
+use makepad_hub::*;
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+struct TestServer {
+    server: HttpServer,
+    dir: std::path::PathBuf
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.terminate();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn start(name: &str) -> TestServer {
+    let dir = std::env::temp_dir().join(format!("makepad_hub_http_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), b"<html></html>").unwrap();
+    std::fs::write(dir.join("style.css"), b"0123456789").unwrap();
+    std::fs::write(dir.join("font.ttf"), b"ttf").unwrap();
+    std::fs::write(dir.join("key.ron"), b"secret").unwrap();
+    std::fs::write(dir.join("keys.ron"), b"secrets").unwrap();
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("app.js"), app_js(200)).unwrap();
+    std::fs::write(dir.join("image.png"), app_js(200)).unwrap();
+    let mut workspaces = HashMap::new();
+    workspaces.insert("main".to_string(), dir.to_str().unwrap().to_string());
+    let server = HttpServer::start_http_server(
+        &HttpServerConfig::Localhost(0),
+        WebSocketChannels::default(),
+        Arc::new(Mutex::new(workspaces))
+    ).expect("Cannot start hub http server");
+    TestServer {server, dir}
+}
+
+fn app_js(lines: usize) -> Vec<u8> {
+    "console.log('hello');\n".repeat(lines).into_bytes()
+}
+
+fn request(server: &TestServer, path: &str, headers: &[(&str, &str)]) -> (u16, Vec<(String, String)>, Vec<u8>) {
+    let mut stream = TcpStream::connect(server.server.listen_address.unwrap()).unwrap();
+    let mut head = format!("GET {} HTTP/1.1\r\nConnection: close\r\n", path);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).unwrap();
+    
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let colon = line.find(':').unwrap();
+        headers.push((line[..colon].to_string(), line[colon + 1..].trim().to_string()));
+    }
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body).unwrap();
+    (status, headers, body)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find( | (k, _) | k.eq_ignore_ascii_case(name)).map( | (_, v) | v.as_str())
+}
+
+#[test]
+fn serves_files_with_validators() {
+    let server = start("validators");
+    let (status, headers, body) = request(&server, "/main/style.css", &[]);
+    assert_eq!((status, body), (200, b"0123456789".to_vec()));
+    assert_eq!(header(&headers, "Content-Type"), Some("text/css; charset=utf-8"));
+    assert_eq!(header(&headers, "Accept-Ranges"), Some("bytes"));
+    assert!(header(&headers, "ETag").is_some());
+    assert!(header(&headers, "Last-Modified").unwrap().ends_with(" GMT"));
+    
+    let (status, headers, _) = request(&server, "/main/font.ttf", &[]);
+    assert_eq!((status, header(&headers, "Content-Type")), (200, Some("font/ttf")));
+    let (status, headers, _) = request(&server, "/main/", &[]);
+    assert_eq!((status, header(&headers, "Content-Type")), (200, Some("text/html; charset=utf-8")));
+    
+    assert_eq!(request(&server, "/main/missing.css", &[]).0, 404);
+}
+
+#[test]
+fn never_serves_keys_or_escapes_the_workspace() {
+    let server = start("keys");
+    // whoever asks gets nothing, not even that the file is there
+    for path in &["/main/key.ron", "/main/keys.ron", "/main/KEYS.RON", "/keys.ron", "/key.ron", "/main/./keys.ron"] {
+        assert_eq!(request(&server, path, &[]).0, 404, "{}", path);
+    }
+    let outside = server.dir.file_name().unwrap().to_str().unwrap().to_string();
+    for path in &[
+        "/main/sub/../keys.ron".to_string(),
+        format!("/main/../{}/index.html", outside),
+        format!("/main/%2e%2e/{}/index.html", outside),
+        "/main//index.html".to_string(),
+        "/main/sub/..".to_string(),
+    ] {
+        let status = request(&server, path, &[]).0;
+        assert!(status == 400 || status == 404, "{} {}", path, status);
+    }
+    assert_eq!(request(&server, "/main/index.html", &[]).0, 200);
+}
+
+#[test]
+fn conditional_requests() {
+    let server = start("conditional");
+    let (_, headers, _) = request(&server, "/main/style.css", &[]);
+    let etag = header(&headers, "ETag").unwrap().to_string();
+    let last_modified = header(&headers, "Last-Modified").unwrap().to_string();
+    
+    let (status, headers, body) = request(&server, "/main/style.css", &[("If-None-Match", &etag)]);
+    assert_eq!((status, body.len()), (304, 0));
+    assert_eq!(header(&headers, "ETag"), Some(etag.as_str()));
+    let weak = format!("\"other\", W/{}", etag);
+    assert_eq!(request(&server, "/main/style.css", &[("If-None-Match", &weak)]).0, 304);
+    assert_eq!(request(&server, "/main/style.css", &[("If-Modified-Since", &last_modified)]).0, 304);
+    
+    // a changed etag wins over a matching date
+    let (status, _, body) = request(&server, "/main/style.css", &[("If-None-Match", "\"stale\""), ("If-Modified-Since", &last_modified)]);
+    assert_eq!((status, body), (200, b"0123456789".to_vec()));
+}
+
+#[test]
+fn etag_changes_within_a_second() {
+    let server = start("etag_nanos");
+    let path = server.dir.join("style.css");
+    let second = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    let set_modified = | time | std::fs::File::options().write(true).open(&path).unwrap().set_modified(time).unwrap();
+    
+    set_modified(second + std::time::Duration::from_millis(100));
+    let (_, first, _) = request(&server, "/main/style.css", &[]);
+    std::fs::write(&path, b"9876543210").unwrap();
+    set_modified(second + std::time::Duration::from_millis(600));
+    // same size and same Last-Modified, only the etag can tell them apart
+    let (status, headers, body) = request(&server, "/main/style.css", &[("If-None-Match", header(&first, "ETag").unwrap())]);
+    assert_eq!((status, body), (200, b"9876543210".to_vec()));
+    assert_eq!(header(&headers, "Last-Modified"), header(&first, "Last-Modified"));
+    assert_ne!(header(&headers, "ETag"), header(&first, "ETag"));
+}
+
+#[test]
+fn range_requests() {
+    let server = start("range");
+    let (status, headers, body) = request(&server, "/main/style.css", &[("Range", "bytes=2-5")]);
+    assert_eq!((status, body), (206, b"2345".to_vec()));
+    assert_eq!(header(&headers, "Content-Range"), Some("bytes 2-5/10"));
+    
+    assert_eq!(request(&server, "/main/style.css", &[("Range", "bytes=7-")]).2, b"789".to_vec());
+    assert_eq!(request(&server, "/main/style.css", &[("Range", "bytes=-3")]).2, b"789".to_vec());
+    assert_eq!(request(&server, "/main/style.css", &[("Range", "bytes=8-100")]).2, b"89".to_vec());
+    
+    let (status, headers, _) = request(&server, "/main/style.css", &[("Range", "bytes=10-")]);
+    assert_eq!(status, 416);
+    assert_eq!(header(&headers, "Content-Range"), Some("bytes */10"));
+    
+    // multiple ranges and a stale If-Range get the whole file
+    assert_eq!(request(&server, "/main/style.css", &[("Range", "bytes=0-1,4-5")]).0, 200);
+    assert_eq!(request(&server, "/main/style.css", &[("Range", "bytes=0-1"), ("If-Range", "\"stale\"")]).0, 200);
+}
+
+#[test]
+fn parse_ranges() {
+    assert_eq!(parse_http_range("bytes=0-0", 10), Some(Ok((0, 0))));
+    assert_eq!(parse_http_range("bytes=-20", 10), Some(Ok((0, 9))));
+    assert_eq!(parse_http_range("bytes=-0", 10), Some(Err(())));
+    assert_eq!(parse_http_range("bytes=5-2", 10), None);
+    assert_eq!(parse_http_range("items=0-1", 10), None);
+    assert_eq!(parse_http_range("bytes=a-b", 10), None);
+    assert_eq!(parse_http_range("bytes=0-", 0), Some(Err(())));
+}
+
+fn assert_gzip_of(body: &[u8], data: &[u8]) {
+    assert_eq!(&body[..3], &[0x1f, 0x8b, 8]);
+    let trailer = &body[body.len() - 8..];
+    assert_eq!(trailer[..4], crc32(data).to_le_bytes());
+    assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+}
+
+#[test]
+fn compressed_responses() {
+    let server = start("compressed");
+    let (status, headers, body) = request(&server, "/main/app.js", &[]);
+    assert_eq!((status, body), (200, app_js(200)));
+    assert_eq!(header(&headers, "Content-Encoding"), None);
+    assert_eq!(header(&headers, "Vary"), Some("Accept-Encoding"));
+    let plain_etag = header(&headers, "ETag").unwrap().to_string();
+    
+    let (status, headers, body) = request(&server, "/main/app.js", &[("Accept-Encoding", "gzip, deflate")]);
+    assert_eq!((status, header(&headers, "Content-Encoding")), (200, Some("gzip")));
+    assert!(body.len() < app_js(200).len() / 10);
+    assert_gzip_of(&body, &app_js(200));
+    // the compressed file is a different representation with its own etag
+    let gzip_etag = header(&headers, "ETag").unwrap().to_string();
+    assert_ne!(gzip_etag, plain_etag);
+    assert_eq!(request(&server, "/main/app.js", &[("Accept-Encoding", "gzip"), ("If-None-Match", &gzip_etag)]).0, 304);
+    
+    let (_, headers, body) = request(&server, "/main/app.js", &[("Accept-Encoding", "gzip;q=0, deflate")]);
+    assert_eq!(header(&headers, "Content-Encoding"), Some("deflate"));
+    assert_eq!(body[0], 0x78);
+    let (_, headers, _) = request(&server, "/main/app.js", &[("Accept-Encoding", "identity")]);
+    assert_eq!(header(&headers, "Content-Encoding"), None);
+    
+    // ranges and binary formats stay as they are
+    let (status, headers, body) = request(&server, "/main/app.js", &[("Accept-Encoding", "gzip"), ("Range", "bytes=0-6")]);
+    assert_eq!((status, header(&headers, "Content-Encoding"), body), (206, None, b"console".to_vec()));
+    let (_, headers, _) = request(&server, "/main/image.png", &[("Accept-Encoding", "gzip")]);
+    assert_eq!(header(&headers, "Content-Encoding"), None);
+    
+    // changing the file drops the cached compression
+    std::fs::write(server.dir.join("app.js"), app_js(300)).unwrap();
+    assert_gzip_of(&request(&server, "/main/app.js", &[("Accept-Encoding", "gzip")]).2, &app_js(300));
+}
+
+#[test]
+fn precompressed_files() {
+    let server = start("precompressed");
+    std::fs::write(server.dir.join("app.js.br"), b"brotli").unwrap();
+    std::fs::write(server.dir.join("app.js.gz"), b"gzip").unwrap();
+    let (_, headers, body) = request(&server, "/main/app.js", &[("Accept-Encoding", "gzip, br")]);
+    assert_eq!((header(&headers, "Content-Encoding"), body), (Some("br"), b"brotli".to_vec()));
+    let (_, headers, body) = request(&server, "/main/app.js", &[("Accept-Encoding", "gzip")]);
+    assert_eq!((header(&headers, "Content-Encoding"), body), (Some("gzip"), b"gzip".to_vec()));
+    
+    // a precompressed file we can't read falls back to the plain file
+    std::fs::remove_file(server.dir.join("app.js.gz")).unwrap();
+    std::fs::create_dir(server.dir.join("app.js.gz")).unwrap();
+    let (status, headers, body) = request(&server, "/main/app.js", &[("Accept-Encoding", "gzip")]);
+    assert_eq!((status, header(&headers, "Content-Encoding"), body), (200, None, app_js(200)));
+    let plain_headers = request(&server, "/main/app.js", &[]).1;
+    assert_eq!(header(&headers, "ETag"), header(&plain_headers, "ETag"));
+}
+
+#[test]
+fn brotli_needs_the_feature() {
+    assert_eq!(compress_bytes(b"data", HttpEncoding::Brotli).is_some(), cfg!(feature = "brotli"));
+    assert_eq!(HttpEncoding::on_the_fly().contains(&HttpEncoding::Brotli), cfg!(feature = "brotli"));
+}
+
+#[test]
+fn negotiate_encoding() {
+    let all = [HttpEncoding::Brotli, HttpEncoding::Gzip, HttpEncoding::Deflate];
+    assert_eq!(HttpEncoding::negotiate("gzip, deflate, br", &all), HttpEncoding::Brotli);
+    assert_eq!(HttpEncoding::negotiate("deflate;q=0.5, gzip;q=0.8", &all), HttpEncoding::Gzip);
+    assert_eq!(HttpEncoding::negotiate("*;q=0.1, br;q=0", &all), HttpEncoding::Gzip);
+    assert_eq!(HttpEncoding::negotiate("BR", &all[1..]), HttpEncoding::Identity);
+    assert_eq!(HttpEncoding::negotiate("", &all), HttpEncoding::Identity);
+}
+
+fn connect_live_reload(server: &TestServer, path: &str) -> BufReader<TcpStream> {
+    let mut stream = TcpStream::connect(server.server.listen_address.unwrap()).unwrap();
+    let key = WebSocket::create_client_key(b"livereload");
+    stream.write_all(WebSocket::create_upgrade_request("localhost", path, &key).as_bytes()).unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("HTTP/1.1 101"), "{}", line);
+    while line != "\r\n" {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    reader
+}
+
+fn read_live_reload(reader: &mut BufReader<TcpStream>, web_socket: &mut WebSocket) -> Vec<WebSocketResult> {
+    loop {
+        let buf = reader.fill_buf().unwrap().to_vec();
+        reader.consume(buf.len());
+        assert!(!buf.is_empty(), "live reload socket closed");
+        let results = web_socket.parse(&buf);
+        if !results.is_empty() {
+            return results
+        }
+    }
+}
+
+fn read_live_reload_msgs(reader: &mut BufReader<TcpStream>, count: usize) -> Vec<LiveReloadMsg> {
+    let mut web_socket = WebSocket::new_client();
+    let mut messages = Vec::new();
+    while messages.len() < count {
+        for result in read_live_reload(reader, &mut web_socket) {
+            match result {
+                WebSocketResult::Text(text) => messages.push(LiveReloadMsg::deserialize_json(&text).unwrap()),
+                other => panic!("unexpected {:?}", other)
+            }
+        }
+    }
+    messages
+}
+
+#[test]
+fn wasm_ready_goes_to_pages_running_it() {
+    let mut server = start("wasmready");
+    let mut page_a = connect_live_reload(&server, "/$livereload?files=%2Fmain%2Fa.wasm");
+    let mut page_b = connect_live_reload(&server, "/$livereload?files=/main/index.html,/main/b.wasm");
+    let mut page_old = connect_live_reload(&server, "/$livereload");
+    request(&server, "/main/b.wasm", &[]);
+    
+    server.server.send_wasm_ready("main/a.wasm");
+    server.server.send_wasm_ready("main/b.wasm");
+    server.server.send_build_start();
+    
+    let a = LiveReloadMsg::WasmReady {path: "main/a.wasm".to_string()};
+    let b = LiveReloadMsg::WasmReady {path: "main/b.wasm".to_string()};
+    assert_eq!(read_live_reload_msgs(&mut page_a, 2), vec![a, LiveReloadMsg::BuildStart]);
+    assert_eq!(read_live_reload_msgs(&mut page_b, 2), vec![b.clone(), LiveReloadMsg::BuildStart]);
+    // a page that didnt list its files hears about what any browser loaded
+    assert_eq!(read_live_reload_msgs(&mut page_old, 2), vec![b, LiveReloadMsg::BuildStart]);
+    server.server.terminate();
+}
+
+#[test]
+fn live_reload() {
+    let mut server = start("livereload");
+    let mut reader = connect_live_reload(&server, "/$livereload?files=/main/index.html,/main/target/app.wasm");
+    let mut web_socket = WebSocket::new_client();
+    
+    // file changes only go out for files a browser loaded
+    server.server.send_file_change("main/style.css");
+    request(&server, "/main/style.css", &[]);
+    server.server.send_file_change("main/style.css");
+    server.server.send_build_start();
+    server.server.send_build_finished(vec![LiveReloadError {
+        path: "main/src/lib.rs".to_string(),
+        line: 3,
+        column: 7,
+        message: "expected one of".to_string()
+    }]);
+    server.server.send_wasm_ready("main/target/app.wasm");
+    
+    let mut messages = Vec::new();
+    while messages.len() < 4 {
+        for result in read_live_reload(&mut reader, &mut web_socket) {
+            match result {
+                WebSocketResult::Text(text) => messages.push(LiveReloadMsg::deserialize_json(&text).unwrap()),
+                other => panic!("unexpected {:?}", other)
+            }
+        }
+    }
+    assert_eq!(messages[0], LiveReloadMsg::FileChange {path: "main/style.css".to_string()});
+    assert_eq!(messages[1], LiveReloadMsg::BuildStart);
+    assert!(matches!(&messages[2], LiveReloadMsg::BuildFinished {errors} if errors[0].line == 3));
+    assert_eq!(messages[3], LiveReloadMsg::WasmReady {path: "main/target/app.wasm".to_string()});
+    assert_eq!(LiveReloadMsg::BuildStart.serialize_json(), "{\"type\":\"build_start\"}");
+    
+    // shutting the hub down closes the socket
+    server.server.terminate();
+    let results = read_live_reload(&mut reader, &mut web_socket);
+    assert_eq!(results, vec![WebSocketResult::Close(WEBSOCKET_CLOSE_GOING_AWAY, String::new())]);
+}
+
+#[test]
+fn live_reload_ping() {
+    let server = start("livereload_ping");
+    let mut reader = connect_live_reload(&server, "/$livereload");
+    let mut ping = WebSocketMessage::new_frame(9, 2, Some([1, 2, 3, 4]));
+    ping.append(b"hi");
+    reader.get_mut().write_all(&ping.take()).unwrap();
+    let results = read_live_reload(&mut reader, &mut WebSocket::new_client());
+    assert_eq!(results, vec![WebSocketResult::Pong(b"hi".to_vec())]);
+}
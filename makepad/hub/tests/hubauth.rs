@@ -0,0 +1,279 @@
+use makepad_hub::*;
+use makepad_microserde::*;
+use std::io::prelude::*;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn start(keyring: HubKeyring) -> (HubServer, SocketAddr) {
+    let hub_router = HubRouter::start_hub_router(HubLog::None);
+    let hub_server = HubServer::start_hub_server(Arc::new(Mutex::new(keyring)), &HubServerConfig::Localhost(0), &hub_router, HubLog::None).expect("Cannot start hub server");
+    let address = hub_server.listen_address.unwrap();
+    (hub_server, address)
+}
+
+// the audit entries land from other threads
+fn wait_for_audit(hub_server: &HubServer, check: impl Fn(&HubAuditEvent) -> bool) -> bool {
+    for _ in 0..100 {
+        if hub_server.audit_log().iter().any(&check) {
+            return true
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn key_connects() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let (mut hub_server, address) = start(keyring);
+
+    let mut hub_client = HubClient::connect_to_server(&hub_key, address, None, HubLog::None).expect("Cannot connect");
+    let rx_read = hub_client.rx_read.take().unwrap();
+    hub_client.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectBuilder("builder".to_string())}).unwrap();
+    let msg = rx_read.recv_timeout(Duration::from_secs(5)).expect("Builder got nothing back");
+    assert!(matches!(msg.msg, HubMsg::ConnectBuilder(_)));
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Connected {key_id, ..} if key_id == "builder")));
+
+    hub_server.terminate();
+}
+
+#[test]
+fn rejects_bad_keys() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    keyring.generate("old");
+    keyring.revoke("old");
+    let (mut hub_server, address) = start(keyring);
+
+    let wrong_secret = HubKey {secret: HubKey::generate("builder").secret, ..hub_key.clone()};
+    assert!(HubClient::connect_to_server(&wrong_secret, address, None, HubLog::None).is_err());
+    assert!(HubClient::connect_to_server(&HubKey::generate("unknown"), address, None, HubLog::None).is_err());
+    assert!(HubClient::connect_to_server(&HubKey::generate("old"), address, None, HubLog::None).is_err());
+    assert!(HubClient::connect_to_server(&HubKey {secret: "nothex".to_string(), ..hub_key.clone()}, address, None, HubLog::None).is_err());
+
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Rejected {reason, ..} if reason == "Wrong secret for key builder")));
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Rejected {reason, ..} if reason == "Unknown key unknown")));
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Rejected {reason, ..} if reason == "Revoked key old")));
+
+    hub_server.terminate();
+}
+
+#[test]
+fn revoke_disconnects() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let other_key = keyring.generate("other");
+    let (mut hub_server, address) = start(keyring);
+
+    let mut hub_client = HubClient::connect_to_server(&hub_key, address, None, HubLog::None).expect("Cannot connect");
+    let mut other_client = HubClient::connect_to_server(&other_key, address, None, HubLog::None).expect("Cannot connect");
+    let rx_read = hub_client.rx_read.take().unwrap();
+    let other_read = other_client.rx_read.take().unwrap();
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Connected {key_id, ..} if key_id == "other")));
+
+    assert!(hub_server.revoke_key("builder"));
+    let msg = rx_read.recv_timeout(Duration::from_secs(5)).expect("Revoked builder was not disconnected");
+    assert!(matches!(msg.msg, HubMsg::ConnectionError(_)));
+    assert!(other_read.recv_timeout(Duration::from_millis(200)).is_err());
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Disconnected {key_id, ..} if key_id == "builder")));
+    assert!(HubClient::connect_to_server(&hub_key, address, None, HubLog::None).is_err());
+
+    hub_server.terminate();
+}
+
+#[test]
+fn revoking_in_keys_ron_disconnects_a_running_hub() {
+    let path = std::env::temp_dir().join(format!("makepad_hub_keys_watch_{}.ron", std::process::id()));
+    let path_str = path.to_string_lossy().to_string();
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let other_key = keyring.generate("other");
+    keyring.save(&path_str).unwrap();
+    let (mut hub_server, address) = start(keyring);
+    hub_server.watch_keyring(&path_str);
+
+    let mut hub_client = HubClient::connect_to_server(&hub_key, address, None, HubLog::None).expect("Cannot connect");
+    let rx_read = hub_client.rx_read.take().unwrap();
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Connected {key_id, ..} if key_id == "builder")));
+
+    // what `builder revoke keys.ron builder` does whilst the hub runs
+    let mut on_disk = HubKeyring::load(&path_str).unwrap();
+    on_disk.revoke("builder");
+    on_disk.save(&path_str).unwrap();
+    let msg = rx_read.recv_timeout(Duration::from_secs(5)).expect("Revoked builder was not disconnected");
+    assert!(matches!(msg.msg, HubMsg::ConnectionError(_)));
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Revoked {key_id} if key_id == "builder")));
+    assert!(HubClient::connect_to_server(&hub_key, address, None, HubLog::None).is_err());
+
+    // and a revoke on the hub ends up in keys.ron
+    assert!(hub_server.revoke_key("other"));
+    assert!(HubKeyring::load(&path_str).unwrap().is_revoked("other"));
+    assert!(HubClient::connect_to_server(&other_key, address, None, HubLog::None).is_err());
+
+    hub_server.terminate();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn rejects_tampered_and_replayed_blocks() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let (mut hub_server, address) = start(keyring);
+
+    let mut msg_buf = Vec::new();
+    ToHubMsg {to: HubMsgTo::Hub, msg: HubMsg::ConnectUI}.ser_bin(&mut msg_buf);
+
+    for replay in &[false, true] {
+        let mut tcp_stream = HubStream::Tcp(TcpStream::connect(address).unwrap());
+        let mut session = hub_auth_connect(&mut tcp_stream, &hub_key).expect("Cannot authenticate");
+        let mut block = (msg_buf.len() as u64).to_le_bytes().to_vec();
+        block.extend_from_slice(&msg_buf);
+        block.extend_from_slice(session.write_key.sign(&msg_buf).as_ref());
+        if *replay {
+            // a valid block, sent twice
+            write_exact_bytes_to_tcp_stream(&mut tcp_stream, &block).unwrap();
+        }
+        else {
+            block[9] ^= 1;
+        }
+        write_exact_bytes_to_tcp_stream(&mut tcp_stream, &block).unwrap();
+        // the hub hangs up without answering
+        let mut response = Vec::new();
+        let _ = tcp_stream.read_to_end(&mut response);
+        assert!(response.is_empty());
+    }
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Disconnected {..})));
+
+    hub_server.terminate();
+}
+
+#[test]
+fn checks_the_tag_before_decoding() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let keyring = Arc::new(Mutex::new(keyring));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let hub = std::thread::spawn(move || {
+        let mut tcp_stream = HubStream::Tcp(listener.accept().unwrap().0);
+        let mut session = hub_auth_accept(&mut tcp_stream, &keyring).unwrap();
+        let first = read_msg_from_tcp_stream::<ToHubMsg>(&mut tcp_stream, &mut session.read_key);
+        let second = read_msg_from_tcp_stream::<ToHubMsg>(&mut tcp_stream, &mut session.read_key);
+        let third = read_msg_from_tcp_stream::<ToHubMsg>(&mut tcp_stream, &mut session.read_key);
+        let fourth = read_msg_from_tcp_stream::<ToHubMsg>(&mut tcp_stream, &mut session.read_key);
+        (first, second, third, fourth)
+    });
+
+    let mut tcp_stream = HubStream::Tcp(TcpStream::connect(address).unwrap());
+    let mut session = hub_auth_connect(&mut tcp_stream, &hub_key).unwrap();
+    let uid = HubUid {addr: HubAddr::None, id: 1};
+    let msg_buf = ToHubMsg {to: HubMsgTo::UI, msg: HubMsg::FileReadResponse {uid, path: "ws/big.bin".to_string(), data: Some(vec![7u8; 4 * 1024 * 1024])}}.serialize_bin();
+    write_block_to_tcp_stream(&mut tcp_stream, &msg_buf, &mut session.write_key).unwrap();
+    // a message with bytes after it in the block
+    let mut trailing = msg_buf.clone();
+    trailing.push(0);
+    write_block_to_tcp_stream(&mut tcp_stream, &trailing, &mut session.write_key).unwrap();
+    // a message that only goes wrong in the tag at the very end
+    let mut block = (msg_buf.len() as u64).to_le_bytes().to_vec();
+    block.extend_from_slice(&msg_buf);
+    let mut tag = session.write_key.sign(&msg_buf).as_ref().to_vec();
+    tag[0] ^= 1;
+    block.extend_from_slice(&tag);
+    write_exact_bytes_to_tcp_stream(&mut tcp_stream, &block).unwrap();
+    // garbage with a bad tag never reaches the decoder
+    let mut block = 3u64.to_le_bytes().to_vec();
+    block.extend_from_slice(&[0xff; 3 + HUB_AUTH_TAG_LEN]);
+    write_exact_bytes_to_tcp_stream(&mut tcp_stream, &block).unwrap();
+
+    let (first, second, third, fourth) = hub.join().unwrap();
+    assert!(matches!(first.unwrap().msg, HubMsg::FileReadResponse {data: Some(data), ..} if data == vec![7u8; 4 * 1024 * 1024]));
+    assert!(matches!(second, Err(e) if e.msg.contains("continues after the message")));
+    assert!(matches!(third, Err(e) if e.msg.contains("authentication failed")));
+    assert!(matches!(fourth, Err(e) if e.msg.contains("authentication failed")));
+}
+
+#[test]
+fn unparsable_message_closes_the_connection() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let (mut hub_server, address) = start(keyring);
+
+    // properly signed, but not a message this hub can read
+    let msg_buf = vec![0xff; 3];
+    let mut tcp_stream = HubStream::Tcp(TcpStream::connect(address).unwrap());
+    let mut session = hub_auth_connect(&mut tcp_stream, &hub_key).expect("Cannot authenticate");
+    let mut block = (msg_buf.len() as u64).to_le_bytes().to_vec();
+    block.extend_from_slice(&msg_buf);
+    block.extend_from_slice(session.write_key.sign(&msg_buf).as_ref());
+    write_exact_bytes_to_tcp_stream(&mut tcp_stream, &block).unwrap();
+    let mut response = Vec::new();
+    let _ = tcp_stream.read_to_end(&mut response);
+    assert!(response.is_empty());
+    assert!(wait_for_audit(&hub_server, | event | matches!(event, HubAuditEvent::Disconnected {key_id, ..} if key_id == "builder")));
+
+    hub_server.terminate();
+}
+
+#[test]
+fn hub_has_to_know_the_key() {
+    // something that talks the protocol but does not have the secret
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let fake_hub = std::thread::spawn(move || {
+        let (mut tcp_stream, _) = listener.accept().unwrap();
+        let mut hello = HUB_AUTH_MAGIC.to_vec();
+        hello.extend_from_slice(&[7u8; HUB_AUTH_NONCE_LEN]);
+        tcp_stream.write_all(&hello).unwrap();
+        let mut response = vec![0u8; 2 + "builder".len() + HUB_AUTH_NONCE_LEN + HUB_AUTH_TAG_LEN];
+        tcp_stream.read_exact(&mut response).unwrap();
+        let mut accept = vec![1u8];
+        accept.extend_from_slice(&[0u8; HUB_AUTH_TAG_LEN]);
+        tcp_stream.write_all(&accept).unwrap();
+    });
+    let mut tcp_stream = HubStream::Tcp(TcpStream::connect(address).unwrap());
+    let result = hub_auth_connect(&mut tcp_stream, &HubKey::generate("builder"));
+    assert!(matches!(result, Err(e) if e.msg == "Hub does not know our key"));
+    fake_hub.join().unwrap();
+}
+
+#[test]
+fn keyring_round_trip() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("a");
+    keyring.generate("b");
+    assert_eq!(hub_key.secret.len(), 64);
+    assert_eq!(hub_key.secret_bytes().unwrap().len(), 32);
+    assert!(keyring.revoke("b"));
+    assert!(!keyring.revoke("c"));
+    assert!(keyring.find("b").is_none());
+    assert!(keyring.find("c").is_none());
+    assert_eq!(keyring.find("a"), Some(&hub_key));
+
+    let loaded: HubKeyring = DeRon::deserialize_ron(&keyring.serialize_ron()).unwrap();
+    assert_eq!(loaded, keyring);
+
+    // a fresh key for a revoked id brings it back
+    let new_b = keyring.generate("b");
+    assert_eq!(keyring.find("b"), Some(&new_b));
+    assert!(HubKey {id: "x".to_string(), secret: "abc".to_string()}.secret_bytes().is_err());
+}
+
+#[test]
+fn broken_keyring_is_left_alone() {
+    let path = std::env::temp_dir().join(format!("makepad_hub_keyring_{}.ron", std::process::id()));
+    let path_str = path.to_string_lossy().to_string();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(HubKeyring::load_if_exists(&path_str).unwrap(), None);
+
+    std::fs::write(&path, "(keys: [(id: \"a\", secret: ").unwrap();
+    assert!(HubKeyring::load_if_exists(&path_str).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "(keys: [(id: \"a\", secret: ");
+
+    let mut keyring = HubKeyring::default();
+    keyring.generate("a");
+    keyring.save(&path_str).unwrap();
+    assert_eq!(HubKeyring::load_if_exists(&path_str).unwrap(), Some(keyring));
+    let _ = std::fs::remove_file(&path);
+}
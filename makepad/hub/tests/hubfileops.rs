@@ -0,0 +1,175 @@
+use makepad_hub::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+mod common;
+use common::*;
+
+fn fileops_dir(name: &str) -> TestDir {
+    test_dir("fileops", name, &[("src/lib.rs", b"fn main(){}"), ("key.ron", b"secret")])
+}
+
+struct TestBuilder {
+    ui_send: HubRouteSend,
+    rx_ui: mpsc::Receiver<FromHubMsg>
+}
+
+impl TestBuilder {
+    fn start(test_dir: &TestDir) -> TestBuilder {
+        let mut hub_router = HubRouter::start_hub_router(HubLog::None);
+        let (tx_ui, rx_ui) = mpsc::channel();
+        let mut ui_send = hub_router.connect_direct(HubRouteType::UI, tx_ui);
+        HubBuilder::run_builder_direct("local", Default::default(), &mut hub_router, | hub_builder, htc | hub_builder.default(htc));
+        let mut workspaces = HashMap::new();
+        workspaces.insert("ws".to_string(), test_dir.dir.clone());
+        let uid = ui_send.alloc_uid();
+        ui_send.send(ToHubMsg {
+            to: HubMsgTo::Builder("local".to_string()),
+            msg: HubMsg::BuilderConfig {uid, config: HubBuilderConfig {http_server: HttpServerConfig::Offline, workspaces, profiles: Vec::new()}}
+        });
+        TestBuilder {ui_send, rx_ui}
+    }
+
+    // sends a request and waits for its final response, skipping pushes and log items
+    fn request(&mut self, make_msg: impl FnOnce(HubUid) -> HubMsg) -> HubMsg {
+        let msg = make_msg(self.ui_send.alloc_uid());
+        self.ui_send.send(ToHubMsg {to: HubMsgTo::Builder("local".to_string()), msg: msg.clone()});
+        loop {
+            let htc = self.rx_ui.recv_timeout(Duration::from_secs(5)).expect("Builder did not respond");
+            if htc.msg.is_final_response_to(&msg) {
+                return htc.msg
+            }
+        }
+    }
+}
+
+fn done(msg: HubMsg) -> bool {
+    match msg {
+        HubMsg::FileCreateResponse {done, ..} |
+        HubMsg::FileDeleteResponse {done, ..} |
+        HubMsg::FileRenameResponse {done, ..} |
+        HubMsg::FileMkdirResponse {done, ..} => done,
+        _ => panic!("Not a file operation response {:?}", msg)
+    }
+}
+
+#[test]
+fn creates_renames_and_deletes() {
+    let test_dir = fileops_dir("ops");
+    let mut builder = TestBuilder::start(&test_dir);
+    let abs = | path: &str | format!("{}/{}", test_dir.dir, path);
+
+    assert!(done(builder.request( | uid | HubMsg::FileCreateRequest {uid, path: "ws/src/new/mod.rs".to_string(), data: b"mod new;".to_vec()})));
+    assert_eq!(std::fs::read(abs("src/new/mod.rs")).unwrap(), b"mod new;");
+    // never overwrites
+    assert!(!done(builder.request( | uid | HubMsg::FileCreateRequest {uid, path: "ws/src/lib.rs".to_string(), data: Vec::new()})));
+    assert_eq!(std::fs::read(abs("src/lib.rs")).unwrap(), b"fn main(){}");
+
+    assert!(done(builder.request( | uid | HubMsg::FileMkdirRequest {uid, path: "ws/examples/deep".to_string()})));
+    assert!(Path::new(&abs("examples/deep")).is_dir());
+
+    assert!(done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: "ws/src/new".to_string(), new_path: "ws/examples/deep/new".to_string()})));
+    assert!(!Path::new(&abs("src/new")).exists());
+    assert_eq!(std::fs::read(abs("examples/deep/new/mod.rs")).unwrap(), b"mod new;");
+    assert!(!done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: "ws/src/lib.rs".to_string(), new_path: "ws/examples/deep/new/mod.rs".to_string()})));
+    assert_eq!(std::fs::read(abs("examples/deep/new/mod.rs")).unwrap(), b"mod new;");
+
+    assert!(done(builder.request( | uid | HubMsg::FileDeleteRequest {uid, path: "ws/examples".to_string()})));
+    assert!(!Path::new(&abs("examples")).exists());
+    assert!(!done(builder.request( | uid | HubMsg::FileDeleteRequest {uid, path: "ws/examples".to_string()})));
+}
+
+#[test]
+fn stats_files_and_folders() {
+    let test_dir = fileops_dir("stat");
+    let mut builder = TestBuilder::start(&test_dir);
+
+    let msg = builder.request( | uid | HubMsg::FileStatRequest {uid, path: "ws/src/lib.rs".to_string()});
+    if let HubMsg::FileStatResponse {stat: Some(stat), ..} = msg {
+        assert_eq!(stat.size, 11);
+        assert!(!stat.is_dir);
+        assert!(stat.modified.is_some());
+        assert!(!stat.readonly);
+        if cfg!(unix) {
+            assert_ne!(stat.mode & 0o600, 0);
+        }
+    }
+    else {
+        panic!("No stat for an existing file {:?}", msg);
+    }
+    let msg = builder.request( | uid | HubMsg::FileStatRequest {uid, path: "ws".to_string()});
+    assert!(matches!(msg, HubMsg::FileStatResponse {stat: Some(FileStat {is_dir: true, ..}), ..}));
+    let msg = builder.request( | uid | HubMsg::FileStatRequest {uid, path: "ws/nothing.rs".to_string()});
+    assert!(matches!(msg, HubMsg::FileStatResponse {stat: None, ..}));
+}
+
+#[test]
+fn refuses_keys_and_escapes() {
+    let test_dir = fileops_dir("refuse");
+    let mut builder = TestBuilder::start(&test_dir);
+    let outside = format!("{}_outside", test_dir.dir);
+
+    assert!(!done(builder.request( | uid | HubMsg::FileDeleteRequest {uid, path: "ws/key.ron".to_string()})));
+    assert!(!done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: "ws/key.ron".to_string(), new_path: "ws/key.txt".to_string()})));
+    assert!(!done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: "ws/src/lib.rs".to_string(), new_path: "ws/keys.ron".to_string()})));
+    assert!(!done(builder.request( | uid | HubMsg::FileCreateRequest {uid, path: "ws/src/../../outside".to_string(), data: Vec::new()})));
+    assert!(!done(builder.request( | uid | HubMsg::FileMkdirRequest {uid, path: "ws/../outside".to_string()})));
+    let msg = builder.request( | uid | HubMsg::FileStatRequest {uid, path: "ws/key.ron".to_string()});
+    assert!(matches!(msg, HubMsg::FileStatResponse {stat: None, ..}));
+    // the workspace folder itself stays put
+    assert!(!done(builder.request( | uid | HubMsg::FileDeleteRequest {uid, path: "ws".to_string()})));
+    assert!(!done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: "ws/".to_string(), new_path: "ws/moved".to_string()})));
+    for path in ["ws/.", "ws//", "ws/src/..", "ws/./", "ws/src//lib.rs"] {
+        assert!(!done(builder.request( | uid | HubMsg::FileDeleteRequest {uid, path: path.to_string()})), "deleted {}", path);
+        assert!(!done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: path.to_string(), new_path: "ws/moved".to_string()})), "renamed {}", path);
+        assert!(!done(builder.request( | uid | HubMsg::FileRenameRequest {uid, path: "ws/src/lib.rs".to_string(), new_path: path.to_string()})), "renamed to {}", path);
+    }
+    assert!(!Path::new(&format!("{}/moved", test_dir.dir)).exists());
+
+    assert_eq!(std::fs::read(format!("{}/key.ron", test_dir.dir)).unwrap(), b"secret");
+    assert!(Path::new(&format!("{}/src/lib.rs", test_dir.dir)).exists());
+    assert!(!Path::new(&outside).exists());
+}
+
+#[test]
+fn refuses_to_write_keys_in_any_case() {
+    let test_dir = fileops_dir("write_keys");
+    let mut builder = TestBuilder::start(&test_dir);
+    let written = | msg: HubMsg | match msg {
+        HubMsg::FileWriteResponse {done, ..} => done,
+        _ => panic!("Not a write response {:?}", msg)
+    };
+
+    for path in ["ws/KEY.RON", "ws/sub/keys.ron", "ws/Key.Ron"] {
+        assert!(!written(builder.request( | uid | HubMsg::FileWriteRequest {uid, path: path.to_string(), data: b"stolen".to_vec()})), "wrote {}", path);
+        let msg = builder.request( | uid | HubMsg::FileStatRequest {uid, path: path.to_string()});
+        assert!(matches!(msg, HubMsg::FileStatResponse {stat: None, ..}), "stat {}", path);
+    }
+    assert!(!Path::new(&format!("{}/sub/keys.ron", test_dir.dir)).exists());
+    assert_eq!(std::fs::read(format!("{}/key.ron", test_dir.dir)).unwrap(), b"secret");
+    assert!(written(builder.request( | uid | HubMsg::FileWriteRequest {uid, path: "ws/sub/notes.ron".to_string(), data: b"fine".to_vec()})));
+    assert_eq!(std::fs::read(format!("{}/sub/notes.ron", test_dir.dir)).unwrap(), b"fine");
+
+    // a key file put there by hand is left out of the tree as well
+    std::fs::write(format!("{}/sub/Key.ron", test_dir.dir), "secret").unwrap();
+    fn names(node: &BuilderFileTreeNode, out: &mut Vec<String>) {
+        match node {
+            BuilderFileTreeNode::Folder {name, folder, ..} => {
+                out.push(name.clone());
+                for node in folder {
+                    names(node, out);
+                }
+            },
+            BuilderFileTreeNode::File {name, ..} => out.push(name.clone())
+        }
+    }
+    let mut tree_names = Vec::new();
+    match builder.request( | uid | HubMsg::BuilderFileTreeRequest {uid, create_digest: false}) {
+        HubMsg::BuilderFileTreeResponse {tree, ..} => names(&tree, &mut tree_names),
+        msg => panic!("Not a tree response {:?}", msg)
+    }
+    assert!(tree_names.iter().any( | name | name == "notes.ron"));
+    assert!(!tree_names.iter().any( | name | name.eq_ignore_ascii_case("key.ron")), "{:?}", tree_names);
+}
@@ -0,0 +1,206 @@
+use makepad_hub::*;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn start_hub(keyring: &Arc<Mutex<HubKeyring>>, port: u16) -> HubServer {
+    let hub_router = HubRouter::start_hub_router(HubLog::None);
+    HubServer::start_hub_server(Arc::clone(keyring), &HubServerConfig::Localhost(port), &hub_router, HubLog::None).expect("Cannot start hub server")
+}
+
+fn recv_until(rx_read: &mpsc::Receiver<FromHubMsg>, timeout: Duration, check: impl Fn(&HubMsg) -> bool) -> Option<FromHubMsg> {
+    let deadline = Instant::now() + timeout;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match rx_read.recv_timeout(left) {
+            Ok(htc) if check(&htc.msg) => return Some(htc),
+            Ok(_) => (),
+            Err(_) => return None
+        }
+    }
+    None
+}
+
+#[test]
+fn backoff_doubles_up_to_the_limit() {
+    let options = HubClientOptions {min_backoff: Duration::from_millis(100), max_backoff: Duration::from_secs(1), ..HubClientOptions::default()};
+    assert_eq!(options.backoff(0), Duration::from_millis(100));
+    assert_eq!(options.backoff(1), Duration::from_millis(200));
+    assert_eq!(options.backoff(3), Duration::from_millis(800));
+    assert_eq!(options.backoff(4), Duration::from_secs(1));
+    assert_eq!(options.backoff(100), Duration::from_secs(1));
+}
+
+#[test]
+fn silent_hub_times_out() {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("builder");
+    let keyring = Arc::new(Mutex::new(keyring));
+    // authenticates and then never says anything again
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (tcp_stream, _) = listener.accept().unwrap();
+        let mut tcp_stream = HubStream::Tcp(tcp_stream);
+        hub_auth_accept(&mut tcp_stream, &keyring).unwrap();
+        std::thread::sleep(Duration::from_secs(5));
+    });
+
+    let options = HubClientOptions {heartbeat_interval: Duration::from_millis(50), heartbeat_timeout: Duration::from_millis(300), ..HubClientOptions::default()};
+    let mut hub_client = HubClient::connect_with_options(&hub_key, address, None, options, HubLog::None).expect("Cannot connect");
+    let rx_read = hub_client.rx_read.take().unwrap();
+    let msg = rx_read.recv_timeout(Duration::from_secs(2)).expect("Silent hub was not detected");
+    assert!(matches!(msg.msg, HubMsg::ConnectionError(_)));
+    // not reconnecting, so that was it
+    assert!(rx_read.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn replays_requests_when_the_builder_arrives() {
+    let mut keyring = HubKeyring::default();
+    let ui_key = keyring.generate("ui");
+    let builder_key = keyring.generate("builder");
+    let keyring = Arc::new(Mutex::new(keyring));
+    let mut hub_server = start_hub(&keyring, 0);
+    let address = hub_server.listen_address.unwrap();
+
+    let mut ui = HubClient::connect_with_options(&ui_key, address, None, HubClientOptions::reconnecting(), HubLog::None).expect("Cannot connect");
+    let ui_read = ui.rx_read.take().unwrap();
+    ui.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectUI}).unwrap();
+    recv_until(&ui_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::ConnectUI)).expect("UI not connected");
+
+    // nobody to hear this yet
+    let uid = ui.alloc_uid();
+    ui.tx_write.send(ToHubMsg {to: HubMsgTo::Builder("late".to_string()), msg: HubMsg::ListPackagesRequest {uid}}).unwrap();
+
+    let mut builder = HubClient::connect_to_server(&builder_key, address, None, HubLog::None).expect("Cannot connect");
+    let builder_read = builder.rx_read.take().unwrap();
+    builder.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectBuilder("late".to_string())}).unwrap();
+    let request = recv_until(&builder_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::ListPackagesRequest {..})).expect("Request was not replayed");
+    assert!(matches!(request.msg, HubMsg::ListPackagesRequest {uid: request_uid} if request_uid == uid));
+    builder.tx_write.send(ToHubMsg {to: HubMsgTo::Client(request.from), msg: HubMsg::ListPackagesResponse {uid, builder: "builder".to_string(), packages: Vec::new()}}).unwrap();
+    recv_until(&ui_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::ListPackagesResponse {..})).expect("UI got no response");
+
+    hub_server.terminate();
+}
+
+#[test]
+fn survives_a_hub_restart_mid_build() {
+    let mut keyring = HubKeyring::default();
+    let ui_key = keyring.generate("ui");
+    let builder_key = keyring.generate("builder");
+    let keyring = Arc::new(Mutex::new(keyring));
+    let mut hub_server = start_hub(&keyring, 0);
+    let address = hub_server.listen_address.unwrap();
+
+    let builds = Arc::new(Mutex::new(0));
+    {
+        let builds = Arc::clone(&builds);
+        std::thread::spawn(move || {
+            HubBuilder::run_builder_networked(builder_key, address, None, "restart", HubLog::None, move | hub_builder, htc | {
+                if let HubMsg::Build {uid, ..} = htc.msg {
+                    *builds.lock().unwrap() += 1;
+                    for i in 0..30 {
+                        hub_builder.route_send.send(ToHubMsg {
+                            to: HubMsgTo::UI,
+                            msg: HubMsg::LogItem {uid, item: HubLogItem::Message(format!("step {}", i))}
+                        });
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Ok(())
+            });
+        });
+    }
+
+    let mut ui = HubClient::connect_with_options(&ui_key, address, None, HubClientOptions::reconnecting(), HubLog::None).expect("Cannot connect");
+    let ui_read = ui.rx_read.take().unwrap();
+    ui.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectUI}).unwrap();
+    // wait for the builder to show up
+    loop {
+        let uid = ui.alloc_uid();
+        ui.tx_write.send(ToHubMsg {to: HubMsgTo::Hub, msg: HubMsg::ListBuildersRequest {uid}}).unwrap();
+        let response = recv_until(&ui_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::ListBuildersResponse {..})).expect("No builder list");
+        if let HubMsg::ListBuildersResponse {builders, ..} = response.msg {
+            if builders.contains(&"restart".to_string()) {
+                break
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let uid = ui.alloc_uid();
+    ui.tx_write.send(ToHubMsg {
+        to: HubMsgTo::Builder("restart".to_string()),
+        msg: HubMsg::Build {uid, workspace: "main".to_string(), package: "p".to_string(), config: "debug".to_string()}
+    }).unwrap();
+    recv_until(&ui_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::LogItem {..})).expect("Build did not start");
+
+    // kill the hub mid build and bring it back on the same port
+    hub_server.terminate();
+    recv_until(&ui_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::ConnectionError(_))).expect("UI did not see the connection drop");
+    std::thread::sleep(Duration::from_millis(200));
+    let mut hub_server = start_hub(&keyring, address.port());
+    assert_eq!(hub_server.listen_address, Some(SocketAddr::from(([127, 0, 0, 1], address.port()))));
+
+    // both come back by themselves, the build finishes and reports to the ui
+    recv_until(&ui_read, Duration::from_secs(10), | msg | matches!(msg, HubMsg::LogItem {uid: log_uid, ..} if *log_uid == uid)).expect("No build output after the restart");
+    recv_until(&ui_read, Duration::from_secs(10), | msg | matches!(msg, HubMsg::BuildFailure {uid: end_uid} | HubMsg::BuildSuccess {uid: end_uid} if *end_uid == uid)).expect("Build did not finish");
+    assert!(*builds.lock().unwrap() >= 1);
+
+    ui.terminate();
+    hub_server.terminate();
+}
+
+#[test]
+fn replays_a_request_once_per_builder_connection() {
+    let mut keyring = HubKeyring::default();
+    let ui_key = keyring.generate("ui");
+    let builder_key = keyring.generate("builder");
+    let keyring = Arc::new(Mutex::new(keyring));
+    let mut hub_server = start_hub(&keyring, 0);
+    let address = hub_server.listen_address.unwrap();
+
+    // after the restart the ui comes back last, so it finds the builder through the builder list
+    let ui_options = HubClientOptions {min_backoff: Duration::from_millis(600), ..HubClientOptions::reconnecting()};
+    let mut ui = HubClient::connect_with_options(&ui_key, address, None, ui_options, HubLog::None).expect("Cannot connect");
+    let ui_read = ui.rx_read.take().unwrap();
+    ui.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectUI}).unwrap();
+    recv_until(&ui_read, Duration::from_secs(5), | msg | matches!(msg, HubMsg::ConnectUI)).expect("UI not connected");
+    let uid = ui.alloc_uid();
+    ui.tx_write.send(ToHubMsg {to: HubMsgTo::Builder("counted".to_string()), msg: HubMsg::ListPackagesRequest {uid}}).unwrap();
+
+    let builder_options = HubClientOptions {min_backoff: Duration::from_millis(50), ..HubClientOptions::reconnecting()};
+    let mut builder = HubClient::connect_with_options(&builder_key, address, None, builder_options, HubLog::None).expect("Cannot connect");
+    let builder_read = builder.rx_read.take().unwrap();
+    builder.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectBuilder("counted".to_string())}).unwrap();
+    // announcing twice does not get the request twice either
+    builder.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectBuilder("counted".to_string())}).unwrap();
+
+    // the request is never answered, so it stays in flight
+    let count_requests = | time: Duration | {
+        let deadline = Instant::now() + time;
+        let mut count = 0;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            if let Ok(FromHubMsg {msg: HubMsg::ListPackagesRequest {uid: request_uid}, ..}) = builder_read.recv_timeout(left) {
+                if request_uid == uid {
+                    count += 1;
+                }
+            }
+        }
+        count
+    };
+    assert_eq!(count_requests(Duration::from_secs(1)), 1);
+
+    hub_server.terminate();
+    std::thread::sleep(Duration::from_millis(200));
+    let mut hub_server = start_hub(&keyring, address.port());
+    assert_eq!(count_requests(Duration::from_secs(3)), 1);
+    // the builder list we asked for ourselves does not show up in the ui
+    while let Ok(htc) = ui_read.try_recv() {
+        assert!(!matches!(htc.msg, HubMsg::ListBuildersResponse {..}), "{:?}", htc.msg);
+    }
+
+    ui.terminate();
+    builder.terminate();
+    hub_server.terminate();
+}
@@ -0,0 +1,181 @@
+use makepad_hub::*;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Duration;
+
+mod common;
+use common::*;
+
+// not quite random, but nothing repeats within a block
+fn test_data(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len).map( | _ | {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as u8
+    }).collect()
+}
+
+fn data_bytes(delta: &FileDelta) -> usize {
+    delta.ops.iter().map( | op | if let FileDeltaOp::Data(data) = op {data.len()} else {0}).sum()
+}
+
+#[test]
+fn delta_sends_only_what_changed() {
+    let old = test_data(20000, 1);
+    let mut new = old.clone();
+    // an insert shifts everything after it, the rolling hash still finds the blocks
+    new.splice(5000..5000, b"inserted".iter().cloned());
+    new[15000] ^= 0xff;
+    new.extend_from_slice(b"appended");
+
+    let signature = FileSignature::new(&old, 512);
+    assert_eq!(signature.blocks.len(), 40);
+    let delta = FileDelta::new(&signature, &new);
+    assert!(data_bytes(&delta) < 2 * 512 + 100, "delta sends {} bytes", data_bytes(&delta));
+    assert_eq!(delta.apply(&old).unwrap(), new);
+
+    // the short last block still matches
+    let short = test_data(1000, 2);
+    let delta = FileDelta::new(&FileSignature::new(&short, 512), &short);
+    assert_eq!(delta.ops, vec![FileDeltaOp::Copy {block: 0, count: 2}]);
+    assert_eq!(delta.apply(&short).unwrap(), short);
+
+    // into and out of nothing
+    assert_eq!(FileDelta::new(&FileSignature::new(b"", 512), &short).apply(b"").unwrap(), short);
+    assert!(FileDelta::new(&FileSignature::new(&short, 512), b"").apply(&short).unwrap().is_empty());
+
+    // a base that moved on is refused
+    assert!(FileDelta::new(&FileSignature::new(&old, 512), &new).apply(&short).is_err());
+}
+
+#[test]
+fn sync_points() {
+    let point = HubSyncPoint::from_path("main/makepad/").unwrap();
+    assert_eq!(point, HubSyncPoint {builder: "main".to_string(), path: "makepad".to_string()});
+    assert_eq!(point.sub_path(""), "makepad");
+    assert_eq!(point.sub_path("src/lib.rs"), "makepad/src/lib.rs");
+    assert_eq!(point.rel_path("main/makepad/src/lib.rs"), Some("src/lib.rs".to_string()));
+    assert_eq!(point.rel_path("main/makepad"), Some("".to_string()));
+    assert_eq!(point.rel_path("main/makepad2/src/lib.rs"), None);
+    assert_eq!(point.rel_path("windows/makepad/src/lib.rs"), None);
+    assert!(HubSyncPoint::from_path("main").is_none());
+}
+
+struct TestHub {
+    ui_send: HubRouteSend,
+    rx_ui: mpsc::Receiver<FromHubMsg>
+}
+
+fn start_hub(builders: &[(&str, &TestDir)]) -> TestHub {
+    let mut hub_router = HubRouter::start_hub_router(HubLog::None);
+    let (tx_ui, rx_ui) = mpsc::channel();
+    let mut ui_send = hub_router.connect_direct(HubRouteType::UI, tx_ui);
+    for (builder, test_dir) in builders {
+        HubBuilder::run_builder_direct(builder, Default::default(), &mut hub_router, | hub_builder, htc | hub_builder.default(htc));
+        let mut workspaces = HashMap::new();
+        workspaces.insert("ws".to_string(), test_dir.dir.clone());
+        let uid = ui_send.alloc_uid();
+        ui_send.send(ToHubMsg {
+            to: HubMsgTo::Builder(builder.to_string()),
+            msg: HubMsg::BuilderConfig {uid, config: HubBuilderConfig {http_server: HttpServerConfig::Offline, workspaces, profiles: Vec::new()}}
+        });
+    }
+    TestHub {ui_send, rx_ui}
+}
+
+fn run_sync(hub: &mut TestHub, sync: &mut HubSync) -> Vec<HubLogItem> {
+    let mut log = Vec::new();
+    while sync.is_running() {
+        let htc = hub.rx_ui.recv_timeout(Duration::from_secs(5)).expect("Sync got stuck");
+        log.extend(sync.handle_hub_msg(&mut hub.ui_send, &htc));
+    }
+    log
+}
+
+fn read(test_dir: &TestDir, path: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/{}", test_dir.dir, path)).unwrap()
+}
+
+#[test]
+fn mirrors_and_reports_conflicts() {
+    let big = test_data(10000, 3);
+    let source = test_dir("sync", "source", &[
+        ("src/lib.rs", b"fn main(){}"),
+        ("src/deep/mod.rs", b"mod deep;"),
+        ("same/same.rs", b"same"),
+        ("big.rs", &big),
+        ("key.ron", b"secret")
+    ]);
+    let mut big_target = big.clone();
+    big_target[100] ^= 1;
+    let target = test_dir("sync", "target", &[
+        ("same/same.rs", b"same"),
+        ("big.rs", &big_target),
+        ("extra.rs", b"only here")
+    ]);
+    let mut hub = start_hub(&[("a", &source), ("b", &target)]);
+    let mut sync = HubSync::new(HubSyncPoint::from_path("a/ws").unwrap(), HubSyncPoint::from_path("b/ws").unwrap());
+
+    sync.start(&mut hub.ui_send);
+    let log = run_sync(&mut hub, &mut sync);
+    assert_eq!(read(&target, "src/lib.rs"), b"fn main(){}");
+    assert_eq!(read(&target, "src/deep/mod.rs"), b"mod deep;");
+    assert_eq!(read(&target, "big.rs"), big);
+    assert!(!std::path::Path::new(&format!("{}/key.ron", target.dir)).exists());
+    assert_eq!(read(&target, "extra.rs"), b"only here");
+    assert!(log.iter().any( | item | matches!(item, HubLogItem::Warning(msg) if msg.contains("ws/extra.rs only exists on b"))));
+    assert!(log.iter().any( | item | matches!(item, HubLogItem::Message(msg) if msg == "Synced 3 files from a/ws to b/ws")));
+
+    // nothing changed, nothing to do, and the file only on b was reported already
+    sync.start(&mut hub.ui_send);
+    let log = run_sync(&mut hub, &mut sync);
+    assert!(log.is_empty(), "{:?}", log);
+
+    // an edit on the source goes over, an edit on both sides is a conflict
+    std::fs::write(format!("{}/src/lib.rs", source.dir), b"fn main(){println!()}").unwrap();
+    sync.sync_file(&mut hub.ui_send, "src/lib.rs");
+    run_sync(&mut hub, &mut sync);
+    assert_eq!(read(&target, "src/lib.rs"), b"fn main(){println!()}");
+
+    std::fs::write(format!("{}/src/deep/mod.rs", source.dir), b"mod source;").unwrap();
+    std::fs::write(format!("{}/src/deep/mod.rs", target.dir), b"mod target;").unwrap();
+    sync.start(&mut hub.ui_send);
+    let log = run_sync(&mut hub, &mut sync);
+    assert_eq!(read(&target, "src/deep/mod.rs"), b"mod target;");
+    assert!(log.iter().any( | item | matches!(item, HubLogItem::Warning(msg) if msg.contains("ws/src/deep/mod.rs changed on b since the last sync"))));
+}
+
+#[test]
+fn baseline_survives_a_restart() {
+    let source = test_dir("sync", "baseline_source", &[("lib.rs", b"v1")]);
+    let target = test_dir("sync", "baseline_target", &[]);
+    let config = test_dir("sync", "baseline_config", &[]);
+    let mut hub = start_hub(&[("a", &source), ("b", &target)]);
+    let new_sync = | | {
+        let mut sync = HubSync::new(HubSyncPoint::from_path("a/ws").unwrap(), HubSyncPoint::from_path("b/ws").unwrap());
+        let path = format!("{}/{}", config.dir, sync.baseline_file_name());
+        sync.load_baseline(&path).unwrap();
+        sync
+    };
+    let mut sync = new_sync();
+    assert_eq!(sync.baseline_file_name(), "sync_b%2fws.bin");
+    sync.start(&mut hub.ui_send);
+    run_sync(&mut hub, &mut sync);
+    assert_eq!(read(&target, "lib.rs"), b"v1");
+
+    // after a restart an edit on the target is still recognised as one
+    std::fs::write(format!("{}/lib.rs", source.dir), b"v2").unwrap();
+    std::fs::write(format!("{}/lib.rs", target.dir), b"edited").unwrap();
+    let mut sync = new_sync();
+    sync.start(&mut hub.ui_send);
+    let log = run_sync(&mut hub, &mut sync);
+    assert_eq!(read(&target, "lib.rs"), b"edited");
+    assert!(log.iter().any( | item | matches!(item, HubLogItem::Warning(msg) if msg.contains("ws/lib.rs changed on b since the last sync"))));
+
+    // a baseline we cannot parse is an error and stays as it is
+    let path = format!("{}/broken.bin", config.dir);
+    std::fs::write(&path, b"broken").unwrap();
+    let mut sync = new_sync();
+    assert!(sync.load_baseline(&path).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"broken");
+}
@@ -0,0 +1,175 @@
+// the fixture in tests/tls is a self signed certificate for localhost, made with
+// openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes -keyout key.pem -out cert.pem -days 36500
+//     -subj "/CN=localhost" -addext "subjectAltName=DNS:localhost" -addext "basicConstraints=critical,CA:FALSE"
+use makepad_hub::*;
+use makepad_http::channel::WebSocketChannels;
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn server_tls() -> HubTlsConfig {
+    HubTlsConfig {cert: fixture("cert.pem"), key: fixture("key.pem")}
+}
+
+fn client_tls(server_name: &str) -> HubTlsClientConfig {
+    HubTlsClientConfig {ca: fixture("cert.pem"), server_name: server_name.to_string()}
+}
+
+fn keyring() -> (Arc<Mutex<HubKeyring>>, HubKey) {
+    let mut keyring = HubKeyring::default();
+    let hub_key = keyring.generate("tls");
+    (Arc::new(Mutex::new(keyring)), hub_key)
+}
+
+#[test]
+fn hub_connection_over_tls() {
+    let mut hub_router = HubRouter::start_hub_router(HubLog::None);
+    let (tx_ui, rx_ui) = mpsc::channel::<FromHubMsg>();
+    let ui_send = hub_router.connect_direct(HubRouteType::UI, tx_ui);
+    
+    let (keyring, hub_key) = keyring();
+    let config = HubServerConfig::Tls(Box::new(HubServerConfig::Localhost(0)), server_tls());
+    let mut hub_server = HubServer::start_hub_server(keyring, &config, &hub_router, HubLog::None).expect("Cannot start hub server");
+    let address = hub_server.listen_address.unwrap();
+    
+    let mut hub_client = HubClient::connect_to_server(&hub_key, address, Some(&client_tls("localhost")), HubLog::None).expect("Cannot connect over tls");
+    let rx_read = hub_client.rx_read.take().unwrap();
+    hub_client.tx_write.send(ToHubMsg {to: HubMsgTo::All, msg: HubMsg::ConnectBuilder("tls".to_string())}).unwrap();
+    
+    let msg = rx_ui.recv_timeout(Duration::from_secs(5)).expect("UI got nothing from the builder");
+    assert!(matches!(&msg.msg, HubMsg::ConnectBuilder(name) if name == "tls"));
+    
+    // and back down to the builder
+    let uid = HubUid {addr: msg.from, id: 1};
+    ui_send.send(ToHubMsg {to: HubMsgTo::Client(msg.from), msg: HubMsg::ListPackagesRequest {uid}});
+    // the builder hears its own announcement first
+    let msg = rx_read.recv_timeout(Duration::from_secs(5)).expect("Builder got nothing");
+    assert!(matches!(msg.msg, HubMsg::ConnectBuilder(_)));
+    let msg = rx_read.recv_timeout(Duration::from_secs(5)).expect("Builder got nothing from the UI");
+    assert!(matches!(msg.msg, HubMsg::ListPackagesRequest {..}));
+    
+    hub_server.terminate();
+}
+
+#[test]
+fn rejects_untrusted_connections() {
+    let hub_router = HubRouter::start_hub_router(HubLog::None);
+    let (keyring, hub_key) = keyring();
+    let config = HubServerConfig::Tls(Box::new(HubServerConfig::Localhost(0)), server_tls());
+    let mut hub_server = HubServer::start_hub_server(keyring, &config, &hub_router, HubLog::None).expect("Cannot start hub server");
+    let address = hub_server.listen_address.unwrap();
+    
+    // the certificate is for localhost only
+    assert!(HubClient::connect_to_server(&hub_key, address, Some(&client_tls("example.com")), HubLog::None).is_err());
+    
+    // plain bytes never make it to the authentication, the server hangs up on them
+    let mut plain = TcpStream::connect(address).unwrap();
+    plain.write_all(b"MKPDHUB2 plain\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let _ = plain.read_to_end(&mut response);
+    assert!(!response.starts_with(b"MKPDHUB2"));
+    
+    hub_server.terminate();
+}
+
+#[test]
+fn missing_certificate() {
+    let hub_router = HubRouter::start_hub_router(HubLog::None);
+    let tls = HubTlsConfig {cert: fixture("missing.pem"), key: fixture("key.pem")};
+    let config = HubServerConfig::Tls(Box::new(HubServerConfig::Localhost(0)), tls);
+    assert!(HubServer::start_hub_server(keyring().0, &config, &hub_router, HubLog::None).is_none());
+}
+
+#[test]
+fn https_server() {
+    let dir = std::env::temp_dir().join(format!("makepad_hub_https_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), b"secure").unwrap();
+    let mut workspaces = HashMap::new();
+    workspaces.insert("main".to_string(), dir.to_str().unwrap().to_string());
+    let config = HttpServerConfig::Tls(Box::new(HttpServerConfig::Localhost(0)), server_tls());
+    let mut server = HttpServer::start_http_server(&config, WebSocketChannels::default(), Arc::new(Mutex::new(workspaces))).expect("Cannot start https server");
+    // the https port is the only one, there is no plain listener behind it
+    assert_eq!(server.server.as_ref().unwrap().listen_address, server.listen_address.unwrap());
+    
+    let tcp_stream = TcpStream::connect(server.listen_address.unwrap()).unwrap();
+    let tls_config = client_tls("localhost").client_config().unwrap();
+    let mut stream = TlsStream::connect(tcp_stream, &tls_config, "localhost").unwrap();
+    stream.write_all(b"GET /main/index.html HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nsecure"));
+    let _ = stream.shutdown(Shutdown::Both);
+    
+    // plain http on the https port goes nowhere
+    let mut plain = TcpStream::connect(server.listen_address.unwrap()).unwrap();
+    plain.write_all(b"GET /main/index.html HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    let _ = plain.read_to_end(&mut response);
+    assert!(!response.starts_with(b"HTTP/1.1 200"));
+    
+    server.terminate();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn large_transfers_both_ways_at_once() {
+    const SIZE: usize = 8 * 1024 * 1024;
+    // both sides write far more than the socket buffers hold before they read anything back
+    fn exchange(stream: TlsStream, fill: u8, started: Option<mpsc::Receiver<()>>, first_read: Option<mpsc::Sender<()>>) -> mpsc::Receiver<(usize, bool)> {
+        let (tx_done, rx_done) = mpsc::channel();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = stream;
+        std::thread::spawn(move || {
+            let mut data = vec![0u8; 65536];
+            let mut total = 0;
+            let mut same = true;
+            while total < SIZE {
+                let n = reader.read(&mut data).unwrap();
+                if n == 0 {
+                    break
+                }
+                if total == 0 {
+                    if let Some(first_read) = &first_read {
+                        first_read.send(()).unwrap();
+                    }
+                }
+                same &= data[0..n].iter().all( | b | *b != fill);
+                total += n;
+            }
+            let _ = tx_done.send((total, same));
+        });
+        std::thread::spawn(move || {
+            // the server can only write once the handshake is done
+            if let Some(started) = started {
+                started.recv().unwrap();
+            }
+            let chunk = vec![fill; 65536];
+            for _ in 0..SIZE / chunk.len() {
+                writer.write_all(&chunk).unwrap();
+            }
+        });
+        rx_done
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server_config = server_tls().server_config().unwrap();
+    let server = std::thread::spawn(move || {
+        let (tx_started, rx_started) = mpsc::channel();
+        exchange(TlsStream::accept(listener.accept().unwrap().0, &server_config), 2, Some(rx_started), Some(tx_started))
+    });
+    let client_config = client_tls("localhost").client_config().unwrap();
+    let client = TlsStream::connect(TcpStream::connect(address).unwrap(), &client_config, "localhost").unwrap();
+    let client_done = exchange(client, 1, None, None);
+    let server_done = server.join().unwrap();
+    assert_eq!(server_done.recv_timeout(Duration::from_secs(30)).expect("Server read stalled"), (SIZE, true));
+    assert_eq!(client_done.recv_timeout(Duration::from_secs(30)).expect("Client read stalled"), (SIZE, true));
+}
@@ -0,0 +1,190 @@
+#![cfg(unix)]
+use makepad_hub::*;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::*;
+
+fn process_dir(name: &str) -> TestDir {
+    let test_dir = test_dir("process", name, &[]);
+    std::fs::create_dir_all(format!("{}/bin", test_dir.dir)).unwrap();
+    test_dir
+}
+
+fn sh(script: &str, options: ProgramOptions) -> Process {
+    Process::start_with_options("sh", &["-c", script], "/", &[], &options).unwrap()
+}
+
+// everything the process writes until it closes its output
+fn output(process: &mut Process) -> String {
+    let rx_line = process.rx_line.take().unwrap();
+    let mut out = String::new();
+    while let Ok(line) = rx_line.recv_timeout(Duration::from_secs(10)) {
+        if let Some((_, line)) = line {
+            out.push_str(&line);
+        }
+    }
+    out
+}
+
+#[test]
+fn reports_exit_codes() {
+    assert_eq!(sh("exit 3", ProgramOptions::default()).wait(), ProgramExit::Code(3));
+    assert!(sh("true", ProgramOptions::default()).wait().is_success());
+    assert_eq!(sh("kill -9 $$", ProgramOptions::default()).wait(), ProgramExit::Signal(9));
+}
+
+#[test]
+fn forwards_stdin() {
+    let mut process = sh("cat", ProgramOptions {stdin: true, ..ProgramOptions::default()});
+    process.write_stdin(b"hello\n").unwrap();
+    process.close_stdin();
+    assert_eq!(output(&mut process), "hello\n");
+    assert!(process.wait().is_success());
+    // without the option stdin stays closed
+    assert!(sh("cat", ProgramOptions::default()).write_stdin(b"x").is_err());
+}
+
+#[test]
+fn runs_on_a_pty() {
+    let mut process = sh("test -t 0 && test -t 1 && echo tty; printf 'name? '; read x; echo got $x", ProgramOptions {pty: true, ..ProgramOptions::default()});
+    let rx_line = process.rx_line.take().unwrap();
+    // the prompt has no newline but still arrives
+    let mut out = String::new();
+    while !out.contains("name? ") {
+        if let Some((is_stderr, line)) = rx_line.recv_timeout(Duration::from_secs(10)).unwrap() {
+            assert!(!is_stderr);
+            out.push_str(&line);
+        }
+    }
+    assert!(out.starts_with("tty\n"));
+    process.write_stdin(b"makepad\n").unwrap();
+    let mut rest = String::new();
+    while let Ok(Some((_, line))) = rx_line.recv_timeout(Duration::from_secs(10)) {
+        rest.push_str(&line);
+    }
+    assert!(rest.contains("got makepad\n"));
+    assert!(process.wait().is_success());
+}
+
+#[test]
+fn kills_the_process_group() {
+    // the background sleep keeps the output open unless it dies too
+    let mut process = sh("sleep 30 & wait", ProgramOptions::default());
+    std::thread::sleep(Duration::from_millis(200));
+    let start = Instant::now();
+    process.kill();
+    output(&mut process);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(process.wait(), ProgramExit::Killed);
+}
+
+#[test]
+fn enforces_limits() {
+    let mut process = sh("sleep 30", ProgramOptions {time_limit_ms: Some(200), ..ProgramOptions::default()});
+    assert!(!process.check_time_limit());
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(process.check_time_limit());
+    assert_eq!(process.wait(), ProgramExit::TimeLimit);
+
+    let mut process = sh("while :; do :; done", ProgramOptions {cpu_limit_secs: Some(1), ..ProgramOptions::default()});
+    assert_eq!(process.wait(), ProgramExit::CpuLimit);
+
+    let mut process = sh("exit 3", ProgramOptions {cpu_limit_secs: Some(u64::MAX), ..ProgramOptions::default()});
+    assert_eq!(process.wait(), ProgramExit::Code(3));
+}
+
+fn start_builder(test_dir: &TestDir) -> (HubRouter, HubRouteSend, mpsc::Receiver<FromHubMsg>) {
+    let mut hub_router = HubRouter::start_hub_router(HubLog::None);
+    let (tx_ui, rx_ui) = mpsc::channel();
+    let mut ui_send = hub_router.connect_direct(HubRouteType::UI, tx_ui);
+    HubBuilder::run_builder_direct("local", Default::default(), &mut hub_router, | hub_builder, htc | hub_builder.default(htc));
+    let mut workspaces = HashMap::new();
+    workspaces.insert("ws".to_string(), test_dir.dir.clone());
+    let uid = ui_send.alloc_uid();
+    ui_send.send(ToHubMsg {
+        to: HubMsgTo::Builder("local".to_string()),
+        msg: HubMsg::BuilderConfig {uid, config: HubBuilderConfig {http_server: HttpServerConfig::Offline, workspaces, profiles: Vec::new()}}
+    });
+    (hub_router, ui_send, rx_ui)
+}
+
+#[test]
+fn runs_programs_from_the_ui() {
+    let test_dir = process_dir("run");
+    let script = format!("{}/bin/greet.sh", test_dir.dir);
+    std::fs::write(&script, "#!/bin/sh\nread name\necho hello $name\nexit 7\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let (_hub_router, mut ui_send, rx_ui) = start_builder(&test_dir);
+    let run_uid = ui_send.alloc_uid();
+    ui_send.send(ToHubMsg {
+        to: HubMsgTo::Builder("local".to_string()),
+        msg: HubMsg::ProgramRun {
+            uid: run_uid,
+            path: "ws/bin/greet.sh".to_string(),
+            args: Vec::new(),
+            options: ProgramOptions {stdin: true, ..ProgramOptions::default()}
+        }
+    });
+
+    let mut messages = Vec::new();
+    loop {
+        let htc = rx_ui.recv_timeout(Duration::from_secs(10)).expect("program did not end");
+        match htc.msg {
+            HubMsg::ProgramBegin {uid} if uid == run_uid => ui_send.send(ToHubMsg {
+                to: HubMsgTo::Builder("local".to_string()),
+                msg: HubMsg::ProgramStdin {uid: run_uid, input: "makepad\n".to_string(), eof: true}
+            }),
+            HubMsg::LogItem {uid, item: HubLogItem::Message(message)} if uid == run_uid => messages.push(message),
+            HubMsg::ProgramEnd {uid, exit} if uid == run_uid => {
+                assert_eq!(exit, ProgramExit::Code(7));
+                break
+            },
+            _ => ()
+        }
+    }
+    assert_eq!(messages[0], "hello makepad\n");
+    assert!(messages[1].contains("exited with code 7"));
+}
+
+#[test]
+fn time_limit_stops_a_program_that_keeps_printing() {
+    let test_dir = process_dir("chatty");
+    let script = format!("{}/bin/chatty.sh", test_dir.dir);
+    // prints more often than the builder polls, so the output never goes quiet
+    std::fs::write(&script, "#!/bin/sh\nwhile :; do echo tick; sleep 0.02; done\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let (_hub_router, mut ui_send, rx_ui) = start_builder(&test_dir);
+    let run_uid = ui_send.alloc_uid();
+    ui_send.send(ToHubMsg {
+        to: HubMsgTo::Builder("local".to_string()),
+        msg: HubMsg::ProgramRun {
+            uid: run_uid,
+            path: "ws/bin/chatty.sh".to_string(),
+            args: Vec::new(),
+            options: ProgramOptions {time_limit_ms: Some(500), ..ProgramOptions::default()}
+        }
+    });
+
+    let start = Instant::now();
+    let mut ticks = 0;
+    loop {
+        // the output never stops by itself, so only the limit ends this
+        assert!(start.elapsed() < Duration::from_secs(5), "program ran past its time limit");
+        let htc = rx_ui.recv_timeout(Duration::from_secs(10)).expect("program did not end");
+        match htc.msg {
+            HubMsg::LogItem {uid, item: HubLogItem::Message(message)} if uid == run_uid && message == "tick\n" => ticks += 1,
+            HubMsg::ProgramEnd {uid, exit} if uid == run_uid => {
+                assert_eq!(exit, ProgramExit::TimeLimit);
+                break
+            },
+            _ => ()
+        }
+    }
+    assert!(ticks > 0);
+}
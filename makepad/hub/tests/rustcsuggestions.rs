@@ -0,0 +1,83 @@
+use makepad_hub::*;
+use makepad_microserde::*;
+
+fn span(file_name: &str, line: u32, column_start: u32, column_end: u32, replacement: Option<&str>, applicability: Option<&str>) -> String {
+    format!(
+        r#"{{"file_name":"{}","byte_start":0,"byte_end":0,"line_start":{},"line_end":{},"column_start":{},"column_end":{},"is_primary":true,"text":[],"label":null,"suggested_replacement":{},"suggestion_applicability":{},"expansion":null}}"#,
+        file_name,
+        line,
+        line,
+        column_start,
+        column_end,
+        replacement.map( | r | format!("\"{}\"", r)).unwrap_or("null".to_string()),
+        applicability.map( | a | format!("\"{}\"", a)).unwrap_or("null".to_string())
+    )
+}
+
+fn child(message: &str, spans: &[String]) -> String {
+    format!(r#"{{"message":"{}","code":null,"level":"help","spans":[{}],"children":[],"rendered":null}}"#, message, spans.join(","))
+}
+
+fn message(children: &[String]) -> RustcMessage {
+    let json = format!(
+        r#"{{"message":"unused import: `std::fs`","code":null,"level":"warning","spans":[{}],"children":[{}],"rendered":null}}"#,
+        span("src/lib.rs", 1, 5, 12, None, None),
+        children.join(",")
+    );
+    DeJson::deserialize_json(&json).unwrap()
+}
+
+#[test]
+fn keeps_machine_applicable_edits() {
+    let message = message(&[
+        child("the item is unused", &[]),
+        child("remove the whole `use` item", &[span("src/lib.rs", 1, 1, 14, Some(""), Some("MachineApplicable"))]),
+    ]);
+    let suggestions = message.suggestions("src/lib.rs");
+    assert_eq!(suggestions, vec![LocSuggestion {
+        message: "remove the whole `use` item".to_string(),
+        applicability: LocApplicability::MachineApplicable,
+        edits: vec![LocEdit {line_start: 1, column_start: 1, line_end: 1, column_end: 14, replacement: "".to_string()}]
+    }]);
+    assert!(suggestions[0].is_quick_fix());
+}
+
+#[test]
+fn groups_edits_and_takes_the_weakest_applicability() {
+    let message = message(&[
+        child("consider borrowing here", &[
+            span("src/lib.rs", 3, 9, 10, Some("&x"), Some("MachineApplicable")),
+            span("src/lib.rs", 4, 9, 10, Some("&y"), Some("MaybeIncorrect")),
+            span("src/other.rs", 1, 1, 1, Some("nope"), Some("MachineApplicable")),
+        ]),
+        child("use a placeholder", &[span("src/lib.rs", 5, 1, 1, Some("/* value */"), Some("HasPlaceholders"))]),
+    ]);
+    let suggestions = message.suggestions("src/lib.rs");
+    assert_eq!(suggestions.len(), 2);
+    assert_eq!(suggestions[0].applicability, LocApplicability::MaybeIncorrect);
+    assert_eq!(suggestions[0].edits.len(), 2);
+    assert!(!suggestions[0].is_quick_fix());
+    assert_eq!(suggestions[1].applicability, LocApplicability::HasPlaceholders);
+    // edits in other files are not ours to apply
+    assert_eq!(message.suggestions("src/other.rs")[0].edits[0].replacement, "nope");
+    assert!(message.suggestions("src/main.rs").is_empty());
+}
+
+#[test]
+fn loc_messages_carry_their_suggestions() {
+    let msg = HubLogItem::LocWarning(LocMessage {
+        path: "src/lib.rs".to_string(),
+        line: 3,
+        column: 5,
+        body: "unused import".to_string(),
+        range: Some((1, 2)),
+        rendered: None,
+        explanation: Some("E0432".to_string()),
+        suggestions: vec![LocSuggestion {
+            message: "remove the whole `use` item".to_string(),
+            applicability: LocApplicability::MachineApplicable,
+            edits: vec![LocEdit {line_start: 1, column_start: 1, line_end: 1, column_end: 14, replacement: "".to_string()}]
+        }]
+    });
+    assert_eq!(HubLogItem::deserialize_bin(&msg.serialize_bin()).unwrap(), msg);
+}
@@ -1,192 +1,498 @@
 use proc_macro::{TokenStream};
 use crate::macro_lib::*;
+use crate::nserde_attrs::*;
 
-pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
+pub enum BinVariant {
+    Unit,
+    Tuple(usize),
+    Named(Vec<SerdeField>),
+}
+
+pub struct BinEnumVariant {
+    pub name: String,
+    pub attrs: VariantAttrs,
+    pub kind: BinVariant,
+}
+
+pub enum BinType {
+    Struct(BinVariant),
+    Enum(Vec<BinEnumVariant>),
+}
+
+// the shape of a struct or enum, parsed before generating any code
+pub struct BinDef {
+    pub name: String,
+    pub generic: Option<TokenStream>,
+    pub where_clause: Option<TokenStream>,
+    pub container: ContainerAttrs,
+    pub ty: BinType,
+}
 
+pub fn parse_bin_def(input: TokenStream, bound: Option<&str>) -> Result<BinDef, TokenStream> {
     let mut parser = TokenParser::new(input);
-    let mut tb = TokenBuilder::new();
-    
+    let container = container_attrs(&parser.eat_attributes()) ?;
+    json_only_attrs(&container, "SerBin and DeBin") ?;
+
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let types = parser.eat_all_types();
-            let where_clause = parser.eat_where_clause(Some("SerBin"));
+            let where_clause = parser.eat_where_clause(bound);
 
-            tb.add("impl").stream(generic.clone());
-            tb.add("SerBin for").ident(&name).stream(generic).stream(where_clause);
-            tb.add("{ fn ser_bin ( & self , s : & mut Vec < u8 > ) {");
-
-            if let Some(types) = types{
-                for i in 0..types.len(){
-                     tb.add("self .").unsuf_usize(i).add(". ser_bin ( s ) ;");
-                }
+            let kind = if let Some(types) = types{
+                transparent_field_count(&container, types.len()) ?;
+                BinVariant::Tuple(types.len())
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                for field in fields{
-                    tb.add("self .").ident(&field.name).add(". ser_bin ( s ) ;");
-                }
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = SerdeField::from_struct_fields(fields) ?;
+                transparent_field_count(&container, fields.len()) ?;
+                BinVariant::Named(fields)
             }
             else{
-                return parser.unexpected()
-            }
-            tb.add("} } ;"); 
-            return tb.end();
+                return Err(parser.unexpected())
+            };
+            return Ok(BinDef {name: name, generic: generic, where_clause: where_clause, container: container, ty: BinType::Struct(kind)})
         }
     }
     else if parser.eat_ident("enum"){
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
-            let where_clause = parser.eat_where_clause(Some("SerBin"));
-            
-            tb.add("impl").stream(generic.clone());
-            tb.add("SerBin for").ident(&name).stream(generic).stream(where_clause);
-            tb.add("{ fn ser_bin ( & self , s : & mut Vec < u8 > ) {");
-            tb.add("match self {");
-            
+            let where_clause = parser.eat_where_clause(bound);
+
             if !parser.open_brace(){
-                return parser.unexpected()
+                return Err(parser.unexpected())
             }
-            let mut index = 0;
+            let mut variants = Vec::new();
             while !parser.eat_eot(){
+                let attrs = variant_attrs(&parser.eat_attributes()) ?;
                 // parse ident
                 if let Some(variant) = parser.eat_any_ident(){
-                    if let Some(types) = parser.eat_all_types(){
-                        tb.add("Self ::").ident(&variant).add("(");
-                        for i in 0..types.len(){
-                            tb.ident(&format!("n{}", i)).add(",");
-                        }
-                        tb.add(") => {").suf_u16(index).add(". ser_bin ( s ) ;");
-                        for i in 0..types.len(){
-                            tb.ident(&format!("n{}", i)).add(". ser_bin ( s ) ;");
-                        }
-                        tb.add("}");
+                    let kind = if let Some(types) = parser.eat_all_types(){
+                        BinVariant::Tuple(types.len())
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
-                        tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields.iter(){
-                            tb.ident(&field.name).add(",");
-                        }
-                        tb.add("} => {").suf_u16(index).add(". ser_bin ( s ) ;");
-                        for field in fields{
-                            tb.ident(&field.name).add(". ser_bin ( s ) ;");
-                        }
-                        tb.add("}");
+                        BinVariant::Named(SerdeField::from_struct_fields(fields) ?)
                     }
                     else if parser.is_punct(',') || parser.is_eot(){ // bare variant
-                        tb.add("Self ::").ident(&variant).add("=> {");
-                        tb.suf_u16(index).add(". ser_bin ( s ) ; }");
+                        BinVariant::Unit
                     }
                     else{
-                        return parser.unexpected();
+                        return Err(parser.unexpected());
+                    };
+                    if attrs.other {
+                        if let BinVariant::Unit = kind {}
+                        else {
+                            return Err(error("nserde other needs a variant without fields"))
+                        }
                     }
-                    index += 1;
+                    variants.push(BinEnumVariant {name: variant, attrs: attrs, kind: kind});
                     parser.eat_punct(',');
                 }
                 else{
-                    return parser.unexpected()
+                    return Err(parser.unexpected())
                 }
             }
-            tb.add("} } } ;");
-            return tb.end();
+            if variants.iter().filter( | v | v.attrs.other).count() > 1 {
+                return Err(error("nserde other can only be used on one variant"))
+            }
+            return Ok(BinDef {name: name, generic: generic, where_clause: where_clause, container: container, ty: BinType::Enum(variants)})
         }
     }
-    return parser.unexpected()
-} 
+    Err(parser.unexpected())
+}
 
-pub fn derive_de_bin_impl(input: TokenStream) -> TokenStream {
-    let mut parser = TokenParser::new(input);
+// ser_bin appends to a Vec<u8>, ser_bin_to_writer streams into an io::Write and propagates its errors
+struct SerBinTarget {
+    header: &'static str,
+    call: &'static str,
+    end: &'static str
+}
+
+const SER_BIN_VEC: SerBinTarget = SerBinTarget {
+    header: "fn ser_bin ( & self , s : & mut Vec < u8 > ) {",
+    call: ". ser_bin ( s ) ;",
+    end: "}"
+};
+
+const SER_BIN_WRITER: SerBinTarget = SerBinTarget {
+    header: "fn ser_bin_to_writer ( & self , w : & mut dyn std :: io :: Write ) -> std :: io :: Result < ( ) > {",
+    call: ". ser_bin_to_writer ( w ) ? ;",
+    end: "std :: result :: Result :: Ok ( ( ) ) }"
+};
+
+fn ser_bin_struct(tb: &mut TokenBuilder, target: &SerBinTarget, variant: &BinVariant) {
+    tb.add(target.header);
+    match variant {
+        BinVariant::Unit => (),
+        // borrowed like the enum fields, so a &'a [T] field picks the length prefixed impl
+        BinVariant::Tuple(count) => for i in 0..*count {
+            tb.add("( & self .").unsuf_usize(i).add(")").add(target.call);
+        },
+        BinVariant::Named(fields) => for field in fields.iter().filter( | f | !f.attrs.skip) {
+            tb.add("( & self .").ident(&field.name).add(")").add(target.call);
+        }
+    }
+    tb.add(target.end);
+}
+
+// binds the fields of a variant as n0.. so they cant shadow s or w, returns how many were bound
+fn ser_bin_variant_pattern(tb: &mut TokenBuilder, variant: &BinEnumVariant) -> usize {
+    tb.add("Self ::").ident(&variant.name);
+    match &variant.kind {
+        BinVariant::Unit => 0,
+        BinVariant::Tuple(count) => {
+            tb.add("(");
+            for i in 0..*count {
+                tb.ident(&format!("n{}", i)).add(",");
+            }
+            tb.add(")");
+            *count
+        }
+        BinVariant::Named(fields) => {
+            tb.add("{");
+            let mut count = 0;
+            for field in fields.iter().filter( | f | !f.attrs.skip) {
+                tb.ident(&field.name).add(":").ident(&format!("n{}", count)).add(",");
+                count += 1;
+            }
+            tb.add(".. }");
+            count
+        }
+    }
+}
+
+fn ser_bin_enum(tb: &mut TokenBuilder, target: &SerBinTarget, variants: &[BinEnumVariant]) {
+    tb.add(target.header);
+    tb.add("match self {");
+    for (index, variant) in variants.iter().enumerate() {
+        let count = ser_bin_variant_pattern(tb, variant);
+        tb.add("=> {").suf_u16(index as u16).add(target.call);
+        for i in 0..count {
+            tb.ident(&format!("n{}", i)).add(target.call);
+        }
+        tb.add("}");
+    }
+    tb.add("}");
+    tb.add(target.end);
+}
+
+pub fn derive_ser_bin_impl(input: TokenStream) -> TokenStream {
+    let def = match parse_bin_def(input, Some("SerBin")) {Ok(def) => def, Err(e) => return e};
     let mut tb = TokenBuilder::new();
-    
-    parser.eat_ident("pub");
-    if parser.eat_ident("struct"){
-        if let Some(name) = parser.eat_any_ident(){
-            let generic = parser.eat_generic();
-            let types = parser.eat_all_types();
-            let where_clause = parser.eat_where_clause(Some("DeBin"));
 
-            tb.add("impl").stream(generic.clone());
-            tb.add("DeBin for").ident(&name).stream(generic).stream(where_clause);
-            tb.add("{ fn de_bin ( o : & mut usize , d : & [ u8 ] )");
-            tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeBinErr > { ");
-            tb.add("std :: result :: Result :: Ok ( Self");
+    tb.add("impl").stream(def.generic.clone());
+    tb.add("SerBin for").ident(&def.name).stream(def.generic.clone()).stream(def.where_clause.clone());
+    tb.add("{");
+    if def.container.tagged {
+        // tagged values need their lengths up front, so writers use the buffered default
+        if let Err(e) = ser_bin_tagged(&mut tb, &def) {return e}
+    }
+    else {
+        match &def.ty {
+            BinType::Struct(kind) => {
+                ser_bin_struct(&mut tb, &SER_BIN_VEC, kind);
+                ser_bin_struct(&mut tb, &SER_BIN_WRITER, kind);
+            }
+            BinType::Enum(variants) => {
+                ser_bin_enum(&mut tb, &SER_BIN_VEC, variants);
+                ser_bin_enum(&mut tb, &SER_BIN_WRITER, variants);
+            }
+        }
+    }
+    tb.add("} ;");
+    tb.end()
+}
 
-            if let Some(types) = types{
-                tb.add("(");
-                for _ in 0..types.len(){
-                     tb.add("DeBin :: de_bin ( o , d ) ? ,");
-                }
-                tb.add(")");
+pub fn derive_de_bin_impl(input: TokenStream) -> TokenStream {
+    derive_de_bin_impl_inner(input, false)
+}
+
+pub fn derive_de_bin_ref_impl(input: TokenStream) -> TokenStream {
+    derive_de_bin_impl_inner(input, true)
+}
+
+// DeBinRef impls are generated for the first lifetime of the type, so fields can borrow from the input buffer
+fn de_bin_impl_header(tb: &mut TokenBuilder, def: &BinDef, borrowed: bool) -> Result<(), TokenStream> {
+    tb.add("impl").stream(def.generic.clone());
+    if borrowed {
+        let lifetime = match first_lifetime(&def.generic) {
+            Some(lifetime) => lifetime,
+            None => return Err(error("DeBinRef needs a type with a lifetime parameter"))
+        };
+        tb.add("DeBinRef <").lifetime(&lifetime).add("> for").ident(&def.name);
+        tb.stream(def.generic.clone()).stream(def.where_clause.clone());
+        tb.add("{ fn de_bin_ref ( o : & mut usize , d : &").lifetime(&lifetime).add("[ u8 ] )");
+    }
+    else {
+        tb.add("DeBin for").ident(&def.name).stream(def.generic.clone()).stream(def.where_clause.clone());
+        tb.add("{ fn de_bin ( o : & mut usize , d : & [ u8 ] )");
+    }
+    tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeBinErr > {");
+    Ok(())
+}
+
+// reads the fields of a struct or variant in order, the constructor path is already written
+fn de_bin_fields(tb: &mut TokenBuilder, kind: &BinVariant, de_call: &str) {
+    match kind {
+        BinVariant::Unit => (),
+        BinVariant::Tuple(count) => {
+            tb.add("(");
+            for _ in 0..*count {
+                tb.add(de_call);
             }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                tb.add("{");
-                for field in fields{
-                    tb.ident(&field.name).add(": DeBin :: de_bin ( o , d ) ? ,");
+            tb.add(")");
+        }
+        BinVariant::Named(fields) => {
+            tb.add("{");
+            for field in fields {
+                tb.ident(&field.name).add(":");
+                if field.attrs.skip {
+                    tb.stream(field.default_value(&ContainerAttrs::default())).add(",");
+                }
+                else {
+                    tb.add(de_call);
                 }
-                tb.add("}");
-            }
-            else{
-                return parser.unexpected()
             }
-            tb.add(") } } ;"); 
-            return tb.end();
+            tb.add("}");
         }
     }
-    else if parser.eat_ident("enum"){
-        if let Some(name) = parser.eat_any_ident(){
-            let generic = parser.eat_generic();
-            let where_clause = parser.eat_where_clause(Some("DeBin"));
-            
-            tb.add("impl").stream(generic.clone());
-            tb.add("DeBin for").ident(&name).stream(generic).stream(where_clause);
-            tb.add("{ fn de_bin ( o : & mut usize , d : & [ u8 ] )");
-            tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeBinErr > {");
+}
+
+fn derive_de_bin_impl_inner(input: TokenStream, borrowed: bool) -> TokenStream {
+    let de_fn = if borrowed {"DeBinRef :: de_bin_ref"} else {"DeBin :: de_bin"};
+    let de_call = format!("{} ( o , d ) ? ,", de_fn);
+    let def = match parse_bin_def(input, if borrowed {None} else {Some("DeBin")}) {Ok(def) => def, Err(e) => return e};
+    let mut tb = TokenBuilder::new();
+
+    if let Err(e) = de_bin_impl_header(&mut tb, &def, borrowed) {return e}
+    if def.container.tagged {
+        if let Err(e) = de_bin_tagged(&mut tb, &def, de_fn) {return e}
+        tb.add("} } ;");
+        return tb.end();
+    }
+    match &def.ty {
+        BinType::Struct(kind) => {
+            tb.add("std :: result :: Result :: Ok ( Self");
+            de_bin_fields(&mut tb, kind, &de_call);
+            tb.add(")");
+        }
+        BinType::Enum(variants) => {
             tb.add("let id : u16 = DeBin :: de_bin ( o , d ) ? ;");
             tb.add("match id {");
-            
-            if !parser.open_brace(){
-                return parser.unexpected()
+            for (index, variant) in variants.iter().enumerate() {
+                tb.suf_u16(index as u16).add("=> {");
+                tb.add("std :: result :: Result :: Ok ( Self ::").ident(&variant.name);
+                de_bin_fields(&mut tb, &variant.kind, &de_call);
+                tb.add(") }");
             }
-            let mut index = 0;
-            while !parser.eat_eot(){
-                // parse ident
-                if let Some(variant) = parser.eat_any_ident(){
-                    tb.suf_u16(index as u16).add("=> {");
-                    tb.add("std :: result :: Result :: Ok ( Self ::");
-                    if let Some(types) = parser.eat_all_types(){
-                        tb.ident(&variant).add("(");
-                        for _ in 0..types.len(){
-                            tb.add("DeBin :: de_bin ( o , d ) ? ,");
-                        }
-                        tb.add(")");
-                    }
-                    else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
-                        tb.ident(&variant).add("{");
-                        for field in fields.iter(){
-                            tb.ident(&field.name).add(": DeBin :: de_bin ( o , d ) ? ,");
-                        }
-                        tb.add("}");
-                    }
-                    else if parser.is_punct(',') || parser.is_eot(){ // bare variant
-                        tb.ident(&variant);
-                    }
-                    else{
-                        return parser.unexpected();
-                    }
-                    
-                    tb.add(") }");
-                    index += 1;
-                    parser.eat_punct(',');
-                }
-                else{
-                    return parser.unexpected()
-                }
-            } 
             tb.add("_ => std :: result :: Result :: Err ( makepad_microserde :: DeBinErr { o : * o , l :");
-            tb.unsuf_usize(1).add(", s : d . len ( ) , msg : ").string(&name).add(". to_string ( ) } )");
-            tb.add("} } } ;");
-            return tb.end();
+            tb.unsuf_usize(1).add(", s : d . len ( ) , msg : ").string(&def.name).add(". to_string ( ) } )");
+            tb.add("}");
+        }
+    }
+    tb.add("} } ;");
+    tb.end()
+}
+
+// #[nserde(tagged)] types, see ser_bin_tagged_header in serde_bin.rs for the layout.
+// fields and variants are identified by a hash of their name, tuple fields by their index
+
+fn tagged_id(name: &str) -> u32 {
+    // 32 bit FNV-1a
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in name.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+// variant ids in declaration order, two names that hash the same would decode as each other
+fn tagged_variant_ids(name: &str, variants: &[BinEnumVariant]) -> Result<Vec<u32>, TokenStream> {
+    let mut ret: Vec<u32> = Vec::new();
+    for variant in variants {
+        let ser_name = variant.attrs.rename.clone().unwrap_or(variant.name.clone());
+        let id = tagged_id(&ser_name);
+        if let Some(i) = ret.iter().position( | other | *other == id) {
+            return Err(error(&format!(
+                "nserde tagged variant {}::{} has the same id as {}::{}, rename it",
+                name,
+                variant.name,
+                name,
+                variants[i].name
+            )))
+        }
+        ret.push(id);
+    }
+    Ok(ret)
+}
+
+struct TaggedField {
+    id: u32,
+    label: String,
+    member: Option<String>,
+    skip: bool,
+    default: Option<TokenStream>,
+}
+
+fn tagged_fields(kind: &BinVariant, container: &ContainerAttrs) -> Result<Vec<TaggedField>, TokenStream> {
+    let ret: Vec<TaggedField> = match kind {
+        BinVariant::Unit => Vec::new(),
+        BinVariant::Tuple(count) => (0..*count).map( | i | TaggedField {
+            id: i as u32,
+            label: format!("{}", i),
+            member: None,
+            skip: false,
+            default: None
+        }).collect(),
+        BinVariant::Named(fields) => fields.iter().map( | field | TaggedField {
+            id: tagged_id(&field.ser_name),
+            label: field.ser_name.clone(),
+            member: Some(field.name.clone()),
+            skip: field.attrs.skip,
+            default: field.default_value(container)
+        }).collect()
+    };
+    for (i, a) in ret.iter().enumerate() {
+        if ret[0..i].iter().any( | b | b.id == a.id) {
+            return Err(error(&format!("nserde tagged field {} has the same id as another field, rename it", a.label)))
+        }
+    }
+    Ok(ret)
+}
+
+// writes the field count and then every field as an id, a length and the value
+fn ser_bin_tagged_fields(tb: &mut TokenBuilder, fields: &[TaggedField], values: &[TokenStream]) {
+    tb.suf_u32(fields.iter().filter( | f | !f.skip).count() as u32).add(". ser_bin ( s ) ;");
+    for (field, value) in fields.iter().filter( | f | !f.skip).zip(values) {
+        tb.add("{ let __at = makepad_microserde :: ser_bin_tagged_begin ( s ,").suf_u32(field.id).add(") ;");
+        tb.stream(Some(value.clone())).add(". ser_bin ( s ) ;");
+        tb.add("makepad_microserde :: ser_bin_tagged_end ( s , __at ) ; }");
+    }
+}
+
+fn ser_bin_tagged(tb: &mut TokenBuilder, def: &BinDef) -> Result<(), TokenStream> {
+    tb.add(SER_BIN_VEC.header);
+    tb.add("makepad_microserde :: ser_bin_tagged_header ( s ,").suf_u16(def.container.version).add(",");
+    tb.suf_u16(def.container.min_version).add(") ;");
+    match &def.ty {
+        BinType::Struct(kind) => {
+            let fields = tagged_fields(kind, &def.container) ?;
+            let values: Vec<TokenStream> = fields.iter().filter( | f | !f.skip).map( | field | {
+                let mut tb = TokenBuilder::new();
+                tb.add("( & self .");
+                if let Some(member) = &field.member {tb.ident(member);} else {tb.unsuf_usize(field.id as usize);}
+                tb.add(")");
+                tb.end()
+            }).collect();
+            ser_bin_tagged_fields(tb, &fields, &values);
+        }
+        BinType::Enum(variants) => {
+            let ids = tagged_variant_ids(&def.name, variants) ?;
+            tb.add("match self {");
+            for (variant, id) in variants.iter().zip(ids) {
+                let fields = tagged_fields(&variant.kind, &ContainerAttrs::default()) ?;
+                let count = ser_bin_variant_pattern(tb, variant);
+                let values: Vec<TokenStream> = (0..count).map( | i | {
+                    let mut tb = TokenBuilder::new();
+                    tb.ident(&format!("n{}", i));
+                    tb.end()
+                }).collect();
+                tb.add("=> { let __at = makepad_microserde :: ser_bin_tagged_begin ( s ,").suf_u32(id).add(") ;");
+                if let BinVariant::Unit = variant.kind {}
+                else {
+                    ser_bin_tagged_fields(tb, &fields, &values);
+                }
+                tb.add("makepad_microserde :: ser_bin_tagged_end ( s , __at ) ; }");
+            }
+            tb.add("}");
+        }
+    }
+    tb.add(SER_BIN_VEC.end);
+    Ok(())
+}
+
+// reads the tagged fields in any order skipping unknown ids, then builds path ( .. ) or path { .. }
+fn de_bin_tagged_fields(tb: &mut TokenBuilder, kind: &BinVariant, fields: &[TaggedField], name: &str, de_fn: &str, path: &str) {
+    if let BinVariant::Unit = kind {
+        tb.add(path);
+        return
+    }
+    tb.add("let __count : u32 = DeBin :: de_bin ( o , d ) ? ;");
+    for (i, field) in fields.iter().enumerate() {
+        if !field.skip {
+            tb.add("let mut").ident(&format!("__f{}", i)).add("= None ;");
+        }
+    }
+    tb.add("for _ in 0 .. __count {");
+    tb.add("let ( __id , __end ) = makepad_microserde :: de_bin_tagged_item ( o , d ) ? ;");
+    tb.add("match __id {");
+    for (i, field) in fields.iter().enumerate() {
+        if !field.skip {
+            tb.suf_u32(field.id).add("=>").ident(&format!("__f{}", i));
+            tb.add("= Some (").add(de_fn).add("( o , & d [ .. __end ] ) ? ) ,");
+        }
+    }
+    tb.add("_ => ( ) } * o = __end ; }");
+
+    let is_tuple = if let BinVariant::Tuple(_) = kind {true} else {false};
+    tb.add(path).add(if is_tuple {"("} else {"{"});
+    for (i, field) in fields.iter().enumerate() {
+        if let Some(member) = &field.member {
+            tb.ident(member).add(":");
+        }
+        if field.skip {
+            tb.stream(field.default.clone()).add(",");
+            continue
+        }
+        tb.add("match").ident(&format!("__f{}", i)).add("{ Some ( v ) => v , None =>");
+        if let Some(default) = &field.default {
+            tb.stream(Some(default.clone()));
+        }
+        else {
+            tb.add("return std :: result :: Result :: Err ( makepad_microserde :: DeBinErr { o : * o , l :").unsuf_usize(0);
+            tb.add(", s : d . len ( ) , msg :").string(&format!("missing field {} in {}", field.label, name)).add(". to_string ( ) } )");
+        }
+        tb.add("} ,");
+    }
+    tb.add(if is_tuple {")"} else {"}"});
+}
+
+fn de_bin_tagged(tb: &mut TokenBuilder, def: &BinDef, de_fn: &str) -> Result<(), TokenStream> {
+    tb.add("makepad_microserde :: de_bin_tagged_header ( o , d ,").string(&def.name).add(",");
+    tb.suf_u16(def.container.version).add(") ? ;");
+    match &def.ty {
+        BinType::Struct(kind) => {
+            let fields = tagged_fields(kind, &def.container) ?;
+            if def.container.default {
+                tb.add("let __default : Self = std :: default :: Default :: default ( ) ;");
+            }
+            tb.add("std :: result :: Result :: Ok ( {");
+            de_bin_tagged_fields(tb, kind, &fields, &def.name, de_fn, "Self");
+            tb.add("} )");
+        }
+        BinType::Enum(variants) => {
+            tb.add("let ( __id , __end ) = makepad_microserde :: de_bin_tagged_item ( o , d ) ? ;");
+            let ids = tagged_variant_ids(&def.name, variants) ?;
+            tb.add("let __ret = { let d = & d [ .. __end ] ; match __id {");
+            for (variant, id) in variants.iter().zip(ids) {
+                let fields = tagged_fields(&variant.kind, &ContainerAttrs::default()) ?;
+                tb.suf_u32(id).add("=> {");
+                de_bin_tagged_fields(tb, &variant.kind, &fields, &def.name, de_fn, &format!("Self :: {}", variant.name));
+                tb.add("}");
+            }
+            if let Some(other) = variants.iter().find( | v | v.attrs.other) {
+                tb.add("_ => Self ::").ident(&other.name);
+            }
+            else {
+                tb.add("_ => return std :: result :: Result :: Err ( makepad_microserde :: DeBinErr { o : * o , l :").unsuf_usize(0);
+                tb.add(", s : d . len ( ) , msg : format ! (").string(&format!("unknown variant {{}} in {}, it was written by a newer version", def.name));
+                tb.add(", __id ) } )");
+            }
+            tb.add("} } ; * o = __end ;");
+            tb.add("std :: result :: Result :: Ok ( __ret )");
         }
     }
-    return parser.unexpected()
+    Ok(())
 }
@@ -1,15 +1,106 @@
 use proc_macro::{TokenStream};
 use crate::macro_lib::*;
+use crate::nserde_attrs::*;
+
+fn ser_json_named_fields(tb: &mut TokenBuilder, fields: &[SerdeField], on_self: bool) {
+    let prefix = if on_self {"self ."} else {""};
+    tb.add("s . st_pre ( ) ;");
+    for field in fields {
+        if field.attrs.skip {
+            continue
+        }
+        if field.is_option {
+            tb.add("if let Some ( t ) = ");
+            if on_self {
+                tb.add("&");
+            }
+            tb.add(prefix).ident(&field.name).add("{");
+            tb.add("s . field ( d + 1 ,").string(&field.ser_name).add(") ;");
+            tb.add("t . ser_json ( d + 1 , s ) ; s . conl ( ) ; } ;");
+        }
+        else {
+            tb.add("s . field ( d + 1 ,").string(&field.ser_name).add(" ) ;");
+            tb.add(prefix).ident(&field.name).add(". ser_json ( d + 1 , s ) ; s . conl ( ) ;");
+        }
+    }
+    tb.add("s . st_post ( d ) ;");
+}
+
+// fields following a tag in the same object, each one prefixed by a comma
+fn ser_json_tagged_fields(tb: &mut TokenBuilder, fields: &[SerdeField]) {
+    for field in fields {
+        if field.attrs.skip {
+            continue
+        }
+        if field.is_option {
+            tb.add("if let Some ( t ) = ").ident(&field.name).add("{");
+            tb.add("s . conl ( ) ; s . field ( d + 1 ,").string(&field.ser_name).add(") ;");
+            tb.add("t . ser_json ( d + 1 , s ) ; } ;");
+        }
+        else {
+            tb.add("s . conl ( ) ; s . field ( d + 1 ,").string(&field.ser_name).add(" ) ;");
+            tb.ident(&field.name).add(". ser_json ( d + 1 , s ) ;");
+        }
+    }
+}
+
+// reads the keys of an already opened object into _field locals
+// tag is the key of an internally tagged enum, it was read up front and is passed over here
+fn de_json_read_fields(tb: &mut TokenBuilder, fields: &[SerdeField], tag: Option<&str>) {
+    for field in fields {
+        if !field.attrs.skip {
+            tb.add("let mut").ident(&format!("_{}", field.name)).add("= None ;");
+        }
+    }
+    tb.add("while let Some ( _ ) = s . next_str ( ) {");
+    tb.add("match s . strbuf . as_ref ( ) {");
+    for field in fields {
+        if field.attrs.skip {
+            continue
+        }
+        tb.string(&field.ser_name).add("=> { s . next_colon ( i ) ? ;");
+        tb.ident(&format!("_{}", field.name)).add("= Some ( DeJson :: de_json ( s , i )");
+        tb.add(". map_err ( | e | e . in_field (").string(&field.ser_name).add(") ) ? ) ; } ,");
+    }
+    if let Some(tag) = tag {
+        tb.string(tag).add("=> { s . next_colon ( i ) ? ; makepad_microserde :: JsonValue :: de_json ( s , i ) ? ; } ,");
+    }
+    tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
+    tb.add("} ; s . eat_comma_curly ( i ) ? ;");
+    tb.add("} ;");
+}
+
+fn de_json_construct_fields(tb: &mut TokenBuilder, fields: &[SerdeField], container: &ContainerAttrs) {
+    tb.add("{");
+    for field in fields {
+        tb.ident(&field.name).add(":");
+        let default = field.default_value(container);
+        if field.attrs.skip {
+            tb.stream(default).add(",");
+            continue
+        }
+        tb.add("if let Some ( t ) =").ident(&format!("_{}", field.name)).add("{ t } else {");
+        if let Some(default) = default {
+            tb.stream(Some(default));
+        }
+        else {
+            tb.add("return Err ( s . err_nf (").string(&field.ser_name).add(") )");
+        }
+        tb.add("} ,");
+    }
+    tb.add("}");
+}
 
 pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
 
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
+    let container = match container_attrs(&parser.eat_attributes()) {Ok(c) => c, Err(e) => return e};
+
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
-            
+
             let generic = parser.eat_generic();
             let types = parser.eat_all_types();
             let where_clause = parser.eat_where_clause(Some("SerJson"));
@@ -17,32 +108,32 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
             tb.add("impl").stream(generic.clone());
             tb.add("SerJson for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_json ( & self , d : usize , s : & mut makepad_microserde :: SerJsonState ) {");
-            
+
             if let Some(types) = types{
-                tb.add("s . out . push (").chr('[').add(") ;");
-                for i in 0..types.len(){
-                     tb.add("self .").unsuf_usize(i).add(". ser_json ( d , s ) ;");
-                     if i != types.len() - 1{
-                         tb.add("s . out . push (").chr(',').add(") ;");
-                     }
+                if let Err(e) = transparent_field_count(&container, types.len()) {return e}
+                if container.transparent {
+                    tb.add("self . 0 . ser_json ( d , s ) ;");
+                }
+                else {
+                    tb.add("s . out . push (").chr('[').add(") ;");
+                    for i in 0..types.len(){
+                         tb.add("self .").unsuf_usize(i).add(". ser_json ( d , s ) ;");
+                         if i != types.len() - 1{
+                             tb.add("s . out . push (").chr(',').add(") ;");
+                         }
+                    }
+                    tb.add("s . out . push (").chr(']').add(") ;");
                 }
-                tb.add("s . out . push (").chr(']').add(") ;");
             }
             else if let Some(fields) = parser.eat_all_struct_fields(){
-                tb.add("s . st_pre ( ) ;");
-                // named struct
-                for field in fields{
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) = ").add("& self .").ident(&field.name).add("{");
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
-                        tb.add("t . ser_json ( d + 1 , s ) ; s . conl ( ) ; } ;");
-                    }
-                    else{
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
-                        tb.add("self .").ident(&field.name).add(". ser_json ( d + 1 , s ) ; s . conl ( ) ;");
-                    }
+                let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                if let Err(e) = transparent_field_count(&container, fields.len()) {return e}
+                if container.transparent {
+                    tb.add("self .").ident(&fields[0].name).add(". ser_json ( d , s ) ;");
+                }
+                else {
+                    ser_json_named_fields(&mut tb, &fields, true);
                 }
-                tb.add("s . st_post ( d ) ;");
             }
             else{
                 return parser.unexpected()
@@ -61,25 +152,39 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
             tb.add("{ fn ser_json ( & self , d : usize , s : & mut makepad_microserde :: SerJsonState ) {");
             tb.add("s . out . push (").chr('{').add(") ;");
             tb.add("match self {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
 
             while !parser.eat_eot(){
+                let attrs = match variant_attrs(&parser.eat_attributes()) {Ok(a) => a, Err(e) => return e};
                 // parse ident
                 if let Some(variant) = parser.eat_any_ident(){
+                    let ser_variant = if let Some(rename) = &attrs.rename {rename.clone()} else {variant.clone()};
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("Self ::").ident(&variant).add("(");
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(",");
                         }
                         tb.add(") => {");
-                        tb.add("s . label (").string(&variant).add(") ;");
-                        tb.add("s . out . push (").chr(':').add(") ;");
+                        if let Some(tag) = &container.tag {
+                            if let Some(content) = &container.content {
+                                tb.add("s . field ( d + 1 ,").string(tag).add(") ;");
+                                tb.add("s . label (").string(&ser_variant).add(") ; s . conl ( ) ;");
+                                tb.add("s . field ( d + 1 ,").string(content).add(") ;");
+                            }
+                            else {
+                                return error("nserde tag without content does not support tuple variants")
+                            }
+                        }
+                        else {
+                            tb.add("s . label (").string(&ser_variant).add(") ;");
+                            tb.add("s . out . push (").chr(':').add(") ;");
+                        }
                         tb.add("s . out . push (").chr('[').add(") ;");
-                        
+
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(". ser_json ( d , s ) ;");
                             if i != types.len() - 1{
@@ -90,33 +195,40 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
                         tb.add("}");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
                         tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields.iter(){
+                        for field in fields.iter().filter(|f| !f.attrs.skip){
                             tb.ident(&field.name).add(",");
                         }
-                        tb.add("} => {");
-                        
-                        tb.add("s . label (").string(&variant).add(") ;");
-                        tb.add("s . out . push (").chr(':').add(") ;");
-                        tb.add("s . st_pre ( ) ;");
-                        
-                        for field in fields{
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if let Some ( t ) = ").ident(&field.name).add("{");
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
-                                tb.add("t . ser_json ( d + 1 , s ) ; s . conl ( ) ; } ;");
+                        tb.add(".. } => {");
+                        if let Some(tag) = &container.tag {
+                            tb.add("s . field ( d + 1 ,").string(tag).add(") ;");
+                            tb.add("s . label (").string(&ser_variant).add(") ;");
+                            if let Some(content) = &container.content {
+                                tb.add("s . conl ( ) ; s . field ( d + 1 ,").string(content).add(") ;");
+                                ser_json_named_fields(&mut tb, &fields, false);
                             }
-                            else{
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
-                                tb.ident(&field.name).add(". ser_json ( d + 1 , s ) ; s . conl ( ) ;");
+                            else {
+                                ser_json_tagged_fields(&mut tb, &fields);
                             }
                         }
-                        tb.add("s . st_post ( d ) ; }");
+                        else {
+                            tb.add("s . label (").string(&ser_variant).add(") ;");
+                            tb.add("s . out . push (").chr(':').add(") ;");
+                            ser_json_named_fields(&mut tb, &fields, false);
+                        }
+                        tb.add("}");
                     }
                     else if parser.is_punct(',') || parser.is_eot(){ // bare variant
                         tb.add("Self ::").ident(&variant).add("=> {");
-                        tb.add("s . label (").string(&variant).add(") ;");
-                        tb.add("s . out . push_str (").string(":[]").add(") ; }");
+                        if let Some(tag) = &container.tag {
+                            tb.add("s . field ( d + 1 ,").string(tag).add(") ;");
+                            tb.add("s . label (").string(&ser_variant).add(") ; }");
+                        }
+                        else {
+                            tb.add("s . label (").string(&ser_variant).add(") ;");
+                            tb.add("s . out . push_str (").string(":[]").add(") ; }");
+                        }
                     }
                     else{
                         return parser.unexpected();
@@ -139,7 +251,8 @@ pub fn derive_ser_json_impl(input: TokenStream) -> TokenStream {
 pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
+    let container = match container_attrs(&parser.eat_attributes()) {Ok(c) => c, Err(e) => return e};
+
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
@@ -153,55 +266,51 @@ pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
             tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeJsonErr > { ");
 
             if let Some(types) = types{
-                tb.add("s . block_open ( i ) ? ;");
-                tb.add("let r = Self");
-                tb.add("(");
-                for _ in 0..types.len(){
-                     tb.add("{ let r = DeJson :: de_json ( s , i ) ? ; s . eat_comma_block ( i ) ? ; r } ,");
+                if let Err(e) = transparent_field_count(&container, types.len()) {return e}
+                if container.transparent {
+                    tb.add("std :: result :: Result :: Ok ( Self ( DeJson :: de_json ( s , i ) ? ) )");
                 }
-                tb.add(") ;");
-                tb.add("s . block_close ( i ) ? ;");
-                tb.add("std :: result :: Result :: Ok ( r )");
-            }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                tb.add("s . curly_open ( i ) ? ;");
-                for field in &fields{
-                    tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
+                else {
+                    tb.add("s . block_open ( i ) ? ;");
+                    tb.add("let r = Self");
+                    tb.add("(");
+                    for i in 0..types.len(){
+                         tb.add("{ let r = DeJson :: de_json ( s , i ) . map_err ( | e | e . in_index (").unsuf_usize(i).add(") ) ? ;");
+                         tb.add("s . eat_comma_block ( i ) ? ; r } ,");
+                    }
+                    tb.add(") ;");
+                    tb.add("s . block_close ( i ) ? ;");
+                    tb.add("std :: result :: Result :: Ok ( r )");
                 }
-                tb.add("while let Some ( _ ) = s . next_str ( ) {");
-                tb.add("match s . strbuf . as_ref ( ) {");
-                for field in &fields{
-                    tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                    tb.ident(&format!("_{}",field.name)).add("= Some ( DeJson :: de_json ( s , i ) ? ) ; } ,");
+            }
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                if let Err(e) = transparent_field_count(&container, fields.len()) {return e}
+                if container.transparent {
+                    tb.add("std :: result :: Result :: Ok ( Self {").ident(&fields[0].name);
+                    tb.add(": DeJson :: de_json ( s , i ) ? } )");
                 }
-                tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
-                tb.add("} ; s . eat_comma_curly ( i ) ? ;");
-                tb.add("} ; s . curly_close ( i ) ? ;");
-                
-                tb.add("std :: result :: Result :: Ok ( Self {");
-                for field in fields{
-                    tb.ident(&field.name).add(":");
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { None } ,");
-                    }
-                    else{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { return Err ( s . err_nf (");
-                        tb.string(&field.name).add(") ) } ,");
+                else {
+                    if container.default {
+                        tb.add("let __default : Self = std :: default :: Default :: default ( ) ;");
                     }
+                    tb.add("s . curly_open ( i ) ? ;");
+                    de_json_read_fields(&mut tb, &fields, None);
+                    tb.add("s . curly_close ( i ) ? ;");
+                    tb.add("std :: result :: Result :: Ok ( Self");
+                    de_json_construct_fields(&mut tb, &fields, &container);
+                    tb.add(")");
                 }
-                tb.add("} )");
             }
             else{
                 return parser.unexpected()
             }
-            tb.add("} } ;"); 
+            tb.add("} } ;");
             return tb.end();
         }
     }
     else if parser.eat_ident("enum"){
-        
+
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let where_clause = parser.eat_where_clause(Some("DeJson"));
@@ -211,72 +320,101 @@ pub fn derive_de_json_impl(input: TokenStream) -> TokenStream {
             tb.add("{ fn de_json ( s : & mut makepad_microserde :: DeJsonState , i : & mut std :: str :: Chars )");
             tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeJsonErr > { ");
             tb.add("s . curly_open ( i ) ? ;");
-            tb.add("let _ = s . string ( i ) ? ;");
-            tb.add("s . colon ( i ) ? ;");
-            tb.add("let r = std :: result :: Result :: Ok ( match s . strbuf . as_ref ( ) {");
-            
+            let inline_tag = if container.content.is_none() {container.tag.as_deref()} else {None};
+            if let Some(tag) = inline_tag {
+                // the fields sit next to the tag, which can come anywhere in the object
+                tb.add("let variant = s . peek_key_string ( i ,").string(tag).add(") ? ;");
+                tb.add("let r = std :: result :: Result :: Ok ( match variant . as_ref ( ) {");
+            }
+            else if let Some(tag) = &container.tag {
+                // the tag has to come before the content
+                tb.add("if s . next_str ( ) . is_none ( ) || s . strbuf !=").string(tag).add("{");
+                tb.add("return std :: result :: Result :: Err ( s . err_nf (").string(tag).add(") ) }");
+                tb.add("s . next_colon ( i ) ? ;");
+                tb.add("let variant = s . as_string ( ) ? ; s . next_tok ( i ) ? ; s . eat_comma_curly ( i ) ? ;");
+                tb.add("let r = std :: result :: Result :: Ok ( match variant . as_ref ( ) {");
+            }
+            else {
+                tb.add("let _ = s . string ( i ) ? ;");
+                tb.add("s . colon ( i ) ? ;");
+                tb.add("let r = std :: result :: Result :: Ok ( match s . strbuf . as_ref ( ) {");
+            }
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
             while !parser.eat_eot(){
+                let attrs = match variant_attrs(&parser.eat_attributes()) {Ok(a) => a, Err(e) => return e};
                 // parse ident
                 if let Some(variant) = parser.eat_any_ident(){
-                    tb.string(&variant).add("=> {");
+                    let ser_variant = if let Some(rename) = &attrs.rename {rename.clone()} else {variant.clone()};
+                    tb.string(&ser_variant).add("=> {");
+                    if let Some(content) = &container.content {
+                        if !parser.is_punct(',') && !parser.is_eot() {
+                            tb.add("if s . next_str ( ) . is_none ( ) || s . strbuf !=").string(content).add("{");
+                            tb.add("return std :: result :: Result :: Err ( s . err_nf (").string(content).add(") ) }");
+                            tb.add("s . next_colon ( i ) ? ;");
+                        }
+                    }
                     if let Some(types) = parser.eat_all_types(){
-                        
+                        if container.tag.is_some() && container.content.is_none() {
+                            return error("nserde tag without content does not support tuple variants")
+                        }
                         tb.add("s . block_open ( i ) ? ;");
                         tb.add("let r = Self ::").ident(&variant).add("(");
-                        for _ in 0..types.len(){
-                            tb.add("{ let r = DeJson :: de_json ( s , i ) ? ; s . eat_comma_block ( i ) ? ; r } ,");
+                        for i in 0..types.len(){
+                            tb.add("{ let r = DeJson :: de_json ( s , i ) . map_err ( | e | e . in_index (").unsuf_usize(i).add(") ) ? ;");
+                            tb.add("s . eat_comma_block ( i ) ? ; r } ,");
                         }
                         tb.add(") ;");
-                        tb.add("s . block_close ( i ) ? ; r");
+                        tb.add("s . block_close ( i ) ? ;");
+                        if container.content.is_some() {
+                            tb.add("s . eat_comma_curly ( i ) ? ;");
+                        }
+                        tb.add("r");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
-                        tb.add("s . curly_open ( i ) ? ;");
-                        for field in &fields{
-                            tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
+                        let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                        if inline_tag.is_some() {
+                            de_json_read_fields(&mut tb, &fields, inline_tag);
                         }
-                        tb.add("while let Some ( _ ) = s . next_str ( ) {");
-                        tb.add("match s . strbuf . as_ref ( ) {");
-                        for field in &fields{
-                            tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                            tb.ident(&format!("_{}",field.name)).add("= Some ( DeJson :: de_json ( s , i ) ? ) ; } ,");
-                        }
-                        tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
-                        tb.add("} s . eat_comma_curly ( i ) ? ;");
-                        tb.add("} s . curly_close ( i ) ? ;");
-                        
-                        tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields{
-                            tb.ident(&field.name).add(":");
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { None } ,");
-                            }
-                            else{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { return Err ( s . err_nf (");
-                                tb.string(&field.name).add(") ) } ,");
+                        else {
+                            tb.add("s . curly_open ( i ) ? ;");
+                            de_json_read_fields(&mut tb, &fields, None);
+                            tb.add("s . curly_close ( i ) ? ;");
+                            if container.content.is_some() {
+                                tb.add("s . eat_comma_curly ( i ) ? ;");
                             }
                         }
-                        tb.add("}");
+                        tb.add("Self ::").ident(&variant);
+                        de_json_construct_fields(&mut tb, &fields, &ContainerAttrs::default());
                     }
                     else if parser.is_punct(',') || parser.is_eot(){ // bare variant
-                        tb.add("s . block_open ( i ) ? ; s . block_close ( i ) ? ; Self ::").ident(&variant);
+                        if container.tag.is_none() {
+                            tb.add("s . block_open ( i ) ? ; s . block_close ( i ) ? ;");
+                        }
+                        else if inline_tag.is_some() {
+                            de_json_read_fields(&mut tb, &[], inline_tag);
+                        }
+                        tb.add("Self ::").ident(&variant);
                     }
                     else{
                         return parser.unexpected();
                     }
-                    
+
                     tb.add("}");
                     parser.eat_punct(',');
                 }
                 else{
                     return parser.unexpected()
                 }
-            } 
-            tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
+            }
+            if container.tag.is_some() {
+                tb.add("_ => return std :: result :: Result :: Err ( s . err_enum ( & variant ) )");
+            }
+            else {
+                tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
+            }
             tb.add("} ) ; s . curly_close ( i ) ? ; r } }");
             return tb.end();
         }
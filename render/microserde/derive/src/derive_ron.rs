@@ -1,16 +1,85 @@
- 
 use proc_macro::{TokenStream};
 use crate::macro_lib::*;
+use crate::nserde_attrs::*;
+
+fn ser_ron_named_fields(tb: &mut TokenBuilder, fields: &[SerdeField], on_self: bool) {
+    let prefix = if on_self {"self ."} else {""};
+    tb.add("s . st_pre ( ) ;");
+    for field in fields {
+        if field.attrs.skip {
+            continue
+        }
+        if field.is_option {
+            tb.add("if let Some ( t ) = ");
+            if on_self {
+                tb.add("&");
+            }
+            tb.add(prefix).ident(&field.name).add("{");
+            tb.add("s . field ( d + 1 ,").string(&field.ser_name).add(") ;");
+            tb.add("t . ser_ron ( d + 1 , s ) ; s . conl ( ) ; } ;");
+        }
+        else {
+            tb.add("s . field ( d + 1 ,").string(&field.ser_name).add(" ) ;");
+            tb.add(prefix).ident(&field.name).add(". ser_ron ( d + 1 , s ) ; s . conl ( ) ;");
+        }
+    }
+    tb.add("s . st_post ( d ) ;");
+}
+
+// parses ( key: value, ... ) into _field locals
+fn de_ron_read_fields(tb: &mut TokenBuilder, fields: &[SerdeField]) {
+    tb.add("s . paren_open ( i ) ? ;");
+    for field in fields {
+        if !field.attrs.skip {
+            tb.add("let mut").ident(&format!("_{}", field.name)).add("= None ;");
+        }
+    }
+    tb.add("while let Some ( _ ) = s . next_ident ( ) {");
+    tb.add("match s . identbuf . as_ref ( ) {");
+    for field in fields {
+        if field.attrs.skip {
+            continue
+        }
+        tb.string(&field.ser_name).add("=> { s . next_colon ( i ) ? ;");
+        tb.ident(&format!("_{}", field.name)).add("= Some ( DeRon :: de_ron ( s , i ) ? ) ; } ,");
+    }
+    tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . identbuf ) )");
+    tb.add("} ; s . eat_comma_paren ( i ) ? ;");
+    tb.add("} ; s . paren_close ( i ) ? ;");
+}
+
+fn de_ron_construct_fields(tb: &mut TokenBuilder, fields: &[SerdeField], container: &ContainerAttrs) {
+    tb.add("{");
+    for field in fields {
+        tb.ident(&field.name).add(":");
+        let default = field.default_value(container);
+        if field.attrs.skip {
+            tb.stream(default).add(",");
+            continue
+        }
+        tb.add("if let Some ( t ) =").ident(&format!("_{}", field.name)).add("{ t } else {");
+        if let Some(default) = default {
+            tb.stream(Some(default));
+        }
+        else {
+            tb.add("return Err ( s . err_nf (").string(&field.ser_name).add(") )");
+        }
+        tb.add("} ,");
+    }
+    tb.add("}");
+}
 
 pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
 
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
-    
+    let container = match container_attrs(&parser.eat_attributes()) {Ok(c) => c, Err(e) => return e};
+    if let Err(e) = json_only_attrs(&container, "SerRon and DeRon") {return e}
+
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
-            
+
             let generic = parser.eat_generic();
             let types = parser.eat_all_types();
             let where_clause = parser.eat_where_clause(Some("SerRon"));
@@ -18,32 +87,32 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("impl").stream(generic.clone());
             tb.add("SerRon for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_ron ( & self , d : usize , s : & mut makepad_microserde :: SerRonState ) {");
-            
+
             if let Some(types) = types{
-                tb.add("s . out . push (").chr('(').add(") ;");
-                for i in 0..types.len(){
-                     tb.add("self .").unsuf_usize(i).add(". ser_ron ( d , s ) ;");
-                     if i != types.len() - 1{
-                         tb.add("s . out . push_str (").string(", ").add(") ;");
-                     }
+                if let Err(e) = transparent_field_count(&container, types.len()) {return e}
+                if container.transparent {
+                    tb.add("self . 0 . ser_ron ( d , s ) ;");
                 }
-                tb.add("s . out . push (").chr(')').add(") ;");
-            }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                tb.add("s . st_pre ( ) ;");
-                // named struct
-                for field in fields{
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) = ").add("& self .").ident(&field.name).add("{");
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
-                        tb.add("t . ser_ron ( d + 1 , s ) ; s . conl ( ) ; } ;");
-                    }
-                    else{
-                        tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
-                        tb.add("self .").ident(&field.name).add(". ser_ron ( d + 1 , s ) ; s . conl ( ) ;");
+                else {
+                    tb.add("s . out . push (").chr('(').add(") ;");
+                    for i in 0..types.len(){
+                         tb.add("self .").unsuf_usize(i).add(". ser_ron ( d , s ) ;");
+                         if i != types.len() - 1{
+                             tb.add("s . out . push_str (").string(", ").add(") ;");
+                         }
                     }
+                    tb.add("s . out . push (").chr(')').add(") ;");
+                }
+            }
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                if let Err(e) = transparent_field_count(&container, fields.len()) {return e}
+                if container.transparent {
+                    tb.add("self .").ident(&fields[0].name).add(". ser_ron ( d , s ) ;");
+                }
+                else {
+                    ser_ron_named_fields(&mut tb, &fields, true);
                 }
-                tb.add("s . st_post ( d ) ;");
             }
             else{
                 return parser.unexpected()
@@ -61,24 +130,26 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("SerRon for").ident(&name).stream(generic).stream(where_clause);
             tb.add("{ fn ser_ron ( & self , d : usize , s : & mut makepad_microserde :: SerRonState ) {");
             tb.add("match self {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
 
             while !parser.eat_eot(){
+                let attrs = match variant_attrs(&parser.eat_attributes()) {Ok(a) => a, Err(e) => return e};
                 // parse ident
                 if let Some(variant) = parser.eat_any_ident(){
+                    let ser_variant = if let Some(rename) = &attrs.rename {rename.clone()} else {variant.clone()};
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("Self ::").ident(&variant).add("(");
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(",");
                         }
                         tb.add(") => {");
-                        tb.add("s . out . push_str (").string(&variant).add(") ;");
+                        tb.add("s . out . push_str (").string(&ser_variant).add(") ;");
                         tb.add("s . out . push (").chr('(').add(") ;");
-                        
+
                         for i in 0..types.len(){
                             tb.ident(&format!("n{}", i)).add(". ser_ron ( d , s ) ;");
                             if i != types.len() - 1{
@@ -89,31 +160,19 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
                         tb.add("}");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
                         tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields.iter(){
+                        for field in fields.iter().filter(|f| !f.attrs.skip){
                             tb.ident(&field.name).add(",");
                         }
-                        tb.add("} => {");
-                        
-                        tb.add("s . out . push_str (").string(&variant).add(") ;");
-                        tb.add("s . st_pre ( ) ;");
-                        
-                        for field in fields{
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if ").ident(&field.name).add(". is_some ( ) {");
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(") ;");
-                                tb.ident(&field.name).add(" . ser_ron ( d + 1 , s ) ; s . conl ( ) ; } ;");
-                            }
-                            else{
-                                tb.add("s . field ( d + 1 ,").string(&field.name).add(" ) ;");
-                                tb.ident(&field.name).add(". ser_ron ( d + 1 , s ) ; s . conl ( ) ;");
-                            }
-                        }
-                        tb.add("s . st_post ( d ) ; }");
+                        tb.add(".. } => {");
+                        tb.add("s . out . push_str (").string(&ser_variant).add(") ;");
+                        ser_ron_named_fields(&mut tb, &fields, false);
+                        tb.add("}");
                     }
                     else if parser.is_punct(',') || parser.is_eot(){ // bare variant
                         tb.add("Self ::").ident(&variant).add("=> {");
-                        tb.add("s . out . push_str (").string(&variant).add(") ; }");
+                        tb.add("s . out . push_str (").string(&ser_variant).add(") ; }");
                     }
                     else{
                         return parser.unexpected();
@@ -135,6 +194,9 @@ pub fn derive_ser_ron_impl(input: TokenStream) -> TokenStream {
 pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
     let mut parser = TokenParser::new(input);
     let mut tb = TokenBuilder::new();
+    let container = match container_attrs(&parser.eat_attributes()) {Ok(c) => c, Err(e) => return e};
+    if let Err(e) = json_only_attrs(&container, "SerRon and DeRon") {return e}
+
     parser.eat_ident("pub");
     if parser.eat_ident("struct"){
         if let Some(name) = parser.eat_any_ident(){
@@ -148,55 +210,48 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeRonErr > { ");
 
             if let Some(types) = types{
-                tb.add("s . paren_open ( i ) ? ;");
-                tb.add("let r = Self");
-                tb.add("(");
-                for _ in 0..types.len(){
-                     tb.add("{ let r = DeRon :: de_ron ( s , i ) ? ; s . eat_comma_paren ( i ) ? ; r } ,");
+                if let Err(e) = transparent_field_count(&container, types.len()) {return e}
+                if container.transparent {
+                    tb.add("std :: result :: Result :: Ok ( Self ( DeRon :: de_ron ( s , i ) ? ) )");
                 }
-                tb.add(") ;");
-                tb.add("s . paren_close ( i ) ? ;");
-                tb.add("std :: result :: Result :: Ok ( r ) ");
-            }
-            else if let Some(fields) = parser.eat_all_struct_fields(){ 
-                tb.add("s . paren_open ( i ) ? ;");
-                for field in &fields{
-                    tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
+                else {
+                    tb.add("s . paren_open ( i ) ? ;");
+                    tb.add("let r = Self");
+                    tb.add("(");
+                    for _ in 0..types.len(){
+                         tb.add("{ let r = DeRon :: de_ron ( s , i ) ? ; s . eat_comma_paren ( i ) ? ; r } ,");
+                    }
+                    tb.add(") ;");
+                    tb.add("s . paren_close ( i ) ? ;");
+                    tb.add("std :: result :: Result :: Ok ( r ) ");
                 }
-                tb.add("while let Some ( _ ) = s . next_ident ( ) {");
-                tb.add("match s . identbuf . as_ref ( ) {");
-                for field in &fields{
-                    tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                    tb.ident(&format!("_{}",field.name)).add("= Some ( DeRon :: de_ron ( s , i ) ? ) ; } ,");
+            }
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                if let Err(e) = transparent_field_count(&container, fields.len()) {return e}
+                if container.transparent {
+                    tb.add("std :: result :: Result :: Ok ( Self {").ident(&fields[0].name);
+                    tb.add(": DeRon :: de_ron ( s , i ) ? } )");
                 }
-                tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . identbuf ) )");
-                tb.add("} ; s . eat_comma_paren ( i ) ? ;");
-                tb.add("} ; s . paren_close ( i ) ? ;");
-                
-                tb.add("std :: result :: Result :: Ok ( Self {");
-                for field in fields{
-                    tb.ident(&field.name).add(":");
-                    if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { None } ,");
-                    }
-                    else{
-                        tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                        tb.add("{ t } else { return Err ( s . err_nf (");
-                        tb.string(&field.name).add(") ) } ,");
+                else {
+                    if container.default {
+                        tb.add("let __default : Self = std :: default :: Default :: default ( ) ;");
                     }
+                    de_ron_read_fields(&mut tb, &fields);
+                    tb.add("std :: result :: Result :: Ok ( Self");
+                    de_ron_construct_fields(&mut tb, &fields, &container);
+                    tb.add(")");
                 }
-                tb.add("} )");
             }
             else{
                 return parser.unexpected()
             }
-            tb.add("} } ;"); 
+            tb.add("} } ;");
             return tb.end();
         }
     }
     else if parser.eat_ident("enum"){
-         
+
         if let Some(name) = parser.eat_any_ident(){
             let generic = parser.eat_generic();
             let where_clause = parser.eat_where_clause(Some("DeRon"));
@@ -207,16 +262,18 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
             tb.add("-> std :: result :: Result < Self , makepad_microserde :: DeRonErr > { ");
             tb.add("s . ident ( i ) ? ;");
             tb.add("std :: result :: Result :: Ok ( match s . identbuf . as_ref ( ) {");
-            
+
             if !parser.open_brace(){
                 return parser.unexpected()
             }
             while !parser.eat_eot(){
+                let attrs = match variant_attrs(&parser.eat_attributes()) {Ok(a) => a, Err(e) => return e};
                 // parse ident
                 if let Some(variant) = parser.eat_any_ident(){
-                    tb.string(&variant).add("=> {");
+                    let ser_variant = if let Some(rename) = &attrs.rename {rename.clone()} else {variant.clone()};
+                    tb.string(&ser_variant).add("=> {");
                     if let Some(types) = parser.eat_all_types(){
-                        
+
                         tb.add("s . paren_open ( i ) ? ;");
                         tb.add("let r = Self ::").ident(&variant).add("(");
                         for _ in 0..types.len(){
@@ -226,34 +283,10 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
                         tb.add("s . paren_close ( i ) ? ; r");
                     }
                     else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
-                        tb.add("s . paren_open ( i ) ? ;");
-                        for field in &fields{
-                            tb.add("let mut").ident(&format!("_{}",field.name)).add("= None ;");
-                        }
-                        tb.add("while let Some ( _ ) = s . next_ident ( ) {");
-                        tb.add("match s . identbuf . as_ref ( ) {");
-                        for field in &fields{
-                            tb.string(&field.name).add("=> { s . next_colon ( i ) ? ;");
-                            tb.ident(&format!("_{}",field.name)).add("= Some ( DeRon :: de_ron ( s , i ) ? ) ; } ,");
-                        }
-                        tb.add("_ => return std :: result :: Result :: Err ( s . err_exp ( & s . strbuf ) )");
-                        tb.add("} ; s . eat_comma_paren ( i ) ? ;");
-                        tb.add("} ; s . paren_close ( i ) ? ;");
-                        
-                        tb.add("Self ::").ident(&variant).add("{");
-                        for field in fields{
-                            tb.ident(&field.name).add(":");
-                            if field.ty.into_iter().next().unwrap().to_string() == "Option"{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { None } ,");
-                            }
-                            else{
-                                tb.add("if let Some ( t ) =").ident(&format!("_{}",field.name));
-                                tb.add("{ t } else { return Err ( s . err_nf (");
-                                tb.string(&field.name).add(") ) } ,");
-                            }
-                        }
-                        tb.add("}");
+                        let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                        de_ron_read_fields(&mut tb, &fields);
+                        tb.add("Self ::").ident(&variant);
+                        de_ron_construct_fields(&mut tb, &fields, &ContainerAttrs::default());
                     }
                     else if parser.is_punct(',') || parser.is_eot(){ // bare variant
                         tb.add("Self ::").ident(&variant);
@@ -261,14 +294,14 @@ pub fn derive_de_ron_impl(input: TokenStream) -> TokenStream {
                     else{
                         return parser.unexpected();
                     }
-                    
+
                     tb.add("}");
                     parser.eat_punct(',');
                 }
                 else{
                     return parser.unexpected()
                 }
-            } 
+            }
             tb.add("_ => return std :: result :: Result :: Err ( s . err_enum ( & s . identbuf ) )");
             tb.add("} ) } }");
            return tb.end();
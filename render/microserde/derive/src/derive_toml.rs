@@ -0,0 +1,295 @@
+use proc_macro::{TokenStream};
+use crate::macro_lib::*;
+use crate::nserde_attrs::*;
+
+fn ser_toml_named_fields(tb: &mut TokenBuilder, fields: &[SerdeField], on_self: bool) {
+    let prefix = if on_self {"self ."} else {""};
+    tb.add("let mut t = Vec :: new ( ) ;");
+    for field in fields {
+        if field.attrs.skip {
+            continue
+        }
+        if field.is_option {
+            tb.add("if let Some ( v ) = ");
+            if on_self {
+                tb.add("&");
+            }
+            tb.add(prefix).ident(&field.name).add("{");
+            tb.add("t . push ( (").string(&field.ser_name).add(". to_string ( ) , v . ser_toml ( ) ) ) ; }");
+        }
+        else {
+            tb.add("t . push ( (").string(&field.ser_name).add(". to_string ( ) ,");
+            tb.add(prefix).ident(&field.name).add(". ser_toml ( ) ) ) ;");
+        }
+    }
+    tb.add("makepad_microserde :: Toml :: Table ( t )");
+}
+
+// expects the table in t, builds the fields of Self or a variant
+fn de_toml_named_fields(tb: &mut TokenBuilder, fields: &[SerdeField], container: &ContainerAttrs) {
+    tb.add("{");
+    for field in fields {
+        tb.ident(&field.name).add(":");
+        let default = field.default_value(container);
+        if field.attrs.skip {
+            tb.stream(default).add(",");
+            continue
+        }
+        tb.add("match t . get (").string(&field.ser_name).add(") {");
+        if field.is_option {
+            tb.add("Some ( v ) => Some ( DeToml :: de_toml ( v ) ? ) ,");
+        }
+        else {
+            tb.add("Some ( v ) => DeToml :: de_toml ( v ) ? ,");
+        }
+        tb.add("None => ");
+        if let Some(default) = default {
+            tb.stream(Some(default));
+        }
+        else {
+            tb.add("return Err ( makepad_microserde :: TomlErr :: err_nf (").string(&field.ser_name).add(") )");
+        }
+        tb.add("} ,");
+    }
+    tb.add("}");
+}
+
+fn de_toml_tuple(tb: &mut TokenBuilder, count: usize) {
+    tb.add("(");
+    for i in 0..count {
+        tb.add("DeToml :: de_toml ( & a [").unsuf_usize(i).add("] ) ? ,");
+    }
+    tb.add(")");
+}
+
+fn de_toml_array_len(tb: &mut TokenBuilder, count: usize) {
+    tb.add("let a = match t { makepad_microserde :: Toml :: Array ( a ) if a . len ( ) ==").unsuf_usize(count);
+    tb.add("=> a , _ => return Err ( makepad_microserde :: TomlErr :: err_type (").string(&format!("array of {}", count));
+    tb.add(", t ) ) } ;");
+}
+
+pub fn derive_ser_toml_impl(input: TokenStream) -> TokenStream {
+
+    let mut parser = TokenParser::new(input);
+    let mut tb = TokenBuilder::new();
+    let container = match container_attrs(&parser.eat_attributes()) {Ok(c) => c, Err(e) => return e};
+    if let Err(e) = json_only_attrs(&container, "SerToml and DeToml") {return e}
+
+    parser.eat_ident("pub");
+    if parser.eat_ident("struct"){
+        if let Some(name) = parser.eat_any_ident(){
+
+            let generic = parser.eat_generic();
+            let types = parser.eat_all_types();
+            let where_clause = parser.eat_where_clause(Some("SerToml"));
+
+            tb.add("impl").stream(generic.clone());
+            tb.add("SerToml for").ident(&name).stream(generic).stream(where_clause);
+            tb.add("{ fn ser_toml ( & self ) -> makepad_microserde :: Toml {");
+
+            if let Some(types) = types{
+                if let Err(e) = transparent_field_count(&container, types.len()) {return e}
+                if container.transparent {
+                    tb.add("self . 0 . ser_toml ( )");
+                }
+                else {
+                    tb.add("makepad_microserde :: Toml :: Array ( vec ! [");
+                    for i in 0..types.len(){
+                        tb.add("self .").unsuf_usize(i).add(". ser_toml ( ) ,");
+                    }
+                    tb.add("] )");
+                }
+            }
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                if let Err(e) = transparent_field_count(&container, fields.len()) {return e}
+                if container.transparent {
+                    tb.add("self .").ident(&fields[0].name).add(". ser_toml ( )");
+                }
+                else {
+                    ser_toml_named_fields(&mut tb, &fields, true);
+                }
+            }
+            else{
+                return parser.unexpected()
+            }
+            tb.add("} } ;");
+            return tb.end();
+        }
+    }
+    else if parser.eat_ident("enum"){
+        if let Some(name) = parser.eat_any_ident(){
+            let generic = parser.eat_generic();
+            let where_clause = parser.eat_where_clause(Some("SerToml"));
+
+            tb.add("impl").stream(generic.clone());
+            tb.add("SerToml for").ident(&name).stream(generic).stream(where_clause);
+            tb.add("{ fn ser_toml ( & self ) -> makepad_microserde :: Toml {");
+            tb.add("match self {");
+
+            if !parser.open_brace(){
+                return parser.unexpected()
+            }
+
+            while !parser.eat_eot(){
+                let attrs = match variant_attrs(&parser.eat_attributes()) {Ok(a) => a, Err(e) => return e};
+                // parse ident
+                if let Some(variant) = parser.eat_any_ident(){
+                    let ser_variant = if let Some(rename) = &attrs.rename {rename.clone()} else {variant.clone()};
+                    if let Some(types) = parser.eat_all_types(){
+                        tb.add("Self ::").ident(&variant).add("(");
+                        for i in 0..types.len(){
+                            tb.ident(&format!("n{}", i)).add(",");
+                        }
+                        tb.add(") => makepad_microserde :: Toml :: Table ( vec ! [ (").string(&ser_variant);
+                        tb.add(". to_string ( ) , makepad_microserde :: Toml :: Array ( vec ! [");
+                        for i in 0..types.len(){
+                            tb.ident(&format!("n{}", i)).add(". ser_toml ( ) ,");
+                        }
+                        tb.add("] ) ) ] ) ,");
+                    }
+                    else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                        tb.add("Self ::").ident(&variant).add("{");
+                        for field in fields.iter().filter(|f| !f.attrs.skip){
+                            tb.ident(&field.name).add(",");
+                        }
+                        tb.add(".. } => {");
+                        tb.add("let v = {");
+                        ser_toml_named_fields(&mut tb, &fields, false);
+                        tb.add("} ; makepad_microserde :: Toml :: Table ( vec ! [ (").string(&ser_variant);
+                        tb.add(". to_string ( ) , v ) ] ) }");
+                    }
+                    else if parser.is_punct(',') || parser.is_eot(){ // bare variant
+                        tb.add("Self ::").ident(&variant).add("=> makepad_microserde :: Toml :: Str (");
+                        tb.string(&ser_variant).add(". to_string ( ) ) ,");
+                    }
+                    else{
+                        return parser.unexpected();
+                    }
+                    parser.eat_punct(',');
+                }
+                else{
+                    return parser.unexpected()
+                }
+            }
+            tb.add("} } } ;");
+            return tb.end();
+        }
+    }
+    return parser.unexpected()
+}
+
+pub fn derive_de_toml_impl(input: TokenStream) -> TokenStream {
+    let mut parser = TokenParser::new(input);
+    let mut tb = TokenBuilder::new();
+    let container = match container_attrs(&parser.eat_attributes()) {Ok(c) => c, Err(e) => return e};
+    if let Err(e) = json_only_attrs(&container, "SerToml and DeToml") {return e}
+
+    parser.eat_ident("pub");
+    if parser.eat_ident("struct"){
+        if let Some(name) = parser.eat_any_ident(){
+            let generic = parser.eat_generic();
+            let types = parser.eat_all_types();
+            let where_clause = parser.eat_where_clause(Some("DeToml"));
+
+            tb.add("impl").stream(generic.clone());
+            tb.add("DeToml for").ident(&name).stream(generic).stream(where_clause);
+            tb.add("{ fn de_toml ( t : & makepad_microserde :: Toml )");
+            tb.add("-> std :: result :: Result < Self , makepad_microserde :: TomlErr > { ");
+
+            if let Some(types) = types{
+                if let Err(e) = transparent_field_count(&container, types.len()) {return e}
+                if container.transparent {
+                    tb.add("std :: result :: Result :: Ok ( Self ( DeToml :: de_toml ( t ) ? ) )");
+                }
+                else {
+                    de_toml_array_len(&mut tb, types.len());
+                    tb.add("std :: result :: Result :: Ok ( Self");
+                    de_toml_tuple(&mut tb, types.len());
+                    tb.add(")");
+                }
+            }
+            else if let Some(fields) = parser.eat_all_struct_fields(){
+                let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                if let Err(e) = transparent_field_count(&container, fields.len()) {return e}
+                if container.transparent {
+                    tb.add("std :: result :: Result :: Ok ( Self {").ident(&fields[0].name);
+                    tb.add(": DeToml :: de_toml ( t ) ? } )");
+                }
+                else {
+                    if container.default {
+                        tb.add("let __default : Self = std :: default :: Default :: default ( ) ;");
+                    }
+                    tb.add("if t . as_table ( ) . is_none ( ) {");
+                    tb.add("return Err ( makepad_microserde :: TomlErr :: err_type (").string(&name).add(", t ) ) }");
+                    tb.add("std :: result :: Result :: Ok ( Self");
+                    de_toml_named_fields(&mut tb, &fields, &container);
+                    tb.add(")");
+                }
+            }
+            else{
+                return parser.unexpected()
+            }
+            tb.add("} } ;");
+            return tb.end();
+        }
+    }
+    else if parser.eat_ident("enum"){
+
+        if let Some(name) = parser.eat_any_ident(){
+            let generic = parser.eat_generic();
+            let where_clause = parser.eat_where_clause(Some("DeToml"));
+
+            tb.add("impl").stream(generic.clone());
+            tb.add("DeToml for").ident(&name).stream(generic).stream(where_clause);
+            tb.add("{ fn de_toml ( t : & makepad_microserde :: Toml )");
+            tb.add("-> std :: result :: Result < Self , makepad_microserde :: TomlErr > { ");
+            // unit variants are strings, the others a table with a single key
+            tb.add("let ( variant , t ) = match t {");
+            tb.add("makepad_microserde :: Toml :: Str ( s ) => ( s , t ) ,");
+            tb.add("makepad_microserde :: Toml :: Table ( items ) if items . len ( ) == 1 => ( & items [ 0 ] . 0 , & items [ 0 ] . 1 ) ,");
+            tb.add("_ => return Err ( makepad_microserde :: TomlErr :: err_type (").string(&name).add(", t ) )");
+            tb.add("} ; let _ = t ;");
+            tb.add("std :: result :: Result :: Ok ( match variant . as_ref ( ) {");
+
+            if !parser.open_brace(){
+                return parser.unexpected()
+            }
+            while !parser.eat_eot(){
+                let attrs = match variant_attrs(&parser.eat_attributes()) {Ok(a) => a, Err(e) => return e};
+                // parse ident
+                if let Some(variant) = parser.eat_any_ident(){
+                    let ser_variant = if let Some(rename) = &attrs.rename {rename.clone()} else {variant.clone()};
+                    tb.string(&ser_variant).add("=> {");
+                    if let Some(types) = parser.eat_all_types(){
+                        de_toml_array_len(&mut tb, types.len());
+                        tb.add("Self ::").ident(&variant);
+                        de_toml_tuple(&mut tb, types.len());
+                    }
+                    else if let Some(fields) = parser.eat_all_struct_fields(){ // named variant
+                        let fields = match SerdeField::from_struct_fields(fields) {Ok(f) => f, Err(e) => return e};
+                        tb.add("Self ::").ident(&variant);
+                        de_toml_named_fields(&mut tb, &fields, &ContainerAttrs::default());
+                    }
+                    else if parser.is_punct(',') || parser.is_eot(){ // bare variant
+                        tb.add("Self ::").ident(&variant);
+                    }
+                    else{
+                        return parser.unexpected();
+                    }
+
+                    tb.add("}");
+                    parser.eat_punct(',');
+                }
+                else{
+                    return parser.unexpected()
+                }
+            }
+            tb.add("_ => return std :: result :: Result :: Err ( makepad_microserde :: TomlErr :: err_enum ( variant ) )");
+            tb.add("} ) } }");
+            return tb.end();
+        }
+    }
+    return parser.unexpected()
+}
@@ -2,6 +2,7 @@ extern crate proc_macro;
 use proc_macro::{TokenStream};
 
 mod macro_lib; 
+mod nserde_attrs;
 
 mod derive_bin;
 use crate::derive_bin::*;
@@ -12,34 +13,51 @@ use crate::derive_ron::*;
 mod derive_json;
 use crate::derive_json::*;
 
-#[proc_macro_derive(SerBin)]
+mod derive_toml;
+use crate::derive_toml::*;
+
+#[proc_macro_derive(SerBin, attributes(nserde))]
 pub fn derive_ser_bin(input: TokenStream) -> TokenStream {
     derive_ser_bin_impl(input)
 }
 
-#[proc_macro_derive(DeBin)]
+#[proc_macro_derive(DeBin, attributes(nserde))]
 pub fn derive_de_bin(input: TokenStream) -> TokenStream {
     derive_de_bin_impl(input)
 }
 
-#[proc_macro_derive(SerJson)]
+#[proc_macro_derive(DeBinRef, attributes(nserde))]
+pub fn derive_de_bin_ref(input: TokenStream) -> TokenStream {
+    derive_de_bin_ref_impl(input)
+}
+
+#[proc_macro_derive(SerJson, attributes(nserde))]
 pub fn derive_ser_json(input: TokenStream) -> TokenStream {
     derive_ser_json_impl(input)
 }
 
-#[proc_macro_derive(DeJson)]
+#[proc_macro_derive(DeJson, attributes(nserde))]
 pub fn derive_de_json(input: TokenStream) -> TokenStream {
     derive_de_json_impl(input)
 }
 
 
-#[proc_macro_derive(SerRon)]
+#[proc_macro_derive(SerRon, attributes(nserde))]
 pub fn derive_ser_ron(input: TokenStream) -> TokenStream {
     derive_ser_ron_impl(input)
 }
 
-#[proc_macro_derive(DeRon)]
+#[proc_macro_derive(DeRon, attributes(nserde))]
 pub fn derive_de_ron(input: TokenStream) -> TokenStream {
     derive_de_ron_impl(input)
 }
 
+#[proc_macro_derive(SerToml, attributes(nserde))]
+pub fn derive_ser_toml(input: TokenStream) -> TokenStream {
+    derive_ser_toml_impl(input)
+}
+
+#[proc_macro_derive(DeToml, attributes(nserde))]
+pub fn derive_de_toml(input: TokenStream) -> TokenStream {
+    derive_de_toml_impl(input)
+}
@@ -5,10 +5,25 @@ use proc_macro::token_stream::IntoIter;
 
 // little macro utility lib
 
+// every token gets the span, rustc points at the whole compile_error ! ( .. ) invocation
 pub fn error_span(err: &str, span: Span) -> TokenStream {
     let mut tb = TokenBuilder::new();
-    tb.ident_with_span("compile_error", span).add("! (").string(err).add(") ;");
-    tb.end()
+    tb.add("compile_error ! (").string(err).add(") ;");
+    respan(tb.end(), span)
+}
+
+pub fn respan(ts: TokenStream, span: Span) -> TokenStream {
+    ts.into_iter().map( | tt | match tt {
+        TokenTree::Group(group) => {
+            let mut ret = Group::new(group.delimiter(), respan(group.stream(), span));
+            ret.set_span(span);
+            TokenTree::Group(ret)
+        }
+        mut tt => {
+            tt.set_span(span);
+            tt
+        }
+    }).collect()
 }
 
 pub fn error(err: &str) -> TokenStream {
@@ -17,6 +32,19 @@ pub fn error(err: &str) -> TokenStream {
     tb.end()
 }
 
+// finds the name of the first lifetime in a generic like < 'a , T >
+pub fn first_lifetime(generic: &Option<TokenStream>) -> Option<String> {
+    let mut is_lifetime = false;
+    for tt in generic.clone() ? {
+        match tt {
+            TokenTree::Punct(p) if p.as_char() == '\'' => is_lifetime = true,
+            TokenTree::Ident(i) if is_lifetime => return Some(i.to_string()),
+            _ => is_lifetime = false
+        }
+    }
+    None
+}
+
 pub struct TokenBuilder {
     pub groups: Vec<(Delimiter, TokenStream)>
 }
@@ -70,7 +98,7 @@ impl TokenBuilder {
                 ")" => self.pop_group(Delimiter::Parenthesis),
                 "]" => self.pop_group(Delimiter::Bracket),
                 "?" | ";" | "&" | "^" | ":" | "::" | "," | "!" | "." | "<<" | ">>" |
-                "->" | "=>" | "<" | ">" | "<=" | ">=" | "=" | "==" | "!=" |
+                "->" | "=>" | "<" | ">" | "<=" | ">=" | "=" | "==" | "!=" | "|" | "||" | "&&" |
                 "+" | "+=" | "-" | "-=" | "*" | "*=" | "/" | "/=" | ".." => self.punct(part),
                 _ => {
                     if part.len() == 0{
@@ -96,6 +124,11 @@ impl TokenBuilder {
         self.extend(TokenTree::from(Ident::new(id, span)))
     }
     
+    pub fn lifetime(&mut self, name: &str) -> &mut Self {
+        self.extend(TokenTree::from(Punct::new('\'', Spacing::Joint)));
+        self.ident(name)
+    }
+    
     pub fn punct(&mut self, s: &str) -> &mut Self {
         for (last, c) in s.chars().identify_last() {
             self.extend(TokenTree::from(Punct::new(c, if last {Spacing::Alone} else {Spacing::Joint})));
@@ -112,6 +145,7 @@ impl TokenBuilder {
     pub fn string(&mut self, val: &str) -> &mut Self {self.extend(TokenTree::from(Literal::string(val)))}
     pub fn unsuf_usize(&mut self, val: usize) -> &mut Self {self.extend(TokenTree::from(Literal::usize_unsuffixed(val)))}
     pub fn suf_u16(&mut self, val: u16) -> &mut Self {self.extend(TokenTree::from(Literal::u16_suffixed(val)))}
+    pub fn suf_u32(&mut self, val: u32) -> &mut Self {self.extend(TokenTree::from(Literal::u32_suffixed(val)))}
     pub fn suf_u64(&mut self, val: u64) -> &mut Self {self.extend(TokenTree::from(Literal::u64_suffixed(val)))}
     pub fn unsuf_f32(&mut self, val: f32) -> &mut Self {self.extend(TokenTree::from(Literal::f32_unsuffixed(val)))}
     pub fn chr(&mut self, val:char) -> &mut Self {self.extend(TokenTree::from(Literal::character(val)))}
@@ -349,7 +383,7 @@ impl TokenParser {
     pub fn is_punct(&mut self, what: char) -> bool {
         // check if our punct is multichar.
         if let Some(TokenTree::Punct(current)) = &self.current {
-            if current.as_char() == what && (what == '>' || current.spacing() == Spacing::Alone){
+            if current.as_char() == what && (what == '>' || what == '\'' || what == '&' || current.spacing() == Spacing::Alone){
                 return true
             }
             else {
@@ -490,17 +524,14 @@ impl TokenParser {
             if !self.open_bracket() {
                 break;
             }
-            while let Some(ident) = self.eat_any_ident(){
-                if !self.open_paren() {
-                    break;
+            if let Some(ident) = self.eat_any_ident(){
+                if self.open_paren() {
+                    // lets take the whole ts
+                    results.push(Attribute{name:ident, args:self.eat_level()});
                 }
-                // lets take the whole ts
-                results.push(Attribute{name:ident, args:self.eat_level()});
-                self.eat_punct(',');
-            }
-            if !self.eat_eot(){
-                break
             }
+            // skip whatever is left, like the = "..." of a doc comment
+            self.eat_level();
         }
         return results;
     }
@@ -600,12 +631,25 @@ impl TokenParser {
             tb.add(")");
             return Some(tb.end());
         }
-        else if let Some(ty) = self.eat_any_ident() {
-            tb.ident(&ty);
+        else if self.eat_punct('&'){ // reference type, with an optional lifetime
+            tb.add("&");
+            if self.eat_punct('\'') {
+                tb.lifetime(&self.eat_any_ident() ?);
+            }
+            if self.eat_ident("mut") {
+                tb.add("mut");
+            }
+            tb.stream(Some(self.eat_type() ?));
+            return Some(tb.end());
+        }
+        else {
+            // a type by name or by path like std::option::Option<T>
+            if self.eat_sep() {
+                tb.sep();
+            }
+            tb.stream(Some(self.eat_ident_path() ?));
             tb.stream(self.eat_generic());
-
-            return Some(tb.end())
+            Some(tb.end())
         }
-        return None
     }
 }
@@ -0,0 +1,222 @@
+use proc_macro::{TokenStream, TokenTree, Span};
+use crate::macro_lib::*;
+
+// parses the #[nserde(...)] attributes shared by all the derives
+
+#[derive(Default)]
+pub struct ContainerAttrs {
+    pub transparent: bool,
+    pub default: bool,
+    pub tag: Option<String>,
+    pub content: Option<String>,
+    // binary only, see the tagged encoding in serde_bin.rs
+    pub tagged: bool,
+    pub version: u16,
+    pub min_version: u16,
+}
+
+#[derive(Default)]
+pub struct FieldAttrs {
+    pub rename: Option<String>,
+    pub default: bool,
+    // the path of the function, spanned to the attribute
+    pub default_fn: Option<TokenStream>,
+    pub skip: bool,
+}
+
+#[derive(Default)]
+pub struct VariantAttrs {
+    pub rename: Option<String>,
+    // the tagged binary format decodes unknown variants as this one
+    pub other: bool,
+}
+
+impl FieldAttrs {
+    pub fn has_default(&self) -> bool {
+        self.default || self.default_fn.is_some() || self.skip
+    }
+}
+
+// tag and content shape json objects, the other formats would silently ignore them
+pub fn json_only_attrs(container: &ContainerAttrs, formats: &str) -> Result<(), TokenStream> {
+    if container.tag.is_some() {
+        return Err(error(&format!("nserde tag and content are only supported by SerJson and DeJson, not by {}", formats)))
+    }
+    Ok(())
+}
+
+// the span is the one of the value, or of the key when there is none
+pub fn nserde_key_values(attrs: &[Attribute]) -> Result<Vec<(String, Option<String>, Span)>, TokenStream> {
+    let mut ret = Vec::new();
+    for attr in attrs {
+        if attr.name != "nserde" {
+            continue
+        }
+        let mut parser = TokenParser::new(attr.args.clone());
+        while parser.current.is_some() {
+            let key_span = parser.span();
+            if let Some(key) = parser.eat_any_ident() {
+                if parser.eat_punct('=') {
+                    if let Some(lit) = parser.eat_literal() {
+                        let span = lit.span();
+                        let lit = lit.to_string();
+                        if lit.len() < 2 || !lit.starts_with('"') || !lit.ends_with('"') {
+                            return Err(error(&format!("nserde attribute {} expects a string", key)))
+                        }
+                        ret.push((key, Some(lit[1..lit.len() - 1].to_string()), span));
+                    }
+                    else {
+                        return Err(error(&format!("nserde attribute {} expects a string", key)))
+                    }
+                }
+                else {
+                    ret.push((key, None, key_span.unwrap()));
+                }
+                parser.eat_punct(',');
+            }
+            else {
+                return Err(error("Unexpected token in nserde attribute"))
+            }
+        }
+    }
+    Ok(ret)
+}
+
+pub fn container_attrs(attrs: &[Attribute]) -> Result<ContainerAttrs, TokenStream> {
+    let mut ret = ContainerAttrs::default();
+    for (key, value, _) in nserde_key_values(attrs) ? {
+        match (key.as_ref(), value) {
+            ("transparent", None) => ret.transparent = true,
+            ("default", None) => ret.default = true,
+            ("tag", Some(value)) => ret.tag = Some(value),
+            ("content", Some(value)) => ret.content = Some(value),
+            ("tagged", None) => ret.tagged = true,
+            ("version", Some(value)) => ret.version = parse_version(&key, &value) ?,
+            ("min_version", Some(value)) => ret.min_version = parse_version(&key, &value) ?,
+            _ => return Err(error(&format!("Unknown nserde container attribute {}", key)))
+        }
+    }
+    if ret.content.is_some() && ret.tag.is_none() {
+        return Err(error("nserde attribute content requires tag"))
+    }
+    if (ret.version != 0 || ret.min_version != 0) && !ret.tagged {
+        return Err(error("nserde attribute version requires tagged"))
+    }
+    if ret.min_version > ret.version {
+        return Err(error("nserde min_version cannot be larger than version"))
+    }
+    Ok(ret)
+}
+
+pub fn field_attrs(attrs: &[Attribute]) -> Result<FieldAttrs, TokenStream> {
+    let mut ret = FieldAttrs::default();
+    for (key, value, span) in nserde_key_values(attrs) ? {
+        match (key.as_ref(), value) {
+            ("rename", Some(value)) => ret.rename = Some(value),
+            ("default", None) => ret.default = true,
+            ("default", Some(value)) => ret.default_fn = Some(parse_fn_path(&value, span) ?),
+            ("skip", None) => ret.skip = true,
+            _ => return Err(error(&format!("Unknown nserde field attribute {}", key)))
+        }
+    }
+    Ok(ret)
+}
+
+// default = "..." names a function like default = "my_mod::my_default"
+fn parse_fn_path(value: &str, span: Span) -> Result<TokenStream, TokenStream> {
+    let err = || error_span(&format!("nserde default expects the path of a function, not {:?}", value), span);
+    let path: TokenStream = value.parse().map_err( | _ | err()) ?;
+    let mut is_empty = true;
+    for tt in path.clone() {
+        match tt {
+            TokenTree::Ident(_) => is_empty = false,
+            TokenTree::Punct(p) if p.as_char() == ':' => (),
+            _ => return Err(err())
+        }
+    }
+    if is_empty {
+        return Err(err())
+    }
+    // errors about the function itself then point at the attribute
+    Ok(respan(path, span))
+}
+
+fn parse_version(key: &str, value: &str) -> Result<u16, TokenStream> {
+    value.parse().map_err( | _ | error(&format!("nserde attribute {} expects a number below 65536", key)))
+}
+
+pub fn variant_attrs(attrs: &[Attribute]) -> Result<VariantAttrs, TokenStream> {
+    let mut ret = VariantAttrs::default();
+    for (key, value, _) in nserde_key_values(attrs) ? {
+        match (key.as_ref(), value) {
+            ("rename", Some(value)) => ret.rename = Some(value),
+            ("other", None) => ret.other = true,
+            _ => return Err(error("nserde variants only support rename and other"))
+        }
+    }
+    Ok(ret)
+}
+
+pub struct SerdeField {
+    pub name: String,
+    pub ser_name: String,
+    pub is_option: bool,
+    pub attrs: FieldAttrs,
+}
+
+impl SerdeField {
+    pub fn from_struct_fields(fields: Vec<StructField>) -> Result<Vec<SerdeField>, TokenStream> {
+        let mut ret = Vec::new();
+        for field in fields {
+            let attrs = field_attrs(&field.attrs) ?;
+            ret.push(SerdeField {
+                ser_name: if let Some(rename) = &attrs.rename {rename.clone()} else {field.name.clone()},
+                is_option: is_option_type(field.ty),
+                name: field.name,
+                attrs: attrs,
+            });
+        }
+        Ok(ret)
+    }
+
+    // the value to fill in when the field is skipped or missing, None if it is required
+    pub fn default_value(&self, container: &ContainerAttrs) -> Option<TokenStream> {
+        let mut tb = TokenBuilder::new();
+        if let Some(default_fn) = &self.attrs.default_fn {
+            tb.stream(Some(default_fn.clone())).add("( )");
+        }
+        else if self.attrs.has_default() {
+            tb.add("std :: default :: Default :: default ( )");
+        }
+        else if container.default {
+            tb.add("__default .").ident(&self.name);
+        }
+        else if self.is_option {
+            tb.add("None");
+        }
+        else {
+            return None
+        }
+        Some(tb.end())
+    }
+}
+
+// Option, std::option::Option and core::option::Option all count, the last path segment decides
+fn is_option_type(ty: TokenStream) -> bool {
+    let mut last = None;
+    for tt in ty {
+        match tt {
+            TokenTree::Ident(ident) => last = Some(ident.to_string()),
+            TokenTree::Punct(punct) if punct.as_char() == ':' => (),
+            _ => break
+        }
+    }
+    last.as_deref() == Some("Option")
+}
+
+pub fn transparent_field_count(container: &ContainerAttrs, count: usize) -> Result<(), TokenStream> {
+    if container.transparent && count != 1 {
+        return Err(error("nserde transparent requires exactly one field"))
+    }
+    Ok(())
+}
@@ -1,8 +1,13 @@
 use std::{
-    collections::HashMap,
+    borrow::{Cow, ToOwned},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     hash::Hash,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
     convert::TryInto,
     ffi::{OsStr, OsString},
+    io::{Read, Write},
     path::{PathBuf, Path},
     str,
 };
@@ -15,14 +20,70 @@ pub trait SerBin {
     }
     
     fn ser_bin(&self, s: &mut Vec<u8>);
+    
+    // writes the same bytes as ser_bin without building the whole message in memory first.
+    // the default goes through a temporary buffer, large containers and the derives override it.
+    // every value is a separate write, so wrap unbuffered writers like a TcpStream in a BufWriter
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        let mut s = Vec::new();
+        self.ser_bin(&mut s);
+        w.write_all(&s)
+    }
+    
+    // lets Vec<u8> and [u8] write their bytes in one go, like Hash::hash_slice
+    fn ser_bin_slice_to_writer(items: &[Self], w: &mut dyn Write) -> std::io::Result<()> where Self: Sized {
+        for item in items {
+            item.ser_bin_to_writer(w) ?;
+        }
+        Ok(())
+    }
 }
 
 pub trait DeBin:Sized {
     fn deserialize_bin(d:&[u8])->Result<Self, DeBinErr>{
         DeBin::de_bin(&mut 0, d)
     }
+    
+    fn de_bin_from_reader<R: Read>(r: &mut DeBinReader<R>) -> Result<Self, DeBinErr> {
+        r.de_bin()
+    }
 
     fn de_bin(o:&mut usize, d:&[u8]) -> Result<Self, DeBinErr>;
+    
+    // reads the len items of a Vec<T>, the fixed size types check for all of them up front
+    // so a DeBinReader waits for the whole run at once instead of refilling per item
+    fn de_bin_vec(len:u64, o:&mut usize, d:&[u8]) -> Result<Vec<Self>, DeBinErr> {
+        let mut out = Vec::new();
+        for _ in 0..len{
+            out.push(DeBin::de_bin(o,d)?)
+        }
+        Ok(out)
+    }
+}
+
+// errors like a read of the whole run would when the data ends before len items of size each
+fn de_bin_vec_check(len:u64, size:usize, o:usize, d:&[u8], what:&str) -> Result<(), DeBinErr> {
+    let l = len.saturating_mul(size as u64);
+    if l > (d.len() - o) as u64 {
+        return Err(DeBinErr{o, l:l.min(usize::MAX as u64) as usize, s:d.len(), msg:format!("Vec<{}>", what)})
+    }
+    Ok(())
+}
+
+// deserializes values that borrow from the input buffer like &'a str and &'a [u8].
+// all DeBin types implement it, use #[derive(DeBinRef)] on structs with borrowed fields
+pub trait DeBinRef<'a>:Sized {
+    fn deserialize_bin_ref(d:&'a [u8])->Result<Self, DeBinErr>{
+        DeBinRef::de_bin_ref(&mut 0, d)
+    }
+
+    fn de_bin_ref(o:&mut usize, d:&'a [u8]) -> Result<Self, DeBinErr>;
+}
+
+impl<'a, T> DeBinRef<'a> for T where T: DeBin {
+    fn de_bin_ref(o:&mut usize, d:&'a [u8]) -> Result<Self, DeBinErr> {
+        DeBin::de_bin(o, d)
+    }
 }
 
 
@@ -33,6 +94,13 @@ pub struct DeBinErr{
     pub s: usize
 }
 
+impl DeBinErr {
+    // true if the data ended before the value was complete, so more input could fix it
+    pub fn is_eof(&self) -> bool {
+        self.o.saturating_add(self.l) > self.s
+    }
+}
+
 impl std::fmt::Display for DeBinErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Error deserializing {} ", self.msg)?;
@@ -55,22 +123,33 @@ macro_rules! impl_ser_de_bin_for {
             fn ser_bin(&self, s: &mut Vec<u8>) {
                 s.extend_from_slice(&self.to_le_bytes());
             }
+            
+            fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
         }
         
         impl DeBin for $ty {
             fn de_bin(o:&mut usize, d:&[u8]) -> Result<$ty, DeBinErr> {
                 let l = std::mem::size_of::<$ty>();
                 if *o + l > d.len(){
-                    return Err(DeBinErr{o:*o, l:l, s:d.len(), msg:format!("{}", stringify!($ty))})
+                    return Err(DeBinErr{o:*o, l, s:d.len(), msg:format!("{}", stringify!($ty))})
                 }
                 let ret = $ty::from_le_bytes(d[*o..*o+l].try_into().unwrap());
                 *o += l;
                 Ok(ret)
             }
+            
+            fn de_bin_vec(len:u64, o:&mut usize, d:&[u8]) -> Result<Vec<$ty>, DeBinErr> {
+                de_bin_vec_check(len, std::mem::size_of::<$ty>(), *o, d, stringify!($ty))?;
+                (0..len).map( | _ | DeBin::de_bin(o, d)).collect()
+            }
         }
     };
 }
 
+impl_ser_de_bin_for!(u128);
+impl_ser_de_bin_for!(i128);
 impl_ser_de_bin_for!(f64);
 impl_ser_de_bin_for!(f32);
 impl_ser_de_bin_for!(u64);
@@ -84,6 +163,10 @@ impl SerBin for usize {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         s.extend_from_slice(&(*self as u64).to_le_bytes());
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        w.write_all(&(*self as u64).to_le_bytes())
+    }
 }
 
 impl DeBin for usize {
@@ -107,12 +190,27 @@ impl DeBin for u8 {
         *o += 1;
         Ok(m)
     }
+    
+    fn de_bin_vec(len:u64, o:&mut usize, d:&[u8]) -> Result<Vec<u8>, DeBinErr> {
+        de_bin_vec_check(len, 1, *o, d, "u8")?;
+        let r = d[*o..*o + len as usize].to_vec();
+        *o += len as usize;
+        Ok(r)
+    }
 }
 
 impl SerBin for u8 {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         s.push(*self);
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        w.write_all(&[*self])
+    }
+    
+    fn ser_bin_slice_to_writer(items: &[u8], w: &mut dyn Write) -> std::io::Result<()> {
+        w.write_all(items)
+    }
 }
 
 impl SerBin for bool {
@@ -133,22 +231,62 @@ impl DeBin for bool {
 }
 
 impl SerBin for String {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        self.as_str().ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        self.as_str().ser_bin_to_writer(w)
+    }
+}
+
+impl SerBin for str {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         let len = self.len();
         len.ser_bin(s);
         s.extend_from_slice(self.as_bytes());
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        self.len().ser_bin_to_writer(w) ?;
+        w.write_all(self.as_bytes())
+    }
+}
+
+fn de_bin_bytes<'a>(o:&mut usize, d:&'a [u8], what:&str)->Result<&'a [u8], DeBinErr> {
+    let len:u64 = DeBin::de_bin(o,d)?;
+    if len > (d.len() - *o) as u64 {
+        return Err(DeBinErr{o:*o, l:len as usize, s:d.len(), msg:format!("{}", what)})
+    }
+    let r = &d[*o..(*o+(len as usize))];
+    *o += len as usize;
+    Ok(r)
+}
+
+fn de_bin_str<'a>(o:&mut usize, d:&'a [u8])->Result<&'a str, DeBinErr> {
+    let start = *o;
+    let bytes = de_bin_bytes(o, d, "String")?;
+    match std::str::from_utf8(bytes) {
+        Ok(r) => Ok(r),
+        Err(_) => Err(DeBinErr{o:start, l:0, s:d.len(), msg:format!("String is not utf8")})
+    }
 }
 
 impl DeBin for String {
     fn de_bin(o:&mut usize, d:&[u8])->Result<String, DeBinErr> {
-        let len:u64 = DeBin::de_bin(o,d)?;
-        if *o + (len as usize) > d.len(){
-            return Err(DeBinErr{o:*o, l:1, s:d.len(), msg:format!("String")})
-        } 
-        let r = std::str::from_utf8(&d[*o..(*o+(len as usize))]).unwrap().to_string();
-        *o += len as usize;
-        Ok(r)
+        Ok(de_bin_str(o, d)?.to_string())
+    }
+}
+
+impl<'a> DeBinRef<'a> for &'a str {
+    fn de_bin_ref(o:&mut usize, d:&'a [u8])->Result<&'a str, DeBinErr> {
+        de_bin_str(o, d)
+    }
+}
+
+impl<'a> DeBinRef<'a> for &'a [u8] {
+    fn de_bin_ref(o:&mut usize, d:&'a [u8])->Result<&'a [u8], DeBinErr> {
+        de_bin_bytes(o, d, "[u8]")
     }
 }
 
@@ -160,16 +298,17 @@ impl<T> SerBin for Vec<T> where T: SerBin {
             item.ser_bin(s);
         }
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (self.len() as u64).ser_bin_to_writer(w) ?;
+        T::ser_bin_slice_to_writer(self, w)
+    }
 }
 
 impl<T> DeBin for Vec<T> where T:DeBin{
     fn de_bin(o:&mut usize, d:&[u8])->Result<Vec<T>, DeBinErr> {
         let len:u64 = DeBin::de_bin(o,d)?;
-        let mut out = Vec::new();
-        for _ in 0..len{
-            out.push(DeBin::de_bin(o,d)?)
-        }
-        Ok(out)
+        T::de_bin_vec(len, o, d)
     }
 }
 
@@ -183,6 +322,16 @@ impl<T> SerBin for Option<T> where T: SerBin {
             }
         }
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        match self {
+            None => w.write_all(&[0]),
+            Some(v) => {
+                w.write_all(&[1]) ?;
+                v.ser_bin_to_writer(w)
+            }
+        }
+    }
 }
 
 impl<T> DeBin for Option<T> where T:DeBin{
@@ -230,12 +379,50 @@ impl<T, E> DeBin for Result<T, E> where T: DeBin, E: DeBin {
     }
 }
 
+// a slice is written like a Vec, with its length, so Vec<T> and DeBinRef's &'a [T] read it back
 impl<T> SerBin for [T] where T: SerBin {
     fn ser_bin(&self, s: &mut Vec<u8>) {
+        let len = self.len() as u64;
+        len.ser_bin(s);
         for item in self {
             item.ser_bin(s);
         }
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (self.len() as u64).ser_bin_to_writer(w) ?;
+        T::ser_bin_slice_to_writer(self, w)
+    }
+}
+
+impl<T> SerBin for &[T] where T: SerBin {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (**self).ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (**self).ser_bin_to_writer(w)
+    }
+}
+
+impl SerBin for &str {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (**self).ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (**self).ser_bin_to_writer(w)
+    }
+}
+
+impl<T> SerBin for &T where T: SerBin {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (**self).ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (**self).ser_bin_to_writer(w)
+    }
 }
 
 
@@ -249,12 +436,24 @@ unsafe fn de_bin_array_impl_inner<T>(top: *mut T, count: usize, o:&mut usize, d:
 macro_rules!de_bin_array_impl {
     ( $($count:expr),*) => {
         $(
+        impl<T> SerBin for [T; $count] where T: SerBin {
+            fn ser_bin(&self, s: &mut Vec<u8>) {
+                for item in self {
+                    item.ser_bin(s);
+                }
+            }
+            
+            fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+                T::ser_bin_slice_to_writer(self, w)
+            }
+        }
+        
         impl<T> DeBin for [T; $count] where T: DeBin {
             fn de_bin(o:&mut usize, d:&[u8]) -> Result<Self,
             DeBinErr> {
                 unsafe{
                     let mut to = std::mem::MaybeUninit::<[T; $count]>::uninit();
-                    let top: *mut T = std::mem::transmute(&mut to);
+                    let top = to.as_mut_ptr() as *mut T;
                     de_bin_array_impl_inner(top, $count, o, d)?;
                     Ok(to.assume_init())
                 }
@@ -264,7 +463,9 @@ macro_rules!de_bin_array_impl {
     }
 }
 
-de_bin_array_impl!(2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31,32);
+de_bin_array_impl!(1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31,32);
+de_bin_array_impl!(33,34,35,36,37,38,39,40,41,42,43,44,45,46,47,48,49,50,51,52,53,54,55,56,57,58,59,60,61,62,63,64);
+de_bin_array_impl!(96,128,192,256,384,512,1024,2048,4096);
 
 impl<A,B> SerBin for (A,B) where A: SerBin, B:SerBin {
     fn ser_bin(&self, s: &mut Vec<u8>) {
@@ -333,6 +534,10 @@ impl<T> SerBin for Box<T> where T: SerBin {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         (**self).ser_bin(s)
     }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (**self).ser_bin_to_writer(w)
+    }
 }
 
 impl<T> DeBin for Box<T> where T: DeBin {
@@ -341,6 +546,180 @@ impl<T> DeBin for Box<T> where T: DeBin {
     }
 }
 
+impl<K, V> SerBin for BTreeMap<K, V> where K: SerBin,
+V: SerBin {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        let len = self.len() as u64;
+        len.ser_bin(s);
+        for (k, v) in self {
+            k.ser_bin(s);
+            v.ser_bin(s);
+        }
+    }
+}
+
+impl<K, V> DeBin for BTreeMap<K, V> where K: DeBin + Ord,
+V: DeBin {
+    fn de_bin(o:&mut usize, d:&[u8])->Result<Self, DeBinErr>{
+        let len:u64 = DeBin::de_bin(o,d)?;
+        let mut h = BTreeMap::new();
+        for _ in 0..len{
+            let k = DeBin::de_bin(o,d)?;
+            let v = DeBin::de_bin(o,d)?;
+            h.insert(k, v);
+        }
+        Ok(h)
+    }
+}
+
+// sets and deques are written like a Vec
+macro_rules!impl_ser_de_bin_seq {
+    ( $ty:ident $(, $bound:ident)*) => {
+        impl<T> SerBin for $ty<T> where T: SerBin {
+            fn ser_bin(&self, s: &mut Vec<u8>) {
+                let len = self.len() as u64;
+                len.ser_bin(s);
+                for item in self {
+                    item.ser_bin(s);
+                }
+            }
+        }
+        
+        impl<T> DeBin for $ty<T> where T: DeBin $(+ $bound)* {
+            fn de_bin(o:&mut usize, d:&[u8])->Result<Self, DeBinErr> {
+                let len:u64 = DeBin::de_bin(o,d)?;
+                let mut out = $ty::new();
+                for _ in 0..len{
+                    out.extend(Some(DeBin::de_bin(o,d)?));
+                }
+                Ok(out)
+            }
+        }
+    }
+}
+
+impl_ser_de_bin_seq!(HashSet, Eq, Hash);
+impl_ser_de_bin_seq!(BTreeSet, Ord);
+impl_ser_de_bin_seq!(VecDeque);
+
+impl<T> SerBin for Rc<T> where T: SerBin + ?Sized {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (**self).ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (**self).ser_bin_to_writer(w)
+    }
+}
+
+impl<T> DeBin for Rc<T> where T: DeBin {
+    fn de_bin(o:&mut usize, d:&[u8])->Result<Rc<T>, DeBinErr> {
+        Ok(Rc::new(DeBin::de_bin(o,d)?))
+    }
+}
+
+impl<T> SerBin for Arc<T> where T: SerBin + ?Sized {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (**self).ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (**self).ser_bin_to_writer(w)
+    }
+}
+
+impl<T> DeBin for Arc<T> where T: DeBin {
+    fn de_bin(o:&mut usize, d:&[u8])->Result<Arc<T>, DeBinErr> {
+        Ok(Arc::new(DeBin::de_bin(o,d)?))
+    }
+}
+
+// written through a reference so Cow<[T]> reads back like the Vec it owns
+impl<'a, T> SerBin for Cow<'a, T> where T: ToOwned + ?Sized, for<'b> &'b T: SerBin {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        (&**self).ser_bin(s)
+    }
+    
+    fn ser_bin_to_writer(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        (&**self).ser_bin_to_writer(w)
+    }
+}
+
+impl<'a, T> DeBin for Cow<'a, T> where T: ToOwned + ?Sized, T::Owned: DeBin {
+    fn de_bin(o:&mut usize, d:&[u8])->Result<Cow<'a, T>, DeBinErr> {
+        Ok(Cow::Owned(DeBin::de_bin(o,d)?))
+    }
+}
+
+impl SerBin for Duration {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        self.as_secs().ser_bin(s);
+        self.subsec_nanos().ser_bin(s);
+    }
+}
+
+impl DeBin for Duration {
+    fn de_bin(o:&mut usize, d:&[u8])->Result<Duration, DeBinErr> {
+        let start = *o;
+        let secs:u64 = DeBin::de_bin(o,d)?;
+        let nanos:u32 = DeBin::de_bin(o,d)?;
+        if nanos >= 1_000_000_000 {
+            return Err(DeBinErr{o:start, l:12, s:d.len(), msg:format!("Duration nanos out of range {}", nanos)})
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+// a SystemTime is stored as seconds since the unix epoch, negative before it, plus
+// the nanoseconds after that second. the json and ron impls use the same split.
+pub(crate) fn system_time_to_unix(t: &SystemTime) -> (i64, u32) {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+        Err(e) => {
+            let before = e.duration();
+            if before.subsec_nanos() == 0 {
+                (-(before.as_secs() as i64), 0)
+            }
+            else {
+                (-(before.as_secs() as i64) - 1, 1_000_000_000 - before.subsec_nanos())
+            }
+        }
+    }
+}
+
+pub(crate) fn system_time_from_unix(secs: i64, nanos: u32) -> Option<SystemTime> {
+    if nanos >= 1_000_000_000 {
+        return None
+    }
+    let base = if secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+    }
+    else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))
+    };
+    base?.checked_add(Duration::from_nanos(nanos as u64))
+}
+
+impl SerBin for SystemTime {
+    fn ser_bin(&self, s: &mut Vec<u8>) {
+        let (secs, nanos) = system_time_to_unix(self);
+        secs.ser_bin(s);
+        nanos.ser_bin(s);
+    }
+}
+
+impl DeBin for SystemTime {
+    fn de_bin(o:&mut usize, d:&[u8])->Result<SystemTime, DeBinErr> {
+        let start = *o;
+        let secs:i64 = DeBin::de_bin(o,d)?;
+        let nanos:u32 = DeBin::de_bin(o,d)?;
+        if let Some(t) = system_time_from_unix(secs, nanos) {
+            return Ok(t)
+        }
+        Err(DeBinErr{o:start, l:12, s:d.len(), msg:format!("SystemTime out of range {}s {}ns", secs, nanos)})
+    }
+}
+
 impl SerBin for PathBuf {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         self.as_os_str().ser_bin(s)
@@ -371,7 +750,7 @@ impl SerBin for OsStr {
 impl SerBin for char {
     fn ser_bin(&self, s: &mut Vec<u8>) {
         let mut bytes = [0; 4];
-        self.encode_utf8(&mut bytes).as_bytes().ser_bin(s);
+        s.extend_from_slice(self.encode_utf8(&mut bytes).as_bytes());
     }
 }
 
@@ -407,6 +786,136 @@ impl DeBin for char {
     }
 }
 
+// #[nserde(tagged)] types are written as
+//   u16 version, u16 min_version: the schema version of the writer and the oldest one that can read it
+//   struct: u32 field count, then per field a u32 id, a u64 length and the value
+//   enum: u32 variant id, u64 length, then the fields like a struct
+// so older readers skip the fields and variants they dont know about instead of misreading the rest
+pub fn ser_bin_tagged_header(s: &mut Vec<u8>, version: u16, min_version: u16) {
+    version.ser_bin(s);
+    min_version.ser_bin(s);
+}
+
+pub fn de_bin_tagged_header(o: &mut usize, d: &[u8], name: &str, version: u16) -> Result<u16, DeBinErr> {
+    let start = *o;
+    let written: u16 = DeBin::de_bin(o, d)?;
+    let min_version: u16 = DeBin::de_bin(o, d)?;
+    if min_version > version {
+        return Err(DeBinErr{o:start, l:0, s:d.len(), msg:format!(
+            "{} version {} needs at least version {} to read, this is version {}", name, written, min_version, version
+        )})
+    }
+    Ok(written)
+}
+
+// writes the id and room for the length, returns where the length goes
+pub fn ser_bin_tagged_begin(s: &mut Vec<u8>, id: u32) -> usize {
+    id.ser_bin(s);
+    let at = s.len();
+    0u64.ser_bin(s);
+    at
+}
+
+pub fn ser_bin_tagged_end(s: &mut Vec<u8>, at: usize) {
+    let len = (s.len() - at - 8) as u64;
+    s[at..at + 8].copy_from_slice(&len.to_le_bytes());
+}
+
+// reads an id and a length, returns the id and the offset where the item ends
+pub fn de_bin_tagged_item(o: &mut usize, d: &[u8]) -> Result<(u32, usize), DeBinErr> {
+    let id: u32 = DeBin::de_bin(o, d)?;
+    let len: u64 = DeBin::de_bin(o, d)?;
+    if len > (d.len() - *o) as u64 {
+        return Err(DeBinErr{o:*o, l:len as usize, s:d.len(), msg:format!("tagged item {}", id)})
+    }
+    Ok((id, *o + len as usize))
+}
+
+// the most a DeBinReader buffers for one value unless told otherwise
+pub const DE_BIN_READER_MAX_LEN: usize = 256 * 1024 * 1024;
+
+// reads values from a stream like a TcpStream. the reader pulls in more data whenever
+// a value turns out to be incomplete, and keeps whatever follows it for the next value.
+// a value that needs more than max_len bytes is an error, whatever its length prefix claims
+pub struct DeBinReader<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    o: usize,
+    max_len: usize
+}
+
+impl<R: Read> DeBinReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_max_len(reader, DE_BIN_READER_MAX_LEN)
+    }
+    
+    pub fn with_max_len(reader: R, max_len: usize) -> Self {
+        Self {reader, buf: Vec::new(), o: 0, max_len}
+    }
+    
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+    
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+    
+    // the bytes read from the stream but not consumed yet
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf[self.o..]
+    }
+    
+    pub fn de_bin<T: DeBin>(&mut self) -> Result<T, DeBinErr> {
+        loop {
+            let mut o = 0;
+            let err = match T::de_bin(&mut o, &self.buf[self.o..]) {
+                Ok(value) => {
+                    self.o += o;
+                    return Ok(value)
+                }
+                Err(err) => err
+            };
+            if !err.is_eof() {
+                return Err(err)
+            }
+            // wait only for the bytes the value still needs, more may never come on a live socket
+            let need = err.o.saturating_add(err.l).saturating_sub(err.s).max(1);
+            if !self.fill(need)? {
+                return Err(err)
+            }
+        }
+    }
+    
+    // reads until need more bytes are buffered, taking whatever else is already there.
+    // returns false if the stream ended before any arrived
+    fn fill(&mut self, need: usize) -> Result<bool, DeBinErr> {
+        if self.o > 0 {
+            self.buf.drain(0..self.o);
+            self.o = 0;
+        }
+        if self.buf.len().saturating_add(need) > self.max_len {
+            return Err(DeBinErr{o:self.buf.len(), l:0, s:self.buf.len(), msg:format!("value larger than {} bytes", self.max_len)})
+        }
+        let mut chunk = [0u8; 16384];
+        let mut total = 0;
+        while total < need {
+            // never buffer past the limit, the bytes after a value belong to the next one
+            let room = (self.max_len - self.buf.len()).min(chunk.len());
+            match self.reader.read(&mut chunk[0..room]) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[0..n]);
+                    total += n;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(DeBinErr{o:self.buf.len(), l:0, s:self.buf.len(), msg:format!("read failed {}", e)})
+            }
+        }
+        Ok(total > 0)
+    }
+}
+
 // Given a first byte, determines how many bytes are in this UTF-8 character.
 #[inline]
 pub fn utf8_char_width(b: u8) -> usize {
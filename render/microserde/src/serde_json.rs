@@ -1,6 +1,11 @@
-use std::collections::{HashMap};
+use std::borrow::{Cow, ToOwned};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::hash::Hash;
+use std::rc::Rc;
 use std::str::Chars;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use crate::serde_bin::{system_time_from_unix, system_time_to_unix};
 
 pub struct SerJsonState {
     pub out: String
@@ -36,6 +41,10 @@ impl SerJsonState {
     }
     
     pub fn st_post(&mut self, d: usize) {
+        // fields are written with a comma after each, json does not allow the last one
+        if self.out.ends_with(',') {
+            self.out.pop();
+        }
         self.indent(d);
         self.out.push('}');
     }
@@ -60,22 +69,48 @@ pub trait DeJson: Sized {
     fn deserialize_json(input: &str) -> Result<Self,
     DeJsonErr> {
         let mut state = DeJsonState::default();
+        Self::deserialize_json_with(&mut state, input)
+    }
+    
+    // also accepts // and /* */ comments and unquoted keys, for hand edited files
+    fn deserialize_json_lenient(input: &str) -> Result<Self,
+    DeJsonErr> {
+        let mut state = DeJsonState {lenient: true, ..DeJsonState::default()};
+        Self::deserialize_json_with(&mut state, input)
+    }
+    
+    // rejects trailing commas and anything after the value
+    fn deserialize_json_strict(input: &str) -> Result<Self,
+    DeJsonErr> {
+        let mut state = DeJsonState {strict: true, ..DeJsonState::default()};
+        Self::deserialize_json_with(&mut state, input)
+    }
+    
+    fn deserialize_json_with(state: &mut DeJsonState, input: &str) -> Result<Self,
+    DeJsonErr> {
         let mut chars = input.chars();
         state.next(&mut chars);
         state.next_tok(&mut chars) ?;
-        DeJson::de_json(&mut state, &mut chars)
+        let value = DeJson::de_json(state, &mut chars) ?;
+        if state.strict && state.tok != DeJsonTok::Eof {
+            return Err(state.err_token("end of input"))
+        }
+        Ok(value)
     }
     
     fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self,
     DeJsonErr>;
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum DeJsonTok {
     Str,
     Char(char),
     U64(u64),
     I64(i64),
+    // integers that do not fit in 64 bits
+    U128(u128),
+    I128(i128),
     F64(f64),
     Bool(bool),
     BareIdent,
@@ -94,7 +129,7 @@ impl Default for DeJsonTok {
     fn default() -> Self {DeJsonTok::Bof}
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct DeJsonState {
     pub cur: char,
     pub tok: DeJsonTok,
@@ -102,18 +137,60 @@ pub struct DeJsonState {
     pub numbuf:String,
     pub identbuf:String,
     pub line: usize,
-    pub col: usize
+    pub col: usize,
+    // where the current token starts, errors point here
+    pub tok_line: usize,
+    pub tok_col: usize,
+    pub lenient: bool,
+    pub strict: bool,
 }
 
 pub struct DeJsonErr{
     pub msg:String,
     pub line:usize,
-    pub col:usize
+    pub col:usize,
+    // the field path of the value that failed, like builds[2].package
+    pub path:String,
+}
+
+impl DeJsonErr {
+    // errors get their path on the way out, so segments are prepended innermost first
+    pub fn in_field(mut self, name: &str) -> DeJsonErr {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            self.path.insert_str(0, name);
+        }
+        else {
+            self.path.insert(0, '.');
+            self.path.insert_str(0, name);
+        }
+        self
+    }
+    
+    pub fn in_index(mut self, index: usize) -> DeJsonErr {
+        if self.path.is_empty() || self.path.starts_with('[') {
+            self.path.insert_str(0, &format!("[{}]", index));
+        }
+        else {
+            self.path.insert_str(0, &format!("[{}].", index));
+        }
+        self
+    }
 }
 
 impl std::fmt::Debug for DeJsonErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Json Deserialize error: {}, line:{} col:{}", self.msg, self.line+1, self.col+1)
+        if self.path.is_empty() {
+            write!(f, "Json Deserialize error: {}, line:{} col:{}", self.msg, self.line+1, self.col+1)
+        }
+        else {
+            write!(f, "Json Deserialize error at {}: {}, line:{} col:{}", self.path, self.msg, self.line+1, self.col+1)
+        }
+    }
+}
+
+impl std::fmt::Display for DeJsonErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
     }
 }
 
@@ -134,38 +211,45 @@ impl DeJsonState {
         }
     }
     
+    pub fn err(&self, msg: String) -> DeJsonErr {
+        DeJsonErr{msg, line:self.tok_line, col:self.tok_col, path:String::new()}
+    }
+    
     pub fn err_exp(&self, name: &str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Unexpected key {}", name), line:self.line, col:self.col}
+        self.err(format!("Unexpected key {}", name))
     }
     
     pub fn err_nf(&self, name: &str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Key not found {}", name), line:self.line, col:self.col}
+        self.err(format!("Key not found {}", name))
     }
 
     pub fn err_enum(&self, name: &str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Enum not defined {}", name), line:self.line, col:self.col}
+        self.err(format!("Enum not defined {}", name))
     }
 
     pub fn err_token(&self, what:&str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Unexpected token {:?} expected {} ", self.tok, what), line:self.line, col:self.col}
+        self.err(format!("Unexpected token {:?} expected {}", self.tok, what))
     }
 
     pub fn err_range(&self, what:&str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Value out of range {} ", what), line:self.line, col:self.col}
+        self.err(format!("Value out of range {}", what))
     }
 
     pub fn err_type(&self, what:&str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Token wrong type {} ", what), line:self.line, col:self.col}
+        self.err(format!("Token wrong type {}", what))
     }
 
     pub fn err_parse(&self, what:&str) -> DeJsonErr {
-        DeJsonErr{msg:format!("Cannot parse {} ", what), line:self.line, col:self.col}
+        self.err(format!("Cannot parse {}", what))
     }
     
     pub fn eat_comma_block(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
         match self.tok {
             DeJsonTok::Comma => {
                 self.next_tok(i) ?;
+                if self.strict && self.tok == DeJsonTok::BlockClose {
+                    return Err(self.err("Trailing comma before ]".to_string()))
+                }
                 Ok(())
             },
             DeJsonTok::BlockClose => {
@@ -181,6 +265,9 @@ impl DeJsonState {
         match self.tok {
             DeJsonTok::Comma => {
                 self.next_tok(i) ?;
+                if self.strict && self.tok == DeJsonTok::CurlyClose {
+                    return Err(self.err("Trailing comma before }".to_string()))
+                }
                 Ok(())
             },
             DeJsonTok::CurlyClose => {
@@ -192,6 +279,24 @@ impl DeJsonState {
         }
     }
     
+    // the string under key in the object being read, looked up on a copy so the
+    // object can still be read from the start. the key can be anywhere in the object
+    pub fn peek_key_string(&self, i: &Chars, key: &str) -> Result<String, DeJsonErr> {
+        let mut s = self.clone();
+        let mut i = i.clone();
+        while s.next_str().is_some() {
+            if s.strbuf == key {
+                s.next_colon(&mut i) ?;
+                return s.as_string()
+            }
+            let name = s.as_string() ?;
+            s.next_colon(&mut i) ?;
+            JsonValue::de_json(&mut s, &mut i).map_err( | e | e.in_field(&name)) ?;
+            s.eat_comma_curly(&mut i) ?;
+        }
+        Err(self.err_nf(key))
+    }
+    
     pub fn colon(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
         match self.tok {
             DeJsonTok::Colon => {
@@ -323,9 +428,48 @@ impl DeJsonState {
         Err(self.err_token("string"))
     }
     
-    pub fn next_tok(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
-        while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' {
+    fn skip_comment(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+        self.next(i);
+        if self.cur == '/' { // single line comment
+            while self.cur != '\0' && self.cur != '\n' {
+                self.next(i);
+            }
+        }
+        else if self.cur == '*' { // multiline comment
             self.next(i);
+            let mut last_star = false;
+            loop {
+                if self.cur == '\0' {
+                    return Err(self.err_parse("comment, missing */"));
+                }
+                if self.cur == '/' && last_star {
+                    self.next(i);
+                    break;
+                }
+                last_star = self.cur == '*';
+                self.next(i);
+            }
+        }
+        else {
+            return Err(self.err_parse("comment"));
+        }
+        Ok(())
+    }
+    
+    pub fn next_tok(&mut self, i: &mut Chars) -> Result<(), DeJsonErr> {
+        loop {
+            while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' {
+                self.next(i);
+            }
+            self.tok_line = self.line;
+            self.tok_col = self.col.saturating_sub(1);
+            if self.cur != '/' {
+                break
+            }
+            if !self.lenient {
+                return Err(self.err("Unexpected character '/', comments are only accepted in lenient mode".to_string()));
+            }
+            self.skip_comment(i) ?;
         }
         if self.cur == '\0' {
             self.tok = DeJsonTok::Eof;
@@ -376,12 +520,26 @@ impl DeJsonState {
                     self.numbuf.push(self.cur);
                     self.next(i);
                 }
-                if self.cur == '.' {
-                    self.numbuf.push(self.cur);
-                    self.next(i);
-                    while self.cur >= '0' && self.cur <= '9' {
+                if self.cur == '.' || self.cur == 'e' || self.cur == 'E' {
+                    if self.cur == '.' {
                         self.numbuf.push(self.cur);
                         self.next(i);
+                        while self.cur >= '0' && self.cur <= '9' {
+                            self.numbuf.push(self.cur);
+                            self.next(i);
+                        }
+                    }
+                    if self.cur == 'e' || self.cur == 'E' {
+                        self.numbuf.push(self.cur);
+                        self.next(i);
+                        if self.cur == '-' || self.cur == '+' {
+                            self.numbuf.push(self.cur);
+                            self.next(i);
+                        }
+                        while self.cur >= '0' && self.cur <= '9' {
+                            self.numbuf.push(self.cur);
+                            self.next(i);
+                        }
                     }
                     if let Ok(num) = self.numbuf.parse() {
                         self.tok = DeJsonTok::F64(num);
@@ -397,24 +555,29 @@ impl DeJsonState {
                             self.tok = DeJsonTok::I64(num);
                             return Ok(())
                         }
-                        else {
-                            return Err(self.err_parse("number"));
+                        if let Ok(num) = self.numbuf.parse() {
+                            self.tok = DeJsonTok::I128(num);
+                            return Ok(())
                         }
+                        return Err(self.err_parse("number"));
                     }
                     if let Ok(num) = self.numbuf.parse() {
                         self.tok = DeJsonTok::U64(num);
                         return Ok(())
                     }
-                    else {
-                        return Err(self.err_parse("number"));
+                    if let Ok(num) = self.numbuf.parse() {
+                        self.tok = DeJsonTok::U128(num);
+                        return Ok(())
                     }
+                    return Err(self.err_parse("number"));
                 }
             },
-            'a'..='z' | 'A'..='Z' | '_' => {
+            'a'..='z' | 'A'..='Z' | '_' | '$' => {
                 self.identbuf.truncate(0);
                 while self.cur >= 'a' && self.cur <= 'z'
                     || self.cur >= 'A' && self.cur <= 'Z'
-                    || self.cur == '_' {
+                    || self.cur >= '0' && self.cur <= '9'
+                    || self.cur == '_' || self.cur == '$' {
                     self.identbuf.push(self.cur);
                     self.next(i);
                 }
@@ -430,8 +593,22 @@ impl DeJsonState {
                     self.tok = DeJsonTok::Null;
                     return Ok(())
                 }
+                // an unquoted key is only recognised by the colon following it
+                while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' {
+                    self.next(i);
+                }
+                if self.cur == ':' {
+                    if self.lenient {
+                        self.strbuf.truncate(0);
+                        self.strbuf.push_str(&self.identbuf);
+                        self.tok = DeJsonTok::Str;
+                        return Ok(())
+                    }
+                    self.tok = DeJsonTok::BareIdent;
+                    return Err(self.err(format!("Unquoted key {}, keys are only unquoted in lenient mode", self.identbuf)));
+                }
                 self.tok = DeJsonTok::BareIdent;
-                return Err(self.err_token(&format!("Got ##{}## needed true, false, null", self.identbuf)));
+                return Err(self.err(format!("Unexpected identifier {}, expected true, false or null", self.identbuf)));
             }
             '"' => {
                 self.strbuf.truncate(0);
@@ -443,7 +620,14 @@ impl DeJsonState {
                             'n'=>self.strbuf.push('\n'),
                             'r'=>self.strbuf.push('\r'),
                             't'=>self.strbuf.push('\t'),
+                            'b'=>self.strbuf.push('\u{8}'),
+                            'f'=>self.strbuf.push('\u{c}'),
                             '0'=>self.strbuf.push('\0'),
+                            'u'=>{
+                                let c = self.hex_escape(i) ?;
+                                self.strbuf.push(c);
+                                continue;
+                            },
                             '\0'=>{
                                 return Err(self.err_parse("string"));
                             },
@@ -464,10 +648,39 @@ impl DeJsonState {
                 return Ok(())
             },
             _ => {
-                return Err(self.err_token("tokenizer"));
+                return Err(self.err(format!("Unexpected character {:?}", self.cur)));
             }
         }
     }
+    
+    // reads the XXXX of a \uXXXX escape with cur on the u, and leaves cur after it
+    fn hex4(&mut self, i: &mut Chars) -> Result<u32, DeJsonErr> {
+        let mut v = 0;
+        for _ in 0..4 {
+            self.next(i);
+            v = v * 16 + self.cur.to_digit(16).ok_or_else( || self.err_parse("\\u escape")) ?;
+        }
+        self.next(i);
+        Ok(v)
+    }
+    
+    // utf16 surrogate pairs are written as two escapes
+    fn hex_escape(&mut self, i: &mut Chars) -> Result<char, DeJsonErr> {
+        let hi = self.hex4(i) ?;
+        if (0xd800..0xdc00).contains(&hi) {
+            if self.cur == '\\' {
+                self.next(i);
+                if self.cur == 'u' {
+                    let lo = self.hex4(i) ?;
+                    if (0xdc00..0xe000).contains(&lo) {
+                        return Ok(std::char::from_u32(0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)).unwrap())
+                    }
+                }
+            }
+            return Err(self.err_parse("\\u escape, unpaired surrogate"))
+        }
+        std::char::from_u32(hi).ok_or_else( || self.err_parse("\\u escape"))
+    }
 }
 
 macro_rules!impl_ser_de_json_unsigned {
@@ -535,9 +748,9 @@ impl_ser_de_json_unsigned!(u32, std::u32::MAX);
 impl_ser_de_json_unsigned!(u16, std::u16::MAX);
 impl_ser_de_json_unsigned!(u8, std::u8::MAX);
 impl_ser_de_json_signed!(i64, std::i64::MIN, std::i64::MAX);
-impl_ser_de_json_signed!(i32, std::i64::MIN, std::i64::MAX);
-impl_ser_de_json_signed!(i16, std::i64::MIN, std::i64::MAX);
-impl_ser_de_json_signed!(i8, std::i64::MIN, std::i8::MAX);
+impl_ser_de_json_signed!(i32, std::i32::MIN, std::i32::MAX);
+impl_ser_de_json_signed!(i16, std::i16::MIN, std::i16::MAX);
+impl_ser_de_json_signed!(i8, std::i8::MIN, std::i8::MAX);
 impl_ser_de_json_float!(f64);
 impl_ser_de_json_float!(f32);
 
@@ -583,6 +796,12 @@ impl DeJson for bool {
 }
 
 impl SerJson for String {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        self.as_str().ser_json(d, s)
+    }
+}
+
+impl SerJson for str {
     fn ser_json(&self, _d: usize, s: &mut SerJsonState) {
         s.out.push('"');
         for c in self.chars() {
@@ -590,9 +809,10 @@ impl SerJson for String {
                 '\n'=>{s.out.push('\\');s.out.push('n');},
                 '\r'=>{s.out.push('\\');s.out.push('r');},
                 '\t'=>{s.out.push('\\');s.out.push('t');},
-                '\0'=>{s.out.push('\\');s.out.push('0');},
+                '\0'=>s.out.push_str("\\u0000"),
                 '\\'=>{s.out.push('\\');s.out.push('\\');},
                 '"'=>{s.out.push('\\');s.out.push('"');},
+                c if (c as u32) < 0x20 => s.out.push_str(&format!("\\u{:04x}", c as u32)),
                 _=>s.out.push(c)
             }
         }
@@ -631,7 +851,7 @@ impl<T> DeJson for Vec<T> where T: DeJson {
         s.block_open(i) ?;
         
         while s.tok != DeJsonTok::BlockClose {
-            out.push(DeJson::de_json(s, i) ?);
+            out.push(DeJson::de_json(s, i).map_err( | e | e.in_index(out.len())) ?);
             s.eat_comma_block(i) ?;
         }
         s.block_close(i) ?;
@@ -641,22 +861,14 @@ impl<T> DeJson for Vec<T> where T: DeJson {
 
 impl<T> SerJson for [T] where T: SerJson {
     fn ser_json(&self, d: usize, s: &mut SerJsonState) {
-        s.out.push('[');
-        let last = self.len() -1;
-        for (index,item) in self.iter().enumerate() {
-            item.ser_json(d + 1, s);
-            if index != last{
-                s.out.push(',');
-            }
-        }
-        s.out.push(']');
+        ser_json_seq(self.iter(), d, s);
     }
 }
 
 unsafe fn de_json_array_impl_inner<T>(top: *mut T, count: usize, s: &mut DeJsonState, i: &mut Chars) -> Result<(), DeJsonErr> where T:DeJson{
     s.block_open(i) ?;
     for c in 0..count {
-        top.add(c).write(DeJson::de_json(s, i) ?);
+        top.add(c).write(DeJson::de_json(s, i).map_err( | e | e.in_index(c)) ?);
         s.eat_comma_block(i) ?;
     }
     s.block_close(i) ?;
@@ -671,7 +883,7 @@ macro_rules!de_json_array_impl {
             DeJsonErr> {
                 unsafe{
                     let mut to = std::mem::MaybeUninit::<[T; $count]>::uninit();
-                    let top: *mut T = std::mem::transmute(&mut to);
+                    let top = to.as_mut_ptr() as *mut T;
                     de_json_array_impl_inner(top, $count, s, i)?;
                     Ok(to.assume_init())
                 }
@@ -681,12 +893,14 @@ macro_rules!de_json_array_impl {
     }
 }
 
-de_json_array_impl!(2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31,32);
+de_json_array_impl!(1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31,32);
+de_json_array_impl!(33,34,35,36,37,38,39,40,41,42,43,44,45,46,47,48,49,50,51,52,53,54,55,56,57,58,59,60,61,62,63,64);
+de_json_array_impl!(96,128,192,256,384,512,1024,2048,4096);
 
-fn de_json_comma_block<T>(s: &mut DeJsonState, i: &mut Chars) -> Result<T, DeJsonErr> where T: DeJson {
-    let t = DeJson::de_json(s, i);
+fn de_json_comma_block<T>(s: &mut DeJsonState, i: &mut Chars, index: usize) -> Result<T, DeJsonErr> where T: DeJson {
+    let t = DeJson::de_json(s, i).map_err( | e | e.in_index(index)) ?;
     s.eat_comma_block(i) ?;
-    t
+    Ok(t)
 }
 
 impl<A, B> SerJson for (A, B) where A: SerJson,
@@ -704,7 +918,7 @@ impl<A, B> DeJson for (A, B) where A: DeJson,
 B: DeJson {
     fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B), DeJsonErr> {
         s.block_open(i) ?;
-        let r = (de_json_comma_block(s, i) ?, de_json_comma_block(s, i) ?);
+        let r = (de_json_comma_block(s, i, 0) ?, de_json_comma_block(s, i, 1) ?);
         s.block_close(i) ?;
         Ok(r)
     }
@@ -729,7 +943,7 @@ B: DeJson,
 C: DeJson {
     fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B, C), DeJsonErr> {
         s.block_open(i) ?;
-        let r = (de_json_comma_block(s, i) ?, de_json_comma_block(s, i) ?, de_json_comma_block(s, i) ?);
+        let r = (de_json_comma_block(s, i, 0) ?, de_json_comma_block(s, i, 1) ?, de_json_comma_block(s, i, 2) ?);
         s.block_close(i) ?;
         Ok(r)
     }
@@ -758,7 +972,7 @@ C: DeJson,
 D: DeJson {
     fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B, C, D), DeJsonErr> {
         s.block_open(i) ?;
-        let r = (de_json_comma_block(s, i) ?, de_json_comma_block(s, i) ?, de_json_comma_block(s, i) ?, de_json_comma_block(s, i) ?);
+        let r = (de_json_comma_block(s, i, 0) ?, de_json_comma_block(s, i, 1) ?, de_json_comma_block(s, i, 2) ?, de_json_comma_block(s, i, 3) ?);
         s.block_close(i) ?;
         Ok(r)
     }
@@ -767,21 +981,7 @@ D: DeJson {
 impl<K, V> SerJson for HashMap<K, V> where K: SerJson,
 V: SerJson {
     fn ser_json(&self, d: usize, s: &mut SerJsonState) {
-        s.out.push('{');
-        let last = self.len() - 1;
-        let mut index = 0;
-        for (k, v) in self {
-            s.indent(d + 1);
-            k.ser_json(d + 1, s);
-            s.out.push(':');
-            v.ser_json(d + 1, s);
-            if index != last{
-                s.conl();
-            }
-            index += 1;
-        }
-        s.indent(d);
-        s.out.push('}');
+        ser_json_map(self.iter(), d, s);
     }
 }
 
@@ -813,4 +1013,491 @@ impl<T> DeJson for Box<T> where T: DeJson {
     fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Box<T>, DeJsonErr> {
         Ok(Box::new(DeJson::de_json(s, i) ?))
     }
-}
\ No newline at end of file
+}
+fn ser_json_seq<'a, T: 'a + SerJson>(items: impl Iterator<Item = &'a T>, d: usize, s: &mut SerJsonState) {
+    s.out.push('[');
+    for (index, item) in items.enumerate() {
+        if index != 0 {
+            s.out.push(',');
+        }
+        s.indent(d + 1);
+        item.ser_json(d + 1, s);
+    }
+    s.out.push(']');
+}
+
+fn ser_json_map<'a, K: 'a + SerJson, V: 'a + SerJson>(items: impl Iterator<Item = (&'a K, &'a V)>, d: usize, s: &mut SerJsonState) {
+    s.out.push('{');
+    for (index, (k, v)) in items.enumerate() {
+        if index != 0 {
+            s.conl();
+        }
+        s.indent(d + 1);
+        k.ser_json(d + 1, s);
+        s.out.push(':');
+        v.ser_json(d + 1, s);
+    }
+    s.indent(d);
+    s.out.push('}');
+}
+
+impl SerJson for u128 {
+    fn ser_json(&self, _d: usize, s: &mut SerJsonState) {
+        s.out.push_str(&self.to_string());
+    }
+}
+
+impl DeJson for u128 {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<u128, DeJsonErr> {
+        let val = match s.tok {
+            DeJsonTok::U64(v) => v as u128,
+            DeJsonTok::U128(v) => v,
+            DeJsonTok::I64(_) | DeJsonTok::I128(_) => return Err(s.err_range("negative value for u128")),
+            _ => return Err(s.err_token("unsigned integer"))
+        };
+        s.next_tok(i) ?;
+        Ok(val)
+    }
+}
+
+impl SerJson for i128 {
+    fn ser_json(&self, _d: usize, s: &mut SerJsonState) {
+        s.out.push_str(&self.to_string());
+    }
+}
+
+impl DeJson for i128 {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<i128, DeJsonErr> {
+        let val = match s.tok {
+            DeJsonTok::U64(v) => v as i128,
+            DeJsonTok::I64(v) => v as i128,
+            DeJsonTok::I128(v) => v,
+            DeJsonTok::U128(v) if v <= i128::MAX as u128 => v as i128,
+            DeJsonTok::U128(v) => return Err(s.err_range(&format!("{}>{}", v, i128::MAX))),
+            _ => return Err(s.err_token("signed integer"))
+        };
+        s.next_tok(i) ?;
+        Ok(val)
+    }
+}
+
+impl<K, V> SerJson for BTreeMap<K, V> where K: SerJson,
+V: SerJson {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        ser_json_map(self.iter(), d, s);
+    }
+}
+
+impl<K, V> DeJson for BTreeMap<K, V> where K: DeJson + Ord,
+V: DeJson {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self,
+    DeJsonErr> {
+        let mut h = BTreeMap::new();
+        s.curly_open(i) ?;
+        while s.tok != DeJsonTok::CurlyClose {
+            let k = DeJson::de_json(s, i) ?;
+            s.colon(i) ?;
+            let v = DeJson::de_json(s, i) ?;
+            s.eat_comma_curly(i) ?;
+            h.insert(k, v);
+        }
+        s.curly_close(i) ?;
+        Ok(h)
+    }
+}
+
+// sets and deques are written like a Vec
+macro_rules!impl_ser_de_json_seq {
+    ( $ ty: ident $ (, $ bound: ident) *) => {
+        impl<T> SerJson for $ ty<T> where T: SerJson {
+            fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+                ser_json_seq(self.iter(), d, s);
+            }
+        }
+        
+        impl<T> DeJson for $ ty<T> where T: DeJson $ (+ $ bound) * {
+            fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+                let mut out = $ ty::new();
+                s.block_open(i) ?;
+                let mut index = 0;
+                while s.tok != DeJsonTok::BlockClose {
+                    out.extend(Some(DeJson::de_json(s, i).map_err( | e | e.in_index(index)) ?));
+                    index += 1;
+                    s.eat_comma_block(i) ?;
+                }
+                s.block_close(i) ?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+impl_ser_de_json_seq!(HashSet, Eq, Hash);
+impl_ser_de_json_seq!(BTreeSet, Ord);
+impl_ser_de_json_seq!(VecDeque);
+
+impl<T> SerJson for Rc<T> where T: SerJson + ?Sized {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        (**self).ser_json(d, s)
+    }
+}
+
+impl<T> DeJson for Rc<T> where T: DeJson {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Rc<T>, DeJsonErr> {
+        Ok(Rc::new(DeJson::de_json(s, i) ?))
+    }
+}
+
+impl<T> SerJson for Arc<T> where T: SerJson + ?Sized {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        (**self).ser_json(d, s)
+    }
+}
+
+impl<T> DeJson for Arc<T> where T: DeJson {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Arc<T>, DeJsonErr> {
+        Ok(Arc::new(DeJson::de_json(s, i) ?))
+    }
+}
+
+impl<'a, T> SerJson for Cow<'a, T> where T: SerJson + ToOwned + ?Sized {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        (**self).ser_json(d, s)
+    }
+}
+
+impl<'a, T> DeJson for Cow<'a, T> where T: ToOwned + ?Sized, T::Owned: DeJson {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Cow<'a, T>, DeJsonErr> {
+        Ok(Cow::Owned(DeJson::de_json(s, i) ?))
+    }
+}
+
+// Duration and SystemTime are written as a two field object
+fn ser_json_pair<A: SerJson, B: SerJson>(a: (&str, A), b: (&str, B), d: usize, s: &mut SerJsonState) {
+    s.st_pre();
+    s.field(d + 1, a.0);
+    a.1.ser_json(d + 1, s);
+    s.conl();
+    s.field(d + 1, b.0);
+    b.1.ser_json(d + 1, s);
+    s.st_post(d);
+}
+
+fn de_json_pair<A: DeJson, B: DeJson>(a: &str, b: &str, s: &mut DeJsonState, i: &mut Chars) -> Result<(A, B), DeJsonErr> {
+    let mut va = None;
+    let mut vb = None;
+    s.curly_open(i) ?;
+    while s.next_str().is_some() {
+        if s.strbuf == a {
+            s.next_colon(i) ?;
+            va = Some(DeJson::de_json(s, i) ?);
+        }
+        else if s.strbuf == b {
+            s.next_colon(i) ?;
+            vb = Some(DeJson::de_json(s, i) ?);
+        }
+        else {
+            return Err(s.err_exp(&s.strbuf));
+        }
+        s.eat_comma_curly(i) ?;
+    }
+    s.curly_close(i) ?;
+    match (va, vb) {
+        (Some(va), Some(vb)) => Ok((va, vb)),
+        (None, _) => Err(s.err_nf(a)),
+        (_, None) => Err(s.err_nf(b)),
+    }
+}
+
+impl SerJson for Duration {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        ser_json_pair(("secs", self.as_secs()), ("nanos", self.subsec_nanos()), d, s);
+    }
+}
+
+impl DeJson for Duration {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Duration, DeJsonErr> {
+        let (secs, nanos): (u64, u32) = de_json_pair("secs", "nanos", s, i) ?;
+        if nanos >= 1_000_000_000 {
+            return Err(s.err_range(&format!("Duration nanos {}", nanos)))
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl SerJson for SystemTime {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        let (secs, nanos) = system_time_to_unix(self);
+        ser_json_pair(("secs_since_epoch", secs), ("nanos_since_epoch", nanos), d, s);
+    }
+}
+
+impl DeJson for SystemTime {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<SystemTime, DeJsonErr> {
+        let (secs, nanos): (i64, u32) = de_json_pair("secs_since_epoch", "nanos_since_epoch", s, i) ?;
+        system_time_from_unix(secs, nanos).ok_or_else( || s.err_range(&format!("SystemTime {}s {}ns", secs, nanos)))
+    }
+}
+
+// dynamic json value, objects keep their keys in document order
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Str(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "boolean",
+            JsonValue::U64(_) | JsonValue::I64(_) => "integer",
+            JsonValue::F64(_) => "float",
+            JsonValue::Str(_) => "string",
+            JsonValue::Array(_) => "array",
+            JsonValue::Object(_) => "object",
+        }
+    }
+    
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        if let JsonValue::Object(items) = self {
+            for (k, v) in items {
+                if k == key {
+                    return Some(v)
+                }
+            }
+        }
+        None
+    }
+    
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsonValue> {
+        if let JsonValue::Object(items) = self {
+            for (k, v) in items {
+                if k == key {
+                    return Some(v)
+                }
+            }
+        }
+        None
+    }
+    
+    pub fn index(&self, index: usize) -> Option<&JsonValue> {
+        if let JsonValue::Array(items) = self {
+            return items.get(index)
+        }
+        None
+    }
+    
+    // looks up a dotted path like "files.0.name", numeric segments index into arrays
+    pub fn path(&self, path: &str) -> Option<&JsonValue> {
+        let mut node = self;
+        for key in path.split('.') {
+            node = match node {
+                JsonValue::Array(_) => node.index(key.parse().ok() ?) ?,
+                _ => node.get(key) ?
+            };
+        }
+        Some(node)
+    }
+    
+    pub fn insert(&mut self, key: &str, value: JsonValue) {
+        if let JsonValue::Object(items) = self {
+            for (k, v) in items.iter_mut() {
+                if k == key {
+                    *v = value;
+                    return
+                }
+            }
+            items.push((key.to_string(), value));
+        }
+    }
+    
+    pub fn is_null(&self) -> bool {
+        matches!(self, JsonValue::Null)
+    }
+    
+    pub fn as_str(&self) -> Option<&str> {
+        if let JsonValue::Str(v) = self {Some(v)} else {None}
+    }
+    
+    pub fn as_bool(&self) -> Option<bool> {
+        if let JsonValue::Bool(v) = self {Some(*v)} else {None}
+    }
+    
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::U64(v) => Some(*v),
+            JsonValue::I64(v) if *v >= 0 => Some(*v as u64),
+            _ => None
+        }
+    }
+    
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::I64(v) => Some(*v),
+            JsonValue::U64(v) if *v <= i64::MAX as u64 => Some(*v as i64),
+            _ => None
+        }
+    }
+    
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::F64(v) => Some(*v),
+            JsonValue::U64(v) => Some(*v as f64),
+            JsonValue::I64(v) => Some(*v as f64),
+            _ => None
+        }
+    }
+    
+    pub fn as_array(&self) -> Option<&Vec<JsonValue >> {
+        if let JsonValue::Array(v) = self {Some(v)} else {None}
+    }
+    
+    pub fn as_object(&self) -> Option<&Vec<(String, JsonValue) >> {
+        if let JsonValue::Object(v) = self {Some(v)} else {None}
+    }
+    
+    // writes the value indented with 4 spaces per level, serialize_json stays compact
+    pub fn write_pretty(&self) -> String {
+        let mut s = SerJsonState {
+            out: String::new()
+        };
+        self.ser_json_pretty(0, &mut s);
+        s.out
+    }
+    
+    fn ser_json_pretty(&self, d: usize, s: &mut SerJsonState) {
+        fn indent(d: usize, s: &mut SerJsonState) {
+            for _ in 0..d {
+                s.out.push_str("    ");
+            }
+        }
+        match self {
+            JsonValue::Array(items) if !items.is_empty() => {
+                s.out.push_str("[\n");
+                for (index, item) in items.iter().enumerate() {
+                    indent(d + 1, s);
+                    item.ser_json_pretty(d + 1, s);
+                    if index != items.len() - 1 {
+                        s.out.push(',');
+                    }
+                    s.out.push('\n');
+                }
+                indent(d, s);
+                s.out.push(']');
+            }
+            JsonValue::Object(items) if !items.is_empty() => {
+                s.out.push_str("{\n");
+                for (index, (k, v)) in items.iter().enumerate() {
+                    indent(d + 1, s);
+                    k.ser_json(d + 1, s);
+                    s.out.push_str(": ");
+                    v.ser_json_pretty(d + 1, s);
+                    if index != items.len() - 1 {
+                        s.out.push(',');
+                    }
+                    s.out.push('\n');
+                }
+                indent(d, s);
+                s.out.push('}');
+            }
+            _ => self.ser_json(d, s)
+        }
+    }
+}
+
+impl SerJson for JsonValue {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        match self {
+            JsonValue::Null => s.out.push_str("null"),
+            JsonValue::Bool(v) => v.ser_json(d, s),
+            JsonValue::U64(v) => v.ser_json(d, s),
+            JsonValue::I64(v) => v.ser_json(d, s),
+            JsonValue::F64(v) => {
+                // json has no nan or infinity, and 1.0 has to stay a float when read back
+                if !v.is_finite() {
+                    s.out.push_str("null");
+                }
+                else {
+                    let start = s.out.len();
+                    v.ser_json(d, s);
+                    if !s.out[start..].contains(['.', 'e']) {
+                        s.out.push_str(".0");
+                    }
+                }
+            }
+            JsonValue::Str(v) => v.ser_json(d, s),
+            JsonValue::Array(items) => {
+                s.out.push('[');
+                for (index, item) in items.iter().enumerate() {
+                    if index != 0 {
+                        s.out.push(',');
+                    }
+                    item.ser_json(d + 1, s);
+                }
+                s.out.push(']');
+            }
+            JsonValue::Object(items) => {
+                s.st_pre();
+                for (index, (k, v)) in items.iter().enumerate() {
+                    if index != 0 {
+                        s.conl();
+                    }
+                    k.ser_json(d + 1, s);
+                    s.out.push(':');
+                    v.ser_json(d + 1, s);
+                }
+                s.st_post(d);
+            }
+        }
+    }
+}
+
+impl DeJson for JsonValue {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<Self, DeJsonErr> {
+        let value = match s.tok {
+            DeJsonTok::Null => JsonValue::Null,
+            DeJsonTok::Bool(v) => JsonValue::Bool(v),
+            DeJsonTok::U64(v) => JsonValue::U64(v),
+            DeJsonTok::I64(v) => JsonValue::I64(v),
+            DeJsonTok::F64(v) => JsonValue::F64(v),
+            // like most json readers, integers beyond 64 bits become floats
+            DeJsonTok::U128(v) => JsonValue::F64(v as f64),
+            DeJsonTok::I128(v) => JsonValue::F64(v as f64),
+            DeJsonTok::Str => JsonValue::Str(s.as_string() ?),
+            DeJsonTok::BlockOpen => {
+                let mut items = Vec::new();
+                s.block_open(i) ?;
+                while s.tok != DeJsonTok::BlockClose {
+                    items.push(DeJson::de_json(s, i).map_err( | e | e.in_index(items.len())) ?);
+                    s.eat_comma_block(i) ?;
+                }
+                s.block_close(i) ?;
+                return Ok(JsonValue::Array(items))
+            }
+            DeJsonTok::CurlyOpen => {
+                let mut obj = JsonValue::Object(Vec::new());
+                s.curly_open(i) ?;
+                while s.tok != DeJsonTok::CurlyClose {
+                    let key = s.as_string() ?;
+                    s.next_colon(i) ?;
+                    let value = DeJson::de_json(s, i).map_err( | e | e.in_field(&key)) ?;
+                    s.eat_comma_curly(i) ?;
+                    obj.insert(&key, value);
+                }
+                s.curly_close(i) ?;
+                return Ok(obj)
+            }
+            _ => return Err(s.err_token("json value"))
+        };
+        s.next_tok(i) ?;
+        Ok(value)
+    }
+}
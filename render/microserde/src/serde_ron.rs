@@ -1,6 +1,11 @@
-use std::collections::{HashMap};
+use std::borrow::{Cow, ToOwned};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::hash::Hash;
+use std::rc::Rc;
 use std::str::Chars;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use crate::serde_bin::{system_time_from_unix, system_time_to_unix};
 
 pub struct SerRonState {
     pub out: String
@@ -68,6 +73,9 @@ pub enum DeRonTok {
     Str,
     U64(u64),
     I64(i64),
+    // integers that do not fit in 64 bits
+    U128(u128),
+    I128(i128),
     F64(f64),
     Bool(bool),
     Char(char),
@@ -460,23 +468,28 @@ impl DeRonState {
                                 self.tok = DeRonTok::I64(num);
                                 return Ok(())
                             }
-                            else {
-                                return Err(self.err_parse("number"));
+                            if let Ok(num) = self.numbuf.parse() {
+                                self.tok = DeRonTok::I128(num);
+                                return Ok(())
                             }
+                            return Err(self.err_parse("number"));
                         }
                         if let Ok(num) = self.numbuf.parse() {
                             self.tok = DeRonTok::U64(num);
                             return Ok(())
                         }
-                        else {
-                            return Err(self.err_parse("number"));
+                        if let Ok(num) = self.numbuf.parse() {
+                            self.tok = DeRonTok::U128(num);
+                            return Ok(())
                         }
+                        return Err(self.err_parse("number"));
                     }
                 },
                 'a'..='z' | 'A'..='Z' | '_' => {
                     self.identbuf.truncate(0);
                     while self.cur >= 'a' && self.cur <= 'z'
                         || self.cur >= 'A' && self.cur <= 'Z'
+                        || self.cur >= '0' && self.cur <= '9'
                         || self.cur == '_' {
                         self.identbuf.push(self.cur);
                         self.next(i);
@@ -494,16 +507,26 @@ impl DeRonState {
                 },
                 '\'' => {
                     self.next(i);
-                    if self.cur == '\\' {
+                    let chr = if self.cur == '\\' {
                         self.next(i);
+                        match self.cur {
+                            'n' => '\n',
+                            'r' => '\r',
+                            't' => '\t',
+                            '0' => '\0',
+                            c => c
+                        }
                     }
-                    let chr = self.cur;
+                    else {
+                        self.cur
+                    };
                     self.next(i);
                     if self.cur != '\'' {
                         return Err(self.err_token("char"));
                     }
                     self.next(i);
                     self.tok = DeRonTok::Char(chr);
+                    return Ok(())
                 },
                 '"' => {
                     self.strbuf.truncate(0);
@@ -659,6 +682,12 @@ impl DeRon for bool {
 }
 
 impl SerRon for String {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        self.as_str().ser_ron(d, s)
+    }
+}
+
+impl SerRon for str {
     fn ser_ron(&self, _d: usize, s: &mut SerRonState) {
         s.out.push('"');
         for c in self.chars() {
@@ -686,14 +715,7 @@ impl DeRon for String {
 
 impl<T> SerRon for Vec<T> where T: SerRon {
     fn ser_ron(&self, d: usize, s: &mut SerRonState) {
-        s.out.push_str("[\n");
-        for item in self {
-            s.indent(d + 1);
-            item.ser_ron(d + 1, s);
-            s.conl();
-        }
-        s.indent(d);
-        s.out.push(']');
+        ser_ron_seq(self.iter(), d, s);
     }
 }
 
@@ -714,12 +736,11 @@ impl<T> DeRon for Vec<T> where T: DeRon {
 impl<T> SerRon for [T] where T: SerRon {
     fn ser_ron(&self, d: usize, s: &mut SerRonState) {
         s.out.push('(');
-        let last = self.len() - 1;
         for (index, item) in self.iter().enumerate() {
-            item.ser_ron(d + 1, s);
-            if index != last {
+            if index != 0 {
                 s.out.push_str(", ");
             }
+            item.ser_ron(d + 1, s);
         }
         s.out.push(')');
     }
@@ -743,7 +764,7 @@ macro_rules!de_ron_array_impl {
                 DeRonErr> {
                     unsafe {
                         let mut to = std::mem::MaybeUninit::<[T; $ count]>::uninit();
-                        let top: *mut T = std::mem::transmute(&mut to);
+                        let top = to.as_mut_ptr() as *mut T;
                         de_ron_array_impl_inner(top, $ count, s, i) ?;
                         Ok(to.assume_init())
                     }
@@ -753,7 +774,9 @@ macro_rules!de_ron_array_impl {
     }
 }
 
-de_ron_array_impl!(2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32);
+de_ron_array_impl!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32);
+de_ron_array_impl!(33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64);
+de_ron_array_impl!(96, 128, 192, 256, 384, 512, 1024, 2048, 4096);
 
 fn de_ron_comma_paren<T>(s: &mut DeRonState, i: &mut Chars) -> Result<T, DeRonErr> where T: DeRon {
     let t = DeRon::de_ron(s, i);
@@ -839,16 +862,7 @@ D: DeRon {
 impl<K, V> SerRon for HashMap<K, V> where K: SerRon,
 V: SerRon {
     fn ser_ron(&self, d: usize, s: &mut SerRonState) {
-        s.out.push_str("{\n");
-        for (k, v) in self {
-            s.indent(d + 1);
-            k.ser_ron(d + 1, s);
-            s.out.push_str(":");
-            v.ser_ron(d + 1, s);
-            s.conl();
-        }
-        s.indent(d);
-        s.out.push('}');
+        ser_ron_map(self.iter(), d, s);
     }
 }
 
@@ -880,4 +894,559 @@ impl<T> DeRon for Box<T> where T: DeRon {
     fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Box<T>, DeRonErr> {
         Ok(Box::new(DeRon::de_ron(s, i) ?))
     }
-}
\ No newline at end of file
+}
+fn ser_ron_seq<'a, T: 'a + SerRon>(items: impl Iterator<Item = &'a T>, d: usize, s: &mut SerRonState) {
+    s.out.push_str("[\n");
+    for item in items {
+        s.indent(d + 1);
+        item.ser_ron(d + 1, s);
+        s.conl();
+    }
+    s.indent(d);
+    s.out.push(']');
+}
+
+fn ser_ron_map<'a, K: 'a + SerRon, V: 'a + SerRon>(items: impl Iterator<Item = (&'a K, &'a V)>, d: usize, s: &mut SerRonState) {
+    s.out.push_str("{\n");
+    for (k, v) in items {
+        s.indent(d + 1);
+        k.ser_ron(d + 1, s);
+        s.out.push(':');
+        v.ser_ron(d + 1, s);
+        s.conl();
+    }
+    s.indent(d);
+    s.out.push('}');
+}
+
+impl SerRon for u128 {
+    fn ser_ron(&self, _d: usize, s: &mut SerRonState) {
+        s.out.push_str(&self.to_string());
+    }
+}
+
+impl DeRon for u128 {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<u128, DeRonErr> {
+        let val = match s.tok {
+            DeRonTok::U64(v) => v as u128,
+            DeRonTok::U128(v) => v,
+            DeRonTok::I64(_) | DeRonTok::I128(_) => return Err(s.err_range("negative value for u128")),
+            _ => return Err(s.err_token("unsigned integer"))
+        };
+        s.next_tok(i) ?;
+        Ok(val)
+    }
+}
+
+impl SerRon for i128 {
+    fn ser_ron(&self, _d: usize, s: &mut SerRonState) {
+        s.out.push_str(&self.to_string());
+    }
+}
+
+impl DeRon for i128 {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<i128, DeRonErr> {
+        let val = match s.tok {
+            DeRonTok::U64(v) => v as i128,
+            DeRonTok::I64(v) => v as i128,
+            DeRonTok::I128(v) => v,
+            DeRonTok::U128(v) if v <= i128::MAX as u128 => v as i128,
+            DeRonTok::U128(v) => return Err(s.err_range(&format!("{}>{}", v, i128::MAX))),
+            _ => return Err(s.err_token("signed integer"))
+        };
+        s.next_tok(i) ?;
+        Ok(val)
+    }
+}
+
+impl<K, V> SerRon for BTreeMap<K, V> where K: SerRon,
+V: SerRon {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        ser_ron_map(self.iter(), d, s);
+    }
+}
+
+impl<K, V> DeRon for BTreeMap<K, V> where K: DeRon + Ord,
+V: DeRon {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Self,
+    DeRonErr> {
+        let mut h = BTreeMap::new();
+        s.curly_open(i) ?;
+        while s.tok != DeRonTok::CurlyClose {
+            let k = DeRon::de_ron(s, i) ?;
+            s.colon(i) ?;
+            let v = DeRon::de_ron(s, i) ?;
+            s.eat_comma_curly(i) ?;
+            h.insert(k, v);
+        }
+        s.curly_close(i) ?;
+        Ok(h)
+    }
+}
+
+// sets and deques are written like a Vec
+macro_rules!impl_ser_de_ron_seq {
+    ( $ ty: ident $ (, $ bound: ident) *) => {
+        impl<T> SerRon for $ ty<T> where T: SerRon {
+            fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+                ser_ron_seq(self.iter(), d, s);
+            }
+        }
+        
+        impl<T> DeRon for $ ty<T> where T: DeRon $ (+ $ bound) * {
+            fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Self, DeRonErr> {
+                let mut out = $ ty::new();
+                s.block_open(i) ?;
+                while s.tok != DeRonTok::BlockClose {
+                    out.extend(Some(DeRon::de_ron(s, i) ?));
+                    s.eat_comma_block(i) ?;
+                }
+                s.block_close(i) ?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+impl_ser_de_ron_seq!(HashSet, Eq, Hash);
+impl_ser_de_ron_seq!(BTreeSet, Ord);
+impl_ser_de_ron_seq!(VecDeque);
+
+impl<T> SerRon for Rc<T> where T: SerRon + ?Sized {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        (**self).ser_ron(d, s)
+    }
+}
+
+impl<T> DeRon for Rc<T> where T: DeRon {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Rc<T>, DeRonErr> {
+        Ok(Rc::new(DeRon::de_ron(s, i) ?))
+    }
+}
+
+impl<T> SerRon for Arc<T> where T: SerRon + ?Sized {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        (**self).ser_ron(d, s)
+    }
+}
+
+impl<T> DeRon for Arc<T> where T: DeRon {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Arc<T>, DeRonErr> {
+        Ok(Arc::new(DeRon::de_ron(s, i) ?))
+    }
+}
+
+impl<'a, T> SerRon for Cow<'a, T> where T: SerRon + ToOwned + ?Sized {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        (**self).ser_ron(d, s)
+    }
+}
+
+impl<'a, T> DeRon for Cow<'a, T> where T: ToOwned + ?Sized, T::Owned: DeRon {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Cow<'a, T>, DeRonErr> {
+        Ok(Cow::Owned(DeRon::de_ron(s, i) ?))
+    }
+}
+
+// Duration and SystemTime are written as a two field struct
+fn ser_ron_pair<A: SerRon, B: SerRon>(a: (&str, A), b: (&str, B), d: usize, s: &mut SerRonState) {
+    s.st_pre();
+    s.field(d + 1, a.0);
+    a.1.ser_ron(d + 1, s);
+    s.conl();
+    s.field(d + 1, b.0);
+    b.1.ser_ron(d + 1, s);
+    s.conl();
+    s.st_post(d);
+}
+
+fn de_ron_pair<A: DeRon, B: DeRon>(a: &str, b: &str, s: &mut DeRonState, i: &mut Chars) -> Result<(A, B), DeRonErr> {
+    let mut va = None;
+    let mut vb = None;
+    s.paren_open(i) ?;
+    while s.next_ident().is_some() {
+        if s.identbuf == a {
+            s.next_colon(i) ?;
+            va = Some(DeRon::de_ron(s, i) ?);
+        }
+        else if s.identbuf == b {
+            s.next_colon(i) ?;
+            vb = Some(DeRon::de_ron(s, i) ?);
+        }
+        else {
+            return Err(s.err_exp(&s.identbuf));
+        }
+        s.eat_comma_paren(i) ?;
+    }
+    s.paren_close(i) ?;
+    match (va, vb) {
+        (Some(va), Some(vb)) => Ok((va, vb)),
+        (None, _) => Err(s.err_nf(a)),
+        (_, None) => Err(s.err_nf(b)),
+    }
+}
+
+impl SerRon for Duration {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        ser_ron_pair(("secs", self.as_secs()), ("nanos", self.subsec_nanos()), d, s);
+    }
+}
+
+impl DeRon for Duration {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Duration, DeRonErr> {
+        let (secs, nanos): (u64, u32) = de_ron_pair("secs", "nanos", s, i) ?;
+        if nanos >= 1_000_000_000 {
+            return Err(s.err_range(&format!("Duration nanos {}", nanos)))
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+impl SerRon for SystemTime {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        let (secs, nanos) = system_time_to_unix(self);
+        ser_ron_pair(("secs_since_epoch", secs), ("nanos_since_epoch", nanos), d, s);
+    }
+}
+
+impl DeRon for SystemTime {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<SystemTime, DeRonErr> {
+        let (secs, nanos): (i64, u32) = de_ron_pair("secs_since_epoch", "nanos_since_epoch", s, i) ?;
+        system_time_from_unix(secs, nanos).ok_or_else( || s.err_range(&format!("SystemTime {}s {}ns", secs, nanos)))
+    }
+}
+
+// dynamic ron value, structs and maps keep their entries in document order
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RonValue {
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Char(char),
+    Str(String),
+    // a bare identifier, like a unit enum variant or None
+    Ident(String),
+    // (a, b) or Name(a, b), () is an empty tuple
+    Tuple(Option<String>, Vec<RonValue>),
+    // (a: 1) or Name(a: 1)
+    Struct(Option<String>, Vec<(String, RonValue)>),
+    List(Vec<RonValue>),
+    Map(Vec<(RonValue, RonValue)>),
+}
+
+impl RonValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RonValue::Bool(_) => "boolean",
+            RonValue::U64(_) | RonValue::I64(_) => "integer",
+            RonValue::F64(_) => "float",
+            RonValue::Char(_) => "char",
+            RonValue::Str(_) => "string",
+            RonValue::Ident(_) => "identifier",
+            RonValue::Tuple(_, _) => "tuple",
+            RonValue::Struct(_, _) => "struct",
+            RonValue::List(_) => "list",
+            RonValue::Map(_) => "map",
+        }
+    }
+    
+    // the identifier of a unit, named tuple or named struct
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            RonValue::Ident(name) => Some(name),
+            RonValue::Tuple(Some(name), _) | RonValue::Struct(Some(name), _) => Some(name),
+            _ => None
+        }
+    }
+    
+    // looks up a struct field, or a map entry with a string or identifier key
+    pub fn get(&self, key: &str) -> Option<&RonValue> {
+        match self {
+            RonValue::Struct(_, fields) => {
+                for (k, v) in fields {
+                    if k == key {
+                        return Some(v)
+                    }
+                }
+            }
+            RonValue::Map(items) => {
+                for (k, v) in items {
+                    match k {
+                        RonValue::Str(k) | RonValue::Ident(k) if k == key => return Some(v),
+                        _ => ()
+                    }
+                }
+            }
+            _ => ()
+        }
+        None
+    }
+    
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut RonValue> {
+        match self {
+            RonValue::Struct(_, fields) => {
+                for (k, v) in fields {
+                    if k == key {
+                        return Some(v)
+                    }
+                }
+            }
+            RonValue::Map(items) => {
+                for (k, v) in items {
+                    match k {
+                        RonValue::Str(k) | RonValue::Ident(k) if k == key => return Some(v),
+                        _ => ()
+                    }
+                }
+            }
+            _ => ()
+        }
+        None
+    }
+    
+    pub fn index(&self, index: usize) -> Option<&RonValue> {
+        match self {
+            RonValue::List(items) | RonValue::Tuple(_, items) => items.get(index),
+            _ => None
+        }
+    }
+    
+    // looks up a dotted path like "builders.0.name", numeric segments index into lists and tuples
+    pub fn path(&self, path: &str) -> Option<&RonValue> {
+        let mut node = self;
+        for key in path.split('.') {
+            node = match node {
+                RonValue::List(_) | RonValue::Tuple(_, _) => node.index(key.parse().ok() ?) ?,
+                _ => node.get(key) ?
+            };
+        }
+        Some(node)
+    }
+    
+    pub fn as_str(&self) -> Option<&str> {
+        if let RonValue::Str(v) = self {Some(v)} else {None}
+    }
+    
+    pub fn as_ident(&self) -> Option<&str> {
+        if let RonValue::Ident(v) = self {Some(v)} else {None}
+    }
+    
+    pub fn as_bool(&self) -> Option<bool> {
+        if let RonValue::Bool(v) = self {Some(*v)} else {None}
+    }
+    
+    pub fn as_char(&self) -> Option<char> {
+        if let RonValue::Char(v) = self {Some(*v)} else {None}
+    }
+    
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            RonValue::U64(v) => Some(*v),
+            RonValue::I64(v) if *v >= 0 => Some(*v as u64),
+            _ => None
+        }
+    }
+    
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            RonValue::I64(v) => Some(*v),
+            RonValue::U64(v) if *v <= i64::MAX as u64 => Some(*v as i64),
+            _ => None
+        }
+    }
+    
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            RonValue::F64(v) => Some(*v),
+            RonValue::U64(v) => Some(*v as f64),
+            RonValue::I64(v) => Some(*v as f64),
+            _ => None
+        }
+    }
+    
+    pub fn as_list(&self) -> Option<&Vec<RonValue >> {
+        if let RonValue::List(v) = self {Some(v)} else {None}
+    }
+    
+    pub fn as_fields(&self) -> Option<&Vec<(String, RonValue) >> {
+        if let RonValue::Struct(_, v) = self {Some(v)} else {None}
+    }
+}
+
+impl SerRon for RonValue {
+    fn ser_ron(&self, d: usize, s: &mut SerRonState) {
+        match self {
+            RonValue::Bool(v) => v.ser_ron(d, s),
+            RonValue::U64(v) => v.ser_ron(d, s),
+            RonValue::I64(v) => v.ser_ron(d, s),
+            RonValue::F64(v) => {
+                // the tokenizer only reads a float when it has a fraction
+                let start = s.out.len();
+                v.ser_ron(d, s);
+                if v.is_finite() && !s.out[start..].contains('.') {
+                    s.out.push_str(".0");
+                }
+            }
+            RonValue::Char(v) => {
+                s.out.push('\'');
+                match v {
+                    '\n' => s.out.push_str("\\n"),
+                    '\r' => s.out.push_str("\\r"),
+                    '\t' => s.out.push_str("\\t"),
+                    '\0' => s.out.push_str("\\0"),
+                    '\\' => s.out.push_str("\\\\"),
+                    '\'' => s.out.push_str("\\'"),
+                    _ => s.out.push(*v)
+                }
+                s.out.push('\'');
+            }
+            RonValue::Str(v) => v.ser_ron(d, s),
+            RonValue::Ident(v) => s.out.push_str(v),
+            RonValue::Tuple(name, items) => {
+                if let Some(name) = name {
+                    s.out.push_str(name);
+                }
+                s.out.push('(');
+                for (index, item) in items.iter().enumerate() {
+                    if index != 0 {
+                        s.out.push_str(", ");
+                    }
+                    item.ser_ron(d, s);
+                }
+                s.out.push(')');
+            }
+            RonValue::Struct(name, fields) => {
+                if let Some(name) = name {
+                    s.out.push_str(name);
+                }
+                s.st_pre();
+                for (k, v) in fields {
+                    s.field(d + 1, k);
+                    v.ser_ron(d + 1, s);
+                    s.conl();
+                }
+                s.st_post(d);
+            }
+            RonValue::List(items) if items.is_empty() => s.out.push_str("[]"),
+            RonValue::List(items) => {
+                s.out.push_str("[\n");
+                for item in items {
+                    s.indent(d + 1);
+                    item.ser_ron(d + 1, s);
+                    s.conl();
+                }
+                s.indent(d);
+                s.out.push(']');
+            }
+            RonValue::Map(items) if items.is_empty() => s.out.push_str("{}"),
+            RonValue::Map(items) => {
+                s.out.push_str("{\n");
+                for (k, v) in items {
+                    s.indent(d + 1);
+                    k.ser_ron(d + 1, s);
+                    s.out.push(':');
+                    v.ser_ron(d + 1, s);
+                    s.conl();
+                }
+                s.indent(d);
+                s.out.push('}');
+            }
+        }
+    }
+}
+
+impl RonValue {
+    // the body of (..) decides between a tuple and a struct by its first entry
+    fn de_ron_paren(name: Option<String>, s: &mut DeRonState, i: &mut Chars) -> Result<RonValue, DeRonErr> {
+        let mut items = Vec::new();
+        let mut fields = Vec::new();
+        s.paren_open(i) ?;
+        while s.tok != DeRonTok::ParenClose {
+            if s.tok == DeRonTok::Ident {
+                let ident = s.identbuf.clone();
+                s.next_tok(i) ?;
+                if s.tok == DeRonTok::Colon && items.is_empty() {
+                    s.next_tok(i) ?;
+                    fields.push((ident, DeRon::de_ron(s, i) ?));
+                }
+                else if !fields.is_empty() {
+                    return Err(s.err_token(":"));
+                }
+                else {
+                    items.push(Self::de_ron_ident(ident, s, i) ?);
+                }
+            }
+            else if !fields.is_empty() {
+                return Err(s.err_token("field name"));
+            }
+            else {
+                items.push(DeRon::de_ron(s, i) ?);
+            }
+            s.eat_comma_paren(i) ?;
+        }
+        s.paren_close(i) ?;
+        if !fields.is_empty() {
+            Ok(RonValue::Struct(name, fields))
+        }
+        else {
+            Ok(RonValue::Tuple(name, items))
+        }
+    }
+    
+    // an identifier that has already been consumed, optionally followed by a (..) body
+    fn de_ron_ident(ident: String, s: &mut DeRonState, i: &mut Chars) -> Result<RonValue, DeRonErr> {
+        if s.tok == DeRonTok::ParenOpen {
+            return Self::de_ron_paren(Some(ident), s, i)
+        }
+        Ok(RonValue::Ident(ident))
+    }
+}
+
+impl DeRon for RonValue {
+    fn de_ron(s: &mut DeRonState, i: &mut Chars) -> Result<Self, DeRonErr> {
+        let value = match s.tok {
+            DeRonTok::Bool(v) => RonValue::Bool(v),
+            DeRonTok::U64(v) => RonValue::U64(v),
+            DeRonTok::I64(v) => RonValue::I64(v),
+            DeRonTok::F64(v) => RonValue::F64(v),
+            DeRonTok::U128(v) => RonValue::F64(v as f64),
+            DeRonTok::I128(v) => RonValue::F64(v as f64),
+            DeRonTok::Char(v) => RonValue::Char(v),
+            DeRonTok::Str => RonValue::Str(s.as_string() ?),
+            DeRonTok::Ident => {
+                let ident = s.identbuf.clone();
+                s.next_tok(i) ?;
+                return Self::de_ron_ident(ident, s, i)
+            }
+            DeRonTok::ParenOpen => return Self::de_ron_paren(None, s, i),
+            DeRonTok::BlockOpen => {
+                let mut items = Vec::new();
+                s.block_open(i) ?;
+                while s.tok != DeRonTok::BlockClose {
+                    items.push(DeRon::de_ron(s, i) ?);
+                    s.eat_comma_block(i) ?;
+                }
+                s.block_close(i) ?;
+                return Ok(RonValue::List(items))
+            }
+            DeRonTok::CurlyOpen => {
+                let mut items = Vec::new();
+                s.curly_open(i) ?;
+                while s.tok != DeRonTok::CurlyClose {
+                    let k = DeRon::de_ron(s, i) ?;
+                    s.colon(i) ?;
+                    let v = DeRon::de_ron(s, i) ?;
+                    s.eat_comma_curly(i) ?;
+                    items.push((k, v));
+                }
+                s.curly_close(i) ?;
+                return Ok(RonValue::Map(items))
+            }
+            _ => return Err(s.err_token("ron value"))
+        };
+        s.next_tok(i) ?;
+        Ok(value)
+    }
+}
@@ -1,137 +1,269 @@
 use std::collections::{HashMap};
+use std::hash::Hash;
 use std::str::Chars;
+use std::convert::TryFrom;
 
 #[derive(Default)]
 pub struct TomlParser {
     pub cur: char,
     pub line: usize,
-    pub col: usize
+    pub col: usize,
+    // tables defined by a [header], and arrays created by [[header]]
+    defined: Vec<Vec<String >>,
+    array_tables: Vec<Vec<String >>,
 }
 
-#[derive(PartialEq, Debug)]
-pub enum TomlTok {
-    Ident(String),
-    Str(String),
-    U64(u64),
-    I64(i64),
-    F64(f64),
-    Bool(bool),
-    Nan(bool),
-    Inf(bool),
-    Date(String),
-    Equals,
-    BlockOpen,
-    BlockClose,
-    Comma,
-    Bof,
-    Eof
-}
-
-pub enum Toml{
+#[derive(Clone, Debug, PartialEq)]
+pub enum Toml {
     Str(String),
     Bool(bool),
+    Int(i64),
     Num(f64),
     Date(String),
     Array(Vec<Toml>),
+    Table(Vec<(String, Toml)>),
 }
 
-pub struct TomlErr{
-    pub msg:String,
-    pub line:usize,
-    pub col:usize
+pub struct TomlErr {
+    pub msg: String,
+    pub line: usize,
+    pub col: usize
 }
 
 impl std::fmt::Debug for TomlErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Toml error: {}, line:{} col:{}", self.msg, self.line+1, self.col+1)
+        write!(f, "Toml error: {}, line:{} col:{}", self.msg, self.line + 1, self.col + 1)
     }
 }
 
-impl TomlParser {
-    pub fn to_val(&mut self, tok:TomlTok, i: &mut Chars)->Result<Toml, TomlErr>{
-        match tok{
-            TomlTok::BlockOpen=>{
-                let mut vals = Vec::new();
-                loop{
-                    let tok = self.next_tok(i)?;
-                    if tok == TomlTok::BlockClose || tok == TomlTok::Eof{
-                        break
-                    }
-                    if tok != TomlTok::Comma{
-                        vals.push(self.to_val(tok, i)?);
-                    }
+impl TomlErr {
+    pub fn new(msg: String) -> TomlErr {
+        TomlErr {msg: msg, line: 0, col: 0}
+    }
+
+    pub fn err_type(what: &str, got: &Toml) -> TomlErr {
+        TomlErr::new(format!("Expected {} got {}", what, got.type_name()))
+    }
+
+    pub fn err_nf(key: &str) -> TomlErr {
+        TomlErr::new(format!("Key not found {}", key))
+    }
+
+    pub fn err_enum(name: &str) -> TomlErr {
+        TomlErr::new(format!("Enum not defined {}", name))
+    }
+}
+
+impl Toml {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Toml::Str(_) => "string",
+            Toml::Bool(_) => "boolean",
+            Toml::Int(_) => "integer",
+            Toml::Num(_) => "float",
+            Toml::Date(_) => "datetime",
+            Toml::Array(_) => "array",
+            Toml::Table(_) => "table",
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Toml> {
+        if let Toml::Table(items) = self {
+            for (k, v) in items {
+                if k == key {
+                    return Some(v)
                 }
-                Ok(Toml::Array(vals))
-            },
-            TomlTok::Str(v)=>Ok(Toml::Str(v)),
-            TomlTok::U64(v)=>Ok(Toml::Num(v as f64)),
-            TomlTok::I64(v)=>Ok(Toml::Num(v as f64)),
-            TomlTok::F64(v)=>Ok(Toml::Num(v as f64)),
-            TomlTok::Bool(v)=>Ok(Toml::Bool(v)),
-            TomlTok::Nan(v)=>Ok(Toml::Num(if v{-std::f64::NAN}else{std::f64::NAN})),
-            TomlTok::Inf(v)=>Ok(Toml::Num(if v{-std::f64::INFINITY}else{std::f64::INFINITY})),
-            TomlTok::Date(v)=>Ok(Toml::Date(v)),
-            _=>Err(self.err_token(tok))
-        }
-    }
-    
-    pub fn parse_key_value(&mut self, local_scope:&String, key:String, i: &mut Chars, out:&mut HashMap<String, Toml>)->Result<(), TomlErr>{
-        let tok = self.next_tok(i)?;
-        if tok != TomlTok::Equals{
-            return Err(self.err_token(tok));
-        }
-        let tok = self.next_tok(i)?;
-        let val = self.to_val(tok, i)?;
-        let key = if local_scope.len()>0{
-            format!("{}.{}", local_scope, key)
-        }
-        else{
-            key
-        };
-        out.insert(key, val);
-        Ok(())
+            }
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Toml> {
+        if let Toml::Table(items) = self {
+            for (k, v) in items {
+                if k == key {
+                    return Some(v)
+                }
+            }
+        }
+        None
+    }
+
+    // looks up a dotted path like "workspace.members", it does not handle quoted keys with dots
+    pub fn path(&self, path: &str) -> Option<&Toml> {
+        let mut node = self;
+        for key in path.split('.') {
+            node = node.get(key) ?;
+        }
+        Some(node)
+    }
+
+    pub fn insert(&mut self, key: &str, value: Toml) {
+        if let Toml::Table(items) = self {
+            for (k, v) in items.iter_mut() {
+                if k == key {
+                    *v = value;
+                    return
+                }
+            }
+            items.push((key.to_string(), value));
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        if let Toml::Str(v) = self {Some(v)} else {None}
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Toml::Bool(v) = self {Some(*v)} else {None}
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        if let Toml::Int(v) = self {Some(*v)} else {None}
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Toml::Num(v) => Some(*v),
+            Toml::Int(v) => Some(*v as f64),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Toml >> {
+        if let Toml::Array(v) = self {Some(v)} else {None}
     }
-    
-    pub fn parse(data:&str)->Result<HashMap<String, Toml>, TomlErr>{
+
+    pub fn as_table(&self) -> Option<&Vec<(String, Toml) >> {
+        if let Toml::Table(v) = self {Some(v)} else {None}
+    }
+
+    fn is_array_of_tables(&self) -> bool {
+        if let Toml::Array(items) = self {
+            return items.len() > 0 && items.iter().all( | v | if let Toml::Table(_) = v {true} else {false})
+        }
+        false
+    }
+}
+
+impl TomlParser {
+
+    pub fn parse(data: &str) -> Result<Toml, TomlErr> {
         let i = &mut data.chars();
         let mut t = TomlParser::default();
         t.next(i);
-        let mut out = HashMap::new();
-        let mut local_scope = String::new();
-        loop{
-            let tok = t.next_tok(i)?;
-            match tok{
-                TomlTok::Eof=>{ // at eof. 
-                    return Ok(out);
+        let mut root = Toml::Table(Vec::new());
+        let mut local_scope = Vec::new();
+        loop {
+            t.skip_ws_nl(i);
+            match t.cur {
+                '\0' => { // at eof.
+                    return Ok(root);
                 },
-                TomlTok::BlockOpen=>{ // its a scope
-                    // we should expect an ident or a string
-                    let tok = t.next_tok(i)?;
-                    match tok{
-                        TomlTok::Str(key)=>{ // a key
-                            local_scope = key;
-                        },
-                        TomlTok::Ident(key)=>{ // also a key
-                            local_scope = key;
-                        },
-                        _=>return Err(t.err_token(tok))
+                '[' => { // its a scope
+                    t.next(i);
+                    let is_array = if t.cur == '[' {t.next(i); true} else {false};
+                    let key = t.parse_key(i) ?;
+                    t.expect(i, ']') ?;
+                    if is_array {
+                        t.expect(i, ']') ?;
                     }
-                    let tok = t.next_tok(i)?;
-                    if tok != TomlTok::BlockClose{
-                        return Err(t.err_token(tok))
+                    t.end_of_line(i) ?;
+                    if is_array {
+                        t.push_array_table(&mut root, &key) ?;
                     }
+                    else {
+                        t.define_table(&mut root, &key) ?;
+                    }
+                    local_scope = key;
                 },
-                TomlTok::Str(key)=>{ // a key
-                    t.parse_key_value(&local_scope, key, i, &mut out)?;
-                },
-                TomlTok::Ident(key)=>{ // also a key
-                    t.parse_key_value(&local_scope, key, i, &mut out)?;
-                },
-                _=>return Err(t.err_token(tok))
+                _ => {
+                    let key = t.parse_key(i) ?;
+                    t.expect(i, '=') ?;
+                    t.skip_ws(i);
+                    let value = t.parse_value(i) ?;
+                    t.end_of_line(i) ?;
+                    let table = t.table_at(&mut root, &local_scope) ?;
+                    t.insert_dotted(table, &key, value) ?;
+                }
             }
         }
     }
-    
+
+    fn define_table(&mut self, root: &mut Toml, key: &Vec<String>) -> Result<(), TomlErr> {
+        if self.defined.contains(key) || self.array_tables.contains(key) {
+            return Err(self.err_parse(&format!("table [{}] defined twice", key.join("."))))
+        }
+        if let Toml::Table(items) = self.table_at(root, &key[0..key.len() - 1]) ? {
+            if let Some((_, value)) = items.iter().find( | (k, _) | *k == key[key.len() - 1]) {
+                if let Toml::Table(_) = value {}
+                else {
+                    return Err(self.err_parse(&format!("key {} is not a table", key.join("."))))
+                }
+            }
+        }
+        self.table_at(root, key) ?;
+        self.defined.push(key.clone());
+        Ok(())
+    }
+
+    fn push_array_table(&mut self, root: &mut Toml, key: &Vec<String>) -> Result<(), TomlErr> {
+        let is_new = !self.array_tables.contains(key);
+        let last = key[key.len() - 1].clone();
+        let parent = self.table_at(root, &key[0..key.len() - 1]) ?;
+        match parent.get_mut(&last) {
+            Some(Toml::Array(items)) if !is_new => {
+                items.push(Toml::Table(Vec::new()));
+            },
+            Some(_) => {
+                return Err(self.err_parse(&format!("key {} is not an array of tables", key.join("."))))
+            },
+            None => {
+                parent.insert(&last, Toml::Array(vec![Toml::Table(Vec::new())]));
+            }
+        }
+        if is_new {
+            self.array_tables.push(key.clone());
+        }
+        // a new array element starts with fresh subtables
+        self.defined.retain( | d | !(d.len() > key.len() && d[0..key.len()] == key[..]));
+        Ok(())
+    }
+
+    // walks down the path creating implicit tables, arrays of tables resolve to their last element
+    fn table_at<'a>(&self, node: &'a mut Toml, path: &[String]) -> Result<&'a mut Toml, TomlErr> {
+        if path.len() == 0 {
+            return Ok(node)
+        }
+        if node.get(&path[0]).is_none() {
+            node.insert(&path[0], Toml::Table(Vec::new()));
+        }
+        let child = match node.get_mut(&path[0]) {
+            Some(Toml::Array(items)) => {
+                match items.last_mut() {
+                    Some(last) if last.as_table().is_some() => last,
+                    _ => return Err(self.err_parse(&format!("key {} is not a table", path[0])))
+                }
+            }
+            Some(child) => child,
+            None => unreachable!()
+        };
+        if child.as_table().is_none() {
+            return Err(self.err_parse(&format!("key {} is not a table", path[0])))
+        }
+        self.table_at(child, &path[1..])
+    }
+
+    fn insert_dotted(&self, table: &mut Toml, key: &[String], value: Toml) -> Result<(), TomlErr> {
+        let table = self.table_at(table, &key[0..key.len() - 1]) ?;
+        let last = &key[key.len() - 1];
+        if table.get(last).is_some() {
+            return Err(self.err_parse(&format!("duplicate key {}", key.join("."))))
+        }
+        table.insert(last, value);
+        Ok(())
+    }
+
     pub fn next(&mut self, i: &mut Chars) {
         if let Some(c) = i.next() {
             self.cur = c;
@@ -140,199 +272,729 @@ impl TomlParser {
                 self.col = 0;
             }
             else {
-                self.col = 0;
+                self.col += 1;
             }
         }
         else {
             self.cur = '\0';
         }
     }
-    
-    pub fn err_token(&self, tok:TomlTok) -> TomlErr {
-        TomlErr{msg:format!("Unexpected token {:?} ", tok), line:self.line, col:self.col}
+
+    pub fn err_token(&self, what: &str) -> TomlErr {
+        TomlErr {msg: format!("Unexpected {:?} expected {}", self.cur, what), line: self.line, col: self.col}
     }
-    
-    pub fn err_parse(&self, what:&str) -> TomlErr {
-        TomlErr{msg:format!("Cannot parse toml {} ", what), line:self.line, col:self.col}
+
+    pub fn err_parse(&self, what: &str) -> TomlErr {
+        TomlErr {msg: format!("Cannot parse toml {} ", what), line: self.line, col: self.col}
     }
-    
-    pub fn next_tok(&mut self, i: &mut Chars) -> Result<TomlTok, TomlErr> {
-        while self.cur == '\n' || self.cur == '\r' || self.cur == '\t' || self.cur == ' ' {
+
+    fn skip_ws(&mut self, i: &mut Chars) {
+        while self.cur == ' ' || self.cur == '\t' {
             self.next(i);
         }
-        loop{
-            if self.cur == '\0' {
-                return Ok(TomlTok::Eof)
+    }
+
+    fn skip_comment(&mut self, i: &mut Chars) {
+        if self.cur == '#' {
+            while self.cur != '\n' && self.cur != '\0' {
+                self.next(i);
+            }
+        }
+    }
+
+    // skips whitespace, newlines and comments
+    fn skip_ws_nl(&mut self, i: &mut Chars) {
+        loop {
+            self.skip_ws(i);
+            self.skip_comment(i);
+            if self.cur == '\n' || self.cur == '\r' {
+                self.next(i);
             }
+            else {
+                return
+            }
+        }
+    }
+
+    fn expect(&mut self, i: &mut Chars, what: char) -> Result<(), TomlErr> {
+        self.skip_ws(i);
+        if self.cur != what {
+            return Err(self.err_token(&what.to_string()))
+        }
+        self.next(i);
+        Ok(())
+    }
+
+    fn end_of_line(&mut self, i: &mut Chars) -> Result<(), TomlErr> {
+        self.skip_ws(i);
+        self.skip_comment(i);
+        if self.cur == '\r' {
+            self.next(i);
+        }
+        match self.cur {
+            '\n' | '\0' => Ok(()),
+            _ => Err(self.err_token("end of line"))
+        }
+    }
+
+    fn is_bare_key_char(c: char) -> bool {
+        c >= 'a' && c <= 'z' || c >= 'A' && c <= 'Z' || c >= '0' && c <= '9' || c == '_' || c == '-'
+    }
+
+    pub fn parse_key(&mut self, i: &mut Chars) -> Result<Vec<String>, TomlErr> {
+        let mut key = Vec::new();
+        loop {
+            self.skip_ws(i);
             match self.cur {
-                ',' => {
-                    self.next(i);
-                    return Ok(TomlTok::Comma)
-                }
-                '[' => {
-                    self.next(i);
-                    return Ok(TomlTok::BlockOpen)
-                }
-                ']' => {
+                '"' => {
                     self.next(i);
-                    return Ok(TomlTok::BlockClose)
-                }
-                '=' => {
+                    key.push(self.parse_basic_string(i) ?);
+                },
+                '\'' => {
                     self.next(i);
-                    return Ok(TomlTok::Equals)
-                }
-                '+' | '-' | '0'..='9' => {
-                    let mut num = String::new();
-                    let is_neg = if self.cur == '-' {
-                        num.push(self.cur);
+                    key.push(self.parse_literal_string(i) ?);
+                },
+                c if Self::is_bare_key_char(c) => {
+                    let mut ident = String::new();
+                    while Self::is_bare_key_char(self.cur) {
+                        ident.push(self.cur);
                         self.next(i);
-                        true
                     }
-                    else {
-                        if self.cur == '+' {
-                            self.next(i);
-                        }
-                        false
-                    };
-                    if self.cur == 'n' {
+                    key.push(ident);
+                },
+                _ => return Err(self.err_token("key"))
+            }
+            self.skip_ws(i);
+            if self.cur == '.' {
+                self.next(i);
+            }
+            else {
+                return Ok(key)
+            }
+        }
+    }
+
+    pub fn parse_value(&mut self, i: &mut Chars) -> Result<Toml, TomlErr> {
+        match self.cur {
+            '"' => {
+                self.next(i);
+                if self.cur == '"' {
+                    self.next(i);
+                    if self.cur == '"' {
                         self.next(i);
-                        if self.cur == 'a' {
-                            self.next(i);
-                            if self.cur == 'n' {
-                                self.next(i);
-                                return Ok(TomlTok::Nan(is_neg))
-                            }
-                            else {
-                                return Err(self.err_parse("nan"))
-                            }
-                        }
-                        else {
-                            return Err(self.err_parse("nan"))
-                        }
+                        return Ok(Toml::Str(self.parse_multiline_string(i, '"') ?))
                     }
-                     if self.cur == 'i' {
+                    return Ok(Toml::Str(String::new()))
+                }
+                Ok(Toml::Str(self.parse_basic_string(i) ?))
+            },
+            '\'' => {
+                self.next(i);
+                if self.cur == '\'' {
+                    self.next(i);
+                    if self.cur == '\'' {
                         self.next(i);
-                        if self.cur == 'n' {
-                            self.next(i);
-                            if self.cur == 'f' {
-                                self.next(i);
-                                return Ok(TomlTok::Inf(is_neg))
-                            }
-                            else {
-                                return Err(self.err_parse("inf"))
-                            }
-                        }
-                        else {
-                            return Err(self.err_parse("nan"))
-                        }
+                        return Ok(Toml::Str(self.parse_multiline_string(i, '\'') ?))
                     }
-                    while self.cur >= '0' && self.cur <= '9' || self.cur == '_' {
-                        if self.cur != '_' {
-                            num.push(self.cur);
-                        }
+                    return Ok(Toml::Str(String::new()))
+                }
+                Ok(Toml::Str(self.parse_literal_string(i) ?))
+            },
+            '[' => {
+                self.next(i);
+                let mut vals = Vec::new();
+                loop {
+                    self.skip_ws_nl(i);
+                    if self.cur == ']' {
                         self.next(i);
+                        break;
                     }
-                    if self.cur == '.' {
-                        num.push(self.cur);
-                        self.next(i);
-                        while self.cur >= '0' && self.cur <= '9' || self.cur == '_' {
-                            if self.cur != '_' {
-                                num.push(self.cur);
-                            }
+                    vals.push(self.parse_value(i) ?);
+                    self.skip_ws_nl(i);
+                    match self.cur {
+                        ',' => self.next(i),
+                        ']' => {
                             self.next(i);
-                        }
-                        if let Ok(num) = num.parse() {
-                            return Ok(TomlTok::F64(num))
-                        }
-                        else {
-                            return Err(self.err_parse("number"));
-                        }
+                            break;
+                        },
+                        _ => return Err(self.err_token(", or ]"))
                     }
-                    else if self.cur == '-' { // lets assume its a date. whatever. i don't feel like more parsing today
-                        num.push(self.cur);
-                        self.next(i);
-                        while self.cur >= '0' && self.cur <= '9' || self.cur == ':' || self.cur == '-' || self.cur == 'T' {
-                            num.push(self.cur);
+                }
+                Ok(Toml::Array(vals))
+            },
+            '{' => {
+                self.next(i);
+                let mut table = Toml::Table(Vec::new());
+                self.skip_ws(i);
+                if self.cur == '}' {
+                    self.next(i);
+                    return Ok(table)
+                }
+                loop {
+                    let key = self.parse_key(i) ?;
+                    self.expect(i, '=') ?;
+                    self.skip_ws(i);
+                    let value = self.parse_value(i) ?;
+                    self.insert_dotted(&mut table, &key, value) ?;
+                    self.skip_ws(i);
+                    match self.cur {
+                        ',' => self.next(i),
+                        '}' => {
                             self.next(i);
-                        }
-                        return Ok(TomlTok::Date(num))
-                    }
-                    else {
-                        if is_neg {
-                            if let Ok(num) = num.parse() {
-                                 return Ok(TomlTok::I64(num))
-                            }
-                            else {
-                                return Err(self.err_parse("number"));
-                            }
-                        }
-                        if let Ok(num) = num.parse() {
-                            return Ok(TomlTok::U64(num))
-                        }
-                        else {
-                            return Err(self.err_parse("number"));
-                        }
-                    }
-                },
-                'a'..='z' | 'A'..='Z' | '_' => {
-                    let mut ident = String::new();
-                    while self.cur >= 'a' && self.cur <= 'z'
-                        || self.cur >= 'A' && self.cur <= 'Z'
-                        || self.cur == '_' || self.cur == '-' {
-                        ident.push(self.cur);
-                        self.next(i);
+                            return Ok(table)
+                        },
+                        _ => return Err(self.err_token(", or }"))
                     }
-                    if self.cur == '.' {
-                        while self.cur == '.' {
+                }
+            },
+            '+' | '-' | '0'..='9' | 'a'..='z' => {
+                let mut word = String::new();
+                while Self::is_bare_key_char(self.cur) || self.cur == '+' || self.cur == '.' || self.cur == ':' {
+                    word.push(self.cur);
+                    self.next(i);
+                }
+                // a date and time can be separated by a space
+                if self.cur == ' ' && Self::is_date(&word) && word.len() == 10 {
+                    let mut peek = i.clone();
+                    if let Some(c) = peek.next() {
+                        if c >= '0' && c <= '9' {
+                            word.push(' ');
                             self.next(i);
-                            while self.cur >= 'a' && self.cur <= 'z'
-                                || self.cur >= 'A' && self.cur <= 'Z'
-                                || self.cur == '_' || self.cur == '-' {
-                                ident.push(self.cur);
+                            while Self::is_bare_key_char(self.cur) || self.cur == '+' || self.cur == '.' || self.cur == ':' {
+                                word.push(self.cur);
                                 self.next(i);
                             }
                         }
-                        return Ok(TomlTok::Ident(ident))
                     }
-                    if ident == "true" {
-                        return Ok(TomlTok::Bool(true))
-                    }
-                    if ident == "false" {
-                        return Ok(TomlTok::Bool(false))
-                    }
-                    if ident == "inf" {
-                        return Ok(TomlTok::Inf(false))
+                }
+                self.parse_word(&word)
+            },
+            _ => Err(self.err_token("value"))
+        }
+    }
+
+    fn is_date(word: &str) -> bool {
+        let b = word.as_bytes();
+        b.len() >= 10 && b[4] == b'-' && b[7] == b'-' && b[0..4].iter().all( | c | c.is_ascii_digit())
+    }
+
+    fn is_time(word: &str) -> bool {
+        let b = word.as_bytes();
+        b.len() >= 8 && b[2] == b':' && b[5] == b':' && b[0..2].iter().all( | c | c.is_ascii_digit())
+    }
+
+    // underscores are only allowed between digits
+    fn strip_underscores(&self, digits: &str) -> Result<String, TomlErr> {
+        let b = digits.as_bytes();
+        for (index, c) in b.iter().enumerate() {
+            if *c == b'_' {
+                if index == 0 || index == b.len() - 1 || !b[index - 1].is_ascii_alphanumeric() || !b[index + 1].is_ascii_alphanumeric() {
+                    return Err(self.err_parse("number, misplaced _"))
+                }
+            }
+        }
+        Ok(digits.replace('_', ""))
+    }
+
+    fn parse_word(&self, word: &str) -> Result<Toml, TomlErr> {
+        match word {
+            "true" => return Ok(Toml::Bool(true)),
+            "false" => return Ok(Toml::Bool(false)),
+            "inf" | "+inf" => return Ok(Toml::Num(std::f64::INFINITY)),
+            "-inf" => return Ok(Toml::Num(std::f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Toml::Num(std::f64::NAN)),
+            _ => ()
+        }
+        if Self::is_date(word) || Self::is_time(word) {
+            return Ok(Toml::Date(word.to_string()))
+        }
+        let radix = if word.starts_with("0x") {16} else if word.starts_with("0o") {8} else if word.starts_with("0b") {2} else {10};
+        if radix != 10 {
+            let digits = self.strip_underscores(&word[2..]) ?;
+            return match i64::from_str_radix(&digits, radix) {
+                Ok(v) => Ok(Toml::Int(v)),
+                Err(_) => Err(self.err_parse("integer"))
+            }
+        }
+        let num = self.strip_underscores(word) ?;
+        let unsigned = num.trim_start_matches( | c | c == '+' || c == '-');
+        if unsigned.len() == 0 || !unsigned.as_bytes()[0].is_ascii_digit() {
+            return Err(self.err_parse(&format!("value {}", word)))
+        }
+        if unsigned.contains('.') || unsigned.contains('e') || unsigned.contains('E') {
+            if unsigned.starts_with('.') || unsigned.ends_with('.') || unsigned.contains(".e") || unsigned.contains(".E") {
+                return Err(self.err_parse("float"))
+            }
+            return match num.parse() {
+                Ok(v) => Ok(Toml::Num(v)),
+                Err(_) => Err(self.err_parse("float"))
+            }
+        }
+        if unsigned.len() > 1 && unsigned.starts_with('0') {
+            return Err(self.err_parse("integer, leading zero"))
+        }
+        match num.parse() {
+            Ok(v) => Ok(Toml::Int(v)),
+            Err(_) => Err(self.err_parse("integer"))
+        }
+    }
+
+    fn parse_escape(&mut self, i: &mut Chars, val: &mut String) -> Result<(), TomlErr> {
+        match self.cur {
+            'b' => val.push('\u{8}'),
+            't' => val.push('\t'),
+            'n' => val.push('\n'),
+            'f' => val.push('\u{c}'),
+            'r' => val.push('\r'),
+            '"' => val.push('"'),
+            '\\' => val.push('\\'),
+            'u' | 'U' => {
+                let len = if self.cur == 'u' {4} else {8};
+                let mut hex = String::new();
+                for _ in 0..len {
+                    self.next(i);
+                    hex.push(self.cur);
+                }
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+                    val.push(c);
+                }
+                else {
+                    return Err(self.err_parse("unicode escape"))
+                }
+            },
+            _ => return Err(self.err_parse("escape"))
+        }
+        self.next(i);
+        Ok(())
+    }
+
+    // the opening quote has been eaten
+    fn parse_basic_string(&mut self, i: &mut Chars) -> Result<String, TomlErr> {
+        let mut val = String::new();
+        while self.cur != '"' {
+            match self.cur {
+                '\\' => {
+                    self.next(i);
+                    self.parse_escape(i, &mut val) ?;
+                },
+                '\0' | '\n' => return Err(self.err_parse("string")),
+                _ => {
+                    val.push(self.cur);
+                    self.next(i);
+                }
+            }
+        }
+        self.next(i);
+        Ok(val)
+    }
+
+    fn parse_literal_string(&mut self, i: &mut Chars) -> Result<String, TomlErr> {
+        let mut val = String::new();
+        while self.cur != '\'' {
+            if self.cur == '\0' || self.cur == '\n' {
+                return Err(self.err_parse("string"))
+            }
+            val.push(self.cur);
+            self.next(i);
+        }
+        self.next(i);
+        Ok(val)
+    }
+
+    // the three opening quotes have been eaten
+    fn parse_multiline_string(&mut self, i: &mut Chars, quote: char) -> Result<String, TomlErr> {
+        let mut val = String::new();
+        // a newline right after the opening quotes is trimmed
+        if self.cur == '\r' {
+            self.next(i);
+        }
+        if self.cur == '\n' {
+            self.next(i);
+        }
+        loop {
+            if self.cur == quote {
+                let mut count = 0;
+                while self.cur == quote {
+                    count += 1;
+                    self.next(i);
+                }
+                if count >= 3 {
+                    if count > 5 {
+                        return Err(self.err_parse("string, too many quotes"))
                     }
-                    if ident == "nan" {
-                        return Ok(TomlTok::Nan(false))
+                    for _ in 3..count {
+                        val.push(quote);
                     }
-                    return Ok(TomlTok::Ident(ident))
-                },
-                '#' =>{
-                    while self.cur !='\n' && self.cur != '\0'{
+                    return Ok(val)
+                }
+                for _ in 0..count {
+                    val.push(quote);
+                }
+            }
+            else if self.cur == '\\' && quote == '"' {
+                self.next(i);
+                if self.cur == ' ' || self.cur == '\t' || self.cur == '\r' || self.cur == '\n' {
+                    // line ending backslash
+                    while self.cur == ' ' || self.cur == '\t' || self.cur == '\r' || self.cur == '\n' {
                         self.next(i);
                     }
-                },
-                '"' => {
-                    let mut val = String::new();
-                    self.next(i);
-                    while self.cur != '"' {
-                        if self.cur == '\\' {
-                            self.next(i);
-                        }
-                        if self.cur == '\0' {
-                            return Err(self.err_parse("string"));
-                        }
-                        val.push(self.cur);
-                        self.next(i);
+                }
+                else {
+                    self.parse_escape(i, &mut val) ?;
+                }
+            }
+            else if self.cur == '\0' {
+                return Err(self.err_parse("string"))
+            }
+            else {
+                val.push(self.cur);
+                self.next(i);
+            }
+        }
+    }
+}
+
+// writer
+
+fn toml_write_key(key: &str, out: &mut String) {
+    if key.len() > 0 && key.chars().all(TomlParser::is_bare_key_char) {
+        out.push_str(key);
+    }
+    else {
+        toml_write_str(key, out);
+    }
+}
+
+fn toml_write_str(val: &str, out: &mut String) {
+    out.push('"');
+    for c in val.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => out.push_str(&format!("\\u{:04X}", c as u32)),
+            _ => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+fn toml_write_inline(val: &Toml, out: &mut String) {
+    match val {
+        Toml::Str(v) => toml_write_str(v, out),
+        Toml::Bool(v) => out.push_str(if *v {"true"} else {"false"}),
+        Toml::Int(v) => out.push_str(&v.to_string()),
+        Toml::Num(v) => {
+            if v.is_nan() {
+                out.push_str("nan")
+            }
+            else if v.is_infinite() {
+                out.push_str(if *v > 0.0 {"inf"} else {"-inf"})
+            }
+            else {
+                out.push_str(&format!("{:?}", v))
+            }
+        },
+        Toml::Date(v) => out.push_str(v),
+        Toml::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index != 0 {
+                    out.push_str(", ");
+                }
+                toml_write_inline(item, out);
+            }
+            out.push(']');
+        },
+        Toml::Table(items) => {
+            out.push('{');
+            for (index, (k, v)) in items.iter().enumerate() {
+                out.push_str(if index != 0 {", "} else {" "});
+                toml_write_key(k, out);
+                out.push_str(" = ");
+                toml_write_inline(v, out);
+            }
+            out.push_str(if items.len() > 0 {" }"} else {"}"});
+        }
+    }
+}
+
+fn toml_write_header(path: &[String], is_array: bool, out: &mut String) {
+    if out.len() > 0 {
+        out.push('\n');
+    }
+    out.push_str(if is_array {"[["} else {"["});
+    for (index, key) in path.iter().enumerate() {
+        if index != 0 {
+            out.push('.');
+        }
+        toml_write_key(key, out);
+    }
+    out.push_str(if is_array {"]]\n"} else {"]\n"});
+}
+
+fn toml_write_table(items: &[(String, Toml)], path: &mut Vec<String>, out: &mut String) {
+    for (k, v) in items {
+        match v {
+            Toml::Table(_) => (),
+            v if v.is_array_of_tables() => (),
+            _ => {
+                toml_write_key(k, out);
+                out.push_str(" = ");
+                toml_write_inline(v, out);
+                out.push('\n');
+            }
+        }
+    }
+    for (k, v) in items {
+        if let Toml::Table(sub) = v {
+            path.push(k.clone());
+            // tables that only hold other tables get an implicit header
+            let has_values = sub.iter().any( | (_, v) | v.as_table().is_none() && !v.is_array_of_tables());
+            if has_values || sub.len() == 0 {
+                toml_write_header(path, false, out);
+            }
+            toml_write_table(sub, path, out);
+            path.pop();
+        }
+    }
+    for (k, v) in items {
+        if v.is_array_of_tables() {
+            path.push(k.clone());
+            for item in v.as_array().unwrap() {
+                toml_write_header(path, true, out);
+                toml_write_table(item.as_table().unwrap(), path, out);
+            }
+            path.pop();
+        }
+    }
+}
+
+impl Toml {
+    // writes a table as a toml document, other values are written inline
+    pub fn write(&self) -> String {
+        let mut out = String::new();
+        if let Toml::Table(items) = self {
+            toml_write_table(items, &mut Vec::new(), &mut out);
+        }
+        else {
+            toml_write_inline(self, &mut out);
+        }
+        out
+    }
+}
+
+// typed serialization
+
+pub trait SerToml {
+    fn serialize_toml(&self) -> String {
+        self.ser_toml().write()
+    }
+
+    fn ser_toml(&self) -> Toml;
+}
+
+pub trait DeToml: Sized {
+    fn deserialize_toml(data: &str) -> Result<Self, TomlErr> {
+        let toml = TomlParser::parse(data) ?;
+        DeToml::de_toml(&toml)
+    }
+
+    fn de_toml(t: &Toml) -> Result<Self, TomlErr>;
+}
+
+impl SerToml for Toml {
+    fn ser_toml(&self) -> Toml {
+        self.clone()
+    }
+}
+
+impl DeToml for Toml {
+    fn de_toml(t: &Toml) -> Result<Self, TomlErr> {
+        Ok(t.clone())
+    }
+}
+
+macro_rules!impl_ser_de_toml_int {
+    ( $ ty: ident) => {
+        impl SerToml for $ ty {
+            fn ser_toml(&self) -> Toml {
+                Toml::Int(*self as i64)
+            }
+        }
+
+        impl DeToml for $ ty {
+            fn de_toml(t: &Toml) -> Result< $ ty, TomlErr> {
+                if let Toml::Int(v) = t {
+                    if *v >= $ ty::min_value() as i64 && (*v as i128) <= $ ty::max_value() as i128 {
+                        return Ok(*v as $ ty)
                     }
-                    self.next(i);
-                    return Ok(TomlTok::Str(val))
-                },
-                _ => {
-                    return Err(self.err_parse("tokenizer"));
+                    return Err(TomlErr::new(format!("Value out of range {} for {}", v, stringify!( $ ty))))
                 }
+                Err(TomlErr::err_type("integer", t))
             }
         }
     }
-}
\ No newline at end of file
+}
+
+macro_rules!impl_ser_de_toml_float {
+    ( $ ty: ident) => {
+        impl SerToml for $ ty {
+            fn ser_toml(&self) -> Toml {
+                Toml::Num(*self as f64)
+            }
+        }
+
+        impl DeToml for $ ty {
+            fn de_toml(t: &Toml) -> Result< $ ty, TomlErr> {
+                if let Some(v) = t.as_f64() {
+                    return Ok(v as $ ty)
+                }
+                Err(TomlErr::err_type("float", t))
+            }
+        }
+    }
+}
+
+// toml integers are i64, bigger values are written as a decimal string and read back from one
+macro_rules!impl_ser_de_toml_u64 {
+    ( $ ty: ident) => {
+        impl SerToml for $ ty {
+            fn ser_toml(&self) -> Toml {
+                match i64::try_from(*self) {
+                    Ok(v) => Toml::Int(v),
+                    Err(_) => Toml::Str(self.to_string())
+                }
+            }
+        }
+
+        impl DeToml for $ ty {
+            fn de_toml(t: &Toml) -> Result< $ ty, TomlErr> {
+                match t {
+                    Toml::Int(v) => $ ty::try_from(*v).map_err( | _ | TomlErr::new(format!("Value out of range {} for {}", v, stringify!( $ ty)))),
+                    Toml::Str(v) => v.parse::< $ ty>().map_err( | _ | TomlErr::new(format!("Value out of range {} for {}", v, stringify!( $ ty)))),
+                    _ => Err(TomlErr::err_type("integer", t))
+                }
+            }
+        }
+    }
+}
+
+impl_ser_de_toml_u64!(usize);
+impl_ser_de_toml_u64!(u64);
+impl_ser_de_toml_int!(u32);
+impl_ser_de_toml_int!(u16);
+impl_ser_de_toml_int!(u8);
+impl_ser_de_toml_int!(i64);
+impl_ser_de_toml_int!(i32);
+impl_ser_de_toml_int!(i16);
+impl_ser_de_toml_int!(i8);
+impl_ser_de_toml_float!(f64);
+impl_ser_de_toml_float!(f32);
+
+impl SerToml for bool {
+    fn ser_toml(&self) -> Toml {
+        Toml::Bool(*self)
+    }
+}
+
+impl DeToml for bool {
+    fn de_toml(t: &Toml) -> Result<bool, TomlErr> {
+        t.as_bool().ok_or_else( || TomlErr::err_type("boolean", t))
+    }
+}
+
+impl SerToml for String {
+    fn ser_toml(&self) -> Toml {
+        Toml::Str(self.clone())
+    }
+}
+
+impl DeToml for String {
+    fn de_toml(t: &Toml) -> Result<String, TomlErr> {
+        match t {
+            Toml::Str(v) => Ok(v.clone()),
+            _ => Err(TomlErr::err_type("string", t))
+        }
+    }
+}
+
+impl<T> SerToml for Vec<T> where T: SerToml {
+    fn ser_toml(&self) -> Toml {
+        Toml::Array(self.iter().map( | v | v.ser_toml()).collect())
+    }
+}
+
+impl<T> DeToml for Vec<T> where T: DeToml {
+    fn de_toml(t: &Toml) -> Result<Vec<T>, TomlErr> {
+        if let Toml::Array(items) = t {
+            let mut out = Vec::new();
+            for item in items {
+                out.push(DeToml::de_toml(item) ?);
+            }
+            return Ok(out)
+        }
+        Err(TomlErr::err_type("array", t))
+    }
+}
+
+impl<V> SerToml for HashMap<String, V> where V: SerToml {
+    fn ser_toml(&self) -> Toml {
+        let mut items: Vec<(String, Toml)> = self.iter().map( | (k, v) | (k.clone(), v.ser_toml())).collect();
+        items.sort_by( | a, b | a.0.cmp(&b.0));
+        Toml::Table(items)
+    }
+}
+
+impl<K, V> DeToml for HashMap<K, V> where K: From<String> + Eq + Hash,
+V: DeToml {
+    fn de_toml(t: &Toml) -> Result<Self, TomlErr> {
+        if let Toml::Table(items) = t {
+            let mut h = HashMap::new();
+            for (k, v) in items {
+                h.insert(K::from(k.clone()), DeToml::de_toml(v) ?);
+            }
+            return Ok(h)
+        }
+        Err(TomlErr::err_type("table", t))
+    }
+}
+
+impl<T> SerToml for Box<T> where T: SerToml {
+    fn ser_toml(&self) -> Toml {
+        (**self).ser_toml()
+    }
+}
+
+impl<T> DeToml for Box<T> where T: DeToml {
+    fn de_toml(t: &Toml) -> Result<Box<T>, TomlErr> {
+        Ok(Box::new(DeToml::de_toml(t) ?))
+    }
+}
+
+macro_rules!impl_ser_de_toml_tuple {
+    ( $ len: expr, $ ( $ name: ident $ index: tt), *) => {
+        impl< $ ( $ name), *> SerToml for ( $ ( $ name, ) *) where $ ( $ name: SerToml), * {
+            fn ser_toml(&self) -> Toml {
+                Toml::Array(vec![ $ (self. $ index.ser_toml()), *])
+            }
+        }
+
+        impl< $ ( $ name), *> DeToml for ( $ ( $ name, ) *) where $ ( $ name: DeToml), * {
+            fn de_toml(t: &Toml) -> Result<Self, TomlErr> {
+                if let Toml::Array(a) = t {
+                    if a.len() == $ len {
+                        return Ok(( $ (DeToml::de_toml(&a[ $ index]) ?, ) *))
+                    }
+                }
+                Err(TomlErr::err_type(&format!("array of {}", $ len), t))
+            }
+        }
+    }
+}
+
+impl_ser_de_toml_tuple!(2, A 0, B 1);
+impl_ser_de_toml_tuple!(3, A 0, B 1, C 2);
+impl_ser_de_toml_tuple!(4, A 0, B 1, C 2, D 3);
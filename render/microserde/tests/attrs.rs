@@ -0,0 +1,179 @@
+use makepad_microserde::*;
+
+fn default_port() -> u16 {
+    8000
+}
+
+#[derive(Debug, PartialEq, SerBin, DeBin, SerJson, DeJson, SerRon, DeRon)]
+/// doc comments on the container are skipped
+struct Settings {
+    #[nserde(rename = "on_save")]
+    build_on_save: bool,
+    #[nserde(default)]
+    exec_when_done: bool,
+    #[nserde(default = "default_port")]
+    port: u16,
+    #[nserde(skip)]
+    cache: Vec<u32>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, SerJson, DeJson, SerRon, DeRon)]
+#[nserde(default)]
+struct Window {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, PartialEq, SerBin, DeBin, SerJson, DeJson, SerRon, DeRon)]
+#[nserde(transparent)]
+struct Meters(f64);
+
+#[derive(Debug, PartialEq, SerJson, DeJson, SerRon, DeRon)]
+#[nserde(transparent)]
+struct Named {
+    inner: String
+}
+
+#[derive(Debug, PartialEq, SerJson, DeJson, SerRon, DeRon)]
+enum Renamed {
+    #[nserde(rename = "off")]
+    Offline,
+    Local {#[nserde(rename = "p")] port: u16},
+}
+
+#[derive(Debug, PartialEq, SerJson, DeJson, SerRon, DeRon)]
+struct OptionPaths {
+    a: std::option::Option<u32>,
+    b: core::option::Option<u32>,
+    c: ::std::option::Option<u32>,
+}
+
+#[derive(Debug, PartialEq, SerJson, DeJson)]
+#[nserde(tag = "type")]
+enum Internal {
+    Start,
+    Build {package: String, release: bool},
+}
+
+#[derive(Debug, PartialEq, SerJson, DeJson)]
+#[nserde(tag = "t", content = "c")]
+enum Adjacent {
+    Empty,
+    Pair(u32, u32),
+    Named {x: u32},
+}
+
+#[test]
+fn field_attrs_ron() {
+    let settings: Settings = DeRon::deserialize_ron("(on_save:true)").unwrap();
+    assert_eq!(settings, Settings {
+        build_on_save: true,
+        exec_when_done: false,
+        port: 8000,
+        cache: vec![],
+        name: None
+    });
+    assert!(Settings::deserialize_ron("(build_on_save:true)").is_err());
+    let ron = settings.serialize_ron();
+    assert!(ron.contains("on_save:true"));
+    assert!(!ron.contains("cache"));
+}
+
+#[test]
+fn field_attrs_json() {
+    let settings: Settings = DeJson::deserialize_json("{\"on_save\":true}").unwrap();
+    assert_eq!(settings, Settings {
+        build_on_save: true,
+        exec_when_done: false,
+        port: 8000,
+        cache: vec![],
+        name: None
+    });
+
+    let settings = Settings {
+        build_on_save: false,
+        exec_when_done: true,
+        port: 1,
+        cache: vec![1, 2],
+        name: Some("x".to_string())
+    };
+    let json = settings.serialize_json();
+    assert!(json.contains("\"on_save\":false"));
+    assert!(!json.contains("cache"));
+    let back: Settings = DeJson::deserialize_json(&json).unwrap();
+    assert_eq!(back.cache, Vec::<u32>::new());
+    assert_eq!(back.port, 1);
+
+    let bin = settings.serialize_bin();
+    let back: Settings = DeBin::deserialize_bin(&bin).unwrap();
+    assert_eq!(back.cache, Vec::<u32>::new());
+    assert_eq!(back.name, Some("x".to_string()));
+}
+
+#[test]
+fn container_default() {
+    let window: Window = DeRon::deserialize_ron("(width:10)").unwrap();
+    assert_eq!(window, Window {width: 10, height: 0});
+    let window: Window = DeJson::deserialize_json("{}").unwrap();
+    assert_eq!(window, Window::default());
+}
+
+#[test]
+fn transparent() {
+    assert_eq!(Meters(1.5).serialize_json(), "1.5");
+    assert_eq!(Meters(1.5).serialize_ron(), "1.5");
+    let m: Meters = DeRon::deserialize_ron("2.5").unwrap();
+    assert_eq!(m, Meters(2.5));
+    let n: Named = DeJson::deserialize_json("\"hi\"").unwrap();
+    assert_eq!(n, Named {inner: "hi".to_string()});
+    assert_eq!(n.serialize_ron(), "\"hi\"");
+}
+
+#[test]
+fn variant_rename() {
+    assert_eq!(Renamed::Offline.serialize_ron(), "off");
+    let r: Renamed = DeRon::deserialize_ron("Local(p:4)").unwrap();
+    assert_eq!(r, Renamed::Local {port: 4});
+    let r: Renamed = DeJson::deserialize_json("{\"off\":[]}").unwrap();
+    assert_eq!(r, Renamed::Offline);
+}
+
+#[test]
+fn internally_tagged() {
+    let v = Internal::Build {package: "makepad".to_string(), release: true};
+    let json = v.serialize_json();
+    assert_eq!(json, "{\"type\":\"Build\",\"package\":\"makepad\",\"release\":true}");
+    let back: Internal = DeJson::deserialize_json(&json).unwrap();
+    assert_eq!(back, v);
+    assert_eq!(Internal::Start.serialize_json(), "{\"type\":\"Start\"}");
+    let back: Internal = DeJson::deserialize_json("{\"type\":\"Start\"}").unwrap();
+    assert_eq!(back, Internal::Start);
+    assert!(Internal::deserialize_json("{\"type\":\"Stop\"}").is_err());
+
+    // other writers do not always put the tag first
+    let back: Internal = DeJson::deserialize_json("{\"package\":\"makepad\",\"type\":\"Build\",\"release\":true}").unwrap();
+    assert_eq!(back, v);
+    let back: Internal = DeJson::deserialize_json("{\"release\":true,\"package\":\"makepad\",\"type\":\"Build\"}").unwrap();
+    assert_eq!(back, v);
+    assert!(Internal::deserialize_json("{\"package\":\"makepad\",\"release\":true}").is_err());
+    assert!(Internal::deserialize_json("{\"package\":\"makepad\",\"type\":\"Start\"}").is_err());
+}
+
+#[test]
+fn option_by_path() {
+    let back: OptionPaths = DeJson::deserialize_json("{}").unwrap();
+    assert_eq!(back, OptionPaths {a: None, b: None, c: None});
+    let back: OptionPaths = DeRon::deserialize_ron("(b: 2)").unwrap();
+    assert_eq!(back, OptionPaths {a: None, b: Some(2), c: None});
+}
+
+#[test]
+fn adjacently_tagged() {
+    for v in vec![Adjacent::Empty, Adjacent::Pair(1, 2), Adjacent::Named {x: 3}] {
+        let json = v.serialize_json();
+        let back: Adjacent = DeJson::deserialize_json(&json).unwrap();
+        assert_eq!(back, v);
+    }
+    assert_eq!(Adjacent::Pair(1, 2).serialize_json(), "{\"t\":\"Pair\",\"c\":[1,2]}");
+}
@@ -0,0 +1,286 @@
+use makepad_microserde::*;
+use std::io::Read;
+
+#[derive(Debug, PartialEq, SerBin, DeBin)]
+enum Msg {
+    Ping,
+    FileRead {path: String, data: Option<Vec<u8>>},
+    Pair(u32, [u8; 4]),
+}
+
+#[derive(Debug, PartialEq, SerBin, DeBinRef)]
+struct Borrowed<'a> {
+    name: &'a str,
+    data: &'a [u8],
+    id: u64,
+    tags: Vec<String>,
+}
+
+// hands out a few bytes per read, like a slow socket
+struct Trickle {
+    data: Vec<u8>,
+    o: usize,
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(3).min(self.data.len() - self.o);
+        buf[0..n].copy_from_slice(&self.data[self.o..self.o + n]);
+        self.o += n;
+        Ok(n)
+    }
+}
+
+fn messages() -> Vec<Msg> {
+    vec![
+        Msg::Ping,
+        Msg::FileRead {path: "a/b.rs".to_string(), data: Some((0..10000).map( | i | i as u8).collect())},
+        Msg::Pair(7, [1, 2, 3, 4]),
+        Msg::FileRead {path: "gone".to_string(), data: None},
+    ]
+}
+
+#[test]
+fn writer_matches_vec() {
+    for msg in messages() {
+        let mut out = Vec::new();
+        msg.ser_bin_to_writer(&mut out).unwrap();
+        assert_eq!(out, msg.serialize_bin());
+    }
+    let borrowed = Borrowed {name: "x", data: &[9, 8], id: 3, tags: vec!["t".to_string()]};
+    let mut out = Vec::new();
+    borrowed.ser_bin_to_writer(&mut out).unwrap();
+    assert_eq!(out, borrowed.serialize_bin());
+}
+
+#[test]
+fn reader_stream() {
+    let mut data = Vec::new();
+    for msg in messages() {
+        msg.ser_bin_to_writer(&mut data).unwrap();
+    }
+    let mut reader = DeBinReader::new(Trickle {data: data, o: 0});
+    for msg in messages() {
+        let back = Msg::de_bin_from_reader(&mut reader).unwrap();
+        assert_eq!(back, msg);
+    }
+    let err = Msg::de_bin_from_reader(&mut reader).err().unwrap();
+    assert!(err.is_eof());
+}
+
+// a socket where the other side sent some messages and is now waiting for a reply
+struct Live {
+    data: Vec<u8>,
+    o: usize,
+}
+
+impl Read for Live {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        assert!(self.o < self.data.len(), "read would block");
+        let n = buf.len().min(self.data.len() - self.o);
+        buf[0..n].copy_from_slice(&self.data[self.o..self.o + n]);
+        self.o += n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn reader_does_not_wait_for_more() {
+    let mut data = Vec::new();
+    for msg in messages() {
+        msg.ser_bin_to_writer(&mut data).unwrap();
+    }
+    let mut reader = DeBinReader::new(Live {data, o: 0});
+    for msg in messages() {
+        assert_eq!(Msg::de_bin_from_reader(&mut reader).unwrap(), msg);
+    }
+    assert!(reader.buffered().is_empty());
+}
+
+// a socket handing out a packet per read
+struct Packets {
+    data: Vec<u8>,
+    o: usize,
+}
+
+impl Read for Packets {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(1460).min(self.data.len() - self.o);
+        buf[0..n].copy_from_slice(&self.data[self.o..self.o + n]);
+        self.o += n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn reader_streams_large_payloads() {
+    // decoding again from the start after every packet would take minutes here
+    let start = std::time::Instant::now();
+    let bytes: Vec<u8> = (0..8 * 1024 * 1024).map( | i | i as u8).collect();
+    let words: Vec<u32> = (0..1024 * 1024).collect();
+    let msg = Msg::FileRead {path: "big".to_string(), data: Some(bytes.clone())};
+    let mut data = msg.serialize_bin();
+    words.ser_bin(&mut data);
+    let mut reader = DeBinReader::new(Packets {data, o: 0});
+    assert_eq!(Msg::de_bin_from_reader(&mut reader).unwrap(), msg);
+    assert_eq!(reader.de_bin::<Vec<u32>>().unwrap(), words);
+    assert!(start.elapsed() < std::time::Duration::from_secs(5), "{:?}", start.elapsed());
+}
+
+#[test]
+fn slices_share_the_vec_encoding() {
+    // slices, borrowed or not, and vecs carry a length, a fixed size array does not
+    let with_len = vec![2, 0, 0, 0, 0, 0, 0, 0, 1, 2];
+    assert_eq!([1u8, 2][..].serialize_bin(), with_len);
+    assert_eq!(Vec::from([1u8, 2]).serialize_bin(), with_len);
+    let slice: &[u8] = &[1, 2];
+    assert_eq!(slice.serialize_bin(), with_len);
+    assert_eq!(SerBin::serialize_bin(&slice), with_len);
+    assert_eq!([1u8, 2].serialize_bin(), vec![1, 2]);
+    let mut written = Vec::new();
+    slice.ser_bin_to_writer(&mut written).unwrap();
+    assert_eq!(written, with_len);
+    assert_eq!(Vec::<u8>::deserialize_bin(&slice.serialize_bin()).unwrap(), vec![1, 2]);
+    assert_eq!(<&[u8]>::deserialize_bin_ref(&with_len).unwrap(), slice);
+}
+
+#[test]
+fn reader_errors() {
+    // truncated in the middle of the string
+    let mut data = "hello".to_string().serialize_bin();
+    data.truncate(10);
+    let mut reader = DeBinReader::new(Trickle {data: data, o: 0});
+    assert!(reader.de_bin::<String>().err().unwrap().is_eof());
+    
+    let mut data = 2u64.serialize_bin();
+    data.extend_from_slice(&[0xff, 0xfe]);
+    let mut reader = DeBinReader::new(&data[..]);
+    let err = reader.de_bin::<String>().err().unwrap();
+    assert!(!err.is_eof());
+    
+    // a length prefix claiming a terabyte on a stream that never ends
+    let mut data = (1u64 << 40).serialize_bin();
+    data.extend_from_slice(&[0; 16]);
+    let mut reader = DeBinReader::with_max_len((&data[..]).chain(std::io::repeat(0)), 4096);
+    let err = reader.de_bin::<Vec<u8>>().err().unwrap();
+    assert!(!err.is_eof());
+    assert!(reader.buffered().len() <= 4096);
+    // a value that fits still reads
+    let data = vec![7u8; 100].serialize_bin();
+    assert_eq!(DeBinReader::with_max_len(&data[..], 108).de_bin::<Vec<u8>>().unwrap(), vec![7u8; 100]);
+    assert!(DeBinReader::with_max_len(&data[..], 107).de_bin::<Vec<u8>>().is_err());
+}
+
+#[test]
+fn borrowed() {
+    let owned = Borrowed {name: "makepad", data: &[1, 2, 3], id: 42, tags: vec!["a".to_string(), "b".to_string()]};
+    let buf = owned.serialize_bin();
+    let back = Borrowed::deserialize_bin_ref(&buf).unwrap();
+    assert_eq!(back, owned);
+    // the borrowed fields point into the buffer
+    let range = buf.as_ptr_range();
+    assert!(range.contains(&back.name.as_ptr()));
+    assert!(range.contains(&back.data.as_ptr()));
+    // borrowed slices and vecs share the same encoding
+    assert_eq!(Vec::<u8>::deserialize_bin(&SerBin::serialize_bin(&back.data)).unwrap(), vec![1, 2, 3]);
+    assert!(Borrowed::deserialize_bin_ref(&buf[0..buf.len() - 1]).is_err());
+}
+
+// two versions of the same schema, as an old and a new builder would have them
+mod v1 {
+    use makepad_microserde::*;
+    
+    #[derive(Debug, PartialEq, SerBin, DeBin)]
+    #[nserde(tagged, version = "1")]
+    pub struct Config {
+        pub name: String,
+        pub port: u16,
+    }
+    
+    #[derive(Debug, PartialEq, SerBin, DeBin)]
+    #[nserde(tagged, version = "1")]
+    pub enum Msg {
+        Build {uid: u64, config: Config},
+        Kill(u64),
+        #[nserde(other)]
+        Unknown,
+    }
+}
+
+mod v2 {
+    use makepad_microserde::*;
+    
+    #[derive(Debug, PartialEq, SerBin, DeBin)]
+    #[nserde(tagged, version = "2", min_version = "1")]
+    pub struct Config {
+        pub port: u16,
+        pub name: String,
+        pub release: Option<bool>,
+        #[nserde(default)]
+        pub features: Vec<String>,
+    }
+    
+    #[derive(Debug, PartialEq, SerBin, DeBin)]
+    #[nserde(tagged, version = "2", min_version = "1")]
+    pub enum Msg {
+        Ping,
+        Kill(u64),
+        Build {uid: u64, config: Config, jobs: Option<u32>},
+        #[nserde(other)]
+        Unknown,
+    }
+    
+    #[derive(Debug, PartialEq, SerBin, DeBin)]
+    #[nserde(tagged, version = "3", min_version = "3")]
+    pub struct Breaking {
+        pub port: u32,
+    }
+}
+
+#[test]
+fn tagged_forward_compatible() {
+    let new = v2::Msg::Build {
+        uid: 5,
+        config: v2::Config {port: 80, name: "x".to_string(), release: Some(true), features: vec!["a".to_string()]},
+        jobs: Some(4)
+    };
+    let old = v1::Msg::deserialize_bin(&new.serialize_bin()).unwrap();
+    assert_eq!(old, v1::Msg::Build {uid: 5, config: v1::Config {name: "x".to_string(), port: 80}});
+    
+    assert_eq!(v1::Msg::deserialize_bin(&v2::Msg::Ping.serialize_bin()).unwrap(), v1::Msg::Unknown);
+    assert_eq!(v1::Msg::deserialize_bin(&v2::Msg::Kill(3).serialize_bin()).unwrap(), v1::Msg::Kill(3));
+    
+    // values inside a vec keep their place when the old reader skips something
+    let list = vec![v2::Msg::Ping, v2::Msg::Kill(1)];
+    let old: Vec<v1::Msg> = DeBin::deserialize_bin(&list.serialize_bin()).unwrap();
+    assert_eq!(old, vec![v1::Msg::Unknown, v1::Msg::Kill(1)]);
+}
+
+#[test]
+fn tagged_backward_compatible() {
+    let old = v1::Msg::Build {uid: 1, config: v1::Config {name: "y".to_string(), port: 8}};
+    let new = v2::Msg::deserialize_bin(&old.serialize_bin()).unwrap();
+    assert_eq!(new, v2::Msg::Build {
+        uid: 1,
+        config: v2::Config {port: 8, name: "y".to_string(), release: None, features: vec![]},
+        jobs: None
+    });
+}
+
+#[test]
+fn tagged_errors() {
+    let err = v2::Config::deserialize_bin(&v2::Breaking {port: 1}.serialize_bin()).err().unwrap();
+    assert!(err.msg.contains("needs at least version 3"));
+    assert!(!err.is_eof());
+    
+    // the old config requires name, an empty field list is missing it
+    let mut data = Vec::new();
+    ser_bin_tagged_header(&mut data, 2, 0);
+    0u32.ser_bin(&mut data);
+    let err = v1::Config::deserialize_bin(&data).err().unwrap();
+    assert!(err.msg.contains("missing field name"));
+    
+    let mut data = v2::Msg::Kill(1).serialize_bin();
+    data.pop();
+    assert!(v2::Msg::deserialize_bin(&data).err().unwrap().is_eof());
+}
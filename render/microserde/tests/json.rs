@@ -0,0 +1,117 @@
+use makepad_microserde::*;
+
+#[derive(SerJson, DeJson, PartialEq, Debug)]
+struct Build {
+    package: String,
+    jobs: u8,
+}
+
+#[derive(SerJson, DeJson, PartialEq, Debug)]
+struct Config {
+    name: String,
+    builds: Vec<Build>,
+    pos: Pos,
+}
+
+#[derive(SerJson, DeJson, PartialEq, Debug)]
+struct Pos(i8, i8);
+
+const CONFIG: &str = r#"{
+    "name": "hub",
+    "builds": [
+        {"package": "a", "jobs": 1},
+        {"package": "b", "jobs": 2},
+        {"package": 5, "jobs": 3}
+    ],
+    "pos": [1, 2]
+}"#;
+
+#[test]
+fn error_paths() {
+    let err = Config::deserialize_json(CONFIG).unwrap_err();
+    assert_eq!(err.path, "builds[2].package");
+    // the position is where the offending token starts
+    assert_eq!((err.line, err.col), (5, 20));
+    assert_eq!(format!("{:?}", err), "Json Deserialize error at builds[2].package: Unexpected token U64(5) expected string, line:6 col:21");
+    
+    let valid = CONFIG.replace("\"package\": 5", "\"package\": \"c\"");
+    let err = Config::deserialize_json(&valid.replace("\"jobs\": 2", "\"jobs\": 300")).unwrap_err();
+    assert_eq!(err.path, "builds[1].jobs");
+    assert!(err.msg.starts_with("Value out of range"));
+    
+    let err = Config::deserialize_json(&valid.replace("[1, 2]", "[1, -200]")).unwrap_err();
+    assert_eq!(err.path, "pos[1]");
+    
+    let err = Vec::<Vec<u32 >>::deserialize_json("[[1],[2,true]]").unwrap_err();
+    assert_eq!(err.path, "[1][1]");
+    
+    let err = JsonValue::deserialize_json(r#"{"a":{"b":[1,}]}}"#).unwrap_err();
+    assert_eq!(err.path, "a.b[1]");
+    
+    let err = Config::deserialize_json(&valid.replace("\"name\": \"hub\",", "")).unwrap_err();
+    assert_eq!(err.msg, "Key not found name");
+    assert_eq!(err.path, "");
+}
+
+#[test]
+fn strict() {
+    let valid = CONFIG.replace("\"package\": 5", "\"package\": \"c\"");
+    let config = Config::deserialize_json(&valid).unwrap();
+    assert_eq!(config.builds[2].package, "c");
+    // the derive writes plain json that reads back in strict mode
+    assert_eq!(Config::deserialize_json_strict(&config.serialize_json()).unwrap(), config);
+    
+    // trailing commas and trailing input are accepted unless strict
+    assert_eq!(Vec::<u32>::deserialize_json("[1,2,]").unwrap(), vec![1, 2]);
+    assert_eq!(Build::deserialize_json(r#"{"package":"a","jobs":1,}"#).unwrap().jobs, 1);
+    assert_eq!(Build::deserialize_json(r#"{"package":"a","jobs":1}]"#).unwrap().jobs, 1);
+    let err = Vec::<u32>::deserialize_json_strict("[1,2,]").unwrap_err();
+    assert_eq!(err.msg, "Trailing comma before ]");
+    let err = Build::deserialize_json_strict(r#"{"package":"a","jobs":1,}"#).unwrap_err();
+    assert_eq!(err.msg, "Trailing comma before }");
+    let err = Build::deserialize_json("{\"package\":\"a\", // comment\n\"jobs\":1}").unwrap_err();
+    assert!(err.msg.contains("lenient"));
+    let err = Build::deserialize_json(r#"{package:"a","jobs":1}"#).unwrap_err();
+    assert_eq!(err.msg, "Unquoted key package, keys are only unquoted in lenient mode");
+    let err = Build::deserialize_json_strict(r#"{"package":"a","jobs":1}]"#).unwrap_err();
+    assert!(err.msg.contains("end of input"));
+    let err = u32::deserialize_json("yes").unwrap_err();
+    assert_eq!(err.msg, "Unexpected identifier yes, expected true, false or null");
+}
+
+#[test]
+fn lenient() {
+    let input = r#"
+        // a hand edited config
+        {
+            name: "hub", /* unquoted keys */
+            builds: [
+                {package: "a", jobs: 1,},
+                {"package": "b", jobs2_ok$: 1, jobs: 2},
+            ],
+            pos: [1, 2,],
+        }
+    "#;
+    let err = Config::deserialize_json_lenient(input).unwrap_err();
+    assert_eq!(err.msg, "Unexpected key jobs2_ok$");
+    assert_eq!(err.path, "builds[1]");
+    
+    let config = Config::deserialize_json_lenient(&input.replace("jobs2_ok$: 1, ", "")).unwrap();
+    assert_eq!(config.builds[1].package, "b");
+    assert_eq!(config.pos, Pos(1, 2));
+    
+    let err = Config::deserialize_json_lenient("{/* open").unwrap_err();
+    assert!(err.msg.contains("*/"));
+}
+
+#[test]
+fn tokens() {
+    assert_eq!(String::deserialize_json(r#""aAé😀\b\/""#).unwrap(), "aAé😀\u{8}/");
+    assert!(String::deserialize_json(r#""\ud83d""#).is_err());
+    assert!(String::deserialize_json(r#""\u00g0""#).is_err());
+    assert_eq!(f64::deserialize_json("1.5e3").unwrap(), 1500.0);
+    assert_eq!(f64::deserialize_json("-2E-2").unwrap(), -0.02);
+    let s = "tab\t nul\0 bell\u{7}".to_string();
+    assert_eq!(s.serialize_json(), r#""tab\t nul\u0000 bell\u0007""#);
+    assert_eq!(String::deserialize_json(&s.serialize_json()).unwrap(), s);
+}
@@ -0,0 +1,117 @@
+use makepad_microserde::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(SerBin, DeBin, SerJson, DeJson, SerRon, DeRon, PartialEq, Debug)]
+struct Everything {
+    tree: BTreeMap<String, u32>,
+    ids: BTreeSet<u64>,
+    tags: HashSet<String>,
+    queue: VecDeque<i32>,
+    shared: Rc<String>,
+    synced: Arc<Vec<u8>>,
+    name: Cow<'static, str>,
+    big: u128,
+    small: i128,
+    elapsed: Duration,
+    modified: SystemTime,
+    grid: [u8; 40],
+    empty: HashMap<String, u32>,
+}
+
+fn everything() -> Everything {
+    let mut grid = [0u8; 40];
+    for (i, v) in grid.iter_mut().enumerate() {
+        *v = i as u8;
+    }
+    Everything {
+        tree: vec![("b".to_string(), 2), ("a".to_string(), 1)].into_iter().collect(),
+        ids: vec![3, 1, 2].into_iter().collect(),
+        tags: vec!["x".to_string()].into_iter().collect(),
+        queue: vec![-1, 0, 1].into_iter().collect(),
+        shared: Rc::new("rc".to_string()),
+        synced: Arc::new(vec![1, 2, 3]),
+        name: Cow::Borrowed("cow"),
+        big: u128::max_value(),
+        small: i128::min_value(),
+        elapsed: Duration::new(5, 123_456_789),
+        modified: UNIX_EPOCH + Duration::new(1_600_000_000, 42),
+        grid,
+        empty: HashMap::new(),
+    }
+}
+
+fn round_trip<T>(value: &T) where T: SerBin + DeBin + SerJson + DeJson + SerRon + DeRon + PartialEq + Debug {
+    let bin = value.serialize_bin();
+    assert_eq!(&T::deserialize_bin(&bin).unwrap(), value);
+    let json = value.serialize_json();
+    assert_eq!(&T::deserialize_json(&json).unwrap(), value, "{}", json);
+    let ron = value.serialize_ron();
+    assert_eq!(&T::deserialize_ron(&ron).unwrap(), value, "{}", ron);
+}
+
+#[test]
+fn struct_round_trip() {
+    round_trip(&everything());
+}
+
+#[test]
+fn wide_integers() {
+    round_trip(&u128::max_value());
+    round_trip(&i128::min_value());
+    round_trip(&i128::max_value());
+    round_trip(&(u64::max_value() as u128 + 1));
+    round_trip(&(i64::min_value() as i128 - 1));
+    round_trip(&7u128);
+    round_trip(&-7i128);
+    
+    assert!(u128::deserialize_json("-1").is_err());
+    assert!(i128::deserialize_json("340282366920938463463374607431768211455").is_err());
+    assert!(u128::deserialize_ron("\"1\"").is_err());
+    // 64 bit types still reject values that only fit in 128 bits
+    assert!(u64::deserialize_json("18446744073709551616").is_err());
+}
+
+#[test]
+fn time() {
+    let before_epoch = UNIX_EPOCH - Duration::new(10, 250);
+    round_trip(&before_epoch);
+    round_trip(&(UNIX_EPOCH - Duration::from_secs(3)));
+    round_trip(&UNIX_EPOCH);
+    round_trip(&Duration::new(0, 0));
+    
+    assert_eq!(Duration::new(1, 2).serialize_json(), "{\"secs\":1,\"nanos\":2}");
+    assert_eq!(Duration::deserialize_ron("(nanos: 2, secs: 1)").unwrap(), Duration::new(1, 2));
+    assert!(Duration::deserialize_json("{\"secs\":1}").is_err());
+    assert!(Duration::deserialize_json("{\"secs\":1,\"nanos\":1000000000}").is_err());
+    assert!(Duration::deserialize_ron("(secs: 1, nanos: 2, extra: 3)").is_err());
+    
+    let mut bin = Vec::new();
+    1u64.ser_bin(&mut bin);
+    2_000_000_000u32.ser_bin(&mut bin);
+    assert!(Duration::deserialize_bin(&bin).is_err());
+}
+
+#[test]
+fn collections() {
+    round_trip(&BTreeMap::<u32, String>::new());
+    round_trip(&HashSet::<String>::new());
+    round_trip(&VecDeque::<u8>::new());
+    let map: HashMap<u32, BTreeSet<String >> = vec![(1, vec!["a".to_string()].into_iter().collect())].into_iter().collect();
+    round_trip(&map);
+    
+    // sets read back from a list with duplicates collapse them
+    let set = BTreeSet::<u32>::deserialize_json("[3,1,3]").unwrap();
+    assert_eq!(set.into_iter().collect::<Vec<_ >> (), vec![1, 3]);
+    
+    let cow: Cow<[u32]> = Cow::Owned(vec![1, 2]);
+    assert_eq!(Cow::<[u32]>::deserialize_bin(&cow.serialize_bin()).unwrap(), cow);
+    
+    let big = [7u32; 256];
+    assert_eq!(<[u32; 256]>::deserialize_bin(&big.serialize_bin()).unwrap().to_vec(), big.to_vec());
+    assert_eq!(<[u32; 256]>::deserialize_json(&big.serialize_json()).unwrap().to_vec(), big.to_vec());
+}
@@ -0,0 +1,216 @@
+use makepad_microserde::*;
+use std::collections::HashMap;
+
+const MANIFEST: &str = r#"
+# a cargo manifest
+[package]
+name = "makepad-hub"
+version = "0.1.0"
+authors = ["Makepad <info@makepad.nl>"]
+edition = "2018"
+
+[dependencies]
+makepad-microserde = { path = "../../render/microserde", version = "0.1" }
+serde = "1.0"
+
+[dependencies.makepad-http]
+path = "../http"
+
+[workspace]
+members = [
+    "makepad",
+    "examples/*", # trailing comma and comments
+]
+exclude = ["examples/depricated"]
+
+[[example]]
+name = "first"
+crate-type = ["bin"]
+
+[[example]]
+name = "second"
+
+[target.'cfg(windows)'.dependencies]
+winapi = { version = "0.3", features = ["winuser"] }
+"#;
+
+#[test]
+fn parse_manifest() {
+    let toml = TomlParser::parse(MANIFEST).unwrap();
+    assert_eq!(toml.path("package.name"), Some(&Toml::Str("makepad-hub".to_string())));
+    assert_eq!(
+        toml.path("dependencies.makepad-microserde.path"),
+        Some(&Toml::Str("../../render/microserde".to_string()))
+    );
+    assert_eq!(toml.path("dependencies.makepad-http.path").and_then( | v | v.as_str()), Some("../http"));
+    assert_eq!(toml.path("workspace.members").and_then( | v | v.as_array()).map( | v | v.len()), Some(2));
+    let examples = toml.get("example").and_then( | v | v.as_array()).unwrap();
+    assert_eq!(examples.len(), 2);
+    assert_eq!(examples[1].get("name").and_then( | v | v.as_str()), Some("second"));
+    let winapi = toml.get("target").unwrap().get("cfg(windows)").unwrap().path("dependencies.winapi.version");
+    assert_eq!(winapi.and_then( | v | v.as_str()), Some("0.3"));
+}
+
+#[test]
+fn parse_values() {
+    let toml = TomlParser::parse(r#"
+int = +99
+neg = -17
+hex = 0xDEAD_beef
+oct = 0o755
+bin = 0b1101
+big = 1_000_000
+float = 6.626e-34
+frac = 1_2.5
+inf = -inf
+date = 1979-05-27T07:32:00-08:00
+date2 = 1979-05-27 07:32:00
+time = 07:32:00
+basic = "tab\tquote\" \u00E9"
+literal = 'C:\Users\nodejs'
+multi = """
+Roses are red
+Violets are blue"""
+folded = """\
+    The quick \
+    brown fox."""
+multi_literal = '''
+raw \n text'''
+quotes = """Here are two quotation marks: "". Simple enough."""
+five = """"This," she said, "is just a pointless statement.""""
+a.b.c = 1
+inline = { x = 1, y.z = "2" }
+nested = [[1, 2], ["a"], []]
+"quoted key" = true
+"#).unwrap();
+    assert_eq!(toml.get("int"), Some(&Toml::Int(99)));
+    assert_eq!(toml.get("neg"), Some(&Toml::Int(-17)));
+    assert_eq!(toml.get("hex"), Some(&Toml::Int(0xdeadbeef)));
+    assert_eq!(toml.get("oct"), Some(&Toml::Int(0o755)));
+    assert_eq!(toml.get("bin"), Some(&Toml::Int(13)));
+    assert_eq!(toml.get("big"), Some(&Toml::Int(1000000)));
+    assert_eq!(toml.get("float"), Some(&Toml::Num(6.626e-34)));
+    assert_eq!(toml.get("frac"), Some(&Toml::Num(12.5)));
+    assert_eq!(toml.get("inf"), Some(&Toml::Num(f64::NEG_INFINITY)));
+    assert_eq!(toml.get("date"), Some(&Toml::Date("1979-05-27T07:32:00-08:00".to_string())));
+    assert_eq!(toml.get("date2"), Some(&Toml::Date("1979-05-27 07:32:00".to_string())));
+    assert_eq!(toml.get("time"), Some(&Toml::Date("07:32:00".to_string())));
+    assert_eq!(toml.get("basic").and_then( | v | v.as_str()), Some("tab\tquote\" \u{e9}"));
+    assert_eq!(toml.get("literal").and_then( | v | v.as_str()), Some("C:\\Users\\nodejs"));
+    assert_eq!(toml.get("multi").and_then( | v | v.as_str()), Some("Roses are red\nViolets are blue"));
+    assert_eq!(toml.get("folded").and_then( | v | v.as_str()), Some("The quick brown fox."));
+    assert_eq!(toml.get("multi_literal").and_then( | v | v.as_str()), Some("raw \\n text"));
+    assert_eq!(toml.get("quotes").and_then( | v | v.as_str()), Some("Here are two quotation marks: \"\". Simple enough."));
+    assert_eq!(toml.get("five").and_then( | v | v.as_str()), Some("\"This,\" she said, \"is just a pointless statement.\""));
+    assert_eq!(toml.path("a.b.c"), Some(&Toml::Int(1)));
+    assert_eq!(toml.path("inline.y.z").and_then( | v | v.as_str()), Some("2"));
+    assert_eq!(toml.get("nested").and_then( | v | v.as_array()).map( | v | v.len()), Some(3));
+    assert_eq!(toml.get("quoted key"), Some(&Toml::Bool(true)));
+}
+
+#[test]
+fn parse_errors() {
+    assert!(TomlParser::parse("a = 1\na = 2").is_err());
+    assert!(TomlParser::parse("[a]\n[a]").is_err());
+    assert!(TomlParser::parse("a = 01").is_err());
+    assert!(TomlParser::parse("a = 1__0").is_err());
+    assert!(TomlParser::parse("a = \"open").is_err());
+    assert!(TomlParser::parse("a = 1 b = 2").is_err());
+    assert!(TomlParser::parse("a = [1\n[b]").is_err());
+    assert!(TomlParser::parse("a = 1\n[[a]]").is_err());
+    let err = TomlParser::parse("a = 1\nb = ?").err().unwrap();
+    assert_eq!(err.line, 1);
+}
+
+#[test]
+fn round_trip() {
+    let toml = TomlParser::parse(MANIFEST).unwrap();
+    let written = toml.write();
+    let back = TomlParser::parse(&written).unwrap();
+    // plain values are written before subtables, so compare the written form
+    assert_eq!(written, back.write());
+    assert_eq!(toml.path("dependencies.makepad-http"), back.path("dependencies.makepad-http"));
+    assert_eq!(toml.get("example"), back.get("example"));
+    assert!(written.contains("[[example]]"));
+    assert!(written.contains("[package]"));
+    assert!(written.contains("[target.\"cfg(windows)\".dependencies.winapi]"));
+}
+
+#[derive(Debug, PartialEq, SerToml, DeToml)]
+struct Manifest {
+    package: Package,
+    #[nserde(default)]
+    dependencies: HashMap<String, Toml>,
+    #[nserde(rename = "example", default)]
+    examples: Vec<Target>,
+    workspace: Option<Workspace>,
+}
+
+#[derive(Debug, PartialEq, SerToml, DeToml)]
+struct Package {
+    name: String,
+    version: String,
+    authors: Vec<String>,
+    edition: Option<String>,
+}
+
+#[derive(Debug, PartialEq, SerToml, DeToml)]
+struct Target {
+    name: String,
+    #[nserde(rename = "crate-type")]
+    crate_type: Option<Vec<String >>,
+}
+
+#[derive(Debug, PartialEq, SerToml, DeToml)]
+struct Workspace {
+    members: Vec<String>,
+    #[nserde(default)]
+    exclude: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, SerToml, DeToml)]
+enum Profile {
+    Debug,
+    Opt(u8),
+    Custom {name: String, lto: bool},
+}
+
+#[derive(Debug, PartialEq, SerToml, DeToml)]
+struct Profiles {
+    profiles: Vec<Profile>,
+    weights: (f32, u64),
+}
+
+#[test]
+fn typed() {
+    let manifest = Manifest::deserialize_toml(MANIFEST).unwrap();
+    assert_eq!(manifest.package.name, "makepad-hub");
+    assert_eq!(manifest.examples.len(), 2);
+    assert_eq!(manifest.examples[0].crate_type, Some(vec!["bin".to_string()]));
+    assert_eq!(manifest.examples[1].crate_type, None);
+    assert_eq!(manifest.workspace.as_ref().unwrap().exclude, vec!["examples/depricated".to_string()]);
+    assert!(manifest.dependencies.contains_key("makepad-http"));
+
+    let back = Manifest::deserialize_toml(&manifest.serialize_toml()).unwrap();
+    assert_eq!(manifest, back);
+
+    let profiles = Profiles {
+        profiles: vec![Profile::Debug, Profile::Opt(3), Profile::Custom {name: "small".to_string(), lto: true}],
+        weights: (0.5, 7),
+    };
+    let back = Profiles::deserialize_toml(&profiles.serialize_toml()).unwrap();
+    assert_eq!(profiles, back);
+
+    // u64 beyond i64 does not wrap into a negative toml integer
+    let big = Profiles {profiles: Vec::new(), weights: (1.0, u64::MAX)};
+    let toml = big.serialize_toml();
+    assert!(toml.contains("\"18446744073709551615\""), "{}", toml);
+    assert_eq!(Profiles::deserialize_toml(&toml).unwrap(), big);
+    assert_eq!(u64::de_toml(&Toml::Int(i64::MAX)).unwrap(), i64::MAX as u64);
+    assert!(u64::de_toml(&Toml::Int(-1)).is_err());
+    assert!(u8::de_toml(&Toml::Int(256)).is_err());
+
+    let err = Package::deserialize_toml("name = \"x\"").err().unwrap();
+    assert!(err.msg.contains("version"));
+    assert!(Package::deserialize_toml("name = 1\nversion = \"1\"\nauthors = []").is_err());
+}
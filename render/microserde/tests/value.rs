@@ -0,0 +1,119 @@
+use makepad_microserde::*;
+
+#[derive(SerJson, DeJson, SerRon, DeRon, PartialEq, Debug)]
+struct Build {
+    name: String,
+    jobs: u32,
+    release: bool,
+    targets: Vec<String>,
+}
+
+#[derive(SerRon, DeRon, PartialEq, Debug)]
+enum Step {
+    Clean,
+    Copy(String, String),
+    Run {cmd: String, retry: u8},
+}
+
+#[test]
+fn json_parse() {
+    let v = JsonValue::deserialize_json(r#"{"a":{"b":[1,-2,3.5,"x",null,true]},"c":"d"}"#).unwrap();
+    assert_eq!(v.path("a.b.0"), Some(&JsonValue::U64(1)));
+    assert_eq!(v.path("a.b.1").and_then( | v | v.as_i64()), Some(-2));
+    assert_eq!(v.path("a.b.2").and_then( | v | v.as_f64()), Some(3.5));
+    assert_eq!(v.path("a.b.3").and_then( | v | v.as_str()), Some("x"));
+    assert!(v.path("a.b.4").unwrap().is_null());
+    assert_eq!(v.path("a.b.5").and_then( | v | v.as_bool()), Some(true));
+    assert_eq!(v.get("c").and_then( | v | v.as_str()), Some("d"));
+    assert_eq!(v.path("a.b.6"), None);
+    assert_eq!(v.path("a.x"), None);
+    assert_eq!(v.path("c.0"), None);
+    
+    assert!(JsonValue::deserialize_json("[1,").is_err());
+    assert!(JsonValue::deserialize_json("{1:2}").is_err());
+}
+
+#[test]
+fn json_round_trip() {
+    let input = r#"{"name":"hub","jobs":4,"release":false,"targets":["a","b\"c"]}"#;
+    let v = JsonValue::deserialize_json(input).unwrap();
+    assert_eq!(v.serialize_json(), input);
+    
+    // a typed value and its dynamic tree serialize the same
+    let build = Build::deserialize_json(input).unwrap();
+    assert_eq!(build.serialize_json(), input);
+    assert_eq!(JsonValue::deserialize_json(&build.serialize_json()).unwrap(), v);
+    assert_eq!(Build::deserialize_json(&v.serialize_json()).unwrap(), build);
+    
+    let f = JsonValue::Array(vec![JsonValue::F64(1.0), JsonValue::F64(0.25)]);
+    assert_eq!(f.serialize_json(), "[1.0,0.25]");
+    assert_eq!(JsonValue::deserialize_json(&f.serialize_json()).unwrap(), f);
+    
+    let mut v = v;
+    v.insert("jobs", JsonValue::U64(8));
+    v.insert("extra", JsonValue::Null);
+    assert_eq!(v.get("jobs"), Some(&JsonValue::U64(8)));
+    assert_eq!(v.as_object().unwrap().last().unwrap().0, "extra");
+}
+
+#[test]
+fn json_pretty() {
+    let v = JsonValue::deserialize_json(r#"{"a":[1,{"b":2}],"c":[],"d":{}}"#).unwrap();
+    let pretty = v.write_pretty();
+    assert_eq!(pretty, "{\n    \"a\": [\n        1,\n        {\n            \"b\": 2\n        }\n    ],\n    \"c\": [],\n    \"d\": {}\n}");
+    assert_eq!(JsonValue::deserialize_json(&pretty).unwrap(), v);
+}
+
+#[test]
+fn ron_parse() {
+    let v = RonValue::deserialize_ron(r#"
+        Config( // a named struct
+            name: "hub",
+            pos: (1, -2, 0.5),
+            steps: [Clean, Copy("a", "b"), Run(cmd: "make", retry: 2)],
+            env: {"PATH": "/bin", key2: 'x'},
+            empty: (),
+            opt: None,
+        )
+    "#).unwrap();
+    assert_eq!(v.name(), Some("Config"));
+    assert_eq!(v.path("name").and_then( | v | v.as_str()), Some("hub"));
+    assert_eq!(v.path("pos.1").and_then( | v | v.as_i64()), Some(-2));
+    assert_eq!(v.path("pos.2").and_then( | v | v.as_f64()), Some(0.5));
+    assert_eq!(v.path("steps.0").and_then( | v | v.as_ident()), Some("Clean"));
+    assert_eq!(v.path("steps.1").and_then( | v | v.name()), Some("Copy"));
+    assert_eq!(v.path("steps.1.1").and_then( | v | v.as_str()), Some("b"));
+    assert_eq!(v.path("steps.2.retry").and_then( | v | v.as_u64()), Some(2));
+    assert_eq!(v.path("env.PATH").and_then( | v | v.as_str()), Some("/bin"));
+    assert_eq!(v.path("env.key2").and_then( | v | v.as_char()), Some('x'));
+    assert_eq!(v.path("empty"), Some(&RonValue::Tuple(None, Vec::new())));
+    assert_eq!(v.path("opt").and_then( | v | v.as_ident()), Some("None"));
+    assert_eq!(v.path("steps.3"), None);
+    
+    assert!(RonValue::deserialize_ron("(a: 1, 2)").is_err());
+    assert!(RonValue::deserialize_ron("(1, a: 2)").is_err());
+    assert!(RonValue::deserialize_ron("[1,").is_err());
+}
+
+#[test]
+fn ron_round_trip() {
+    let build = Build {
+        name: "hub".to_string(),
+        jobs: 4,
+        release: true,
+        targets: vec!["a".to_string(), "b".to_string()],
+    };
+    let typed = build.serialize_ron();
+    let v = RonValue::deserialize_ron(&typed).unwrap();
+    assert_eq!(v.path("targets.1").and_then( | v | v.as_str()), Some("b"));
+    // the dynamic tree writes the same layout as the derive
+    assert_eq!(v.serialize_ron(), typed);
+    assert_eq!(Build::deserialize_ron(&v.serialize_ron()).unwrap(), build);
+    
+    let steps = vec![Step::Clean, Step::Copy("a".to_string(), "b".to_string()), Step::Run {cmd: "make".to_string(), retry: 1}];
+    let v = RonValue::deserialize_ron(&steps.serialize_ron()).unwrap();
+    assert_eq!(Vec::<Step>::deserialize_ron(&v.serialize_ron()).unwrap(), steps);
+    
+    let v = RonValue::List(vec![RonValue::F64(2.0), RonValue::Char('\n'), RonValue::Char('\''), RonValue::Map(Vec::new())]);
+    assert_eq!(RonValue::deserialize_ron(&v.serialize_ron()).unwrap(), v);
+}
@@ -1,8 +1,11 @@
 use crate::cx::*;
+use crate::cx_http::*;
+use crate::cx_websocket::*;
+use std::collections::HashMap;
 use std::io::prelude::*;
 use std::fs::File;
 use std::io;
-use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
 //use time::precise_time_ns;
 
 #[macro_export]
@@ -18,8 +21,21 @@ pub struct CxDesktop {
     pub file_read_id: u64,
     pub file_reads: Vec<FileRead>,
     pub profiler_start: Option<u64>,
+    pub http_request_id: u64,
+    pub net_events: Arc<Mutex<Vec<DesktopNetEvent>>>,
+    pub websockets: HashMap<String, DesktopWebSocket>,
 }
 
+// what the network threads hand back to the event loop
+pub enum DesktopNetEvent {
+    HttpResponse(HttpResponseEvent),
+    WebSocketMessage(WebSocketMessageEvent),
+    WebSocketClose(WebSocketCloseEvent),
+}
+
+// posted by the network threads to wake up the event loop, never handed to the app
+pub(crate) const NET_EVENT_SIGNAL: Signal = Signal {signal_id: usize::MAX};
+
 impl Default for CxDesktop {
     fn default() -> CxDesktop {
         CxDesktop {
@@ -27,6 +43,9 @@ impl Default for CxDesktop {
             file_read_id: 1,
             file_reads: Vec::new(),
             profiler_start: None,
+            http_request_id: 0,
+            net_events: Arc::new(Mutex::new(Vec::new())),
+            websockets: HashMap::new(),
         }
     }
 }
@@ -97,6 +116,11 @@ impl Cx {
             Event::AppFocusLost => {
                 self.call_all_keys_up();
             },
+            Event::Signal(se) => {
+                if se.signals.remove(&NET_EVENT_SIGNAL).is_some() {
+                    self.process_desktop_net_events();
+                }
+            },
             _ => ()
         };
     }
@@ -153,6 +177,8 @@ impl Cx {
         
         self.process_desktop_file_reads();
         
+        self.process_desktop_net_events();
+        
         self.call_signals_and_triggers();
         
         vsync
@@ -198,6 +224,30 @@ impl Cx {
         }
     }
     
+    pub fn process_desktop_net_events(&mut self) {
+        let net_events = if let Ok(mut net_events) = self.platform.desktop.net_events.lock() {
+            std::mem::replace(&mut *net_events, Vec::new())
+        }
+        else {
+            return
+        };
+        for net_event in net_events {
+            match net_event {
+                DesktopNetEvent::HttpResponse(response) => {
+                    self.call_event_handler(&mut Event::HttpResponse(response));
+                },
+                DesktopNetEvent::WebSocketMessage(message) => {
+                    self.call_event_handler(&mut Event::WebSocketMessage(message));
+                },
+                DesktopNetEvent::WebSocketClose(close) => {
+                    // the next send reconnects
+                    self.platform.desktop.websockets.remove(&close.url);
+                    self.call_event_handler(&mut Event::WebSocketClose(close));
+                }
+            }
+        }
+    }
+    
     pub fn process_to_wasm<F>(&mut self, _msg: u32, mut _event_handler: F) -> u32
     where F: FnMut(&mut Cx, &mut Event)
     {
@@ -239,55 +289,45 @@ impl Cx {
         let _ = io::stdout().flush();
     }
     
-    pub fn websocket_send(&self, _url: &str, _data: &[u8]) {
-        // nop
+    // a connection that died is replaced by a new one. sends that fail later on
+    // come back as a WebSocketMessage error, false means even a new connection refused it
+    pub fn websocket_send(&mut self, url: &str, data: &[u8]) -> bool {
+        let desktop = &mut self.platform.desktop;
+        if let Some(socket) = desktop.websockets.get(url) {
+            if socket.send(data) {
+                return true
+            }
+        }
+        let socket = DesktopWebSocket::connect(url, desktop.net_events.clone());
+        let sent = socket.send(data);
+        desktop.websockets.insert(url.to_string(), socket);
+        sent
+    }
+    
+    pub fn http_request(&mut self, request: HttpRequest) -> u64 {
+        let desktop = &mut self.platform.desktop;
+        desktop.http_request_id += 1;
+        let request_id = desktop.http_request_id;
+        let net_events = desktop.net_events.clone();
+        std::thread::spawn(move || {
+            let response = http_fetch(&request);
+            if let Ok(mut net_events) = net_events.lock() {
+                net_events.push(DesktopNetEvent::HttpResponse(HttpResponseEvent {request_id, response}));
+            }
+            Cx::post_signal(NET_EVENT_SIGNAL, Cx::status_http_send_ok());
+        });
+        request_id
     }
     
     pub fn http_send(&self, verb: &str, path: &str, _proto: &str, domain: &str, port: u16, content_type: &str, body: &[u8], signal: Signal) {
-        
-        fn write_bytes_to_tcp_stream(tcp_stream: &mut TcpStream, bytes: &[u8]) -> bool {
-            let bytes_total = bytes.len();
-            let mut bytes_left = bytes_total;
-            while bytes_left > 0 {
-                let buf = &bytes[(bytes_total - bytes_left)..bytes_total];
-                if let Ok(bytes_written) = tcp_stream.write(buf) {
-                    if bytes_written == 0 {
-                        return false
-                    }
-                    bytes_left -= bytes_written;
-                }
-                else {
-                    return true
-                }
+        let request = HttpRequest::new(verb, &format!("http://{}:{}{}", domain, port, path))
+            .with_body(content_type, body.to_vec());
+        std::thread::spawn(move || {
+            match http_fetch(&request) {
+                Ok(response) if response.is_success() => Cx::post_signal(signal, Cx::status_http_send_ok()),
+                _ => Cx::post_signal(signal, Cx::status_http_send_fail())
             }
-            return false
-        }
-        
-        // start a thread, connect, and report back.
-        let data = body.to_vec();
-        let byte_len = data.len();
-        let header = format!(
-            "{} {} HTTP/1.1\r\nHost: {}\r\nConnect: close\r\nContent-Type:{}\r\nContent-Length:{}\r\n\r\n",
-            verb,
-            path,
-            domain,
-            content_type,
-            byte_len
-        );
-        let host = format!("{}:{}", domain, port);
-        let _connect_thread = {
-            std::thread::spawn(move || {
-                let stream = TcpStream::connect(&host);
-                if let Ok(mut stream) = stream {
-                    if !write_bytes_to_tcp_stream(&mut stream, header.as_bytes())
-                        && !write_bytes_to_tcp_stream(&mut stream, &data) {
-                        Cx::post_signal(signal, Cx::status_http_send_ok());
-                        return
-                    }
-                }
-                Cx::post_signal(signal, Cx::status_http_send_fail());
-            })
-        };
+        });
     }
     /*
     
@@ -0,0 +1,284 @@
+// a small blocking HTTP/1.1 client, the desktop platforms run it on a thread per request
+use crate::cx::*;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// a status or header line longer than this is not a server we want to talk to
+const HTTP_MAX_LINE: usize = 64 * 1024;
+
+// headers that only go to the origin they were meant for
+const HTTP_CREDENTIAL_HEADERS: [&str; 3] = ["Authorization", "Cookie", "Proxy-Authorization"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    // path including the query string, always starts with /
+    pub path: String,
+}
+
+pub fn parse_http_url(url: &str) -> Result<HttpUrl, String> {
+    let rest = if let Some(rest) = url.strip_prefix("http://") {
+        rest
+    }
+    else if url.starts_with("https://") {
+        return Err(format!("https is not supported by the native http client: {}", url))
+    }
+    else {
+        return Err(format!("Not an http url: {}", url))
+    };
+
+    let (authority, path) = match rest.find(&['/', '?', '#'][..]) {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/")
+    };
+    // fragments never go over the wire
+    let path = match path.find('#') {
+        Some(pos) => &path[..pos],
+        None => path
+    };
+    let path = if path.starts_with('/') {path.to_string()} else {format!("/{}", path)};
+    // drop any userinfo
+    let authority = match authority.rfind('@') {
+        Some(pos) => &authority[pos + 1..],
+        None => authority
+    };
+
+    let (host, port) = if authority.starts_with('[') {
+        let end = authority.find(']').ok_or_else( || format!("Unterminated ipv6 host in {}", url)) ?;
+        let host = &authority[..end + 1];
+        match authority[end + 1..].strip_prefix(':') {
+            Some(port) => (host, Some(port)),
+            None => (host, None)
+        }
+    }
+    else {
+        match authority.rfind(':') {
+            Some(pos) => (&authority[..pos], Some(&authority[pos + 1..])),
+            None => (authority, None)
+        }
+    };
+    if host.is_empty() {
+        return Err(format!("No host in url {}", url))
+    }
+    let port = match port {
+        Some(port) => port.parse::<u16>().map_err( | _ | format!("Invalid port in url {}", url)) ?,
+        None => 80
+    };
+    Ok(HttpUrl {host: host.to_string(), port, path})
+}
+
+// resolves a Location header against the url it came from
+pub fn resolve_http_location(base: &str, location: &str) -> String {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return location.to_string()
+    }
+    let scheme_end = base.find("://").map( | p | p + 3).unwrap_or(0);
+    let origin_end = base[scheme_end..].find('/').map( | p | p + scheme_end).unwrap_or(base.len());
+    if let Some(rest) = location.strip_prefix("//") {
+        return format!("{}{}", &base[..scheme_end], rest)
+    }
+    if location.starts_with('/') {
+        return format!("{}{}", &base[..origin_end], location)
+    }
+    // relative to the directory of the base path
+    let base_path = &base[origin_end..];
+    let base_path = match base_path.find(&['?', '#'][..]) {
+        Some(pos) => &base_path[..pos],
+        None => base_path
+    };
+    let dir = match base_path.rfind('/') {
+        Some(pos) => &base_path[..pos + 1],
+        None => "/"
+    };
+    format!("{}{}{}", &base[..origin_end], dir, location)
+}
+
+// tries every address the host resolves to, none of them gets longer than timeout
+pub fn http_connect(parsed: &HttpUrl, timeout: Duration) -> Result<TcpStream, std::io::Error> {
+    let addrs = (parsed.host.trim_matches( | c | c == '[' || c == ']'), parsed.port).to_socket_addrs() ?;
+    let mut last_err = std::io::Error::new(std::io::ErrorKind::NotFound, "host has no addresses");
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = err
+        }
+    }
+    Err(last_err)
+}
+
+pub fn http_fetch(request: &HttpRequest) -> Result<HttpResponse, String> {
+    let mut method = request.method.clone();
+    let mut url = request.url.clone();
+    let mut body = request.body.clone();
+    let mut headers = request.headers.clone();
+    let mut redirects = 0;
+    loop {
+        let parsed = parse_http_url(&url) ?;
+        let mut stream = http_connect(&parsed, Duration::from_secs(30))
+            .map_err( | e | format!("Cannot connect to {}: {}", url, e)) ?;
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(60)));
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(60)));
+
+        write_http_request(&mut stream, &method, &parsed, &headers, &body)
+            .map_err( | e | format!("Cannot send request to {}: {}", url, e)) ?;
+
+        let mut reader = BufReader::new(stream);
+        let mut response = read_http_response(&mut reader, &method, request.max_response_size) ?;
+        response.url = url.clone();
+
+        let is_redirect = matches!(response.status, 301 | 302 | 303 | 307 | 308);
+        if !is_redirect || redirects >= request.max_redirects {
+            return Ok(response)
+        }
+        let location = match response.header("Location") {
+            Some(location) => location.to_string(),
+            None => return Ok(response)
+        };
+        let location = resolve_http_location(&url, &location);
+        // we cannot speak https, the caller gets the redirect and decides what to do with it
+        if location.starts_with("https://") {
+            return Ok(response)
+        }
+        let next = parse_http_url(&location) ?;
+        if !next.host.eq_ignore_ascii_case(&parsed.host) || next.port != parsed.port {
+            headers.retain( | (name, _) | !HTTP_CREDENTIAL_HEADERS.iter().any( | header | name.eq_ignore_ascii_case(header)));
+        }
+        redirects += 1;
+        url = location;
+        // 303 always turns into a GET, and so do 301/302 after a POST like every browser does
+        if response.status == 303 || (method == "POST" && (response.status == 301 || response.status == 302)) {
+            if method != "HEAD" {
+                method = "GET".to_string();
+            }
+            body.truncate(0);
+            headers.retain( | (name, _) | !name.eq_ignore_ascii_case("Content-Type"));
+        }
+    }
+}
+
+pub fn write_http_request<W: Write>(out: &mut W, method: &str, url: &HttpUrl, headers: &[(String, String)], body: &[u8]) -> std::io::Result<()> {
+    let mut head = format!("{} {} HTTP/1.1\r\n", method, url.path);
+    if url.port == 80 {
+        head.push_str(&format!("Host: {}\r\n", url.host));
+    }
+    else {
+        head.push_str(&format!("Host: {}:{}\r\n", url.host, url.port));
+    }
+    head.push_str("Connection: close\r\n");
+    let mut has_length = false;
+    for (name, value) in headers {
+        // we own the connection, so these are not for the caller to set
+        if name.eq_ignore_ascii_case("Host") || name.eq_ignore_ascii_case("Connection") {
+            continue;
+        }
+        if name.eq_ignore_ascii_case("Content-Length") {
+            has_length = true;
+        }
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !has_length && (!body.is_empty() || method == "POST" || method == "PUT") {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    head.push_str("\r\n");
+    out.write_all(head.as_bytes()) ?;
+    out.write_all(body) ?;
+    out.flush()
+}
+
+fn read_http_line<R: BufRead>(input: &mut R) -> Result<String, String> {
+    let mut line = Vec::new();
+    input.by_ref().take(HTTP_MAX_LINE as u64).read_until(b'\n', &mut line).map_err( | e | format!("Error reading http response: {}", e)) ?;
+    if line.is_empty() {
+        return Err("Connection closed during http response".to_string())
+    }
+    if line.len() >= HTTP_MAX_LINE && line.last() != Some(&b'\n') {
+        return Err(format!("Http response line longer than {} bytes", HTTP_MAX_LINE))
+    }
+    while let Some(b'\n') | Some(b'\r') = line.last() {
+        line.pop();
+    }
+    String::from_utf8(line).map_err( | _ | "Http response line is not utf8".to_string())
+}
+
+fn read_http_headers<R: BufRead>(input: &mut R, headers: &mut Vec<(String, String)>) -> Result<(), String> {
+    let mut header_size = 0usize;
+    loop {
+        let line = read_http_line(input) ?;
+        if line.is_empty() {
+            return Ok(())
+        }
+        header_size += line.len();
+        if header_size > HTTP_MAX_LINE {
+            return Err(format!("Http headers longer than {} bytes", HTTP_MAX_LINE))
+        }
+        match line.find(':') {
+            Some(pos) => headers.push((line[..pos].trim().to_string(), line[pos + 1..].trim().to_string())),
+            None => return Err(format!("Invalid http header line: {}", line))
+        }
+    }
+}
+
+// the body is limited to max_size bytes, a longer one is an error rather than a truncated response
+pub fn read_http_response<R: BufRead>(input: &mut R, method: &str, max_size: usize) -> Result<HttpResponse, String> {
+    let mut response = HttpResponse::default();
+    loop {
+        let status_line = read_http_line(input) ?;
+        let mut parts = status_line.splitn(3, ' ');
+        let version = parts.next().unwrap_or("");
+        if !version.starts_with("HTTP/") {
+            return Err(format!("Invalid http status line: {}", status_line))
+        }
+        response.status = parts.next().and_then( | s | s.parse::<u16>().ok())
+            .ok_or_else( || format!("Invalid http status line: {}", status_line)) ?;
+        response.reason = parts.next().unwrap_or("").to_string();
+        response.headers.truncate(0);
+        read_http_headers(input, &mut response.headers) ?;
+        // skip interim responses like 100 Continue
+        if response.status >= 200 || response.status == 101 {
+            break;
+        }
+    }
+
+    if method == "HEAD" || response.status == 204 || response.status == 304 || response.status < 200 {
+        return Ok(response)
+    }
+
+    let chunked = response.header("Transfer-Encoding")
+        .map( | te | te.to_ascii_lowercase().contains("chunked")).unwrap_or(false);
+    if chunked {
+        loop {
+            let line = read_http_line(input) ?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16).map_err( | _ | format!("Invalid chunk size: {}", line)) ?;
+            if size == 0 {
+                // trailers end with an empty line, same as headers
+                read_http_headers(input, &mut response.headers) ?;
+                break;
+            }
+            let start = response.body.len();
+            let end = start.checked_add(size).filter( | end | *end <= max_size)
+                .ok_or_else( || format!("Http response larger than {} bytes", max_size)) ?;
+            response.body.resize(end, 0);
+            input.read_exact(&mut response.body[start..]).map_err( | e | format!("Error reading http chunk: {}", e)) ?;
+            read_http_line(input) ?;
+        }
+    }
+    else if let Some(len) = response.header("Content-Length") {
+        let len = len.parse::<usize>().map_err( | _ | format!("Invalid Content-Length: {}", len)) ?;
+        if len > max_size {
+            return Err(format!("Http response of {} bytes is larger than {} bytes", len, max_size))
+        }
+        response.body.resize(len, 0);
+        input.read_exact(&mut response.body).map_err( | e | format!("Error reading http body: {}", e)) ?;
+    }
+    else {
+        input.by_ref().take(max_size as u64 + 1).read_to_end(&mut response.body).map_err( | e | format!("Error reading http body: {}", e)) ?;
+        if response.body.len() > max_size {
+            return Err(format!("Http response larger than {} bytes", max_size))
+        }
+    }
+    Ok(response)
+}
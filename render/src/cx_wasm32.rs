@@ -448,6 +448,36 @@ impl Cx {
                         WebSocketMessageEvent {url, result: Err(err)}
                     ));
                 }
+                25 => { // http response
+                    let vec_ptr = to_wasm.mu32() as *mut u8;
+                    let vec_len = to_wasm.mu32() as usize;
+                    let request_id = to_wasm.mu32() as u64;
+                    let status = to_wasm.mu32() as u16;
+                    let url = to_wasm.parse_string();
+                    let reason = to_wasm.parse_string();
+                    let headers = to_wasm.parse_string().split("\r\n").filter_map( | line | {
+                        line.find(':').map( | pos | (line[..pos].trim().to_string(), line[pos + 1..].trim().to_string()))
+                    }).collect();
+                    let body = unsafe {Vec::<u8>::from_raw_parts(vec_ptr, vec_len, vec_len)};
+                    self.call_event_handler(&mut Event::HttpResponse(HttpResponseEvent {
+                        request_id,
+                        response: Ok(HttpResponse {url, status, reason, headers, body})
+                    }));
+                }
+                26 => { // http response error
+                    let request_id = to_wasm.mu32() as u64;
+                    let err = to_wasm.parse_string();
+                    self.call_event_handler(&mut Event::HttpResponse(HttpResponseEvent {
+                        request_id,
+                        response: Err(err)
+                    }));
+                }
+                27 => { // websocket close
+                    let url = to_wasm.parse_string();
+                    self.call_event_handler(&mut Event::WebSocketClose(
+                        WebSocketCloseEvent {url}
+                    ));
+                }
                 _ => {
                     panic!("Message unknown")
                 }
@@ -631,8 +661,17 @@ impl Cx {
         self.platform.from_wasm.http_send(verb, path, proto, domain, port, content_type, body, signal);
     }
     
-    pub fn websocket_send(&mut self, url: &str, data: &[u8]) {
+    pub fn http_request(&mut self, request: HttpRequest) -> u64 {
+        self.platform.http_request_id += 1;
+        let request_id = self.platform.http_request_id;
+        self.platform.from_wasm.http_request(request_id, &request);
+        request_id
+    }
+    
+    // the browser reconnects a closed socket on send, failures come back as events
+    pub fn websocket_send(&mut self, url: &str, data: &[u8]) -> bool {
         self.platform.from_wasm.websocket_send(url, data);
+        true
     }
     
     pub fn update_menu(&mut self, _menu: &Menu) {
@@ -791,6 +830,7 @@ pub struct CxPlatform {
     pub xr_last_left_input: XRInput,
     pub xr_last_right_input: XRInput,
     pub file_read_id: u64,
+    pub http_request_id: u64,
 }
 
 impl Default for CxPlatform {
@@ -803,6 +843,7 @@ impl Default for CxPlatform {
             index_buffers: 0,
             vaos: 0,
             file_read_id: 1,
+            http_request_id: 0,
             fingers_down: Vec::new(),
             xr_last_left_input: XRInput::default(),
             xr_last_right_input: XRInput::default(),
@@ -1258,6 +1299,21 @@ impl FromWasm {
         self.add_u8slice(body);
     }
     
+    pub fn http_request(&mut self, request_id: u64, request: &HttpRequest) {
+        // the browser follows redirects and limits responses on its own, max_redirects and max_response_size do not apply here
+        let mut headers = String::new();
+        for (name, value) in &request.headers {
+            headers.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        self.fit(2);
+        self.mu32(31);
+        self.mu32(request_id as u32);
+        self.add_string(&request.method);
+        self.add_string(&request.url);
+        self.add_string(&headers);
+        self.add_u8slice(&request.body);
+    }
+    
     pub fn websocket_send(&mut self, url: &str, data: &[u8]) {
         self.fit(1);
         self.mu32(30);
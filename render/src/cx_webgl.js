@@ -388,6 +388,34 @@
             this.send_string(error);
         }
         
+        websocket_close(url) {
+            let pos = this.fit(1);
+            this.mu32[pos ++] = 27;
+            this.send_string(url);
+        }
+        
+        http_response(request_id, status, url, reason, headers, body) {
+            let vec_len = body.byteLength;
+            let vec_ptr = this.alloc_wasm_vec(vec_len);
+            this.copy_to_wasm(body, vec_ptr);
+            let pos = this.fit(5);
+            this.mu32[pos ++] = 25;
+            this.mu32[pos ++] = vec_ptr;
+            this.mu32[pos ++] = vec_len;
+            this.mu32[pos ++] = request_id;
+            this.mu32[pos ++] = status;
+            this.send_string(url);
+            this.send_string(reason);
+            this.send_string(headers);
+        }
+        
+        http_response_error(request_id, error) {
+            let pos = this.fit(2);
+            this.mu32[pos ++] = 26;
+            this.mu32[pos ++] = request_id;
+            this.send_string(error);
+        }
+        
         end() {
             let pos = this.fit(1);
             this.mu32[pos] = 0;
@@ -1208,6 +1236,34 @@
             req.send(body.buffer);
         }
         
+        http_request(request_id, method, url, headers, body) {
+            var req = new XMLHttpRequest()
+            req.responseType = "arraybuffer";
+            req.addEventListener("error", _ => {
+                this.to_wasm.http_response_error(request_id, "Http request to " + url + " failed");
+                this.do_wasm_io();
+            })
+            req.addEventListener("load", _ => {
+                this.to_wasm.http_response(
+                    request_id,
+                    req.status,
+                    req.responseURL || url,
+                    req.statusText,
+                    req.getAllResponseHeaders(),
+                    req.response
+                );
+                this.do_wasm_io();
+            })
+            req.open(method, url, true);
+            for (let line of headers.split("\r\n")) {
+                let colon = line.indexOf(":");
+                if (colon > 0) {
+                    req.setRequestHeader(line.slice(0, colon).trim(), line.slice(colon + 1).trim());
+                }
+            }
+            req.send(body.buffer);
+        }
+        
         websocket_send(url, data) {
             let socket = this.websockets[url];
             if (!socket) {
@@ -1216,6 +1272,8 @@
                 socket.send_stack = [data];
                 socket.addEventListener('close', event => {
                     this.websockets[url] = null;
+                    this.to_wasm.websocket_close(url);
+                    this.do_wasm_io();
                 })
                 socket.addEventListener('error', event => {
                     this.websockets[url] = null;
@@ -2113,6 +2171,14 @@
             let url = self.parse_string();
             let data = self.parse_u8slice();
             self.websocket_send(url, data);
+        },
+        function http_request_31(self) {
+            let request_id = self.mu32[self.parse ++];
+            let method = self.parse_string();
+            let url = self.parse_string();
+            let headers = self.parse_string();
+            let body = self.parse_u8slice();
+            self.http_request(request_id, method, url, headers, body);
         }
     ]
     
@@ -2366,35 +2432,51 @@
     
     document.addEventListener('DOMContentLoaded', init)
     
-    function watchFileChange() {
-        var req = new XMLHttpRequest()
-        req.timeout = 60000
-        req.addEventListener("error", function() {
-            
+    // tells the hub which files this page runs, so a rebuild of another app leaves it alone
+    function liveReloadFiles() {
+        var files = [location.pathname]
+        var canvasses = document.getElementsByClassName('cx_webgl')
+        for (let i = 0; i < canvasses.length; i ++) {
+            let wasmfile = canvasses[i].getAttribute("wasm")
+            if (wasmfile) files.push(new URL(wasmfile, location.href).pathname)
+        }
+        return files.map(encodeURIComponent).join(",")
+    }
+    
+    // the hub pushes file changes and build progress over a websocket
+    function watchLiveReload(reconnect) {
+        var proto = location.protocol == "https:" ? "wss://" : "ws://"
+        var ws = new WebSocket(proto + location.host + "/$livereload?files=" + liveReloadFiles())
+        ws.addEventListener("open", function() {
+            // we might have missed a rebuild while the hub was gone
+            if (reconnect) location.href = location.href
+        })
+        ws.addEventListener("close", function() {
             setTimeout(function() {
-                location.href = location.href
+                watchLiveReload(true)
             }, 500)
         })
-        req.responseType = 'text'
-        req.addEventListener("load", function() {
-            if (req.status === 201) return watchFileChange();
-            if (req.status === 200) {
-                var msg = JSON.parse(req.response);
-                if (msg.type == "file_change") {
-                    location.href = location.href
+        ws.addEventListener("message", function(event) {
+            var msg = JSON.parse(event.data);
+            if (msg.type == "file_change" || msg.type == "wasm_ready") {
+                location.href = location.href
+            }
+            else if (msg.type == "build_start") {
+                let note = "Rebuilding application..."
+                if (document.title != note) {
+                    document.title = note;
+                    console.log(note);
                 }
-                if (msg.type == "build_start") {
-                    let note = "Rebuilding application..."
-                    if (document.title != note) {
-                        document.title = note;
-                        console.log(note);
-                    }
-                    watchFileChange();
+            }
+            else if (msg.type == "build_finished" && msg.errors.length > 0) {
+                document.title = "Build failed with " + msg.errors.length + " errors";
+                for (let error of msg.errors) {
+                    console.error(error.path + ":" + error.line + ":" + error.column + " " + error.message);
                 }
             }
         })
-        req.open("GET", "/$watch?" + ('' + Math.random()).slice(2))
-        req.send()
     }
-    watchFileChange()
+    document.addEventListener('DOMContentLoaded', function() {
+        watchLiveReload(false)
+    })
 })({})
\ No newline at end of file
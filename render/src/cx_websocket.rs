@@ -0,0 +1,160 @@
+// native websocket client, a writer thread owns the socket and a reader thread feeds the frame parser
+use crate::cx::*;
+use crate::cx_http::*;
+use makepad_http::websocket::{WebSocket, WebSocketMaskRng, WebSocketMessage, WebSocketResult};
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+enum WebSocketOut {
+    Data(Vec<u8>),
+    // a ready made frame from the parser, pongs and the close handshake
+    Frame(Vec<u8>),
+    Close
+}
+
+#[derive(Clone)]
+pub struct DesktopWebSocket {
+    sender: mpsc::Sender<WebSocketOut>
+}
+
+impl DesktopWebSocket {
+    pub fn connect(url: &str, net_events: Arc<Mutex<Vec<DesktopNetEvent>>>) -> DesktopWebSocket {
+        let (sender, receiver) = mpsc::channel();
+        let url = url.to_string();
+        let reader_sender = sender.clone();
+        std::thread::spawn(move || {
+            let stream = match websocket_handshake(&url) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    push_net_event(&net_events, DesktopNetEvent::WebSocketMessage(WebSocketMessageEvent {url: url.clone(), result: Err(err)}));
+                    push_net_event(&net_events, DesktopNetEvent::WebSocketClose(WebSocketCloseEvent {url}));
+                    return
+                }
+            };
+            let (mut stream, reader) = stream;
+            let reader_url = url.clone();
+            let reader_events = net_events.clone();
+            std::thread::spawn(move || websocket_read_loop(reader_url, reader, reader_sender, reader_events));
+
+            let mut rng = WebSocketMaskRng::new(url.as_bytes());
+            while let Ok(out) = receiver.recv() {
+                let frame = match out {
+                    WebSocketOut::Data(data) => {
+                        let mut msg = WebSocketMessage::new_binary_masked(data.len(), rng.next_mask());
+                        msg.append(&data);
+                        msg.take()
+                    },
+                    WebSocketOut::Frame(frame) => frame,
+                    WebSocketOut::Close => break
+                };
+                if let Err(err) = stream.write_all(&frame) {
+                    push_net_event(&net_events, DesktopNetEvent::WebSocketMessage(WebSocketMessageEvent {
+                        url: url.clone(),
+                        result: Err(format!("Cannot send to {}: {}", url, err))
+                    }));
+                    break;
+                }
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+            // whatever was queued after the socket went away never gets out
+            let mut dropped = 0;
+            while let Ok(out) = receiver.try_recv() {
+                if let WebSocketOut::Data(_) = out {
+                    dropped += 1;
+                }
+            }
+            if dropped > 0 {
+                push_net_event(&net_events, DesktopNetEvent::WebSocketMessage(WebSocketMessageEvent {
+                    url: url.clone(),
+                    result: Err(format!("Connection to {} closed, {} queued messages were not sent", url, dropped))
+                }));
+            }
+        });
+        DesktopWebSocket {sender}
+    }
+
+    // false when the connection is already gone. once queued a failed send comes back as an error event
+    pub fn send(&self, data: &[u8]) -> bool {
+        self.sender.send(WebSocketOut::Data(data.to_vec())).is_ok()
+    }
+}
+
+fn push_net_event(net_events: &Arc<Mutex<Vec<DesktopNetEvent>>>, event: DesktopNetEvent) {
+    if let Ok(mut net_events) = net_events.lock() {
+        net_events.push(event);
+    }
+    Cx::post_signal(NET_EVENT_SIGNAL, Cx::status_http_send_ok());
+}
+
+fn websocket_handshake(url: &str) -> Result<(TcpStream, BufReader<TcpStream>), String> {
+    let http_url = if let Some(rest) = url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    }
+    else if url.starts_with("wss://") {
+        return Err(format!("wss is not supported by the native websocket client: {}", url))
+    }
+    else {
+        return Err(format!("Not a websocket url: {}", url))
+    };
+    let parsed = parse_http_url(&http_url) ?;
+    let mut stream = http_connect(&parsed, Duration::from_secs(30))
+        .map_err( | e | format!("Cannot connect to {}: {}", url, e)) ?;
+    let host = if parsed.port == 80 {parsed.host.clone()} else {format!("{}:{}", parsed.host, parsed.port)};
+
+    let seed = format!("{}{:?}", url, SystemTime::now().duration_since(UNIX_EPOCH));
+    let key = WebSocket::create_client_key(seed.as_bytes());
+    stream.write_all(WebSocket::create_upgrade_request(&host, &parsed.path, &key).as_bytes())
+        .map_err( | e | format!("Cannot send websocket upgrade to {}: {}", url, e)) ?;
+
+    // the reader has to live on, the server might have sent frames right after the upgrade
+    let read_stream = stream.try_clone().map_err( | e | format!("Cannot clone socket for {}: {}", url, e)) ?;
+    let mut reader = BufReader::new(read_stream);
+    // the upgrade has no body, the frames that follow stay in the reader
+    let response = read_http_response(&mut reader, "GET", 0) ?;
+    if response.status != 101 {
+        return Err(format!("Websocket upgrade of {} failed with {} {}", url, response.status, response.reason))
+    }
+    if response.header("Sec-WebSocket-Accept") != Some(WebSocket::accept_key(&key).as_str()) {
+        return Err(format!("Websocket upgrade of {} returned the wrong accept key", url))
+    }
+    Ok((stream, reader))
+}
+
+fn websocket_read_loop(url: String, mut reader: BufReader<TcpStream>, sender: mpsc::Sender<WebSocketOut>, net_events: Arc<Mutex<Vec<DesktopNetEvent>>>) {
+    let mut web_socket = WebSocket::new_client();
+    loop {
+        let len = match reader.fill_buf() {
+            Ok(buf) if !buf.is_empty() => {
+                for result in web_socket.parse(buf) {
+                    match result {
+                        WebSocketResult::Data(data) => {
+                            push_net_event(&net_events, DesktopNetEvent::WebSocketMessage(WebSocketMessageEvent {url: url.clone(), result: Ok(data)}));
+                        },
+                        WebSocketResult::Text(text) => {
+                            push_net_event(&net_events, DesktopNetEvent::WebSocketMessage(WebSocketMessageEvent {url: url.clone(), result: Ok(text.into_bytes())}));
+                        },
+                        WebSocketResult::Error(err) => {
+                            push_net_event(&net_events, DesktopNetEvent::WebSocketMessage(WebSocketMessageEvent {url: url.clone(), result: Err(err)}));
+                        },
+                        WebSocketResult::Ping(_) | WebSocketResult::Pong(_) | WebSocketResult::Close(_, _) => ()
+                    }
+                }
+                for reply in web_socket.take_replies() {
+                    let _ = sender.send(WebSocketOut::Frame(reply));
+                }
+                if web_socket.is_closed() {
+                    break;
+                }
+                buf.len()
+            },
+            _ => break
+        };
+        reader.consume(len);
+    }
+    // the writer sends the queued close reply and then shuts the socket down
+    let _ = sender.send(WebSocketOut::Close);
+    push_net_event(&net_events, DesktopNetEvent::WebSocketClose(WebSocketCloseEvent {url}));
+}
@@ -280,6 +280,17 @@ pub struct WebSocketMessageEvent{
     pub result: Result<Vec<u8>, String>
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebSocketCloseEvent{
+    pub url: String
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponseEvent {
+    pub request_id: u64,
+    pub response: Result<HttpResponse, String>
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct FingerDragEvent {
     pub handled: bool,
@@ -345,6 +356,8 @@ pub enum Event {
     TextCopy(TextCopyEvent),
     LiveRecompile(LiveRecompileEvent),
     WebSocketMessage(WebSocketMessageEvent),
+    WebSocketClose(WebSocketCloseEvent),
+    HttpResponse(HttpResponseEvent),
     FingerDrag(FingerDragEvent),
 }
 
@@ -673,6 +686,85 @@ impl Into<TriggerId> for TypeId {
 
 
 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // redirects followed before the redirect itself is returned, browsers always follow them.
+    // a redirect to https is always returned, the desktop client only speaks http
+    pub max_redirects: usize,
+    // a bigger body ends the request with an error instead of eating all memory
+    pub max_response_size: usize,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            max_redirects: 5,
+            max_response_size: 256 * 1024 * 1024,
+        }
+    }
+    
+    pub fn get(url: &str) -> HttpRequest {
+        HttpRequest::new("GET", url)
+    }
+    
+    pub fn post(url: &str, content_type: &str, body: Vec<u8>) -> HttpRequest {
+        HttpRequest::new("POST", url).with_body(content_type, body)
+    }
+    
+    pub fn with_header(mut self, name: &str, value: &str) -> HttpRequest {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    
+    pub fn with_body(mut self, content_type: &str, body: Vec<u8>) -> HttpRequest {
+        self.headers.push(("Content-Type".to_string(), content_type.to_string()));
+        self.body = body;
+        self
+    }
+    
+    pub fn with_max_response_size(mut self, max_response_size: usize) -> HttpRequest {
+        self.max_response_size = max_response_size;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpResponse {
+    // the url the response came from after following redirects
+    pub url: String,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+    
+    pub fn header(&self, name: &str) -> Option<&str> {
+        for (key, value) in &self.headers {
+            if key.eq_ignore_ascii_case(name) {
+                return Some(value)
+            }
+        }
+        None
+    }
+    
+    pub fn body_utf8(&self) -> Result<&str, String> {
+        std::str::from_utf8(&self.body).map_err( | _ | format!("http response from {} is not utf8", self.url))
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct FileRead {
     pub path: String,
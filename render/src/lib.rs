@@ -37,6 +37,10 @@ mod cx_wasm32;
 #[macro_use]
 #[cfg(all(not(feature="ipc"),any(target_os = "linux", target_os="macos", target_os="windows")))]
 mod cx_desktop;
+#[cfg(all(not(feature="ipc"),any(target_os = "linux", target_os="macos", target_os="windows")))]
+mod cx_http;
+#[cfg(all(not(feature="ipc"),any(target_os = "linux", target_os="macos", target_os="windows")))]
+mod cx_websocket;
 
 mod cx_style;
 
@@ -1,6 +1,6 @@
 // this webserver is serving our site. Why? WHYYY. Because it was fun to write. And MUCH faster and MUCH simpler than anything else imaginable.
 
-use std::net::{TcpListener, TcpStream, SocketAddr, Shutdown};
+use std::net::SocketAddr;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::fs;
@@ -13,6 +13,8 @@ use std::io::prelude::*;
 
 use makepad_http::channel::WebSocketChannels;
 use makepad_http::httputil::*;
+use makepad_http::server;
+use makepad_http::server::{HttpRouter, HttpRequest, HttpResponse, HttpReply, HttpServerOptions};
 
 fn main() {
     // config params
@@ -26,7 +28,7 @@ fn main() {
     let zlib_filecache = FileCacheWrap::default();
     let websocket_channels = WebSocketChannels::default();
     
-    let http_server = HttpServer::start_http_server(
+    let mut http_server = HttpServer::start_http_server(
         SocketAddr::from(([0, 0, 0, 0], 80)),
         brotli_filecache.clone(),
         zlib_filecache.clone(),
@@ -66,7 +68,7 @@ fn main() {
         }
     });
     
-    http_server.listen_thread.unwrap().join().expect("can't join thread");
+    http_server.server.join();
 }
 
 #[derive(Clone, Default)]
@@ -80,14 +82,12 @@ pub struct FileCache {
 }
 
 pub struct HttpServer {
-    pub listen_thread: Option<std::thread::JoinHandle<() >>,
-    pub listen_address: Option<SocketAddr>,
+    pub server: server::HttpServer,
 }
 
-
 impl HttpServer {
     
-    fn handle_post(tcp_stream: TcpStream, url: &str, mut body: Vec<u8>) {
+    fn handle_post(url: &str, body: &[u8]) -> HttpResponse {
         match url {
             "/subscribe" => {
                 let mut file = OpenOptions::new()
@@ -97,48 +97,36 @@ impl HttpServer {
                     .open("subscribe.db")
                     .unwrap();
                 
+                let mut body = body.to_vec();
                 body.push('\n' as u8);
                 if let Err(_) = file.write(&body) {
                     println!("Couldn't append email to file");
                 }
+                HttpResponse::new(200)
             },
-            _ => return http_error_out(tcp_stream, 500)
+            _ => HttpResponse::new(500)
         }
     }
     
     
     fn handle_get(
-        mut tcp_stream: TcpStream,
         path: &str,
-        accept_encoding: String,
+        accept_encoding: &str,
         zlib_filecache: FileCache,
         brotli_filecache: FileCache,
-    ) {
+    ) -> HttpResponse {
         
-        let mime_type = if path.ends_with(".html") {"text/html"}
-        else if path.ends_with(".wasm") {"application/wasm"}
-        else if path.ends_with(".js") {"text/javascript"}
-        else if path.ends_with(".ttf") {"application/ttf"}
-        else {"application/octet-stream"};
+        let mime_type = mime_type_for_path(path);
         
         if accept_encoding.contains("br") { // we want the brotli
             if let Some(brotli_filecache) = brotli_filecache.cache {
                 if let Some(data) = brotli_filecache.get(path) {
-                    let header = format!(
-                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\n\
-                            Content-encoding: br\r\n\
-                            Cache-Control: max-age:0\r\n\
-                            Content-Length: {}\r\n\
-                            Connection: close\r\n\r\n",
-                        mime_type,
-                        data.len()
-                    );
-                    write_bytes_to_tcp_stream_no_error(&mut tcp_stream, header.as_bytes());
-                    write_bytes_to_tcp_stream_no_error(&mut tcp_stream, &data);
-                    let _ = tcp_stream.shutdown(Shutdown::Both);
+                    return HttpResponse::ok(mime_type, data.clone())
+                        .with_header("Content-encoding", "br")
+                        .with_header("Cache-Control", "max-age:0")
                 }
                 else {
-                    return http_error_out(tcp_stream, 404);
+                    return HttpResponse::not_found()
                 }
             }
         }
@@ -146,26 +134,16 @@ impl HttpServer {
         if accept_encoding.contains("gzip") || accept_encoding.contains("deflate") {
             if let Some(zlib_filecache) = zlib_filecache.cache {
                 if let Some(data) = zlib_filecache.get(path) {
-                    let header = format!(
-                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\n\
-                            Content-encoding: deflate\r\n\
-                            Cache-Control: max-age:0\r\n\
-                            Content-Length: {}\r\n\
-                            Connection: close\r\n\r\n",
-                        mime_type,
-                        data.len()
-                    );
-                    write_bytes_to_tcp_stream_no_error(&mut tcp_stream, header.as_bytes());
-                    write_bytes_to_tcp_stream_no_error(&mut tcp_stream, &data);
-                    let _ = tcp_stream.shutdown(Shutdown::Both);
-                    return
+                    return HttpResponse::ok(mime_type, data.clone())
+                        .with_header("Content-encoding", "deflate")
+                        .with_header("Cache-Control", "max-age:0")
                 }
                 else {
-                    return http_error_out(tcp_stream, 404);
+                    return HttpResponse::not_found()
                 }
             }
         }
-        return http_error_out(tcp_stream, 500);
+        HttpResponse::new(500)
     }
     
     fn compress_tree_recursive(
@@ -235,99 +213,49 @@ impl HttpServer {
         websocket_channels: WebSocketChannels,
     ) -> Option<HttpServer> {
         
-        let listener = if let Ok(listener) = TcpListener::bind(listen_address.clone()) {listener} else {println!("Cannot bind http server port"); return None};
-        
-        let listen_thread = {
-            std::thread::spawn(move || {
-                for tcp_stream in listener.incoming() {
-                    let mut tcp_stream = if let Ok(tcp_stream) = tcp_stream {
-                        tcp_stream
-                    }
-                    else {
-                        println!("Incoming stream failure");
-                        continue
-                    };
-                    
-                    let zlib_filecache = if let Ok(v) = zlib_filecache.wrap.lock() {
-                        v.clone()
-                    }
-                    else {
-                        FileCache::default()
-                    };
-                    
-                    let brotli_filecache = if let Ok(v) = brotli_filecache.wrap.lock() {
-                        v.clone()
-                    }
-                    else {
-                        FileCache::default()
-                    };
-                    
-                    let websocket_channels = websocket_channels.clone();
-                    let _read_thread = std::thread::spawn(move || {
-                        
-                        let header = HttpHeader::from_tcp_stream(tcp_stream.try_clone().expect("Cannot clone tcp stream"));
-                        
-                        if header.is_none() {
-                            return http_error_out(tcp_stream, 500);
-                        }
-                        let header = header.unwrap();
-                        
-                        if let Some(key) = header.sec_websocket_key {
-                            // lets hash the thing
-                            return websocket_channels.handle_websocket(&mut tcp_stream, &header.path, &key);
-                        }
-                        
-                        if header.verb == "POST" {
-                            // we have to have a content-length or bust
-                            if header.content_length.is_none() {
-                                return http_error_out(tcp_stream, 500);
-                            }
-                            let content_length = header.content_length.unwrap();
-                            
-                            let bytes_total = content_length as usize;
-                            let mut body = Vec::new();
-                            body.resize(bytes_total, 0u8);
-                            // lets read content_length
-                            let mut bytes_left = bytes_total;
-                            while bytes_left > 0 {
-                                let buf = &mut body[(bytes_total - bytes_left)..bytes_total];
-                                let bytes_read = tcp_stream.read(buf);
-                                if bytes_read.is_err() {
-                                    return http_error_out(tcp_stream, 500);
-                                }
-                                let bytes_read = bytes_read.unwrap();
-                                if bytes_read == 0 {
-                                    return http_error_out(tcp_stream, 500);
-                                }
-                                bytes_left -= bytes_read;
-                            }
-                            
-                            return Self::handle_post(tcp_stream, &header.path, body);
-                        }
-                        
-                        if header.verb == "GET" {
-                            if let Some(accept_encoding) = header.accept_encoding {
-                                return Self::handle_get(
-                                    tcp_stream,
-                                    &header.path,
-                                    accept_encoding,
-                                    zlib_filecache,
-                                    brotli_filecache
-                                )
-                            }
-                        }
-                        
-                        return http_error_out(tcp_stream, 500);
-                    });
-                }
+        let router = HttpRouter::new()
+            .add("WEBSOCKET", "/*", move | request: &HttpRequest | {
+                websocket_channels.clone().upgrade(request)
+            })
+            .add("POST", "/*", | request: &HttpRequest | {
+                HttpReply::Response(Self::handle_post(&request.path, &request.body))
             })
-        };
-        Some(HttpServer {
-            listen_thread: Some(listen_thread),
-            listen_address: Some(listen_address.clone()),
-        })
+            .add("GET", "/*", move | request: &HttpRequest | {
+                let accept_encoding = if let Some(accept_encoding) = request.header("Accept-Encoding") {
+                    accept_encoding
+                }
+                else {
+                    return HttpReply::Response(HttpResponse::new(500))
+                };
+                let zlib_filecache = if let Ok(v) = zlib_filecache.wrap.lock() {
+                    v.clone()
+                }
+                else {
+                    FileCache::default()
+                };
+                
+                let brotli_filecache = if let Ok(v) = brotli_filecache.wrap.lock() {
+                    v.clone()
+                }
+                else {
+                    FileCache::default()
+                };
+                // directories serve their index.html
+                let path = if request.path.ends_with("/") {
+                    format!("{}index.html", request.path)
+                }
+                else {
+                    request.path.clone()
+                };
+                HttpReply::Response(Self::handle_get(&path, accept_encoding, zlib_filecache, brotli_filecache))
+            });
+        
+        match server::HttpServer::start(listen_address, router, HttpServerOptions::default()) {
+            Ok(server) => Some(HttpServer {server}),
+            Err(_) => {
+                println!("Cannot bind http server port");
+                None
+            }
+        }
     }
 }
-
-
-
@@ -65,7 +65,22 @@ pub enum TextBufferMessageLevel {
 #[derive(Clone)]
 pub struct TextBufferMessage {
     pub level: TextBufferMessageLevel,
-    pub body: String
+    pub body: String,
+    pub fixes: Vec<TextBufferFix>
+}
+
+// a fix offered with a message, the edits are char offset ranges into the buffer
+#[derive(Clone)]
+pub struct TextBufferFix {
+    pub label: String,
+    pub edits: Vec<TextBufferEdit>
+}
+
+#[derive(Clone)]
+pub struct TextBufferEdit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String
 }
 
 #[derive(Clone, Copy, PartialEq, Default)]
@@ -98,6 +113,7 @@ pub enum TextUndoGrouping {
     Tab,
     Cut,
     Format,
+    Fix(u64),
     Other
 }
 
@@ -120,6 +136,7 @@ impl TextUndoGrouping {
             TextUndoGrouping::Tab => false,
             TextUndoGrouping::Format => false,
             TextUndoGrouping::Cut => false,
+            TextUndoGrouping::Fix(_) => true,
             TextUndoGrouping::Other => false
         }
     }
@@ -532,6 +532,22 @@ impl TextCursorSet {
         })
     }
     
+    // applies the edits back to front so the offsets stay valid, one undo step for the whole fix
+    pub fn apply_fix(&mut self, fix: &TextBufferFix, text_buffer: &mut TextBuffer) {
+        let mut edits = fix.edits.clone();
+        edits.sort_by( | a, b | b.start.cmp(&a.start));
+        let total_char_count = text_buffer.calc_char_count();
+        if edits.iter().any( | edit | edit.start > edit.end || edit.end > total_char_count) {
+            return
+        }
+        self.insert_undo_group += 1;
+        let group = self.insert_undo_group;
+        for edit in &edits {
+            self.clear_and_set_last_cursor_head_and_tail(edit.end, edit.start, text_buffer);
+            self.replace_text(&edit.replacement, text_buffer, Some(TextUndoGrouping::Fix(group)));
+        }
+    }
+    
     pub fn insert_around(&mut self, pre: &str, post: &str, text_buffer: &mut TextBuffer) {
         let mut delta: isize = 0;
         // rolling delta to displace cursors
@@ -51,6 +51,8 @@ pub struct TextEditor {
     pub shadow: ScrollShadow,
     pub message_marker: DrawColor,
     pub search_marker: DrawColor,
+    pub fix_bg: DrawColor,
+    pub fix_text: DrawText,
     pub text: DrawText,
     pub line_number_text: DrawText,
     pub cursors: TextCursorSet,
@@ -122,6 +124,7 @@ pub struct TextEditor {
     pub _draw_cursors: DrawCursors,
     pub _draw_search: DrawCursors,
     pub _draw_messages: DrawCursors,
+    pub _fix: Option<TextBufferFix>,
     
     pub _cursor_blink_timer: Timer,
     pub _cursor_blink_flipflop: f32,
@@ -216,6 +219,10 @@ impl TextEditor {
             paren_pair: DrawColor::new(cx, live_shader!(cx, self::shader_paren_pair)),
             message_marker: DrawColor::new(cx, live_shader!(cx, self::shader_message_marker)),
             search_marker: DrawColor::new(cx, live_shader!(cx, self::shader_search_marker)),
+            fix_bg: DrawColor::new(cx, live_shader!(cx, self::shader_fix_bg))
+                .with_draw_depth(1.3),
+            fix_text: DrawText::new(cx, default_shader!())
+                .with_draw_depth(1.4),
             //code_icon: CodeIcon::proto(cx),
             //view_layout: Layout::default(),
             text: DrawText::new(cx, default_shader!())
@@ -276,6 +283,7 @@ impl TextEditor {
             _draw_cursors: DrawCursors::new(),
             _draw_search: DrawCursors::new(),
             _draw_messages: DrawCursors::new(),
+            _fix: None,
             
             _paren_stack: Vec::new(),
             _indent_stack: Vec::new(),
@@ -331,6 +339,9 @@ impl TextEditor {
             
             self::color_search_marker: #804000;
             
+            self::color_fix_bg: #0e639c;
+            self::color_fix_text: #f;
+            
             self::color_line_number_normal: #88;
             self::color_line_number_highlight: #d4;
             
@@ -477,6 +488,15 @@ impl TextEditor {
                 }
             }
             
+            self::shader_fix_bg: Shader {
+                use makepad_render::drawcolor::shader::*;
+                fn pixel() -> vec4 {
+                    let cx = Df::viewport(pos * rect_size);
+                    cx.box(0., 0., rect_size.x, rect_size.y, 2.);
+                    return cx.fill(color);
+                }
+            }
+            
             self::shader_bg: Shader {
                 use makepad_render::drawcolor::shader::*;
                 fn pixel() -> vec4 {
@@ -494,6 +514,8 @@ impl TextEditor {
         self.colors.indent_line_looping = live_vec4!(cx, self::color_indent_line_looping);
         self.colors.indent_line_flow = live_vec4!(cx, self::color_indent_line_flow);
         self.search_marker.color = live_vec4!(cx, self::color_search_marker);
+        self.fix_bg.color = live_vec4!(cx, self::color_fix_bg);
+        self.fix_text.color = live_vec4!(cx, self::color_fix_text);
         self.colors.paren_pair_match = live_vec4!(cx, self::color_paren_pair_match);
         self.colors.paren_pair_fail = live_vec4!(cx, self::color_paren_pair_fail);
         self.colors.message_marker_error = live_vec4!(cx, self::color_message_marker_error);
@@ -538,6 +560,7 @@ impl TextEditor {
         self.cursor_row.color = live_vec4!(cx, self::color_cursor_row);
         self.text.text_style = live_text_style!(cx, self::text_style_editor_text);
         self.line_number_text.text_style = live_text_style!(cx, self::text_style_editor_text);
+        self.fix_text.text_style = live_text_style!(cx, self::text_style_editor_text);
         /*
         self.bg.shader = live_shader!(cx, self::shader_bg);
         self.indent_lines.shader = live_shader!(cx, self::shader_indent_lines);
@@ -1079,6 +1102,14 @@ impl TextEditor {
             },
             _ => ()
         }
+        // the fix button sits on top of the text, so it gets the click first
+        if let Event::FingerDown(_) = event.hits(cx, self.fix_bg.area(), HitOpt::default()) {
+            if let Some(fix) = self._fix.take() {
+                self.cursors.apply_fix(&fix, text_buffer);
+                self.view.redraw_view(cx);
+                return TextEditorEvent::Change
+            }
+        }
         let mut cursor_moved = false;
         // editor local
         match event.hits(cx, self.view.area(), HitOpt::default()) {
@@ -1126,6 +1157,13 @@ impl TextEditor {
                         return TextEditorEvent::Decl(search)
                     }*/
                 }*/
+                if ke.key_code == KeyCode::Period && (ke.modifiers.logo || ke.modifiers.control) {
+                    if let Some(fix) = self.fix_at_last_cursor(text_buffer) {
+                        self.cursors.apply_fix(&fix, text_buffer);
+                        self.view.redraw_view(cx);
+                        return TextEditorEvent::Change
+                    }
+                }
                 if ke.key_code == KeyCode::KeyF && (ke.modifiers.logo || ke.modifiers.control) {
                     let search = self.cursors.get_ident_around_last_cursor_and_set(text_buffer);
                     return TextEditorEvent::Search(search)
@@ -1845,6 +1883,7 @@ impl TextEditor {
         self.draw_selections(cx);
         self.draw_search_markers(cx);
         self.draw_message_markers(cx, text_buffer);
+        self.draw_fix_button(cx, text_buffer);
         
         // inject a final page
         self._final_fill_height = cx.get_height_total() - self._monospace_size.y;
@@ -1934,6 +1973,48 @@ impl TextEditor {
         }
     }
     
+    // the first fix of a message under the last cursor, as long as the messages match the text
+    pub fn fix_at_last_cursor(&self, text_buffer: &TextBuffer) -> Option<TextBufferFix> {
+        let markers = &text_buffer.markers;
+        if self.read_only || markers.mutation_id != text_buffer.mutation_id {
+            return None
+        }
+        let pos = self.cursors.get_last_cursor_head();
+        for (cursor, body) in markers.message_cursors.iter().zip(markers.message_bodies.iter()) {
+            let (start, end) = cursor.order();
+            if pos >= start && pos <= end {
+                if let Some(fix) = body.fixes.first() {
+                    return Some(fix.clone())
+                }
+            }
+        }
+        None
+    }
+    
+    fn draw_fix_button(&mut self, cx: &mut Cx, text_buffer: &TextBuffer) {
+        self._fix = None;
+        if !self.has_key_focus(cx) {
+            return
+        }
+        let rc = if let Some(last_cursor) = self._draw_cursors.last_cursor {
+            self._draw_cursors.cursors[last_cursor]
+        }
+        else {
+            return
+        };
+        if let Some(fix) = self.fix_at_last_cursor(text_buffer) {
+            let label = format!("fix: {}", fix.label);
+            let char_width = self._monospace_base.x * self.fix_text.text_style.font_size;
+            let pos = vec2(rc.x + 2. * char_width, rc.y);
+            self.fix_bg.draw_quad_abs(cx, Rect {
+                pos: pos,
+                size: vec2(char_width * (label.chars().count() + 2) as f32, rc.h)
+            });
+            self.fix_text.draw_text_abs(cx, vec2(pos.x + char_width, pos.y), &label);
+            self._fix = Some(fix);
+        }
+    }
+    
     pub fn draw_search_markers(&mut self, cx: &mut Cx) {
         let origin = cx.get_turtle_origin();
         